@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use magellanicus::renderer::{get_default_vertical_fov, AddBSPParameter, AddBSPParameterLightmapMaterial, AddBSPParameterLightmapSet, AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, AddFontParameter, AddFontParameterCharacter, AddShaderBasicShaderData, AddShaderData, AddShaderEnvironmentShaderData, AddShaderParameter, AddShaderTransparentChicagoShaderData, AddShaderTransparentChicagoShaderMap, AddSkyParameter, BSP3DNode, BSP3DNodeChild, BSP3DPlane, BSPCluster, BSPData, BSPLeaf, BSPPortal, BSPSubcluster, BitmapFormat, BitmapSprite, BitmapType, FogData, Renderer, RendererParameters, Resolution, ShaderType, MSAA};
+use magellanicus::renderer::{get_default_vertical_fov, AddBSPParameter, AddBSPParameterLightmapMaterial, AddBSPParameterLightmapSet, AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, AddFontParameter, AddFontParameterCharacter, AddShaderBasicShaderData, AddShaderData, AddShaderEnvironmentShaderData, AddShaderModelShaderData, AddShaderParameter, AddShaderTransparentChicagoShaderData, AddShaderTransparentChicagoShaderMap, AddShaderTransparentGenericShaderData, AddShaderTransparentGenericShaderMap, AddShaderTransparentGenericShaderStage, AddShaderTransparentWaterShaderData, AddSkyParameter, AlphaMode, BSP3DNode, BSP3DNodeChild, BSP3DPlane, BSPCluster, BSPData, BSPLeaf, BSPPortal, BSPSubcluster, BitmapFormat, BitmapSprite, BitmapType, FogData, Renderer, RendererParameters, Resolution, ShaderEnvironmentMapFunction, ShaderModelDetailMask, ShaderType, MSAA};
 use std::collections::HashMap;
 use std::mem::transmute;
 use std::path::Path;
@@ -12,7 +12,7 @@ use std::time::Instant;
 use clap::Parser;
 use glam::Vec3;
 use magellanicus::vertex::{LightmapVertex, ModelTriangle, ModelVertex};
-use ringhopper::definitions::{Bitmap, BitmapDataFormat, BitmapDataType, Font, Globals, Scenario, ScenarioStructureBSP, ShaderEnvironment, ShaderModel, ShaderTransparentChicago, ShaderTransparentChicagoExtended, ShaderTransparentChicagoMap, ShaderTransparentGeneric, ShaderTransparentGlass, ShaderTransparentMeter, Sky, UnicodeStringList};
+use ringhopper::definitions::{Bitmap, BitmapDataFormat, BitmapDataType, Font, Globals, Scenario, ScenarioStructureBSP, ShaderEnvironment, ShaderModel, ShaderTransparentChicago, ShaderTransparentChicagoExtended, ShaderTransparentChicagoMap, ShaderTransparentGeneric, ShaderTransparentGenericStage, ShaderTransparentGlass, ShaderTransparentMeter, Sky, UnicodeStringList};
 use ringhopper::primitives::dynamic::DynamicTagDataArray;
 use ringhopper::primitives::engine::Engine;
 use ringhopper::primitives::primitive::{TagGroup, TagPath};
@@ -234,7 +234,8 @@ fn main() -> Result<(), String> {
                 vsync,
                 anisotropic_filtering,
                 msaa,
-                render_scale
+                render_scale,
+                ..Default::default()
             })
         }.unwrap();
 
@@ -664,11 +665,13 @@ impl FlycamTestHandler {
             .filter(|f| f.0.group() == TagGroup::Bitmap)
             .map(|f| (f.0, f.1.get_ref::<Bitmap>().unwrap()));
         
-        for (path, bitmap) in all_bitmaps {
-            Self::load_bitmap(renderer, &path, bitmap).map_err(|e| format!("Failed to load bitmap {path}: {e}"))?;
-        }
+        renderer.begin_bulk_load().map_err(|e| e.to_string())?;
+        let result = all_bitmaps
+            .into_iter()
+            .try_for_each(|(path, bitmap)| Self::load_bitmap(renderer, &path, bitmap).map_err(|e| format!("Failed to load bitmap {path}: {e}")));
+        renderer.end_bulk_load().map_err(|e| e.to_string())?;
 
-        Ok(())
+        result
     }
 
     fn load_bitmap(renderer: &mut Renderer, path: &&TagPath, bitmap: &Bitmap) -> Result<(), String> {
@@ -701,6 +704,8 @@ impl FlycamTestHandler {
                         },
                         resolution: Resolution { width: b.width as u32, height: b.height as u32 },
                         mipmap_count: b.mipmap_count as u32,
+                        alpha_mode: AlphaMode::Straight,
+                        color_key: None,
                         data: {
                             let length = MipmapTextureIterator::new_from_bitmap_data(b)
                                 .map_err(|e| format!("Error with reading bitmap data #{bitmap_index} from {path}: {e:?}"))?
@@ -759,7 +764,8 @@ impl FlycamTestHandler {
                     sequences.push(result);
                 }
                 sequences
-            }
+            },
+            generate_mipmaps: false
         };
 
         renderer.add_bitmap(&path.to_string(), parameter).map_err(|e| e.to_string())
@@ -816,6 +822,8 @@ impl FlycamTestHandler {
                         shader_environment_type: unsafe { transmute(tag.properties.shader_environment_type as u32) },
                         detail_map_function: unsafe { transmute(tag.diffuse.detail_map_function as u32) },
                         micro_detail_map_function: unsafe { transmute(tag.diffuse.micro_detail_map_function as u32) },
+                        detail_fade_start: 0.0,
+                        detail_fade_end: 0.0,
                         reflection_type: unsafe { transmute(tag.reflection._type as u32) },
                     })
                 }
@@ -823,10 +831,24 @@ impl FlycamTestHandler {
             TagGroup::ShaderModel => {
                 let tag = tag.get_ref::<ShaderModel>().unwrap();
                 AddShaderParameter {
-                    data: AddShaderData::BasicShader(AddShaderBasicShaderData {
-                        bitmap: tag.maps.base_map.path().map(|q| q.to_string()),
-                        shader_type: ShaderType::Model,
-                        alpha_tested: !tag.properties.flags.not_alpha_tested
+                    data: AddShaderData::ShaderModel(AddShaderModelShaderData {
+                        alpha_tested: !tag.properties.flags.not_alpha_tested,
+                        two_sided: false,
+                        base_map: tag.maps.base_map.path().map(|q| q.to_string()),
+                        map_uv_scale: [1.0, 1.0],
+                        multipurpose_map: tag.maps.multipurpose_map.path().map(|q| q.to_string()),
+                        detail_map: tag.maps.detail_map.path().map(|q| q.to_string()),
+                        detail_map_function: ShaderEnvironmentMapFunction::DoubleBiasedMultiply,
+                        detail_mask: ShaderModelDetailMask::None,
+                        detail_map_scale: 1.0,
+                        detail_map_v_scale: 1.0,
+                        detail_after_reflection: false,
+                        self_illumination_color: [0.0, 0.0, 0.0],
+                        reflection_cube_map: None,
+                        perpendicular_color: [1.0, 1.0, 1.0],
+                        perpendicular_brightness: 0.0,
+                        parallel_color: [1.0, 1.0, 1.0],
+                        parallel_brightness: 0.0,
                     })
                 }
             },
@@ -855,15 +877,16 @@ impl FlycamTestHandler {
             TagGroup::ShaderTransparentGeneric => {
                 let tag = tag.get_ref::<ShaderTransparentGeneric>().unwrap();
                 AddShaderParameter {
-                    data: AddShaderData::BasicShader(AddShaderBasicShaderData {
-                        bitmap: tag
-                            .maps
-                            .items
-                            .get(0)
-                            .and_then(|b| b.parameters.map.path())
-                            .map(|b| b.to_string()),
-                        shader_type: ShaderType::TransparentGeneric,
-                        alpha_tested: true
+                    data: AddShaderData::ShaderTransparentGeneric(AddShaderTransparentGenericShaderData {
+                        two_sided: tag.properties.flags.two_sided,
+                        first_map_type: unsafe { transmute(tag.properties.first_map_type as u32) },
+                        framebuffer_method: unsafe { transmute(tag.properties.framebuffer_blend_function as u32) },
+                        maps: tag.maps.items.iter().map(|map| AddShaderTransparentGenericShaderMap {
+                            bitmap: map.parameters.map.path().map(|p| p.to_string()),
+                            uv_scale: [map.parameters.map_u_scale as f32, map.parameters.map_v_scale as f32],
+                            uv_offset: [map.parameters.map_u_offset as f32, map.parameters.map_v_offset as f32],
+                        }).collect(),
+                        stages: tag.stages.items.iter().map(generic_stage_to_magellanicus_generic_stage).collect()
                     })
                 }
             },
@@ -877,7 +900,8 @@ impl FlycamTestHandler {
                             .path()
                             .map(|b| b.to_string()),
                         shader_type: ShaderType::TransparentGlass,
-                        alpha_tested: true
+                        alpha_tested: true,
+                        volume_slice: 0.0
                     })
                 }
             },
@@ -891,7 +915,8 @@ impl FlycamTestHandler {
                             .path()
                             .map(|b| b.to_string()),
                         shader_type: ShaderType::TransparentMeter,
-                        alpha_tested: true
+                        alpha_tested: true,
+                        volume_slice: 0.0
                     })
                 }
             },
@@ -901,17 +926,25 @@ impl FlycamTestHandler {
                     data: AddShaderData::BasicShader(AddShaderBasicShaderData {
                         bitmap: None,
                         shader_type: ShaderType::TransparentPlasma,
-                        alpha_tested: true
+                        alpha_tested: true,
+                        volume_slice: 0.0
                     })
                 }
             },
             TagGroup::ShaderTransparentWater => {
                 // let tag = tag.get_ref::<ShaderTransparentWater>().unwrap();
                 AddShaderParameter {
-                    data: AddShaderData::BasicShader(AddShaderBasicShaderData {
-                        bitmap: None,
-                        shader_type: ShaderType::TransparentWater,
-                        alpha_tested: true
+                    data: AddShaderData::ShaderTransparentWater(AddShaderTransparentWaterShaderData {
+                        base_map: None,
+                        base_map_alpha_modulates_reflection: false,
+                        base_map_color_modulates_background: false,
+                        reflection_map: None,
+                        ripple_map: None,
+                        ripple_scale: 1.0,
+                        perpendicular_color: [1.0, 1.0, 1.0],
+                        perpendicular_brightness: 0.2,
+                        parallel_color: [1.0, 1.0, 1.0],
+                        parallel_brightness: 0.6,
                     })
                 }
             },
@@ -969,7 +1002,8 @@ impl FlycamTestHandler {
                     data: data.to_vec(),
                     width,
                     height,
-                    advance_x: c.character_width as i32
+                    advance_x: c.character_width as i32,
+                    kerning: Vec::new()
                 };
 
                 Some(character)
@@ -985,14 +1019,16 @@ impl FlycamTestHandler {
                 max_opacity: sky.outdoor_fog.maximum_density as f32,
                 distance_from: sky.outdoor_fog.start_distance as f32,
                 distance_to: sky.outdoor_fog.opaque_distance as f32,
-                min_opacity: 0.0
+                min_opacity: 0.0,
+                ..FogData::default()
             },
             indoor_fog: FogData {
                 color: [sky.indoor_fog.color.red as f32, sky.indoor_fog.color.green as f32, sky.indoor_fog.color.blue as f32],
                 max_opacity: sky.indoor_fog.maximum_density as f32,
                 distance_from: sky.indoor_fog.start_distance as f32,
                 distance_to: sky.indoor_fog.opaque_distance as f32,
-                min_opacity: 0.0
+                min_opacity: 0.0,
+                ..FogData::default()
             },
         }).map_err(|e| e.to_string())
     }
@@ -1049,7 +1085,8 @@ impl FlycamTestHandler {
                     portals: bsp.cluster_portals.items.iter().map(|p| BSPPortal {
                         front_cluster: p.front_cluster.unwrap_or(0xFFFF) as usize,
                         back_cluster: p.back_cluster.unwrap_or(0xFFFF) as usize,
-                    }).collect()
+                    }).collect(),
+                    cluster_pvs: BSPData::decode_cluster_pvs(bsp.clusters.items.len(), bsp.cluster_data.as_slice())
                 },
             };
 
@@ -1084,7 +1121,7 @@ impl FlycamTestHandler {
                             let a = s.vertex0_index?;
                             let b = s.vertex1_index?;
                             let c = s.vertex2_index?;
-                            Some(ModelTriangle { indices: [a,b,c] })
+                            Some(ModelTriangle { indices: [a as u32, b as u32, c as u32] })
                     }).collect();
 
                     let (material, lightmap) = get_uncompressed_vertices_for_bsp_material(material).map_err(|e| {
@@ -1258,7 +1295,53 @@ fn chicago_map_to_magellanicus_chicago_map(map: &ShaderTransparentChicagoMap) ->
         alpha_function: unsafe { transmute(map.alpha_function as u32) },
         uv_scale: [map.parameters.map_u_scale as f32, map.parameters.map_v_scale as f32],
         uv_offset: [map.parameters.map_u_offset as f32, map.parameters.map_v_offset as f32],
-        alpha_replicate: map.flags.alpha_replicate
+        alpha_replicate: map.flags.alpha_replicate,
+        ..Default::default()
+    }
+}
+
+fn generic_stage_to_magellanicus_generic_stage(stage: &ShaderTransparentGenericStage) -> AddShaderTransparentGenericShaderStage {
+    let color0 = &stage.color0_animation_lower_bound;
+    let color1 = &stage.color1;
+    AddShaderTransparentGenericShaderStage {
+        color_mux: stage.flags.color_mux,
+        alpha_mux: stage.flags.alpha_mux,
+        constant_color0: [color0.red as f32, color0.green as f32, color0.blue as f32, color0.alpha as f32],
+        constant_color1: [color1.red as f32, color1.green as f32, color1.blue as f32, color1.alpha as f32],
+        color_inputs: unsafe { [
+            transmute(stage.input_a as u32),
+            transmute(stage.input_b as u32),
+            transmute(stage.input_c as u32),
+            transmute(stage.input_d as u32)
+        ] },
+        color_input_mappings: unsafe { [
+            transmute(stage.input_a_mapping as u32),
+            transmute(stage.input_b_mapping as u32),
+            transmute(stage.input_c_mapping as u32),
+            transmute(stage.input_d_mapping as u32)
+        ] },
+        color_output_ab: unsafe { transmute(stage.output_ab as u32) },
+        color_output_ab_function: unsafe { transmute(stage.output_ab_function as u32) },
+        color_output_cd: unsafe { transmute(stage.output_cd as u32) },
+        color_output_cd_function: unsafe { transmute(stage.output_cd_function as u32) },
+        color_output_ab_cd_mux_sum: unsafe { transmute(stage.output_ab_cd_mux_sum as u32) },
+        color_output_mapping: unsafe { transmute(stage.output_mapping_color as u32) },
+        alpha_inputs: unsafe { [
+            transmute(stage.input_a_alpha as u32),
+            transmute(stage.input_b_alpha as u32),
+            transmute(stage.input_c_alpha as u32),
+            transmute(stage.input_d_alpha as u32)
+        ] },
+        alpha_input_mappings: unsafe { [
+            transmute(stage.input_a_mapping_alpha as u32),
+            transmute(stage.input_b_mapping_alpha as u32),
+            transmute(stage.input_c_mapping_alpha as u32),
+            transmute(stage.input_d_mapping_alpha as u32)
+        ] },
+        alpha_output_ab: unsafe { transmute(stage.output_ab_alpha as u32) },
+        alpha_output_cd: unsafe { transmute(stage.output_cd_alpha as u32) },
+        alpha_output_ab_cd_mux_sum: unsafe { transmute(stage.output_ab_cd_mux_sum_alpha as u32) },
+        alpha_output_mapping: unsafe { transmute(stage.output_mapping_alpha as u32) }
     }
 }
 
@@ -1,4 +1,5 @@
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 
 /// General Result type
 pub type MResult<T> = Result<T, Error>;
@@ -6,8 +7,19 @@ pub type MResult<T> = Result<T, Error>;
 /// General Error type
 #[derive(Clone, Debug)]
 pub enum Error {
-    GraphicsAPIError { backend: &'static str, error: String },
-    DataError { error: String }
+    GraphicsAPIError {
+        backend: &'static str,
+        error: String,
+
+        /// The error from the graphics API that caused this, if any.
+        source: Option<Arc<dyn std::error::Error + Send + Sync>>
+    },
+    DataError { error: String },
+
+    /// The graphics device was lost, such as from a driver reset or the GPU being removed.
+    ///
+    /// The renderer can't be used after this; drop it and make a new one, re-adding everything that was loaded.
+    DeviceLost
 }
 
 impl Error {
@@ -19,8 +31,18 @@ impl Error {
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
-            Self::GraphicsAPIError { backend, error } => write!(f, "{backend} API error: {error}"),
-            Self::DataError { error } => write!(f, "Data error: {error}")
+            Self::GraphicsAPIError { backend, error, .. } => write!(f, "{backend} API error: {error}"),
+            Self::DataError { error } => write!(f, "Data error: {error}"),
+            Self::DeviceLost => write!(f, "Graphics device lost")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::GraphicsAPIError { source: Some(source), .. } => Some(source.as_ref()),
+            _ => None
         }
     }
 }
@@ -1,47 +1,85 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use data::*;
+pub use data::{FontDrawRequest, RaycastHit, RenderTargetId, TextAlignment, MIN_DRAW_DISTANCE_LIMIT, MAX_DRAW_DISTANCE_LIMIT};
 
 pub use parameters::*;
 use crate::renderer::vulkan::VulkanRenderer;
 use player_viewport::*;
 use crate::error::{Error, MResult};
 
-pub use player_viewport::Camera;
+pub use player_viewport::{Camera, DebugView, Projection};
 pub use player_viewport::get_default_vertical_fov;
 pub use player_viewport::horizontal_to_vertical_fov;
+pub use player_viewport::DEFAULT_CAMERA_UP;
+pub use player_viewport::{ViewportRect, MAX_VIEWPORT_COUNT};
+pub use log::{LogCallback, LogLevel};
+pub use post_process::{PostProcess, PostProcessContext};
+pub use overlay::{OverlayCallback, OverlayContext};
 
-use glam::{FloatExt, Vec3};
+#[cfg(feature = "shader-hot-reload")]
+pub use vulkan::VulkanPipelineType;
+
+use glam::{FloatExt, Mat4, Quat, Vec3};
 use crate::types::FloatColor;
 
 mod parameters;
 mod vulkan;
 mod data;
 mod player_viewport;
+mod frustum;
+mod log;
+mod post_process;
+mod overlay;
 
 pub struct Renderer {
     vulkan: VulkanRenderer,
     player_viewports: Vec<PlayerViewport>,
+    custom_viewport_layout: bool,
 
     bitmaps: HashMap<Arc<String>, Bitmap>,
     shaders: HashMap<Arc<String>, Shader>,
     geometries: HashMap<Arc<String>, Geometry>,
     skies: HashMap<Arc<String>, Sky>,
     bsps: HashMap<Arc<String>, Arc<BSP>>,
+    bsp_draw_distances: HashMap<Arc<String>, f32>,
     fonts: HashMap<Arc<String>, Font>,
+    render_targets: HashMap<Arc<String>, RenderTarget>,
+    next_render_target: usize,
+    paths: PathInterner,
 
     default_bitmaps: DefaultBitmaps,
     current_bsp: Option<Arc<String>>,
+    geometry_instances: Vec<GeometryInstance>,
+    instanced_geometries: Vec<InstancedGeometry>,
+    sprite_instances: Vec<SpriteInstance>,
+    scissor_stack: Vec<[f32; 4]>,
+    stencil_test: Option<u8>,
+    debug_lines: Vec<DebugLineInstance>,
 
     fps_counter_value: f64,
     fps_counter_time: Instant,
     fps_counter_count: u32,
 
-    debug_text: VecDeque<Bitmap>,
+    debug_text: Vec<CharacterQuad>,
     debug_text_stale: bool,
-    debug_font: Option<Arc<String>>,
+    debug_fonts: Vec<Arc<String>>,
+
+    background_color: FloatColor,
+    split_screen_bar: Option<SplitBarStyle>,
+    missing_texture_mode: MissingTextureMode,
+
+    time: f32,
+    frame_delta: f32,
+    last_frame_time: Instant,
+    fog_transition_seconds: f32,
+    dynamic_resolution: Option<DynamicResScale>,
+    post_process: Option<Box<dyn PostProcess>>,
+    overlay_callback: Option<OverlayCallback>,
+    minimized: bool,
+    zero_extent: bool,
 }
 
 impl Renderer {
@@ -51,87 +89,115 @@ impl Renderer {
     /// - `parameters` is invalid
     /// - the renderer backend could not be initialized for some reason
     pub unsafe fn new(surface: &(impl HasRawWindowHandle + HasRawDisplayHandle), parameters: RendererParameters) -> MResult<Self> {
+        Self::new_with_backend(parameters, |parameters| VulkanRenderer::new(parameters, surface))
+    }
+
+    /// Get all MSAA levels that can be used with the surface on the given GPU.
+    ///
+    /// This opens a temporary Vulkan instance, so it should not be called every frame.
+    ///
+    /// Errors if the GPU could not be found or cannot present to the surface.
+    pub unsafe fn supported_msaa(surface: &(impl HasRawWindowHandle + HasRawDisplayHandle), preferred_device: Option<&DeviceSelection>) -> MResult<Vec<MSAA>> {
+        VulkanRenderer::supported_msaa(surface, preferred_device)
+    }
+
+    /// Get the maximum anisotropic filtering level that can be used with the surface on the given GPU.
+    ///
+    /// Returns `None` if anisotropic filtering is unsupported. This opens a temporary Vulkan instance, so it should not be
+    /// called every frame.
+    ///
+    /// Errors if the GPU could not be found or cannot present to the surface.
+    pub unsafe fn supported_max_anisotropy(surface: &(impl HasRawWindowHandle + HasRawDisplayHandle), preferred_device: Option<&DeviceSelection>) -> MResult<Option<f32>> {
+        VulkanRenderer::supported_max_anisotropy(surface, preferred_device)
+    }
+
+    /// Set the function that receives messages logged by the renderer, such as warnings and validation errors.
+    ///
+    /// This applies to all renderers in the process, and it can be set before creating one. If `None`, messages are
+    /// printed to stdout/stderr.
+    pub fn set_log_callback(callback: Option<LogCallback>) {
+        log::set_log_callback(callback)
+    }
+
+    /// Initialize a new renderer without a window surface.
+    ///
+    /// Frames are rendered offscreen at `parameters.resolution` and never presented. Use
+    /// [`Renderer::read_frame_to_buffer`] to retrieve the result of [`Renderer::draw_frame`].
+    ///
+    /// Errors if:
+    /// - `parameters` is invalid
+    /// - the renderer backend could not be initialized for some reason
+    pub fn new_headless(parameters: RendererParameters) -> MResult<Self> {
+        Self::new_with_backend(parameters, VulkanRenderer::new_headless)
+    }
+
+    fn new_with_backend(parameters: RendererParameters, backend: impl FnOnce(&RendererParameters) -> MResult<VulkanRenderer>) -> MResult<Self> {
         if parameters.resolution.height == 0 || parameters.resolution.width == 0 {
             return Err(Error::DataError { error: "resolution has 0 on one or more dimensions".to_owned() })
         }
+        if let Some(dynamic_resolution) = parameters.dynamic_resolution.as_ref() {
+            dynamic_resolution.validate()?;
+        }
+        parameters.fxaa_quality.validate()?;
+        if let Some(split_screen_bar) = parameters.split_screen_bar.as_ref() {
+            split_screen_bar.validate()?;
+        }
+        if !(parameters.fog_transition_seconds >= 0.0 && parameters.fog_transition_seconds.is_finite()) {
+            return Err(Error::DataError { error: "fog_transition_seconds must be a finite, non-negative number".to_owned() })
+        }
+        if parameters.command_buffer_pool_size == 0 || parameters.descriptor_pool_size == 0 {
+            return Err(Error::DataError { error: "command_buffer_pool_size and descriptor_pool_size must be non-zero".to_owned() })
+        }
 
-        let mut player_viewports = vec![PlayerViewport::default(); parameters.number_of_viewports];
+        let Some(layout) = ViewportRect::split_screen_layout(parameters.number_of_viewports) else {
+            return Err(Error::DataError { error: format!("number of viewports was set to {}, but only 1-4 are supported (use set_viewport_layout for more)", parameters.number_of_viewports) })
+        };
 
-        match parameters.number_of_viewports {
-            1 => {
-                player_viewports[0].rel_x = 0.0;
-                player_viewports[0].rel_y = 0.0;
-                player_viewports[0].rel_width = 1.0;
-                player_viewports[0].rel_height = 1.0;
-            }
-            2 => {
-                player_viewports[0].rel_x = 0.0;
-                player_viewports[0].rel_y = 0.0;
-                player_viewports[0].rel_width = 1.0;
-                player_viewports[0].rel_height = 0.5;
-
-                player_viewports[1].rel_x = 0.0;
-                player_viewports[1].rel_y = 0.5;
-                player_viewports[1].rel_width = 1.0;
-                player_viewports[1].rel_height = 0.5;
-            }
-            3 => {
-                player_viewports[0].rel_x = 0.0;
-                player_viewports[0].rel_y = 0.0;
-                player_viewports[0].rel_width = 1.0;
-                player_viewports[0].rel_height = 0.5;
-
-                player_viewports[1].rel_x = 0.0;
-                player_viewports[1].rel_y = 0.5;
-                player_viewports[1].rel_width = 0.5;
-                player_viewports[1].rel_height = 0.5;
-
-                player_viewports[2].rel_x = 0.5;
-                player_viewports[2].rel_y = 0.5;
-                player_viewports[2].rel_width = 0.5;
-                player_viewports[2].rel_height = 0.5;
-            }
-            4 => {
-                player_viewports[0].rel_x = 0.0;
-                player_viewports[0].rel_y = 0.0;
-                player_viewports[0].rel_width = 0.5;
-                player_viewports[0].rel_height = 0.5;
-
-                player_viewports[1].rel_x = 0.5;
-                player_viewports[1].rel_y = 0.0;
-                player_viewports[1].rel_width = 0.5;
-                player_viewports[1].rel_height = 0.5;
-
-                player_viewports[2].rel_x = 0.0;
-                player_viewports[2].rel_y = 0.5;
-                player_viewports[2].rel_width = 0.5;
-                player_viewports[2].rel_height = 0.5;
-
-                player_viewports[3].rel_x = 0.5;
-                player_viewports[3].rel_y = 0.5;
-                player_viewports[3].rel_width = 0.5;
-                player_viewports[3].rel_height = 0.5;
-            }
-            n => return Err(Error::DataError { error: format!("number of viewports was set to {n}, but only 1-4 are supported") })
+        let mut player_viewports = vec![PlayerViewport::default(); layout.len()];
+        for (viewport, rect) in player_viewports.iter_mut().zip(layout) {
+            viewport.set_rect(*rect);
         }
 
         let mut result = Self {
-            vulkan: VulkanRenderer::new(&parameters, surface)?,
+            vulkan: backend(&parameters)?,
             player_viewports,
+            custom_viewport_layout: false,
             bitmaps: HashMap::new(),
             shaders: HashMap::new(),
             geometries: HashMap::new(),
             skies: HashMap::new(),
             bsps: HashMap::new(),
+            bsp_draw_distances: HashMap::new(),
             fonts: HashMap::new(),
+            render_targets: HashMap::new(),
+            next_render_target: 0,
+            paths: PathInterner::default(),
             current_bsp: None,
+            geometry_instances: Vec::new(),
+            instanced_geometries: Vec::new(),
+            sprite_instances: Vec::new(),
+            scissor_stack: Vec::new(),
+            stencil_test: None,
+            debug_lines: Vec::new(),
             default_bitmaps: DefaultBitmaps::default(),
             fps_counter_value: 0.0,
             fps_counter_count: 0,
             fps_counter_time: Instant::now(),
-            debug_text: VecDeque::with_capacity(64),
+            debug_text: Vec::new(),
             debug_text_stale: true,
-            debug_font: None,
+            debug_fonts: Vec::new(),
+            background_color: DEFAULT_BACKGROUND,
+            split_screen_bar: parameters.split_screen_bar,
+            missing_texture_mode: parameters.missing_texture_mode,
+            time: 0.0,
+            frame_delta: 0.0,
+            last_frame_time: Instant::now(),
+            fog_transition_seconds: parameters.fog_transition_seconds,
+            dynamic_resolution: parameters.dynamic_resolution,
+            post_process: None,
+            overlay_callback: None,
+            minimized: false,
+            zero_extent: false,
         };
 
         populate_default_bitmaps(&mut result)?;
@@ -144,19 +210,34 @@ impl Renderer {
     /// All objects added with `add_` methods will be cleared.
     pub fn reset(&mut self) {
         self.bitmaps.clear();
+        self.vulkan.remove_lightmap_views(None);
         self.shaders.clear();
         self.geometries.clear();
         self.skies.clear();
         self.bsps.clear();
+        self.bsp_draw_distances.clear();
         self.fonts.clear();
+        self.render_targets.clear();
+        self.paths.clear();
         self.current_bsp = None;
-        self.debug_font = None;
+        self.geometry_instances.clear();
+        self.instanced_geometries.clear();
+        self.sprite_instances.clear();
+        self.debug_fonts.clear();
         self.default_bitmaps = DefaultBitmaps::default();
 
         populate_default_bitmaps(self).unwrap();
         self.invalidate_debug_text();
     }
 
+    /// Get the shared copy of `path`, so repeated references to the same path don't each allocate.
+    ///
+    /// All loaded objects are keyed by interned paths. Interned paths are kept until [`Renderer::reset`] is called, or
+    /// until the bitmap or shader using one is removed and nothing else holds a copy of it.
+    pub fn intern_path(&mut self, path: &str) -> Arc<String> {
+        self.paths.intern(path)
+    }
+
     /// Add a font with the given parameters.
     ///
     /// Note that replacing fonts is not yet supported.
@@ -164,17 +245,57 @@ impl Renderer {
     /// This will error if:
     /// - `font` is invalid
     pub fn add_font(&mut self, path: &str, font: AddFontParameter) -> MResult<()> {
-        let font_path = Arc::new(path.to_owned());
-        if self.fonts.contains_key(&font_path) {
+        if self.fonts.contains_key(&path.to_owned()) {
             return Err(Error::from_data_error_string(format!("{path} already exists (replacing fonts is not yet supported)")))
         }
 
         font.validate()?;
         let font = Font::load_from_parameters(self, font)?;
+        let font_path = self.intern_path(path);
         self.fonts.insert(font_path, font);
         Ok(())
     }
 
+    /// Get the width and height in pixels of `string` when drawn with a loaded font, including the drop shadow.
+    ///
+    /// This uses the same layout as drawing the text, including `|` alignment codes, tab offsets, and newlines. If
+    /// `font` is one of the debug fonts, characters missing from it are measured with the debug fonts after it, the
+    /// same way debug text is drawn (see [`Renderer::set_debug_fonts`]).
+    ///
+    /// Returns `Err` if `font` is not loaded.
+    pub fn measure_string(&self, font: &str, string: &str, request: FontDrawRequest) -> MResult<(u32, u32)> {
+        let Some((font_path, font_data)) = self.fonts.get_key_value(&font.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't measure string with font {font}: that font is not loaded")))
+        };
+
+        let fallbacks: Vec<&Font> = self
+            .debug_fonts
+            .iter()
+            .skip_while(|f| *f != font_path)
+            .skip(1)
+            .filter_map(|f| self.fonts.get(f))
+            .collect();
+
+        Ok(font_data.measure_string(&fallbacks, string, request))
+    }
+
+    /// Begin deferring bitmap uploads so they are submitted to the GPU together.
+    ///
+    /// This is much faster than submitting each bitmap separately when loading many bitmaps at once. Uploads are
+    /// submitted on [`Renderer::end_bulk_load`] or before the next frame is drawn.
+    ///
+    /// Returns `Err` if a bulk load is already in progress.
+    pub fn begin_bulk_load(&mut self) -> MResult<()> {
+        self.vulkan.begin_bulk_load()
+    }
+
+    /// Submit all uploads deferred since [`Renderer::begin_bulk_load`].
+    ///
+    /// Returns `Err` if no bulk load is in progress.
+    pub fn end_bulk_load(&mut self) -> MResult<()> {
+        self.vulkan.end_bulk_load()
+    }
+
     /// Add a bitmap with the given parameters.
     ///
     /// Note that replacing bitmaps is not yet supported.
@@ -183,37 +304,358 @@ impl Renderer {
     /// - `bitmap` is invalid
     /// - replacing a bitmap would break any dependencies (HUDs, shaders, etc.)
     pub fn add_bitmap(&mut self, path: &str, bitmap: AddBitmapParameter) -> MResult<()> {
-        let bitmap_path = Arc::new(path.to_owned());
-        if self.bitmaps.contains_key(&bitmap_path) {
+        if self.bitmaps.contains_key(&path.to_owned()) {
             return Err(Error::from_data_error_string(format!("{path} already exists (replacing bitmaps is not yet supported)")))
         }
 
         bitmap.validate()?;
         let bitmap = Bitmap::load_from_parameters(self, bitmap)?;
+        let bitmap_path = self.intern_path(path);
         self.bitmaps.insert(bitmap_path, bitmap);
         Ok(())
     }
 
+    /// Remove a bitmap.
+    ///
+    /// Any sprites queued with the bitmap are also removed.
+    ///
+    /// This will error if:
+    /// - `path` is not loaded
+    /// - `path` is a default bitmap
+    /// - `path` is still referenced by any shaders or BSPs
+    pub fn remove_bitmap(&mut self, path: &str) -> MResult<()> {
+        let bitmap_path = path.to_owned();
+        if !self.bitmaps.contains_key(&bitmap_path) {
+            return Err(Error::from_data_error_string(format!("Can't remove bitmap {path}: that bitmap is not loaded")))
+        }
+
+        if self.is_default_bitmap(&bitmap_path) {
+            return Err(Error::from_data_error_string(format!("Can't remove bitmap {path}: default bitmaps can't be removed")))
+        }
+
+        let dependents = self.get_bitmap_dependents(&bitmap_path);
+        if !dependents.is_empty() {
+            return Err(Error::from_data_error_string(format!("Can't remove bitmap {path}: it is still referenced by {}", dependents.join(", "))))
+        }
+
+        self.sprite_instances.retain(|s| s.bitmap.as_ref() != &bitmap_path);
+        self.bitmaps.remove(&bitmap_path);
+        self.vulkan.remove_lightmap_views(Some(path));
+        self.render_targets.remove(&bitmap_path);
+        self.paths.release(path);
+        Ok(())
+    }
+
+    /// Create an offscreen render target that viewports can be drawn to with [`Renderer::draw_to_render_target`].
+    ///
+    /// The output is added as a bitmap at [`RenderTargetId::bitmap_path`] and can be used by shaders added after this
+    /// call, such as for in-world monitors or mirrors. It is black until something is drawn to it.
+    ///
+    /// Returns `Err` if `resolution` has a zero dimension.
+    pub fn create_render_target(&mut self, resolution: Resolution) -> MResult<RenderTargetId> {
+        if resolution.width == 0 || resolution.height == 0 {
+            return Err(Error::from_data_error_string(format!("Render target resolution {}x{} has 0 on one or more dimensions", resolution.width, resolution.height)))
+        }
+
+        let path = Arc::new(format!("<render target {}>", self.next_render_target));
+        self.next_render_target += 1;
+
+        let render_target = RenderTarget::new(self, resolution)?;
+        self.bitmaps.insert(path.clone(), render_target.to_bitmap());
+        self.render_targets.insert(path.clone(), render_target);
+
+        Ok(RenderTargetId { path })
+    }
+
+    /// Draw the scene from `camera` to a render target immediately.
+    ///
+    /// `bsp` is the path of the BSP to draw, or `None` to draw without one. Fog and draw distance are taken from the
+    /// BSP's outdoor fog at the camera's position.
+    ///
+    /// Geometry queued for the next frame is drawn too, but sprites, debug lines, and debug text are not.
+    ///
+    /// Returns `Err` if the render target or BSP is not loaded, if `camera.fov` is not between 0 and pi when using a
+    /// perspective projection, if the height is not positive when using an orthographic projection, if the clip
+    /// planes are invalid, or if `camera.custom_projection` is not finite.
+    pub fn draw_to_render_target(&mut self, id: &RenderTargetId, camera: Camera, bsp: Option<&str>) -> MResult<()> {
+        let Some(render_target) = self.render_targets.get(&id.path) else {
+            return Err(Error::from_data_error_string(format!("Can't draw to {}: that render target does not exist", id.path)))
+        };
+        let render_target = render_target.vulkan.clone();
+
+        if let Err(e) = validate_custom_projection(&camera) {
+            return Err(Error::from_data_error_string(format!("Can't draw to {}: {e}", id.path)))
+        }
+        if camera.custom_projection.is_none() {
+            if camera.projection == Projection::Perspective && !(camera.fov > 0.0 && camera.fov < core::f32::consts::PI) {
+                return Err(Error::from_data_error_string(format!("Can't draw to {}: camera.fov is not between 0 (exclusive) and pi (exclusive)", id.path)))
+            }
+            if let Projection::Orthographic { height } = camera.projection {
+                if !(height > 0.0 && height.is_finite()) {
+                    return Err(Error::from_data_error_string(format!("Can't draw to {}: orthographic height is not a positive number", id.path)))
+                }
+            }
+            if let Err(e) = validate_clip_planes(&camera) {
+                return Err(Error::from_data_error_string(format!("Can't draw to {}: {e}", id.path)))
+            }
+        }
+
+        let bsp = match bsp {
+            Some(path) => match self.bsps.get(&path.to_owned()) {
+                Some(bsp) => Some((bsp.clone(), self.get_bsp_draw_distance(&path.to_owned(), bsp))),
+                None => return Err(Error::from_data_error_string(format!("Can't draw to {}: BSP {path} is not loaded", id.path)))
+            },
+            None => None
+        };
+
+        let rotation = Vec3::from(camera.rotation).try_normalize().unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        let up = get_camera_up(rotation, Vec3::from(camera.up));
+        let mut player_viewport = PlayerViewport {
+            camera: Camera {
+                rotation: rotation.into(),
+                up: up.into(),
+                ..camera
+            },
+            ..PlayerViewport::default()
+        };
+
+        if let Some((bsp, draw_distance)) = bsp.as_ref() {
+            let sky = bsp.bsp_data
+                .find_cluster(camera.position)
+                .and_then(|c| bsp.bsp_data.clusters[c].sky.as_ref())
+                .and_then(|s| self.skies.get(s));
+
+            player_viewport.viewport_fog = sky.map(|sky| {
+                let mut fog_data = sky.outdoor_fog;
+                fog_data.normalize();
+                ViewportFog {
+                    current_fog_data: fog_data,
+                    outdoor_fog_data: sky.outdoor_fog,
+                    indoor_fog_data: sky.indoor_fog,
+                    target_fog_data: sky.outdoor_fog
+                }
+            });
+            player_viewport.draw_distance[1] = *draw_distance;
+        }
+
+        let bsp = bsp.map(|(bsp, _)| bsp);
+        VulkanRenderer::draw_to_render_target(self, &render_target, &player_viewport, &bsp)
+    }
+
+    /// Get the paths of all bitmaps added with [`Renderer::add_bitmap`].
+    pub fn loaded_bitmaps(&self) -> impl Iterator<Item = &str> {
+        self.bitmaps
+            .keys()
+            .filter(|b| !self.is_default_bitmap(b))
+            .map(|b| b.as_str())
+    }
+
+    /// Get the layout of a loaded bitmap, such as the resolution and format of each bitmap and its sequences.
+    ///
+    /// Returns `None` if no bitmap is loaded at `path`.
+    pub fn bitmap_info(&self, path: &str) -> Option<BitmapInfo> {
+        let bitmap = self.bitmaps.get(&path.to_owned())?;
+        Some(BitmapInfo {
+            bitmaps: bitmap.bitmaps.iter().map(|b| BitmapBitmapInfo {
+                bitmap_type: b.bitmap_type,
+                resolution: b.resolution,
+                format: b.format,
+                mipmap_count: b.vulkan.image.mip_levels() - 1
+            }).collect(),
+            sequences: bitmap.sequences.clone()
+        })
+    }
+
+    /// Return `true` if a bitmap was added at `path` with [`Renderer::add_bitmap`].
+    pub fn is_loaded_bitmap(&self, path: &str) -> bool {
+        let bitmap_path = path.to_owned();
+        self.bitmaps.contains_key(&bitmap_path) && !self.is_default_bitmap(&bitmap_path)
+    }
+
+    /// Get the paths of all shaders added with [`Renderer::add_shader`].
+    pub fn loaded_shaders(&self) -> impl Iterator<Item = &str> {
+        self.shaders.keys().map(|s| s.as_str())
+    }
+
+    /// Return `true` if a shader was added at `path` with [`Renderer::add_shader`].
+    pub fn is_loaded_shader(&self, path: &str) -> bool {
+        self.shaders.contains_key(&path.to_owned())
+    }
+
+    /// Get the type of a loaded shader and the bitmaps it references.
+    ///
+    /// Returns `None` if no shader is loaded at `path`.
+    pub fn shader_info(&self, path: &str) -> Option<ShaderInfo> {
+        let shader = self.shaders.get(&path.to_owned())?;
+        let mut bitmaps: Vec<String> = Vec::with_capacity(shader.bitmaps.len());
+        for bitmap in &shader.bitmaps {
+            if !bitmaps.contains(bitmap) {
+                bitmaps.push(bitmap.clone());
+            }
+        }
+        Some(ShaderInfo { shader_type: shader.shader_type, bitmaps })
+    }
+
+    /// Get the paths of all loaded shaders that reference the bitmap at `bitmap_path`.
+    pub fn shaders_using_bitmap<'a>(&'a self, bitmap_path: &'a str) -> impl Iterator<Item = &'a str> {
+        self.shaders
+            .iter()
+            .filter(move |(_, shader)| shader.bitmaps.iter().any(|b| b == bitmap_path))
+            .map(|(path, _)| path.as_str())
+    }
+
+    /// Get the paths of all BSPs added with [`Renderer::add_bsp`].
+    pub fn loaded_bsps(&self) -> impl Iterator<Item = &str> {
+        self.bsps.keys().map(|b| b.as_str())
+    }
+
+    /// Return `true` if a BSP was added at `path` with [`Renderer::add_bsp`].
+    pub fn is_loaded_bsp(&self, path: &str) -> bool {
+        self.bsps.contains_key(&path.to_owned())
+    }
+
+    /// Get the paths of all fonts added with [`Renderer::add_font`].
+    pub fn loaded_fonts(&self) -> impl Iterator<Item = &str> {
+        self.fonts.keys().map(|f| f.as_str())
+    }
+
+    /// Return `true` if a font was added at `path` with [`Renderer::add_font`].
+    pub fn is_loaded_font(&self, path: &str) -> bool {
+        self.fonts.contains_key(&path.to_owned())
+    }
+
+    /// Get a list of everything that references the given bitmap.
+    fn get_bitmap_dependents(&self, path: &String) -> Vec<String> {
+        let shaders = self
+            .shaders
+            .iter()
+            .filter(|(_, shader)| shader.bitmaps.contains(path))
+            .map(|(shader_path, _)| format!("shader {shader_path}"));
+
+        let bsps = self
+            .bsps
+            .iter()
+            .filter(|(_, bsp)| bsp.lightmap_bitmap.as_ref() == Some(path))
+            .map(|(bsp_path, _)| format!("BSP {bsp_path}"));
+
+        shaders.chain(bsps).collect()
+    }
+
+    /// Default bitmaps are used internally, so they are hidden from the public API.
+    fn is_default_bitmap(&self, path: &String) -> bool {
+        let defaults = &self.default_bitmaps;
+        [&defaults.default_2d, &defaults.default_3d, &defaults.default_cubemap]
+            .into_iter()
+            .chain(defaults.missing_2d.as_ref())
+            .any(|d| d.as_ref() == path)
+    }
+
     /// Add a shader.
     ///
-    /// Note that replacing shaders is not yet supported.
+    /// To replace a shader that is already loaded, use [`Renderer::replace_shader`].
     ///
     /// This will error if:
     /// - `pipeline` is invalid
     /// - `pipeline` contains invalid dependencies
     /// - replacing a pipeline would break any dependencies
     pub fn add_shader(&mut self, path: &str, shader: AddShaderParameter) -> MResult<()> {
-        let shader_path = Arc::new(path.to_owned());
-        if self.shaders.contains_key(&shader_path) {
-            return Err(Error::from_data_error_string(format!("{path} already exists (replacing shaders is not yet supported)")))
+        if self.shaders.contains_key(&path.to_owned()) {
+            return Err(Error::from_data_error_string(format!("{path} already exists (use replace_shader to replace it)")))
         }
 
         shader.validate(self)?;
         let shader = Shader::load_from_parameters(self, shader)?;
+        let shader_path = self.intern_path(path);
+        self.shaders.insert(shader_path, shader);
+        Ok(())
+    }
+
+    /// Replace a shader that is already loaded.
+    ///
+    /// Anything that references the shader will use the new shader.
+    ///
+    /// This will error if:
+    /// - `path` is not loaded
+    /// - `shader` is invalid
+    /// - `shader` contains invalid dependencies
+    /// - replacing the shader would break any dependencies
+    pub fn replace_shader(&mut self, path: &str, shader: AddShaderParameter) -> MResult<()> {
+        let Some((shader_path, old_shader)) = self.shaders.get_key_value(&path.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't replace shader {path}: that shader is not loaded")))
+        };
+        let shader_path = shader_path.clone();
+        let was_transparent = old_shader.vulkan.pipeline_data.is_transparent();
+
+        shader.validate(self)?;
+        let shader = Shader::load_from_parameters(self, shader)?;
+
+        // See AddBSPParameter::validate
+        if shader.shader_type == ShaderType::Model {
+            if let Some((bsp, _)) = self.bsps.iter().find(|(_, bsp)| bsp.geometries.iter().any(|g| g.shader == shader_path)) {
+                return Err(Error::from_data_error_string(format!("Can't replace shader {path}: BSP {bsp} references it, and {:?} shaders aren't allowed for BSPs", shader.shader_type)))
+            }
+        }
+
+        let is_transparent = shader.vulkan.pipeline_data.is_transparent();
+        let mut bsps_to_resort = Vec::new();
+        if was_transparent != is_transparent {
+            for (bsp_path, bsp) in self.bsps.iter_mut() {
+                if !bsp.geometries.iter().any(|g| g.shader == shader_path) {
+                    continue
+                }
+                let Some(bsp) = Arc::get_mut(bsp) else {
+                    return Err(Error::from_data_error_string(format!("Can't replace shader {path}: BSP {bsp_path} is in use")))
+                };
+                bsps_to_resort.push(bsp);
+            }
+        }
+
         self.shaders.insert(shader_path, shader);
+        for bsp in bsps_to_resort {
+            bsp.vulkan.resort_geometries(&self.shaders, &bsp.geometries);
+        }
+
+        Ok(())
+    }
+
+    /// Remove a shader.
+    ///
+    /// This will error if:
+    /// - `path` is not loaded
+    /// - `path` is still referenced by any BSPs or geometries
+    pub fn remove_shader(&mut self, path: &str) -> MResult<()> {
+        let shader_path = path.to_owned();
+        if !self.shaders.contains_key(&shader_path) {
+            return Err(Error::from_data_error_string(format!("Can't remove shader {path}: that shader is not loaded")))
+        }
+
+        let dependents = self.get_shader_dependents(&shader_path);
+        if !dependents.is_empty() {
+            return Err(Error::from_data_error_string(format!("Can't remove shader {path}: it is still referenced by {}", dependents.join(", "))))
+        }
+
+        self.shaders.remove(&shader_path);
+        self.paths.release(path);
         Ok(())
     }
 
+    /// Get a list of everything that references the given shader.
+    fn get_shader_dependents(&self, path: &String) -> Vec<String> {
+        let bsps = self
+            .bsps
+            .iter()
+            .filter(|(_, bsp)| bsp.geometries.iter().any(|g| g.shader.as_ref() == path))
+            .map(|(bsp_path, _)| format!("BSP {bsp_path}"));
+
+        let geometries = self
+            .geometries
+            .iter()
+            .filter(|(_, geometry)| geometry.geometries.iter().map(|g| g.parts.iter()).flatten().any(|p| p.shader.as_ref() == path))
+            .map(|(geometry_path, _)| format!("geometry {geometry_path}"));
+
+        bsps.chain(geometries).collect()
+    }
+
     /// Add a geometry.
     ///
     /// Note that replacing geometries is not yet supported.
@@ -222,9 +664,16 @@ impl Renderer {
     /// - `geometry` is invalid
     /// - `geometry` contains invalid dependencies
     /// - replacing a geometry would break any dependencies
-    #[allow(unused_variables)]
     pub fn add_geometry(&mut self, path: &str, geometry: AddGeometryParameter) -> MResult<()> {
-        todo!()
+        if self.geometries.contains_key(&path.to_owned()) {
+            return Err(Error::from_data_error_string(format!("{path} already exists (replacing geometries is not yet supported)")))
+        }
+
+        geometry.validate(self)?;
+        let geometry = Geometry::load_from_parameters(self, geometry)?;
+        let geometry_path = self.intern_path(path);
+        self.geometries.insert(geometry_path, geometry);
+        Ok(())
     }
 
     /// Add a sky.
@@ -249,7 +698,8 @@ impl Renderer {
             indoor_fog = FogData::default();
         }
 
-        self.skies.insert(Arc::new(path.to_owned()), Sky {
+        let sky_path = self.intern_path(path);
+        self.skies.insert(sky_path, Sky {
             geometry: sky.geometry.map(|s| self.geometries.get_key_value(&s).unwrap().0.clone()),
             outdoor_fog,
             indoor_fog
@@ -266,13 +716,13 @@ impl Renderer {
     /// - `bsp` is invalid
     /// - `bsp` contains invalid dependencies
     pub fn add_bsp(&mut self, path: &str, bsp: AddBSPParameter) -> MResult<()> {
-        let bsp_path = Arc::new(path.to_owned());
-        if self.bsps.contains_key(&bsp_path) {
+        if self.bsps.contains_key(&path.to_owned()) {
             return Err(Error::from_data_error_string(format!("{path} already exists (replacing BSPs is not yet supported)")))
         }
 
         bsp.validate(self)?;
         let bsp = BSP::load_from_parameters(self, bsp)?;
+        let bsp_path = self.intern_path(path);
         self.bsps.insert(bsp_path, Arc::new(bsp));
         Ok(())
     }
@@ -300,128 +750,972 @@ impl Renderer {
             self.current_bsp = None;
         }
 
+        // The previous BSP's skies no longer apply, so fog gets picked up again from the camera's new cluster.
+        for viewport in &mut self.player_viewports {
+            viewport.viewport_fog = None;
+        }
+        self.fixup_fog_and_render_distances();
+
         Ok(())
     }
 
-    /// Rebuild the swapchain.
+    /// Get the draw distance of the given BSP, or `None` if it is not loaded.
     ///
-    /// You must use this when the window is resized or if the swapchain is invalidated.
-    pub fn rebuild_swapchain(&mut self, parameters: RendererParameters) -> MResult<()> {
-        if parameters.resolution.height == 0 || parameters.resolution.width == 0 {
-            return Err(Error::DataError { error: "resolution has 0 on one or more dimensions".to_owned() })
-        }
-        self.vulkan.rebuild_swapchain(
-            &parameters
-        )
+    /// This is the override set with [`Renderer::set_bsp_draw_distance`] if there is one, or else the draw distance
+    /// calculated from the size of the BSP.
+    pub fn bsp_draw_distance(&self, path: &str) -> Option<f32> {
+        let bsp_path = path.to_owned();
+        let bsp = self.bsps.get(&bsp_path)?;
+        Some(self.get_bsp_draw_distance(&bsp_path, bsp))
     }
 
-    /// Set the position, rotation, and FoV of the camera for the given viewport.
-    ///
-    /// `fov` must be in radians, and `position` must be a vector.
+    /// Override the draw distance of the given BSP.
     ///
-    /// # Panics
+    /// `draw_distance` is clamped between [`MIN_DRAW_DISTANCE_LIMIT`] and [`MAX_DRAW_DISTANCE_LIMIT`]. Fog may still
+    /// limit the draw distance further.
     ///
-    /// Panics if `viewport >= self.viewport_count()` or if `!(camera.fov > 0.0 && camera.fov < PI)`
-    pub fn set_camera_for_viewport(&mut self, viewport: usize, camera: Camera) {
-        assert!(camera.fov > 0.0 && camera.fov < core::f32::consts::PI, "camera.fov is not between 0 (exclusive) and pi (exclusive)");
-
-        let viewport = &mut self.player_viewports[viewport];
-        if camera == viewport.camera {
-            return;
+    /// Errors if `path` refers to a BSP that isn't loaded or `draw_distance` is NaN.
+    pub fn set_bsp_draw_distance(&mut self, path: &str, draw_distance: f32) -> MResult<()> {
+        if draw_distance.is_nan() {
+            return Err(Error::from_data_error_string(format!("Can't set draw distance of BSP {path}: draw distance is NaN")))
         }
+        let Some((key, _)) = self.bsps.get_key_value(&path.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't set draw distance of BSP {path}: that BSP is not loaded")))
+        };
+        self.bsp_draw_distances.insert(key.clone(), draw_distance.clamp(MIN_DRAW_DISTANCE_LIMIT, MAX_DRAW_DISTANCE_LIMIT));
+        self.fixup_fog_and_render_distances();
+        Ok(())
+    }
 
-        // FIXME: determine how fast it is supposed to be transitioned here?
-        let fog_transition_amount = Vec3::from(camera.position).distance(Vec3::from(viewport.camera.position)).min(10.0) / 10.0;
-        if let Some(n) = viewport.viewport_fog.as_mut() {
-            n.transition_amount = (n.transition_amount + fog_transition_amount).min(1.0);
-        }
+    /// Remove the draw distance override of the given BSP, if any, going back to the calculated draw distance.
+    pub fn clear_bsp_draw_distance(&mut self, path: &str) {
+        self.bsp_draw_distances.remove(&path.to_owned());
+        self.fixup_fog_and_render_distances();
+    }
 
-        viewport.camera = Camera {
-            position: camera.position,
-            rotation: Vec3::from(camera.rotation).try_normalize().unwrap_or(Vec3::new(0.0, 1.0, 0.0)).into(),
-            fov: camera.fov,
-            lightmaps: camera.lightmaps,
-            fog: camera.fog
-        };
+    fn get_bsp_draw_distance(&self, path: &String, bsp: &BSP) -> f32 {
+        self.bsp_draw_distances
+            .get(path)
+            .copied()
+            .unwrap_or(bsp.draw_distance)
+    }
 
-        self.invalidate_debug_text();
+    /// Get the path of the current BSP, if any.
+    pub fn current_bsp(&self) -> Option<&str> {
+        self.current_bsp.as_ref().map(|b| b.as_str())
     }
 
-    /// Get the camera data for the given viewport.
+    /// Find the closest surface of the current BSP hit by a ray.
     ///
-    /// # Panics
+    /// To cast from a viewport's camera, use the camera's position and rotation from
+    /// [`Renderer::get_camera_for_viewport`] as `origin` and `direction`.
     ///
-    /// Panics if `viewport >= self.viewport_count()`
-    pub fn get_camera_for_viewport(&self, viewport: usize) -> Camera {
-        self.player_viewports[viewport].camera
+    /// Returns `None` if no BSP is loaded, nothing was hit, or `direction` has no length.
+    pub fn raycast_bsp(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<RaycastHit> {
+        let bsp = self.bsps.get(self.current_bsp.as_ref()?)?;
+        bsp.raycast(origin, direction)
     }
 
-    /// Get the number of viewports.
-    pub fn get_viewport_count(&self) -> usize {
-        self.player_viewports.len()
+    /// Draw a geometry on the next frame with the given world transform.
+    ///
+    /// Instances are only drawn for one frame and are cleared after [`Renderer::draw_frame`], so this
+    /// must be called every frame for each geometry that should be visible.
+    ///
+    /// Returns `Err` if `path` refers to a geometry that isn't loaded.
+    pub fn draw_geometry(&mut self, path: &str, transform: Mat4) -> MResult<()> {
+        let Some((geometry, _)) = self.geometries.get_key_value(&path.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't draw geometry {path}: that geometry is not loaded")))
+        };
+
+        self.geometry_instances.push(GeometryInstance {
+            geometry: geometry.clone(),
+            transform
+        });
+
+        Ok(())
     }
 
-    /// Draw a frame.
+    /// Draw a geometry on the next frame once for each of the given world transforms.
     ///
-    /// If `true`, the swapchain needs rebuilt.
-    pub fn draw_frame(&mut self) -> MResult<bool> {
-        if self.debug_text_stale {
-            self.draw_debug_text()?;
+    /// Unlike calling [`Renderer::draw_geometry`] for each transform, all instances are drawn with one draw per part,
+    /// which is much faster for scenery that is placed many times. Instances outside the camera are skipped, and the
+    /// level of detail is picked for the closest instance.
+    ///
+    /// Transparent parts are depth sorted using the closest instance, so they are not sorted between instances.
+    ///
+    /// Like [`Renderer::draw_geometry`], this is only drawn for one frame.
+    ///
+    /// Returns `Err` if `path` refers to a geometry that isn't loaded.
+    pub fn draw_geometry_instanced(&mut self, path: &str, transforms: &[Mat4]) -> MResult<()> {
+        let Some((geometry, _)) = self.geometries.get_key_value(&path.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't draw geometry {path}: that geometry is not loaded")))
+        };
+
+        if transforms.is_empty() {
+            return Ok(())
         }
-        self.fixup_fog_and_render_distances();
-        let result = VulkanRenderer::draw_frame(self)?;
 
-        self.update_frame_rate_counter();
+        self.instanced_geometries.push(InstancedGeometry {
+            geometry: geometry.clone(),
+            transforms: transforms.to_vec()
+        });
 
-        Ok(result)
+        Ok(())
     }
 
-    /// Set whether debug info is displayed.
+    /// Clear all geometries queued with [`Renderer::draw_geometry`] and [`Renderer::draw_geometry_instanced`] without
+    /// drawing them.
+    pub fn clear_geometry_instances(&mut self) {
+        self.geometry_instances.clear();
+        self.instanced_geometries.clear();
+    }
+
+    /// Draw a sprite on the next frame over the given viewport.
     ///
-    /// Returns `Err` if the `font` is not loaded.
-    pub fn set_debug_font(&mut self, font: Option<&str>) -> MResult<()> {
-        match font {
-            Some(font) => {
-                let Some(font) = self.fonts.get_key_value(&font.to_owned()) else {
-                    return Err(Error::from_data_error_string(format!("Font {font} is not loaded")))
-                };
-                self.debug_font = Some(font.0.clone())
-            }
-            None => {
-                self.debug_font = None;
-            }
+    /// `rect` is the x, y, width, and height of the sprite relative to the viewport, where `[0.0, 0.0, 1.0, 1.0]`
+    /// covers the entire viewport. The sprite is multiplied by `color` and alpha blended.
+    ///
+    /// `frame_index` is the index of the bitmap or sprite in the sequence.
+    ///
+    /// The sprite is limited to the current scissor rectangle and stencil test, if any (see [`Renderer::push_scissor`]
+    /// and [`Renderer::set_stencil_test`]).
+    ///
+    /// Like [`Renderer::draw_geometry`], sprites are only drawn for one frame and are cleared after
+    /// [`Renderer::draw_frame`].
+    ///
+    /// Returns `Err` if `path` refers to a bitmap that isn't loaded, or if the sequence, frame, or viewport is invalid.
+    pub fn draw_sprite(&mut self, viewport: usize, path: &str, sequence_index: usize, frame_index: usize, rect: [f32; 4], color: FloatColor) -> MResult<()> {
+        if viewport >= self.player_viewports.len() {
+            return Err(Error::from_data_error_string(format!("Can't draw sprite {path}: viewport {viewport} does not exist")))
         }
 
-        self.invalidate_debug_text();
+        let (bitmap, bitmap_index, texture_rect) = self.get_sprite_frame(path, sequence_index, frame_index)?;
+
+        self.sprite_instances.push(SpriteInstance {
+            viewport,
+            bitmap,
+            bitmap_index,
+            texture_rect,
+            rect,
+            color,
+            scissor: self.current_scissor(),
+            stencil: self.stencil_test.map_or(SpriteStencil::None, SpriteStencil::Test)
+        });
+
         Ok(())
     }
 
-    pub fn invalidate_debug_text(&mut self) {
-        self.debug_text_stale = true;
-    }
+    /// Look up a frame of a sprite sequence, returning the bitmap's key, the bitmap index, and the texture rect.
+    fn get_sprite_frame(&self, path: &str, sequence_index: usize, frame_index: usize) -> MResult<(Arc<String>, usize, [f32; 4])> {
+        let Some((key, bitmap)) = self.bitmaps.get_key_value(&path.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't draw sprite {path}: that bitmap is not loaded")))
+        };
 
-    fn fixup_fog_and_render_distances(&mut self) {
-        let Some(bsp) = self.current_bsp.as_ref().and_then(|b| self.bsps.get(b)) else { return };
+        let Some(sequence) = bitmap.sequences.get(sequence_index) else {
+            return Err(Error::from_data_error_string(format!("Can't draw sprite {path}: sequence {sequence_index} does not exist")))
+        };
 
-        // First pass: get fog
-        for viewport in &mut self.player_viewports {
-            let Some(cluster) = bsp.bsp_data.find_cluster(viewport.camera.position) else {
-                continue
-            };
+        let frame = match sequence {
+            BitmapSequence::Bitmap { first, count } => (frame_index < *count)
+                .then(|| (*first + frame_index, [0.0, 0.0, 1.0, 1.0])),
+            BitmapSequence::Sprites { sprites } => sprites
+                .get(frame_index)
+                .map(|s| (s.bitmap, [s.left, s.top, s.right, s.bottom]))
+        };
 
-            let cluster = &bsp.bsp_data.clusters[cluster];
-            let sky = cluster.sky.as_ref().and_then(|s| self.skies.get(s));
+        let Some((bitmap_index, texture_rect)) = frame else {
+            return Err(Error::from_data_error_string(format!("Can't draw sprite {path}: frame {frame_index} of sequence {sequence_index} does not exist")))
+        };
 
-            let Some(viewport_fog) = viewport.viewport_fog.as_mut() else {
-                let Some(sky) = sky else {
-                    continue
-                };
+        if bitmap.bitmaps[bitmap_index].bitmap_type != BitmapType::Dim2D {
+            return Err(Error::from_data_error_string(format!("Can't draw sprite {path}: bitmap {bitmap_index} is not a 2D texture")))
+        }
+
+        Ok((key.clone(), bitmap_index, texture_rect))
+    }
+
+    /// Draw a crosshair on the next frame in the center of the given viewport.
+    ///
+    /// `size` is the width and height of the crosshair relative to the height of the viewport, so it stays square in
+    /// split-screen and scales with the resolution. If `bitmap_path` is `None`, a plus is drawn, which becomes a dot at
+    /// small sizes; otherwise, the first frame of the bitmap's first sequence is drawn. The crosshair is multiplied by
+    /// `color` and alpha blended.
+    ///
+    /// Like [`Renderer::draw_sprite`], the crosshair is only drawn for one frame.
+    ///
+    /// Returns `Err` if `bitmap_path` refers to a bitmap that isn't loaded or has no frames, or if `size` or the
+    /// viewport is invalid.
+    pub fn draw_crosshair(&mut self, viewport: usize, bitmap_path: Option<&str>, size: f32, color: FloatColor) -> MResult<()> {
+        let Some(player_viewport) = self.player_viewports.get(viewport) else {
+            return Err(Error::from_data_error_string(format!("Can't draw crosshair: viewport {viewport} does not exist")))
+        };
+
+        if !size.is_finite() || size < 0.0 {
+            return Err(Error::from_data_error_string(format!("Can't draw crosshair: invalid size {size}")))
+        }
+
+        let resolution = self.vulkan.get_current_resolution();
+        let viewport_width = player_viewport.rel_width * resolution.width as f32;
+        let viewport_height = player_viewport.rel_height * resolution.height as f32;
+        if viewport_width <= 0.0 || viewport_height <= 0.0 {
+            return Ok(())
+        }
+
+        let height = size;
+        let width = size * viewport_height / viewport_width;
+
+        if let Some(path) = bitmap_path {
+            return self.draw_sprite(viewport, path, 0, 0, [0.5 - width / 2.0, 0.5 - height / 2.0, width, height], color)
+        }
+
+        // Bars are an eighth of the crosshair thick, but at least one pixel.
+        let thickness_x = (width / 8.0).max(1.0 / viewport_width);
+        let thickness_y = (height / 8.0).max(1.0 / viewport_height);
+        let bar_width = width.max(thickness_x);
+        let arm_height = (height - thickness_y) / 2.0;
+
+        // The vertical bar is split around the horizontal one so the center is not blended twice.
+        let bars = [
+            [0.5 - bar_width / 2.0, 0.5 - thickness_y / 2.0, bar_width, thickness_y],
+            [0.5 - thickness_x / 2.0, 0.5 - height / 2.0, thickness_x, arm_height],
+            [0.5 - thickness_x / 2.0, 0.5 + thickness_y / 2.0, thickness_x, arm_height]
+        ];
+
+        // The second sequence of the default 2D bitmap is white.
+        let white = self.default_bitmaps.default_2d.clone();
+        for bar in bars.into_iter().filter(|b| b[2] > 0.0 && b[3] > 0.0) {
+            self.draw_sprite(viewport, &white, 1, 0, bar, color)?;
+        }
+
+        Ok(())
+    }
+
+    /// Clear all sprites queued with [`Renderer::draw_sprite`] without drawing them.
+    pub fn clear_sprite_instances(&mut self) {
+        self.sprite_instances.clear();
+    }
+
+    /// Clip sprites drawn after this call to `rect` until [`Renderer::pop_scissor`] is called.
+    ///
+    /// `rect` is the x, y, width, and height relative to the viewport, like the `rect` of
+    /// [`Renderer::draw_sprite`]. It is intersected with the current scissor rectangle, if any.
+    ///
+    /// The scissor stack is cleared after [`Renderer::draw_frame`].
+    ///
+    /// Returns `Err` if `rect` is not finite or has a negative size.
+    pub fn push_scissor(&mut self, rect: [f32; 4]) -> MResult<()> {
+        if rect.iter().any(|f| !f.is_finite()) || rect[2] < 0.0 || rect[3] < 0.0 {
+            return Err(Error::from_data_error_string(format!("Invalid scissor rectangle {rect:?}")))
+        }
+
+        let [x, y, width, height] = rect;
+        let [current_x, current_y, current_width, current_height] = self.current_scissor();
+
+        let left = x.max(current_x);
+        let top = y.max(current_y);
+        let right = (x + width).min(current_x + current_width);
+        let bottom = (y + height).min(current_y + current_height);
+
+        self.scissor_stack.push([left, top, (right - left).max(0.0), (bottom - top).max(0.0)]);
+        Ok(())
+    }
+
+    /// Restore the scissor rectangle from before the last [`Renderer::push_scissor`].
+    ///
+    /// Returns `Err` if there is no scissor rectangle to pop.
+    pub fn pop_scissor(&mut self) -> MResult<()> {
+        self.scissor_stack
+            .pop()
+            .map(|_| ())
+            .ok_or_else(|| Error::from_data_error_string("No scissor rectangle to pop".to_owned()))
+    }
+
+    fn current_scissor(&self) -> [f32; 4] {
+        self.scissor_stack.last().copied().unwrap_or([0.0, 0.0, 1.0, 1.0])
+    }
+
+    /// Write `value` to the stencil buffer over `rect` on the next frame, in order with sprites.
+    ///
+    /// `rect` is the x, y, width, and height relative to the viewport, like the `rect` of [`Renderer::draw_sprite`].
+    /// Nothing is drawn to the screen, but sprites drawn after this with [`Renderer::set_stencil_test`] can be limited to
+    /// the mask. The stencil buffer is cleared to 0 before sprites are drawn each frame.
+    ///
+    /// Returns `Err` if the stencil buffer is not enabled (see [`RendererParameters::stencil`]), if the viewport is
+    /// invalid, or if `rect` is not finite or has a negative size.
+    pub fn draw_stencil_mask(&mut self, viewport: usize, rect: [f32; 4], value: u8) -> MResult<()> {
+        if !self.vulkan.has_stencil() {
+            return Err(Error::from_data_error_string("Can't draw stencil mask: the stencil buffer is not enabled".to_owned()))
+        }
+        if viewport >= self.player_viewports.len() {
+            return Err(Error::from_data_error_string(format!("Can't draw stencil mask: viewport {viewport} does not exist")))
+        }
+        if rect.iter().any(|f| !f.is_finite()) || rect[2] < 0.0 || rect[3] < 0.0 {
+            return Err(Error::from_data_error_string(format!("Can't draw stencil mask: invalid rectangle {rect:?}")))
+        }
+
+        // The white default bitmap is never sampled, as masks are drawn as boxes.
+        self.sprite_instances.push(SpriteInstance {
+            viewport,
+            bitmap: self.default_bitmaps.default_2d.clone(),
+            bitmap_index: 1,
+            texture_rect: [0.0, 0.0, 1.0, 1.0],
+            rect,
+            color: [1.0, 1.0, 1.0, 1.0],
+            scissor: self.current_scissor(),
+            stencil: SpriteStencil::Mask(value)
+        });
+
+        Ok(())
+    }
+
+    /// Only draw sprites queued after this call where the stencil buffer equals `value`, or `None` to draw them
+    /// everywhere.
+    ///
+    /// Values are written with [`Renderer::draw_stencil_mask`], and everything else is 0. This is reset after
+    /// [`Renderer::draw_frame`].
+    ///
+    /// Returns `Err` if `value` is `Some` and the stencil buffer is not enabled (see [`RendererParameters::stencil`]).
+    pub fn set_stencil_test(&mut self, value: Option<u8>) -> MResult<()> {
+        if value.is_some() && !self.vulkan.has_stencil() {
+            return Err(Error::from_data_error_string("Can't set stencil test: the stencil buffer is not enabled".to_owned()))
+        }
+        self.stencil_test = value;
+        Ok(())
+    }
+
+    /// Draw a line in world space on the next frame over the given viewport.
+    ///
+    /// Lines are alpha blended and hidden behind geometry, but they do not write to the depth buffer. Like
+    /// [`Renderer::draw_geometry`], lines are only drawn for one frame and are cleared after [`Renderer::draw_frame`].
+    ///
+    /// Returns `Err` if the viewport is invalid.
+    pub fn draw_debug_line(&mut self, viewport: usize, start: [f32; 3], end: [f32; 3], color: FloatColor) -> MResult<()> {
+        if viewport >= self.player_viewports.len() {
+            return Err(Error::from_data_error_string(format!("Can't draw debug line: viewport {viewport} does not exist")))
+        }
+
+        self.debug_lines.push(DebugLineInstance { viewport, start, end, color });
+        Ok(())
+    }
+
+    /// Clear all lines queued with [`Renderer::draw_debug_line`] without drawing them.
+    pub fn clear_debug_lines(&mut self) {
+        self.debug_lines.clear();
+    }
+
+    /// Rebuild the swapchain.
+    ///
+    /// You must use this when the window is resized or if the swapchain is invalidated.
+    ///
+    /// If the resolution has 0 on one or more dimensions, such as when the window is minimized, the swapchain is left
+    /// as is and frames are skipped until this is called again with a valid resolution.
+    pub fn rebuild_swapchain(&mut self, parameters: RendererParameters) -> MResult<()> {
+        if parameters.resolution.height == 0 || parameters.resolution.width == 0 {
+            self.zero_extent = true;
+            return Ok(())
+        }
+        parameters.fxaa_quality.validate()?;
+        self.vulkan.rebuild_swapchain(
+            &parameters
+        )?;
+        self.zero_extent = false;
+
+        for viewport in &mut self.player_viewports {
+            if let Some(horizontal_fov) = viewport.horizontal_fov {
+                viewport.camera.fov = viewport.get_vertical_fov(horizontal_fov, parameters.resolution);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the color drawn behind everything when no BSP or sky provides one.
+    ///
+    /// Default = opaque black
+    pub fn set_background_color(&mut self, color: FloatColor) {
+        self.background_color = color;
+    }
+
+    /// Set the style of the bars drawn between split-screen viewports, or `None` to not draw them.
+    ///
+    /// Errors if `style` is invalid.
+    pub fn set_split_screen_bar(&mut self, style: Option<SplitBarStyle>) -> MResult<()> {
+        if let Some(style) = style.as_ref() {
+            style.validate()?;
+        }
+        self.split_screen_bar = style;
+        Ok(())
+    }
+
+    /// Set the elapsed time in seconds used for animating shaders.
+    ///
+    /// The time is advanced automatically each frame, so this only needs to be called to reset or skip it.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Get the elapsed time in seconds used for animating shaders.
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    /// Set the render scale.
+    ///
+    /// Only the images that are rendered to are recreated, so this is much cheaper than [`Renderer::rebuild_swapchain`]
+    /// and can be used for dynamic resolution. The resolution is clamped to what the GPU supports.
+    ///
+    /// Errors if `render_scale` is not a positive number.
+    pub fn set_render_scale(&mut self, render_scale: f32) -> MResult<()> {
+        self.vulkan.set_render_scale(render_scale)
+    }
+
+    /// Set whether to automatically adjust the render scale to keep the frame time near a target.
+    ///
+    /// If `None`, the current render scale is kept.
+    ///
+    /// Errors if `dynamic_resolution` is invalid.
+    pub fn set_dynamic_resolution(&mut self, dynamic_resolution: Option<DynamicResScale>) -> MResult<()> {
+        if let Some(d) = dynamic_resolution.as_ref() {
+            d.validate()?;
+        }
+        self.dynamic_resolution = dynamic_resolution;
+        Ok(())
+    }
+
+    /// Enable or disable FXAA, or change its quality.
+    ///
+    /// Errors if `quality` is invalid or FXAA is unsupported by the device.
+    pub fn set_fxaa(&mut self, enabled: bool, quality: FXAAQuality) -> MResult<()> {
+        quality.validate()?;
+        self.vulkan.set_fxaa(enabled.then_some(quality))
+    }
+
+    /// Set how colors are mapped to the displayable range.
+    ///
+    /// This is best used with HDR or sRGB-correct rendering, as it works in linear space. The default,
+    /// [`TonemapOperator::Passthrough`], skips tonemapping unless the exposure is changed.
+    ///
+    /// Errors if tonemapping is unsupported by the device.
+    pub fn set_tonemap(&mut self, tonemap: TonemapOperator) -> MResult<()> {
+        let (_, exposure) = self.vulkan.get_tonemap();
+        self.vulkan.set_tonemap(tonemap, exposure)
+    }
+
+    /// Set the exposure that colors are multiplied by before tonemapping.
+    ///
+    /// The default is 1.0.
+    ///
+    /// Errors if `exposure` is not a positive number or tonemapping is unsupported by the device.
+    pub fn set_exposure(&mut self, exposure: f32) -> MResult<()> {
+        let (tonemap, _) = self.vulkan.get_tonemap();
+        self.vulkan.set_tonemap(tonemap, exposure)
+    }
+
+    /// Set a custom pass to run on each frame before it is copied to the window, replacing any existing one.
+    ///
+    /// This is not run when drawing to render targets.
+    pub fn set_post_process(&mut self, post_process: Box<dyn PostProcess>) {
+        self.post_process = Some(post_process);
+    }
+
+    /// Remove the pass set with [`Renderer::set_post_process`], if any.
+    pub fn clear_post_process(&mut self) {
+        self.post_process = None;
+    }
+
+    /// Set a function to call for each player viewport while it is drawn, replacing any existing one.
+    ///
+    /// The function can queue boxes, sprites, and text with the [`OverlayContext`] it is given, which are drawn over
+    /// the viewport's geometry. This is not called when drawing to render targets.
+    ///
+    /// If what it queued can't be drawn, [`Renderer::draw_frame`] returns the error.
+    pub fn set_overlay_callback(&mut self, callback: OverlayCallback) {
+        self.overlay_callback = Some(callback);
+    }
+
+    /// Remove the function set with [`Renderer::set_overlay_callback`], if any.
+    pub fn clear_overlay_callback(&mut self) {
+        self.overlay_callback = None;
+    }
+
+    /// Set the filter used when scaling the rendered frame to the window.
+    ///
+    /// This takes effect on the next frame.
+    pub fn set_upscale_filter(&mut self, upscale_filter: UpscaleFilter) {
+        self.vulkan.set_upscale_filter(upscale_filter)
+    }
+
+    /// Set the anisotropic filtering level, or `None` to disable it.
+    ///
+    /// All loaded shaders and BSP lightmaps are rebuilt to use the new samplers, so this is not cheap.
+    ///
+    /// Errors if the level is unsupported by the device (see [`Renderer::supported_max_anisotropy`]).
+    pub fn set_anisotropic_filtering(&mut self, anisotropic_filtering: Option<f32>) -> MResult<()> {
+        let previous_sampler = self.vulkan.set_anisotropic_filtering(anisotropic_filtering)?;
+
+        // Build everything before replacing anything, so nothing is left using the old sampler if this fails.
+        let (materials, mut lightmaps) = match self.rebuild_default_sampler_users() {
+            Ok(n) => n,
+            Err(e) => {
+                self.vulkan.restore_default_2d_sampler(previous_sampler);
+                return Err(e)
+            }
+        };
+
+        let mut bsps_to_update = Vec::with_capacity(self.bsps.len());
+        for (bsp_path, bsp) in self.bsps.iter_mut() {
+            let Some(bsp) = Arc::get_mut(bsp) else {
+                self.vulkan.restore_default_2d_sampler(previous_sampler);
+                return Err(Error::from_data_error_string(format!("Can't change anisotropic filtering: BSP {bsp_path} is in use")))
+            };
+            if let Some(lightmap_images) = lightmaps.remove(bsp_path) {
+                bsps_to_update.push((bsp, lightmap_images));
+            }
+        }
+
+        for (bsp, lightmap_images) in bsps_to_update {
+            bsp.vulkan.lightmap_images = lightmap_images;
+        }
+        for (path, material) in materials {
+            if let Some(shader) = self.shaders.get_mut(&path) {
+                shader.vulkan = material;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build new materials for every shader and new lightmap descriptor sets for every BSP, without replacing them.
+    fn rebuild_default_sampler_users(&mut self) -> MResult<(Vec<(Arc<String>, vulkan::VulkanMaterialShaderData)>, HashMap<Arc<String>, vulkan::LightmapDescriptorSets>)> {
+        let shader_paths: Vec<Arc<String>> = self.shaders.keys().cloned().collect();
+        let mut materials = Vec::with_capacity(shader_paths.len());
+        for path in shader_paths {
+            let parameter = self.shaders[&path].parameter.clone();
+            materials.push((path, vulkan::VulkanMaterialShaderData::new_from_parameters(self, parameter)?));
+        }
+
+        let bsp_paths: Vec<Arc<String>> = self.bsps.keys().cloned().collect();
+        let mut lightmaps = HashMap::with_capacity(bsp_paths.len());
+        for path in bsp_paths {
+            let bsp = &self.bsps[&path];
+            let lightmap_bitmap = bsp.lightmap_bitmap.clone();
+            let lightmap_indices: Vec<usize> = bsp.vulkan.lightmap_images.keys().copied().collect();
+            let lightmap_images = vulkan::VulkanBSPData::make_lightmap_descriptor_sets(self, lightmap_bitmap.as_ref(), lightmap_indices.into_iter())?;
+            lightmaps.insert(path, lightmap_images);
+        }
+
+        Ok((materials, lightmaps))
+    }
+
+    /// Enable or disable vSync.
+    ///
+    /// This recreates the swapchain with the appropriate present mode. If vSync is disabled, `Mailbox` or `Immediate`
+    /// is used if supported, falling back to `Fifo` otherwise.
+    ///
+    /// Errors if no suitable present mode is supported by the surface.
+    pub fn set_vsync(&mut self, vsync: bool) -> MResult<()> {
+        self.vulkan.set_vsync(vsync)
+    }
+
+    /// Set the present mode preference, or `None` to determine it from vSync.
+    ///
+    /// This recreates the swapchain with the preferred present mode, or the first fallback the surface supports.
+    ///
+    /// Errors if no suitable present mode is supported by the surface.
+    pub fn set_present_mode(&mut self, present_mode: Option<PresentModePreference>) -> MResult<()> {
+        self.vulkan.set_present_mode(present_mode)
+    }
+
+    /// Return `true` if the renderer is rendering and presenting in HDR.
+    ///
+    /// This may be `false` even if [`RendererParameters::hdr`] was set, if the surface does not support HDR.
+    pub fn is_hdr(&self) -> bool {
+        self.vulkan.is_hdr()
+    }
+
+    /// Return `true` if the renderer is doing sRGB-correct rendering.
+    ///
+    /// This may be `false` even if [`RendererParameters::srgb_correct`] was set, if the surface does not support sRGB.
+    /// It is always `true` when presenting in HDR.
+    pub fn is_srgb_correct(&self) -> bool {
+        self.vulkan.is_srgb_correct()
+    }
+
+    /// Set whether or not the window is minimized.
+    ///
+    /// While minimized, [`Renderer::draw_frame`] skips rendering.
+    pub fn set_minimized(&mut self, minimized: bool) {
+        self.minimized = minimized;
+    }
+
+    /// Return `true` if frames are being skipped, either because the window was set as minimized or because the last
+    /// swapchain rebuild had no area.
+    pub fn is_minimized(&self) -> bool {
+        self.minimized || self.zero_extent
+    }
+
+    /// Set the position, rotation, and FoV of the camera for the given viewport.
+    ///
+    /// `fov` must be in radians, and `position` must be a vector.
+    ///
+    /// If `camera.up` is zero or parallel to `camera.rotation`, it will be replaced with a valid up vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if:
+    /// - `viewport >= self.viewport_count()`
+    /// - `!(camera.fov > 0.0 && camera.fov < PI)` when using [`Projection::Perspective`]
+    /// - `!(height > 0.0)` when using [`Projection::Orthographic`]
+    /// - `camera.z_near` is not positive, or `camera.z_far` is not greater than `camera.z_near`
+    /// - `camera.custom_projection` is not finite
+    ///
+    /// The FoV, orthographic height, and clip planes are not checked if `camera.custom_projection` is set.
+    pub fn set_camera_for_viewport(&mut self, viewport: usize, camera: Camera) {
+        if let Err(e) = validate_custom_projection(&camera) {
+            panic!("{e}");
+        }
+        if camera.custom_projection.is_none() {
+            match camera.projection {
+                Projection::Perspective => assert!(camera.fov > 0.0 && camera.fov < core::f32::consts::PI, "camera.fov is not between 0 (exclusive) and pi (exclusive)"),
+                Projection::Orthographic { height } => assert!(height > 0.0 && height.is_finite(), "orthographic height is not a positive number")
+            }
+            if let Err(e) = validate_clip_planes(&camera) {
+                panic!("{e}");
+            }
+        }
+
+        let viewport = &mut self.player_viewports[viewport];
+        if camera == viewport.camera {
+            return;
+        }
+
+        // The FoV was explicitly changed, so it should no longer follow the horizontal FoV.
+        if camera.fov != viewport.camera.fov {
+            viewport.horizontal_fov = None;
+        }
+
+        let rotation = Vec3::from(camera.rotation).try_normalize().unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        let up = get_camera_up(rotation, Vec3::from(camera.up));
+
+        viewport.camera = Camera {
+            rotation: rotation.into(),
+            up: up.into(),
+            ..camera
+        };
+
+        self.invalidate_debug_text();
+    }
+
+    /// Set the position, orientation, and FoV of the camera for the given viewport using a quaternion.
+    ///
+    /// The identity quaternion faces +Y with the up vector being [`DEFAULT_CAMERA_UP`] (the renderer uses a left-handed
+    /// view matrix), so the forward and up vectors of the camera are `orientation * +Y` and
+    /// `orientation * DEFAULT_CAMERA_UP`, respectively. All other camera settings are kept.
+    ///
+    /// `fov` must be in radians.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `viewport >= self.viewport_count()` or if `!(fov > 0.0 && fov < PI)`
+    pub fn set_camera_orientation_quat(&mut self, viewport: usize, position: [f32; 3], orientation: Quat, fov: f32) {
+        let (rotation, up) = get_camera_vectors_from_quat(orientation);
+        let camera = Camera {
+            position,
+            rotation: rotation.into(),
+            up: up.into(),
+            fov,
+            ..self.player_viewports[viewport].camera
+        };
+        self.set_camera_for_viewport(viewport, camera);
+    }
+
+    /// Set the FoV of the camera for the given viewport using a horizontal FoV.
+    ///
+    /// The vertical FoV is calculated using the actual aspect ratio of the viewport, and it will be recalculated when the
+    /// swapchain is rebuilt, keeping the horizontal FoV constant. Setting the FoV with [`Renderer::set_camera_for_viewport`]
+    /// cancels this.
+    ///
+    /// `horizontal_fov` must be in radians.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `viewport >= self.viewport_count()` or if `!(horizontal_fov > 0.0 && horizontal_fov < PI)`
+    pub fn set_camera_horizontal_fov(&mut self, viewport: usize, horizontal_fov: f32) {
+        assert!(horizontal_fov > 0.0 && horizontal_fov < core::f32::consts::PI, "horizontal_fov is not between 0 (exclusive) and pi (exclusive)");
+
+        let player_viewport = &self.player_viewports[viewport];
+        let camera = Camera {
+            fov: player_viewport.get_vertical_fov(horizontal_fov, self.vulkan.get_current_resolution()),
+            ..player_viewport.camera
+        };
+        self.set_camera_for_viewport(viewport, camera);
+        self.player_viewports[viewport].horizontal_fov = Some(horizontal_fov);
+    }
+
+    /// Get the camera data for the given viewport.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `viewport >= self.viewport_count()`
+    pub fn get_camera_for_viewport(&self, viewport: usize) -> Camera {
+        self.player_viewports[viewport].camera
+    }
+
+    /// Get the number of viewports.
+    pub fn get_viewport_count(&self) -> usize {
+        self.player_viewports.len()
+    }
+
+    /// Enable or disable drawing the given viewport without changing the layout.
+    ///
+    /// Disabled viewports keep their cameras and fog, but they are filled with the background color instead of being
+    /// drawn, and sprites queued for them are skipped. Split screen bars are still drawn for the whole layout.
+    ///
+    /// Viewports are enabled by default.
+    ///
+    /// Returns `Err` if the viewport is invalid.
+    pub fn set_viewport_enabled(&mut self, viewport: usize, enabled: bool) -> MResult<()> {
+        let Some(player_viewport) = self.player_viewports.get_mut(viewport) else {
+            return Err(Error::from_data_error_string(format!("Can't set viewport {viewport} enabled: that viewport does not exist")))
+        };
+        player_viewport.enabled = enabled;
+        Ok(())
+    }
+
+    /// Get whether the given viewport is drawn, or `None` if the viewport is invalid.
+    ///
+    /// See [`Renderer::set_viewport_enabled`].
+    pub fn is_viewport_enabled(&self, viewport: usize) -> Option<bool> {
+        self.player_viewports.get(viewport).map(|v| v.enabled)
+    }
+
+    /// Set the number of viewports and the region of the screen each one covers.
+    ///
+    /// Viewports that already exist keep their cameras. Split screen bars are not drawn for custom layouts.
+    ///
+    /// Returns `Err` if `layout` is empty, has more than [`MAX_VIEWPORT_COUNT`] viewports, or if any rect is not
+    /// within 0.0-1.0 or has no area.
+    pub fn set_viewport_layout(&mut self, layout: &[ViewportRect]) -> MResult<()> {
+        if layout.is_empty() || layout.len() > MAX_VIEWPORT_COUNT {
+            return Err(Error::from_data_error_string(format!("Viewport count must be 1-{MAX_VIEWPORT_COUNT}, got {}", layout.len())))
+        }
+        for rect in layout {
+            rect.validate()?;
+        }
+
+        self.player_viewports.resize(layout.len(), PlayerViewport::default());
+        let resolution = self.vulkan.get_current_resolution();
+        for (viewport, rect) in self.player_viewports.iter_mut().zip(layout) {
+            viewport.set_rect(*rect);
+            if let Some(horizontal_fov) = viewport.horizontal_fov {
+                viewport.camera.fov = viewport.get_vertical_fov(horizontal_fov, resolution);
+            }
+        }
+
+        self.custom_viewport_layout = true;
+        self.vulkan.set_viewport_count(layout.len());
+        self.debug_lines.retain(|l| l.viewport < layout.len());
+        self.sprite_instances.retain(|s| s.viewport < layout.len());
+        self.invalidate_debug_text();
+        Ok(())
+    }
+
+    /// Draw a frame.
+    ///
+    /// Returns `false` if the swapchain is out of date or has no area and needs rebuilt with
+    /// [`Renderer::rebuild_swapchain`].
+    ///
+    /// Nothing is drawn while minimized (see [`Renderer::set_minimized`]) or while the swapchain has no area, but
+    /// instances queued for this frame are still cleared.
+    pub fn draw_frame(&mut self) -> MResult<bool> {
+        if self.minimized || self.zero_extent {
+            self.advance_time();
+            self.geometry_instances.clear();
+            self.instanced_geometries.clear();
+            self.sprite_instances.clear();
+            self.scissor_stack.clear();
+            self.stencil_test = None;
+            self.debug_lines.clear();
+            return Ok(!self.zero_extent)
+        }
+
+        if self.debug_text_stale {
+            self.draw_debug_text()?;
+            self.debug_text_stale = false;
+        }
+        self.advance_time();
+        self.fixup_fog_and_render_distances();
+        let result = VulkanRenderer::draw_frame(self)?;
+
+        self.geometry_instances.clear();
+        self.instanced_geometries.clear();
+        self.sprite_instances.clear();
+        self.scissor_stack.clear();
+        self.stencil_test = None;
+        self.debug_lines.clear();
+        if self.update_frame_rate_counter() {
+            self.invalidate_debug_text();
+            self.update_dynamic_resolution()?;
+        }
+
+        Ok(result)
+    }
+
+    /// Recompile a pipeline's GLSL sources from disk and rebuild it.
+    ///
+    /// This is intended for shader development. The sources are read from where the crate was built. The pipeline's
+    /// descriptor set layouts must not change, and all pipelines revert to the built-in shaders when the swapchain is
+    /// rebuilt.
+    ///
+    /// Returns `Err` with the compiler output if compilation fails, in which case the current pipeline is kept.
+    #[cfg(feature = "shader-hot-reload")]
+    pub fn reload_pipeline(&mut self, pipeline_type: VulkanPipelineType) -> MResult<()> {
+        self.vulkan.reload_pipeline(pipeline_type)
+    }
+
+    /// Get how much device memory is used by loaded data.
+    ///
+    /// Default bitmaps and render targets are not included.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport {
+            device_local_total: self.vulkan.get_device_local_memory(),
+            ..Default::default()
+        };
+
+        for (path, bitmap) in &self.bitmaps {
+            if self.is_default_bitmap(path) || self.render_targets.contains_key(path) {
+                continue
+            }
+            let size = bitmap.memory_size();
+            if self.bsps.values().any(|b| b.lightmap_bitmap.as_ref() == Some(path.as_ref())) {
+                report.lightmaps += size;
+            }
+            else {
+                report.bitmaps += size;
+            }
+        }
+
+        report.bsp_geometry = self
+            .bsps
+            .values()
+            .filter_map(|b| b.vulkan.subbuffers.as_ref())
+            .map(|b| b.memory_size())
+            .sum();
+
+        report.geometries = self
+            .geometries
+            .values()
+            .filter_map(|g| g.vulkan.subbuffers.as_ref())
+            .map(|b| b.memory_size())
+            .sum();
+
+        report.fonts = self.fonts.values().map(|f| f.atlas.memory_size()).sum();
+
+        report
+    }
+
+    /// List all GPUs on the system, including ones that are unsuitable for rendering.
+    ///
+    /// Use this to populate [`RendererParameters::preferred_device`].
+    pub fn list_physical_devices() -> MResult<Vec<PhysicalDeviceInfo>> {
+        VulkanRenderer::list_physical_devices()
+    }
+
+    /// Get the frame rate, averaged over roughly the last second.
+    ///
+    /// Returns 0.0 until the first second of frames has been drawn.
+    pub fn current_fps(&self) -> f64 {
+        self.fps_counter_value
+    }
+
+    /// Get the time between frames, averaged over roughly the last second.
+    ///
+    /// Returns [`Duration::ZERO`] until the first second of frames has been drawn.
+    pub fn average_frame_time(&self) -> Duration {
+        if self.fps_counter_value > 0.0 {
+            Duration::from_secs_f64(1.0 / self.fps_counter_value)
+        }
+        else {
+            Duration::ZERO
+        }
+    }
+
+    /// Get the GPU timings of the most recent frame whose timings are available.
+    ///
+    /// Timings are read back a few frames late to avoid stalling the GPU.
+    ///
+    /// Returns `None` if [`RendererParameters::gpu_frame_timings`] is not set, timestamps are unsupported by the GPU, or
+    /// no frame has finished yet.
+    pub fn last_frame_timings(&self) -> Option<FrameTimings> {
+        self.vulkan.get_last_frame_timings()
+    }
+
+    /// Read back the most recently drawn frame.
+    ///
+    /// This waits for the GPU to finish drawing the frame. The returned pixels are RGBA8 at the render resolution
+    /// (which may differ from the window resolution if a render scale is set), with rows from top to bottom.
+    ///
+    /// Returns `Err` if no frame has been drawn since the renderer was created or the swapchain was rebuilt.
+    pub fn read_frame_to_buffer(&mut self) -> MResult<(Resolution, Vec<u8>)> {
+        VulkanRenderer::read_frame_to_buffer(self)
+    }
+
+    /// Set whether debug info is displayed.
+    ///
+    /// Returns `Err` if the `font` is not loaded.
+    pub fn set_debug_font(&mut self, font: Option<&str>) -> MResult<()> {
+        match font {
+            Some(font) => self.set_debug_fonts(&[font]),
+            None => self.set_debug_fonts(&[])
+        }
+    }
+
+    /// Set the fonts used for debug info.
+    ///
+    /// The first font is the primary font. Characters missing from it are drawn with the first subsequent font that
+    /// has them. If `fonts` is empty, debug info is not displayed.
+    ///
+    /// Returns `Err` if any font in `fonts` is not loaded.
+    pub fn set_debug_fonts(&mut self, fonts: &[&str]) -> MResult<()> {
+        let mut debug_fonts = Vec::with_capacity(fonts.len());
+        for font in fonts {
+            let Some(font) = self.fonts.get_key_value(&font.to_string()) else {
+                return Err(Error::from_data_error_string(format!("Font {font} is not loaded")))
+            };
+            debug_fonts.push(font.0.clone());
+        }
+
+        self.debug_fonts = debug_fonts;
+        self.invalidate_debug_text();
+        Ok(())
+    }
+
+    pub fn invalidate_debug_text(&mut self) {
+        self.debug_text_stale = true;
+    }
+
+    fn fixup_fog_and_render_distances(&mut self) {
+        let Some((bsp_path, bsp)) = self.current_bsp.as_ref().and_then(|b| self.bsps.get_key_value(b)) else { return };
+        let bsp_draw_distance = self.get_bsp_draw_distance(bsp_path, bsp);
+
+        // First pass: get fog
+        for viewport in &mut self.player_viewports {
+            let Some(cluster) = bsp.bsp_data.find_cluster(viewport.camera.position) else {
+                continue
+            };
+
+            let cluster = &bsp.bsp_data.clusters[cluster];
+            let sky = cluster.sky.as_ref().and_then(|s| self.skies.get(s));
+
+            let Some(viewport_fog) = viewport.viewport_fog.as_mut() else {
+                let Some(sky) = sky else {
+                    continue
+                };
                 viewport.viewport_fog = Some(ViewportFog {
                     current_fog_data: sky.outdoor_fog,
                     outdoor_fog_data: sky.outdoor_fog,
                     indoor_fog_data: sky.indoor_fog,
-                    target_fog_data: sky.outdoor_fog,
-                    transition_amount: 0.0
+                    target_fog_data: sky.outdoor_fog
                 });
                 continue;
             };
@@ -438,34 +1732,49 @@ impl Renderer {
             }
         }
 
+        // Exponentially approach the target so the transition is frame rate independent; after
+        // fog_transition_seconds, less than 1% of the difference remains.
+        let transition_amount = if self.fog_transition_seconds > 0.0 {
+            1.0 - (-5.0 * self.frame_delta / self.fog_transition_seconds).exp()
+        }
+        else {
+            1.0
+        };
+
         // Second pass: render distances and transitions
         for viewport in &mut self.player_viewports {
             viewport.draw_distance[0] = DRAW_DISTANCE_MINIMUM;
             if let Some(f) = viewport.viewport_fog.as_mut() {
-                if f.transition_amount > 0.0 {
-                    f.current_fog_data.distance_from = f.current_fog_data.distance_from.lerp(f.target_fog_data.distance_from, f.transition_amount);
-                    f.current_fog_data.distance_to = f.current_fog_data.distance_to.lerp(f.target_fog_data.distance_to, f.transition_amount);
-                    f.current_fog_data.min_opacity = f.current_fog_data.min_opacity.lerp(f.target_fog_data.min_opacity, f.transition_amount);
-                    f.current_fog_data.max_opacity = f.current_fog_data.max_opacity.lerp(f.target_fog_data.max_opacity, f.transition_amount);
-                    f.current_fog_data.color = Vec3::from(f.current_fog_data.color).lerp(Vec3::from(f.target_fog_data.color), f.transition_amount).to_array();
-                    f.transition_amount = 0.0;
+                if f.current_fog_data != f.target_fog_data {
+                    f.current_fog_data.distance_from = f.current_fog_data.distance_from.lerp(f.target_fog_data.distance_from, transition_amount);
+                    f.current_fog_data.distance_to = f.current_fog_data.distance_to.lerp(f.target_fog_data.distance_to, transition_amount);
+                    f.current_fog_data.min_opacity = f.current_fog_data.min_opacity.lerp(f.target_fog_data.min_opacity, transition_amount);
+                    f.current_fog_data.max_opacity = f.current_fog_data.max_opacity.lerp(f.target_fog_data.max_opacity, transition_amount);
+                    f.current_fog_data.color = Vec3::from(f.current_fog_data.color).lerp(Vec3::from(f.target_fog_data.color), transition_amount).to_array();
+                    f.current_fog_data.density = f.current_fog_data.density.lerp(f.target_fog_data.density, transition_amount);
+                    f.current_fog_data.mode = f.target_fog_data.mode;
                 }
                 f.current_fog_data.normalize();
-                if f.current_fog_data.max_opacity == 1.0 {
-                    viewport.draw_distance[1] = bsp.draw_distance.min(f.current_fog_data.distance_to);
+                if f.current_fog_data.max_opacity == 1.0 && f.current_fog_data.mode == FogMode::Linear {
+                    viewport.draw_distance[1] = bsp_draw_distance.min(f.current_fog_data.distance_to);
                     continue;
                 }
             }
-            viewport.draw_distance[1] = bsp.draw_distance;
+            viewport.draw_distance[1] = bsp_draw_distance;
         }
     }
 
     fn draw_debug_text(&mut self) -> MResult<()> {
-        let Some(f) = self.debug_font.as_ref() else {
+        let Some((f, fallbacks)) = self.debug_fonts.split_first() else {
+            self.debug_text.clear();
             return Ok(())
         };
 
         let font = self.fonts.get(f).expect("selected debug font no longer loaded?");
+        let fallbacks: Vec<&Font> = fallbacks
+            .iter()
+            .map(|f| self.fonts.get(f).expect("selected debug font no longer loaded?"))
+            .collect();
 
         let fps = self.fps_counter_value;
         let fps_ms = (1000.0 / fps) as f32;
@@ -521,14 +1830,8 @@ impl Renderer {
         }
 
         let mut vec = Vec::new();
-        font.generate_string_draws(&text, request, &mut vec);
-        let parameter = font.draw_string_buffer_to_bitmap(&vec, request);
-        let bitmap = Bitmap::load_from_parameters(self, parameter)?;
-        self.debug_text.push_back(bitmap);
-
-        if self.debug_text.len() == self.debug_text.capacity() {
-            self.debug_text.pop_front();
-        }
+        font.generate_string_draws(&fallbacks, &text, request, &mut vec);
+        font.generate_string_quads(&fallbacks, &vec, &mut self.debug_text);
 
         Ok(())
     }
@@ -540,9 +1843,11 @@ impl Renderer {
         &self.bitmaps[&self.default_bitmaps.default_cubemap].bitmaps[default_type as usize]
     }
     fn get_or_default_2d(&self, bitmap: &Option<String>, bitmap_index: usize, default_type: DefaultType) -> &BitmapBitmap {
-        let bitmap = match bitmap.as_ref() {
-            Some(n) => &self.bitmaps[n].bitmaps[bitmap_index],
-            None => &self.get_default_2d(default_type)
+        let missing = self.default_bitmaps.missing_2d.as_ref().filter(|_| default_type == DefaultType::White);
+        let bitmap = match (bitmap.as_ref(), missing) {
+            (Some(n), _) => &self.bitmaps[n].bitmaps[bitmap_index],
+            (None, Some(missing)) => &self.bitmaps[missing].bitmaps[0],
+            (None, None) => &self.get_default_2d(default_type)
         };
         debug_assert_eq!(BitmapType::Dim2D, bitmap.bitmap_type);
         bitmap
@@ -563,7 +1868,14 @@ impl Renderer {
         debug_assert_eq!(BitmapType::Cubemap, bitmap.bitmap_type);
         bitmap
     }
-    fn update_frame_rate_counter(&mut self) {
+    fn advance_time(&mut self) {
+        let now = Instant::now();
+        self.frame_delta = (now - self.last_frame_time).as_secs_f32();
+        self.time += self.frame_delta;
+        self.last_frame_time = now;
+    }
+    /// Returns true if the frame rate was updated.
+    fn update_frame_rate_counter(&mut self) -> bool {
         self.fps_counter_count = self.fps_counter_count.saturating_add(1);
 
         let now = Instant::now();
@@ -572,10 +1884,145 @@ impl Renderer {
             self.fps_counter_value = self.fps_counter_count as f64 / ((microseconds_since as f64) / 1000000.0);
             self.fps_counter_time = now;
             self.fps_counter_count = 0;
+            return true
         }
+
+        false
+    }
+    fn update_dynamic_resolution(&mut self) -> MResult<()> {
+        let Some(dynamic_resolution) = self.dynamic_resolution else {
+            return Ok(())
+        };
+        if self.fps_counter_value <= 0.0 {
+            return Ok(())
+        }
+
+        let frame_time_ms = (1000.0 / self.fps_counter_value) as f32;
+        let target_ms = dynamic_resolution.target_ms;
+        let current_scale = self.vulkan.get_render_scale();
+
+        // Scale down roughly in proportion to how far over the target we are, but only scale back up once there is
+        // plenty of headroom, so it doesn't oscillate around the target.
+        let unclamped_scale = if frame_time_ms > target_ms {
+            current_scale * (target_ms / frame_time_ms).max(0.75)
+        }
+        else if frame_time_ms < target_ms * 0.8 {
+            current_scale * 1.1
+        }
+        else {
+            current_scale
+        };
+        let new_scale = unclamped_scale.clamp(dynamic_resolution.min_scale, dynamic_resolution.max_scale);
+
+        if (new_scale - current_scale).abs() > current_scale * 0.01 {
+            self.vulkan.set_render_scale(new_scale)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// GPU timings of a frame, in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameTimings {
+    /// Time spent drawing each viewport.
+    pub viewports: Vec<f32>,
+
+    /// Time spent drawing split screen bars, sprites, and debug text.
+    pub overlays: f32,
+
+    /// Time spent resolving and copying the frame to the swapchain.
+    pub blit: f32,
+
+    /// Total GPU time of the frame.
+    pub total: f32
+}
+
+/// Describes a loaded shader, returned by [`Renderer::shader_info`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderInfo {
+    pub shader_type: ShaderType,
+
+    /// Paths of all bitmaps referenced by the shader, without duplicates.
+    pub bitmaps: Vec<String>
+}
+
+/// Layout of a loaded bitmap, returned by [`Renderer::bitmap_info`].
+#[derive(Clone)]
+pub struct BitmapInfo {
+    pub bitmaps: Vec<BitmapBitmapInfo>,
+    pub sequences: Vec<AddBitmapSequenceParameter>
+}
+
+/// Describes one bitmap of a loaded bitmap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BitmapBitmapInfo {
+    pub bitmap_type: BitmapType,
+    pub resolution: Resolution,
+
+    /// Format of the data the bitmap was loaded from.
+    pub format: BitmapFormat,
+
+    /// Number of mipmaps, excluding the base level; this includes mipmaps generated on the GPU.
+    pub mipmap_count: u32
+}
+
+/// Device memory used by loaded data, in bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MemoryReport {
+    /// Images of all bitmaps that are not BSP lightmaps.
+    pub bitmaps: u64,
+
+    /// Images of bitmaps used as BSP lightmaps.
+    pub lightmaps: u64,
+
+    /// Vertex and index buffers of BSPs.
+    pub bsp_geometry: u64,
+
+    /// Vertex and index buffers of geometries (models).
+    pub geometries: u64,
+
+    /// Glyph atlases of fonts.
+    pub fonts: u64,
+
+    /// Total device-local memory of the GPU.
+    ///
+    /// The amount of memory actually available may be lower, as other processes can use it too.
+    pub device_local_total: u64
+}
+
+impl MemoryReport {
+    /// Get the total memory used by loaded data.
+    pub fn total_used(&self) -> u64 {
+        self.bitmaps + self.lightmaps + self.bsp_geometry + self.geometries + self.fonts
     }
 }
 
+/// Describes a GPU that can be selected with [`DeviceSelection`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicalDeviceInfo {
+    /// Index for [`DeviceSelection::Index`].
+    pub index: usize,
+
+    /// Name of the device.
+    pub name: String,
+
+    /// Type of the device.
+    pub device_type: GPUType,
+
+    /// Total device-local memory in bytes.
+    pub vram: u64
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GPUType {
+    Discrete,
+    Integrated,
+    Virtual,
+    CPU,
+    Other
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(usize)]
 enum DefaultType {
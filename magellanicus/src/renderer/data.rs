@@ -4,6 +4,8 @@ mod shader;
 mod bsp;
 mod sky;
 mod font;
+mod render_target;
+mod path_interner;
 
 pub use bitmap::*;
 pub use geometry::*;
@@ -11,3 +13,5 @@ pub use shader::*;
 pub use bsp::*;
 pub use sky::*;
 pub use font::*;
+pub use render_target::*;
+pub use path_interner::*;
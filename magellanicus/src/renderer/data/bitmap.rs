@@ -1,8 +1,8 @@
 use core::iter;
 use std::sync::Arc;
 use crate::error::MResult;
-use crate::types::to_rgbaf32;
-use crate::renderer::{AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, BitmapFormat, Renderer, Resolution};
+use crate::types::{to_rgbaf32, FloatColor};
+use crate::renderer::{AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, AlphaMode, BitmapFormat, MissingTextureMode, Renderer, Resolution};
 use crate::renderer::vulkan::VulkanBitmapData;
 
 #[derive(Default)]
@@ -10,6 +10,9 @@ pub struct DefaultBitmaps {
     pub default_2d: Arc<String>,
     pub default_3d: Arc<String>,
     pub default_cubemap: Arc<String>,
+
+    /// Used instead of the white 2D default if [`MissingTextureMode::Checkerboard`] is set.
+    pub missing_2d: Option<Arc<String>>,
 }
 
 pub struct Bitmap {
@@ -17,6 +20,13 @@ pub struct Bitmap {
     pub sequences: Vec<BitmapSequence>
 }
 
+impl Bitmap {
+    /// Get the size of all images in device memory in bytes.
+    pub fn memory_size(&self) -> u64 {
+        self.bitmaps.iter().map(|b| b.vulkan.memory_size()).sum()
+    }
+}
+
 impl Bitmap {
     pub fn load_from_parameters(renderer: &mut Renderer, parameter: AddBitmapParameter) -> MResult<Self> {
         parameter.validate()?;
@@ -26,7 +36,8 @@ impl Bitmap {
             let bitmap = BitmapBitmap {
                 resolution: b.resolution,
                 bitmap_type: b.bitmap_type,
-                vulkan: VulkanBitmapData::new(&mut renderer.vulkan, &b)?
+                format: b.format,
+                vulkan: VulkanBitmapData::new(&mut renderer.vulkan, &b, parameter.generate_mipmaps)?
             };
             bitmaps.push(bitmap);
         }
@@ -48,7 +59,10 @@ pub enum BitmapType {
 pub struct BitmapBitmap {
     pub vulkan: VulkanBitmapData,
     pub resolution: Resolution,
-    pub bitmap_type: BitmapType
+    pub bitmap_type: BitmapType,
+
+    /// Format of the data the bitmap was loaded from, which may differ from the format of the image.
+    pub format: BitmapFormat
 }
 
 #[derive(Clone)]
@@ -66,6 +80,35 @@ pub struct BitmapSprite {
     pub right: f32
 }
 
+/// Describes a sprite to be drawn for the current frame.
+#[derive(Clone, Debug)]
+pub struct SpriteInstance {
+    pub viewport: usize,
+    pub bitmap: Arc<String>,
+    pub bitmap_index: usize,
+    pub texture_rect: [f32; 4],
+    pub rect: [f32; 4],
+    pub color: FloatColor,
+
+    /// Clip rectangle relative to the viewport (x, y, width, height)
+    pub scissor: [f32; 4],
+
+    pub stencil: SpriteStencil
+}
+
+/// Determines how a sprite instance uses the stencil buffer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpriteStencil {
+    /// Draw the sprite regardless of the stencil buffer.
+    None,
+
+    /// Only draw the sprite where the stencil buffer equals this value.
+    Test(u8),
+
+    /// Write this value to the stencil buffer over the sprite's rect instead of drawing it.
+    Mask(u8)
+}
+
 pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
     fn make_add_bitmap_parameter(renderer: &mut Renderer, path: &str, bitmap_type: BitmapType) -> MResult<Arc<String>> {
         // note: black is fully transparent in source data, but all release builds are fully opaque
@@ -112,6 +155,8 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
                     bitmap_type,
                     resolution: Resolution { width: 1, height: 1 },
                     mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
                     data: black_data,
                 },
                 AddBitmapBitmapParameter {
@@ -119,6 +164,8 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
                     bitmap_type,
                     resolution: Resolution { width: 1, height: 1 },
                     mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
                     data: white_data,
                 },
                 AddBitmapBitmapParameter {
@@ -126,6 +173,8 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
                     bitmap_type,
                     resolution: Resolution { width: 1, height: 1 },
                     mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
                     data: gray_data,
                 },
                 AddBitmapBitmapParameter {
@@ -133,6 +182,8 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
                     bitmap_type,
                     resolution: Resolution { width: 1, height: 1 },
                     mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
                     data: blue_gray_data,
                 }
             ],
@@ -142,6 +193,41 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
                 AddBitmapSequenceParameter::Bitmap { first: 2, count: 1 },
                 AddBitmapSequenceParameter::Bitmap { first: 3, count: 1 },
             ],
+            generate_mipmaps: false
+        };
+
+        renderer.add_bitmap(path, add_data)?;
+        Ok(renderer.bitmaps.get_key_value(&path.to_string()).unwrap().0.clone())
+    }
+
+    fn make_checkerboard(renderer: &mut Renderer, path: &str) -> MResult<Arc<String>> {
+        const SIZE: u32 = 64;
+        const CELL_SIZE: u32 = 8;
+
+        let magenta: [u8; 16] = to_rgbaf32([1.0, 0.0, 1.0, 1.0]);
+        let black: [u8; 16] = to_rgbaf32([0.0, 0.0, 0.0, 1.0]);
+
+        let data: Vec<u8> = (0..SIZE)
+            .flat_map(|y| (0..SIZE).map(move |x| (x / CELL_SIZE + y / CELL_SIZE) % 2 == 0))
+            .flat_map(|is_magenta| if is_magenta { magenta } else { black })
+            .collect();
+
+        let add_data = AddBitmapParameter {
+            bitmaps: vec![
+                AddBitmapBitmapParameter {
+                    format: BitmapFormat::R32G32B32A32SFloat,
+                    bitmap_type: BitmapType::Dim2D,
+                    resolution: Resolution { width: SIZE, height: SIZE },
+                    mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
+                    data,
+                }
+            ],
+            sequences: vec![
+                AddBitmapSequenceParameter::Bitmap { first: 0, count: 1 },
+            ],
+            generate_mipmaps: false
         };
 
         renderer.add_bitmap(path, add_data)?;
@@ -152,10 +238,16 @@ pub fn populate_default_bitmaps(renderer: &mut Renderer) -> MResult<()> {
     let default_3d = make_add_bitmap_parameter(renderer, "~default_3d", BitmapType::Dim3D { depth: 1 })?;
     let default_cubemap = make_add_bitmap_parameter(renderer, "~default_cubemap", BitmapType::Cubemap)?;
 
+    let missing_2d = match renderer.missing_texture_mode {
+        MissingTextureMode::Neutral => None,
+        MissingTextureMode::Checkerboard => Some(make_checkerboard(renderer, "~missing_2d")?)
+    };
+
     renderer.default_bitmaps = DefaultBitmaps {
         default_2d,
         default_3d,
-        default_cubemap
+        default_cubemap,
+        missing_2d
     };
 
     Ok(())
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use glam::Vec3;
 use crate::error::MResult;
 use crate::renderer::vulkan::VulkanBSPData;
 use crate::renderer::{AddBSPParameter, AddBSPParameterLightmapMaterial, BSPData, Renderer};
@@ -8,15 +9,64 @@ pub const MIN_DRAW_DISTANCE_LIMIT: f32 = 100.0;
 pub const MAX_DRAW_DISTANCE_LIMIT: f32 = 2250.0;
 pub const DRAW_DISTANCE_MINIMUM: f32 = 0.0625;
 
+/// Describes the width of the indices used by a BSP.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BSPIndexWidth {
+    /// All materials have few enough vertices to use 16-bit indices.
+    U16,
+
+    /// At least one material has more than 65536 vertices, so 32-bit indices are needed.
+    U32
+}
+
+impl BSPIndexWidth {
+    /// Get the narrowest index width that can address every vertex of each of the given materials.
+    pub fn for_materials<'a>(materials: impl IntoIterator<Item = &'a AddBSPParameterLightmapMaterial>) -> Self {
+        // 16-bit indices can address up to 65536 vertices.
+        if materials.into_iter().all(|m| m.shader_vertices.len() <= u16::MAX as usize + 1) {
+            BSPIndexWidth::U16
+        }
+        else {
+            BSPIndexWidth::U32
+        }
+    }
+
+    /// Convert indices to this width.
+    ///
+    /// All indices must fit in this width.
+    pub fn narrow_indices(self, indices: Vec<u32>) -> BSPIndices {
+        match self {
+            BSPIndexWidth::U16 => BSPIndices::U16(indices.into_iter().map(|i| i as u16).collect()),
+            BSPIndexWidth::U32 => BSPIndices::U32(indices)
+        }
+    }
+}
+
+/// Indices of a BSP at the width it uses.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BSPIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>)
+}
+
 pub struct BSP {
     pub vulkan: VulkanBSPData,
     pub geometries: Vec<BSPGeometry>,
     pub bsp_data: BSPData,
+
+    /// Indices of geometries visible in each cluster.
     pub cluster_surfaces: Vec<Vec<usize>>,
+
+    /// Vertex positions of each surface, in the same order as the surface indices of the BSP data, for ray casts.
+    pub surfaces: Vec<[[f32; 3]; 3]>,
     pub geometry_indices_sorted_by_material: Vec<usize>,
+    pub index_width: BSPIndexWidth,
 
     /// Calculated based on the size of the BSP, clamped between [`MIN_DRAW_DISTANCE_LIMIT`] and [`MAX_DRAW_DISTANCE_LIMIT`].
-    pub draw_distance: f32
+    pub draw_distance: f32,
+
+    /// Path to the lightmap bitmap, if any.
+    pub lightmap_bitmap: Option<String>
 }
 
 impl BSP {
@@ -50,7 +100,11 @@ impl BSP {
             });
 
         let count = add_bsp_iterator.clone().count();
+
+        let index_width = BSPIndexWidth::for_materials(add_bsp_iterator.clone().map(|m| m.material_data));
+
         let mut geometries = Vec::with_capacity(count);
+        let mut surfaces = Vec::new();
 
         let mut max_x = f32::NEG_INFINITY;
         let mut max_y = f32::NEG_INFINITY;
@@ -63,7 +117,12 @@ impl BSP {
         let mut index_offset = 0u32;
 
         for data in add_bsp_iterator {
+            let centroid = Vec3::from(data.material_data.centroid);
+            let mut radius_squared = 0.0f32;
+
             for p in &data.material_data.shader_vertices {
+                radius_squared = radius_squared.max(centroid.distance_squared(Vec3::from(p.position)));
+
                 min_x = min_x.min(p.position[0]);
                 min_y = min_y.min(p.position[1]);
                 min_z = min_z.min(p.position[2]);
@@ -72,6 +131,9 @@ impl BSP {
                 max_z = max_z.max(p.position[2]);
             }
 
+            let vertices = &data.material_data.shader_vertices;
+            surfaces.extend(data.material_data.surfaces.iter().map(|s| s.indices.map(|i| vertices[i as usize].position)));
+
             let index_count = (data.material_data.surfaces.len() * 3) as u32;
             geometries.push(BSPGeometry {
                 shader: renderer.shaders.get_key_value(&data.material_data.shader).unwrap().0.clone(),
@@ -79,6 +141,7 @@ impl BSP {
                 material_reflexive_index: data.material_reflexive_index,
                 lightmap_reflexive_index: data.lightmap_reflexive_index,
                 centroid: data.material_data.centroid,
+                radius: radius_squared.sqrt(),
                 offset: VertexOffsets {
                     index_offset,
                     vertex_offset,
@@ -90,6 +153,25 @@ impl BSP {
             index_offset += index_count;
         }
 
+        // Geometries next to each other in the buffers that share a shader and lightmap can be drawn in one call if
+        // their indices are relative to the same vertex, so rebase them onto the first geometry of each run as long as
+        // the indices still fit. VulkanBSPData offsets the indices to match.
+        let max_index = match index_width {
+            BSPIndexWidth::U16 => u16::MAX as i64,
+            BSPIndexWidth::U32 => u32::MAX as i64
+        };
+        for i in 1..geometries.len() {
+            let (previous, this) = (&geometries[i - 1], &geometries[i]);
+            if previous.shader != this.shader || previous.lightmap_index != this.lightmap_index {
+                continue
+            }
+            let next_vertex_offset = geometries.get(i + 1).map_or(vertex_offset, |g| g.offset.vertex_offset);
+            let last_vertex = (next_vertex_offset - previous.offset.vertex_offset - 1) as i64;
+            if last_vertex <= max_index {
+                geometries[i].offset.vertex_offset = previous.offset.vertex_offset;
+            }
+        }
+
         let mut geometry_indices_sorted_by_material = Vec::from_iter(0usize..geometries.len());
         geometry_indices_sorted_by_material.sort_by(|a, b| {
             geometries[*a].shader.cmp(&geometries[*b].shader)
@@ -106,20 +188,241 @@ impl BSP {
         }.clamp(MIN_DRAW_DISTANCE_LIMIT, MAX_DRAW_DISTANCE_LIMIT);
 
         let bsp_data = &mut add_bsp_parameter.bsp_data;
-        let cluster_surfaces: Vec<Vec<usize>> = Vec::with_capacity(bsp_data.clusters.len());
+        let cluster_surfaces: Vec<Vec<usize>> = bsp_data
+            .clusters
+            .iter()
+            .map(|cluster| {
+                let mut geometry_indices: Vec<usize> = cluster
+                    .subclusters
+                    .iter()
+                    .map(|s| s.surface_indices.iter())
+                    .flatten()
+                    .map(|surface| {
+                        // Surfaces are laid out in the same order as the geometries, so find the geometry whose
+                        // index range contains this surface.
+                        let index = (*surface * 3) as u32;
+                        geometries.partition_point(|g| g.offset.index_offset + g.offset.index_count <= index)
+                    })
+                    .collect();
+                geometry_indices.sort();
+                geometry_indices.dedup();
+                geometry_indices
+            })
+            .collect();
 
-        let vulkan = VulkanBSPData::new(renderer, &add_bsp_parameter, &geometries)?;
+        let vulkan = VulkanBSPData::new(renderer, &add_bsp_parameter, &geometries, index_width)?;
 
-        Ok(Self { vulkan, geometries, bsp_data: add_bsp_parameter.bsp_data, cluster_surfaces, draw_distance, geometry_indices_sorted_by_material })
+        Ok(Self {
+            vulkan,
+            geometries,
+            bsp_data: add_bsp_parameter.bsp_data,
+            cluster_surfaces,
+            surfaces,
+            draw_distance,
+            geometry_indices_sorted_by_material,
+            index_width,
+            lightmap_bitmap: add_bsp_parameter.lightmap_bitmap
+        })
     }
 }
 
+impl BSP {
+    /// Get which geometries are potentially visible from the given position.
+    ///
+    /// Returns `None` if all geometries should be drawn (e.g. no PVS is present or the position is outside of the
+    /// BSP).
+    pub fn get_visible_geometries(&self, position: [f32; 3]) -> Option<Vec<bool>> {
+        let pvs = self.bsp_data.cluster_pvs.as_ref()?;
+        let cluster = self.bsp_data.find_cluster(position)?;
+        let mut visible = vec![false; self.geometries.len()];
+
+        for c in core::iter::once(&cluster).chain(pvs[cluster].iter()) {
+            for geometry in &self.cluster_surfaces[*c] {
+                visible[*geometry] = true;
+            }
+        }
+
+        Some(visible)
+    }
+
+    /// Find the closest surface hit by a ray.
+    ///
+    /// Surfaces are hit from either side. If the BSP has clusters, only surfaces in a subcluster whose bounds are
+    /// crossed by the ray are tested, starting with the cluster containing `origin`.
+    ///
+    /// Returns `None` if nothing was hit or `direction` has no length.
+    pub fn raycast(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<RaycastHit> {
+        let origin_vec = Vec3::from(origin);
+        let direction = Vec3::from(direction).try_normalize()?;
+
+        // Closest surface index and distance so far
+        let mut closest: Option<(usize, f32)> = None;
+        let test_surface = |surface: usize, closest: &mut Option<(usize, f32)>| {
+            let Some(distance) = ray_triangle_distance(origin_vec, direction, self.surfaces[surface]) else { return };
+            if closest.map_or(true, |(_, d)| distance < d) {
+                *closest = Some((surface, distance));
+            }
+        };
+
+        let clusters = &self.bsp_data.clusters;
+        if clusters.is_empty() {
+            for surface in 0..self.surfaces.len() {
+                test_surface(surface, &mut closest);
+            }
+        }
+        else {
+            // A hit in the origin's cluster lets any subcluster that the ray enters further away be skipped.
+            let start = self.bsp_data.find_cluster(origin);
+            let cluster_order = start
+                .into_iter()
+                .chain((0..clusters.len()).filter(|c| Some(*c) != start));
+
+            for cluster in cluster_order {
+                for subcluster in &clusters[cluster].subclusters {
+                    let bounds_from = Vec3::from(subcluster.world_bounds_from);
+                    let bounds_to = Vec3::from(subcluster.world_bounds_to);
+                    let Some(entry) = ray_box_distance(origin_vec, direction, bounds_from, bounds_to) else { continue };
+                    if closest.is_some_and(|(_, d)| entry > d) {
+                        continue
+                    }
+                    for surface in subcluster.surface_indices.iter().copied() {
+                        test_surface(surface, &mut closest);
+                    }
+                }
+            }
+        }
+
+        let (surface, distance) = closest?;
+
+        // Surfaces are laid out in the same order as the geometries.
+        let index = (surface * 3) as u32;
+        let geometry_index = self.geometries.partition_point(|g| g.offset.index_offset + g.offset.index_count <= index);
+
+        Some(RaycastHit {
+            position: (origin_vec + direction * distance).to_array(),
+            geometry_index,
+            distance
+        })
+    }
+}
+
+/// Describes where a ray hit a BSP.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RaycastHit {
+    /// Point where the ray hit the surface.
+    pub position: [f32; 3],
+
+    /// Index of the BSP geometry that was hit.
+    pub geometry_index: usize,
+
+    /// Distance from the origin of the ray to `position`.
+    pub distance: f32
+}
+
+/// Get the distance along a ray to where it hits a triangle, using the Möller-Trumbore algorithm.
+///
+/// `direction` must be normalized.
+fn ray_triangle_distance(origin: Vec3, direction: Vec3, triangle: [[f32; 3]; 3]) -> Option<f32> {
+    let [a, b, c] = triangle.map(Vec3::from);
+    let edge1 = b - a;
+    let edge2 = c - a;
+
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let t = origin - a;
+    let u = t.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None
+    }
+
+    let q = t.cross(edge1);
+    let v = direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None
+    }
+
+    let distance = edge2.dot(q) * inverse_determinant;
+    (distance >= 0.0).then_some(distance)
+}
+
+/// Get the distance along a ray to where it enters a box, or 0 if it starts inside it.
+fn ray_box_distance(origin: Vec3, direction: Vec3, from: Vec3, to: Vec3) -> Option<f32> {
+    let inverse_direction = direction.recip();
+    let t1 = (from - origin) * inverse_direction;
+    let t2 = (to - origin) * inverse_direction;
+
+    let entry = t1.min(t2).max_element().max(0.0);
+    let exit = t1.max(t2).min_element();
+    (entry <= exit).then_some(entry)
+}
+
 pub struct BSPGeometry {
     pub offset: VertexOffsets,
     pub shader: Arc<String>,
     pub lightmap_index: Option<usize>,
     pub centroid: [f32; 3],
 
+    /// Distance from the centroid to the furthest vertex.
+    pub radius: f32,
+
     pub material_reflexive_index: usize,
     pub lightmap_reflexive_index: usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::{ModelTriangle, ModelVertex};
+
+    fn make_material(vertex_count: usize) -> AddBSPParameterLightmapMaterial {
+        let vertex = ModelVertex {
+            position: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            binormal: [0.0, 1.0, 0.0],
+            tangent: [1.0, 0.0, 0.0],
+            texture_coords: [0.0; 2]
+        };
+        let last = vertex_count as u32 - 1;
+        AddBSPParameterLightmapMaterial {
+            shader_vertices: vec![vertex; vertex_count],
+            lightmap_vertices: None,
+            surfaces: vec![
+                ModelTriangle { indices: [0, 1, 2] },
+                ModelTriangle { indices: [last - 2, last - 1, last] }
+            ],
+            shader: "shader".to_owned(),
+            centroid: [0.0; 3]
+        }
+    }
+
+    fn material_indices(material: &AddBSPParameterLightmapMaterial) -> Vec<u32> {
+        material.surfaces.iter().flat_map(|s| s.indices).collect()
+    }
+
+    #[test]
+    fn small_materials_use_16_bit_indices() {
+        let materials = [make_material(3), make_material(u16::MAX as usize + 1)];
+        let width = BSPIndexWidth::for_materials(materials.iter());
+        assert_eq!(width, BSPIndexWidth::U16);
+
+        let indices = material_indices(&materials[1]);
+        let expected = indices.iter().map(|i| *i as u16).collect();
+        assert_eq!(width.narrow_indices(indices), BSPIndices::U16(expected));
+    }
+
+    #[test]
+    fn large_material_indices_round_trip() {
+        let materials = [make_material(3), make_material(70000)];
+        let width = BSPIndexWidth::for_materials(materials.iter());
+        assert_eq!(width, BSPIndexWidth::U32);
+
+        let indices = material_indices(&materials[1]);
+        assert_eq!(indices[3..], [69997, 69998, 69999]);
+        assert_eq!(width.narrow_indices(indices.clone()), BSPIndices::U32(indices));
+    }
+}
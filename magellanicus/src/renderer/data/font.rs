@@ -1,54 +1,250 @@
 mod colors;
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::iter::FusedIterator;
 use std::ops::Range;
 use std::str::Chars;
 use glam::Vec4;
-use crate::error::MResult;
+use crate::error::{Error, MResult};
 use crate::types::FloatColor;
-use crate::renderer::{AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, AddFontParameter, BitmapFormat, BitmapType, Renderer, Resolution};
+use crate::renderer::{AddBitmapBitmapParameter, AddBitmapParameter, AddBitmapSequenceParameter, AddFontParameter, AddFontParameterCharacter, AlphaMode, BitmapFormat, Renderer, Resolution};
+use crate::renderer::data::{Bitmap, BitmapType};
+use crate::renderer::vulkan::VulkanCharacterData;
 use crate::renderer::data::font::colors::{ControlCode, ColorCodes};
 
 pub struct Font {
     pub line_height: u32,
     pub characters: HashMap<char, FontCharacter>,
-    pub colors: ColorCodes
+    pub kerning: HashMap<(char, char), i32>,
+    pub colors: ColorCodes,
+
+    /// Drawn for characters that are not present in this font or any of its fallbacks.
+    pub notdef: FontCharacter,
+
+    /// Solid block used for drawing underlines.
+    pub solid: FontCharacter,
+
+    /// Glyph atlas containing every character of this font, with one bitmap per page.
+    pub atlas: Bitmap
 }
 
 impl Font {
-    pub fn load_from_parameters(_: &Renderer, parameter: AddFontParameter) -> MResult<Font> {
-        // TODO: Add bold/italic/underline variants
+    pub fn load_from_parameters(renderer: &mut Renderer, parameter: AddFontParameter) -> MResult<Font> {
+        let kerning = collect_kerning(&parameter.characters);
+
+        let mut glyphs = parameter.characters;
+        glyphs.push(FontCharacter::notdef(parameter.line_height));
+        glyphs.push(FontCharacter::solid());
+
+        let (positions, page_heights) = pack_atlas(&glyphs)?;
+        let mut page_data: Vec<Vec<u8>> = page_heights
+            .iter()
+            .map(|height| vec![0u8; ATLAS_WIDTH as usize * *height as usize * 4])
+            .collect();
+
+        for (glyph, position) in glyphs.iter().zip(positions.iter()) {
+            let atlas_data = &mut page_data[position.page];
+            for glyph_y in 0..glyph.height {
+                for glyph_x in 0..glyph.width {
+                    let pixel = (position.x as usize + glyph_x) + (position.y as usize + glyph_y) * ATLAS_WIDTH as usize;
+                    atlas_data[pixel * 4..pixel * 4 + 4].copy_from_slice(&[0xFF, 0xFF, 0xFF, glyph.data[glyph_x + glyph_y * glyph.width]]);
+                }
+            }
+        }
 
-        let characters = parameter
-            .characters
+        let page_count = page_data.len();
+        let atlas_parameter = AddBitmapParameter {
+            bitmaps: page_data
+                .into_iter()
+                .zip(page_heights.iter())
+                .map(|(data, height)| AddBitmapBitmapParameter {
+                    format: BitmapFormat::A8B8G8R8,
+                    bitmap_type: BitmapType::Dim2D,
+                    resolution: Resolution { width: ATLAS_WIDTH, height: *height },
+                    mipmap_count: 0,
+                    alpha_mode: AlphaMode::Straight,
+                    color_key: None,
+                    data
+                })
+                .collect(),
+            sequences: vec![AddBitmapSequenceParameter::Bitmap { first: 0, count: page_count }],
+            generate_mipmaps: false
+        };
+
+        let atlas = Bitmap::load_from_parameters(renderer, atlas_parameter)?;
+        let pages: Vec<VulkanCharacterData> = atlas
+            .bitmaps
+            .iter()
+            .map(VulkanCharacterData::new)
+            .collect::<MResult<_>>()?;
+
+        let atlas_width = ATLAS_WIDTH as f32;
+        let mut characters: Vec<FontCharacter> = glyphs
             .into_iter()
-            .map(|c| {
-                let character = FontCharacter {
+            .zip(positions)
+            .map(|(c, position)| {
+                let atlas_height = page_heights[position.page] as f32;
+                let (x, y) = (position.x as f32, position.y as f32);
+                FontCharacter {
                     character: c.character,
+                    texture_rect: [
+                        x / atlas_width,
+                        y / atlas_height,
+                        (x + c.width as f32) / atlas_width,
+                        (y + c.height as f32) / atlas_height
+                    ],
                     data: c.data,
                     width: c.width,
                     height: c.height,
-                    advance_x: c.advance_x
-                };
-                (c.character, character)
+                    advance_x: c.advance_x,
+                    vulkan: pages[position.page].clone()
+                }
             })
             .collect();
 
+        let mut solid = characters.pop().expect("solid block was added");
+        let notdef = characters.pop().expect("notdef was added");
+
+        // Only sample the center pixel so the edges of the block are not filtered with the padding.
+        let [left, top, right, bottom] = solid.texture_rect;
+        let (pixel_width, pixel_height) = ((right - left) / 3.0, (bottom - top) / 3.0);
+        solid.texture_rect = [left + pixel_width, top + pixel_height, right - pixel_width, bottom - pixel_height];
+
         Ok(Font {
             line_height: parameter.line_height,
-            characters,
-            colors: ColorCodes::default()
+            characters: characters.into_iter().map(|c| (c.character, c)).collect(),
+            kerning,
+            colors: ColorCodes::default(),
+            notdef,
+            solid,
+            atlas
         })
     }
 }
 
+/// Get the kerning pairs of `characters`, keyed by each character and the character after it.
+fn collect_kerning(characters: &[AddFontParameterCharacter]) -> HashMap<(char, char), i32> {
+    characters
+        .iter()
+        .flat_map(|c| c.kerning.iter().map(|(next, amount)| ((c.character, *next), *amount)))
+        .collect()
+}
+
+/// Get the advance of `character` when followed by `next`, adjusted by any kerning pair.
+fn kerned_advance(kerning: &HashMap<(char, char), i32>, advance_x: i32, character: char, next: char) -> i32 {
+    advance_x + kerning.get(&(character, next)).copied().unwrap_or_default()
+}
+
+/// Width of glyph atlas pages in pixels.
+const ATLAS_WIDTH: u32 = 1024;
+
+/// Maximum height of glyph atlas pages in pixels. All Vulkan devices support 2D images at least this large.
+const ATLAS_MAX_HEIGHT: u32 = 4096;
+
+/// Empty space between glyphs in the atlas so filtering does not bleed into neighboring glyphs.
+const ATLAS_PADDING: u32 = 1;
+
+/// Location of a glyph in the atlas.
+#[derive(Copy, Clone, Default)]
+struct AtlasPosition {
+    page: usize,
+    x: u32,
+    y: u32
+}
+
+/// Place glyphs in rows, starting a new page when one fills up.
+///
+/// Returns the location of each glyph and the height of each page.
+fn pack_atlas(glyphs: &[AddFontParameterCharacter]) -> MResult<(Vec<AtlasPosition>, Vec<u32>)> {
+    // Packing the tallest glyphs first wastes less space in each row.
+    let mut order: Vec<usize> = (0..glyphs.len()).collect();
+    order.sort_by_key(|i| Reverse(glyphs[*i].height));
+
+    let mut positions = vec![AtlasPosition::default(); glyphs.len()];
+    let mut page_heights = Vec::new();
+    let mut x = ATLAS_PADDING;
+    let mut y = ATLAS_PADDING;
+    let mut row_height = 0;
+
+    for i in order {
+        let glyph = &glyphs[i];
+        if glyph.width > (ATLAS_WIDTH - ATLAS_PADDING * 2) as usize || glyph.height > (ATLAS_MAX_HEIGHT - ATLAS_PADDING * 2) as usize {
+            return Err(Error::from_data_error_string(format!("Character {:?} ({}x{}) is too large for the font atlas", glyph.character, glyph.width, glyph.height)))
+        }
+
+        let (width, height) = (glyph.width as u32, glyph.height as u32);
+        if x + width + ATLAS_PADDING > ATLAS_WIDTH {
+            x = ATLAS_PADDING;
+            y += row_height + ATLAS_PADDING;
+            row_height = 0;
+        }
+        if y + height + ATLAS_PADDING > ATLAS_MAX_HEIGHT {
+            page_heights.push(y + row_height);
+            x = ATLAS_PADDING;
+            y = ATLAS_PADDING;
+            row_height = 0;
+        }
+
+        positions[i] = AtlasPosition { page: page_heights.len(), x, y };
+        x += width + ATLAS_PADDING;
+        row_height = row_height.max(height);
+    }
+
+    page_heights.push(y + row_height + ATLAS_PADDING);
+
+    Ok((positions, page_heights))
+}
+
 pub struct FontCharacter {
     pub character: char,
     pub data: Vec<u8>,
     pub width: usize,
     pub height: usize,
-    pub advance_x: i32
+    pub advance_x: i32,
+
+    /// Location of the character in the atlas (left, top, right, bottom)
+    pub texture_rect: [f32; 4],
+
+    pub vulkan: VulkanCharacterData
+}
+
+impl FontCharacter {
+    /// Generate a hollow box that roughly fills a line.
+    fn notdef(line_height: u32) -> AddFontParameterCharacter {
+        let height = (line_height as usize).max(3);
+        let width = (height / 2).max(3);
+
+        let mut data = vec![0u8; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    data[x + y * width] = 0xFF;
+                }
+            }
+        }
+
+        AddFontParameterCharacter {
+            character: '\u{FFFD}',
+            data,
+            width,
+            height,
+            advance_x: width as i32 + 1,
+            kerning: Vec::new()
+        }
+    }
+
+    /// Generate a small white square.
+    fn solid() -> AddFontParameterCharacter {
+        AddFontParameterCharacter {
+            character: '\u{2588}',
+            data: vec![0xFF; 3 * 3],
+            width: 3,
+            height: 3,
+            advance_x: 3,
+            kerning: Vec::new()
+        }
+    }
 }
 
 #[derive(Default, Copy, Clone, PartialEq)]
@@ -60,10 +256,14 @@ pub struct FontDrawRequest {
 }
 
 impl Font {
-    pub fn generate_string_draws(&self, string: &str, request: FontDrawRequest, characters: &mut Vec<DrawableCharacter>) {
+    /// Generate draws for `string`.
+    ///
+    /// Characters missing from this font are looked up in `fallbacks` in order, and if no font has them, a `.notdef`
+    /// box is drawn instead.
+    pub fn generate_string_draws(&self, fallbacks: &[&Font], string: &str, request: FontDrawRequest, characters: &mut Vec<DrawableCharacter>) {
         characters.clear();
         characters.reserve(string.len());
-        characters.extend(self.iterate_characters(string, request.color, TextState {
+        characters.extend(self.iterate_characters(fallbacks, string, request.color, TextState {
             alignment: request.alignment,
             ..Default::default()
         }));
@@ -72,17 +272,75 @@ impl Font {
         let mut current_line_range = 1..usize::MAX;
         for i in 0..characters.len() {
             if !current_line_range.contains(&i) {
-                self.handle_new_line(request, &characters, &mut current_line_range, &mut offset_x, i);
+                self.handle_new_line(fallbacks, request, &characters, &mut current_line_range, &mut offset_x, i);
             }
 
+            let advance_x = self.get_advance(fallbacks, &characters, i, current_line_range.end);
             let character = &mut characters[i];
             character.x = offset_x;
             character.y = character.state.y as i32;
-            offset_x += self.characters[&character.character].advance_x;
+            offset_x += advance_x;
+        }
+    }
+
+    /// Get the size of the bitmap needed to fit `string`, including the drop shadow, without rasterizing it.
+    ///
+    /// This uses the same layout as [`Font::generate_string_draws`].
+    pub fn measure_string(&self, fallbacks: &[&Font], string: &str, request: FontDrawRequest) -> (u32, u32) {
+        let mut characters = Vec::new();
+        self.generate_string_draws(fallbacks, string, request, &mut characters);
+
+        let mut left = i32::MAX;
+        let mut right = i32::MIN;
+        let mut bottom = 0i32;
+
+        for character in &characters {
+            let (width, height) = self.get_drawn_size(fallbacks, character);
+            left = left.min(character.x);
+            right = right.max(character.x + width);
+            bottom = bottom.max(character.y + height);
+        }
+
+        if characters.is_empty() {
+            return (0, 0)
+        }
+
+        // +1 for the drop shadow
+        let width = (right - left).max(0) as u32 + 1;
+        let height = bottom.max(0) as u32 + 1;
+        (width, height)
+    }
+
+    fn get_drawn_size(&self, fallbacks: &[&Font], character: &DrawableCharacter) -> (i32, i32) {
+        let character_data = self.get_character(fallbacks, character);
+        let state = character.state;
+
+        let bold_offset = self.get_bold_offset(state);
+        let mut width = character_data.width as i32 + bold_offset + self.get_italics_shear(state, character_data.height, 0);
+        let mut height = character_data.height as i32;
+
+        if state.underline {
+            width = width.max(character_data.advance_x + bold_offset);
+            height = height.max(self.line_height as i32);
+        }
+
+        (width, height)
+    }
+
+    fn get_bold_offset(&self, state: TextState) -> i32 {
+        if state.bold { (self.line_height / BOLD_DIVISOR).max(1) as i32 } else { 0 }
+    }
+
+    fn get_italics_shear(&self, state: TextState, height: usize, y: usize) -> i32 {
+        if state.italics && height > 0 {
+            ((height - 1 - y) as f32 * ITALICS_SLOPE).round() as i32
+        }
+        else {
+            0
         }
     }
 
-    pub fn draw_string_buffer_to_bitmap(&self, characters: &[DrawableCharacter], request: FontDrawRequest) -> AddBitmapParameter {
+    pub fn draw_string_buffer_to_bitmap(&self, fallbacks: &[&Font], characters: &[DrawableCharacter], request: FontDrawRequest) -> AddBitmapParameter {
         let Some(pixel_count) = request.resolution.width.checked_mul(request.resolution.height) else {
             panic!("width * height overflows")
         };
@@ -91,6 +349,7 @@ impl Font {
         for character in characters {
             // Draw the drop shadow
             self.draw_character(
+                fallbacks,
                 request,
                 bitmap_data.as_mut_slice(),
                 character,
@@ -101,6 +360,7 @@ impl Font {
 
             // Now the actual color
             self.draw_character(
+                fallbacks,
                 request,
                 bitmap_data.as_mut_slice(),
                 character,
@@ -116,22 +376,68 @@ impl Font {
             Vec::from_raw_parts(v_clone.as_mut_ptr() as *mut u8, v_clone.len() * 4, v_clone.capacity())
         };
 
-        let bitmap = AddBitmapBitmapParameter {
-            format: BitmapFormat::A8B8G8R8,
-            bitmap_type: BitmapType::Dim2D,
-            resolution: request.resolution,
-            mipmap_count: 0,
-            data: destruction_9000
-        };
+        let Resolution { width, height } = request.resolution;
+        AddBitmapParameter::from_rgba8(width, height, destruction_9000)
+            .expect("the buffer is always width * height pixels")
+    }
+
+    /// Generate textured quads for drawing `characters` with the glyph atlas.
+    ///
+    /// This draws the same thing as [`Font::draw_string_buffer_to_bitmap`] without rasterizing anything on the CPU.
+    pub fn generate_string_quads(&self, fallbacks: &[&Font], characters: &[DrawableCharacter], quads: &mut Vec<CharacterQuad>) {
+        quads.clear();
+        quads.reserve(characters.len() * 2);
+        for character in characters {
+            // Draw the drop shadow
+            self.generate_character_quads(
+                fallbacks,
+                quads,
+                character,
+                [0.0, 0.0, 0.0, character.color[3]],
+                character.x + 1,
+                character.y + 1
+            );
 
-        AddBitmapParameter {
-            bitmaps: vec![bitmap],
-            sequences: vec![AddBitmapSequenceParameter::Bitmap { first: 0, count: 1 }]
+            // Now the actual color
+            self.generate_character_quads(
+                fallbacks,
+                quads,
+                character,
+                character.color,
+                character.x,
+                character.y
+            );
         }
     }
 
+    fn get_character<'a>(&'a self, fallbacks: &[&'a Font], character: &DrawableCharacter) -> &'a FontCharacter {
+        match character.font_index {
+            Some(0) => &self.characters[&character.character],
+            Some(n) => &fallbacks[n - 1].characters[&character.character],
+            None => &self.notdef
+        }
+    }
+
+    fn get_advance(&self, fallbacks: &[&Font], characters: &[DrawableCharacter], i: usize, line_end: usize) -> i32 {
+        let character = &characters[i];
+        let advance_x = self.get_character(fallbacks, character).advance_x;
+
+        let Some(next) = characters.get(i + 1).filter(|n| i + 1 < line_end && n.font_index == character.font_index) else {
+            return advance_x
+        };
+
+        let kerning = match character.font_index {
+            Some(0) => &self.kerning,
+            Some(n) => &fallbacks[n - 1].kerning,
+            None => return advance_x
+        };
+
+        kerned_advance(kerning, advance_x, character.character, next.character)
+    }
+
     fn draw_character(
         &self,
+        fallbacks: &[&Font],
         request: FontDrawRequest,
         bitmap_data: &mut [[u8; 4]],
         character: &DrawableCharacter,
@@ -139,58 +445,92 @@ impl Font {
         x_offset: i32,
         y_offset: i32,
     ) {
-        let character_data = &self.characters[&character.character];
+        let character_data = self.get_character(fallbacks, character);
+        let state = character.state;
 
-        for x in 0..character_data.width {
-            let x_offset = x_offset + x as i32;
-            if x_offset < 0 {
-                continue;
-            }
-            let x_offset = x_offset as usize;
-            if x_offset >= request.resolution.width as usize {
-                break;
-            }
-            for y in 0..character_data.height {
-                let y_offset = y_offset + y as i32;
-                if y_offset < 0 {
-                    continue;
-                }
-                let y_offset = y_offset as usize;
-                if y_offset >= request.resolution.height as usize {
-                    break;
-                }
+        // Bold is done by drawing the glyph again, shifted to the right.
+        let bold_offset = self.get_bold_offset(state);
+
+        for y in 0..character_data.height {
+            // Italics shear each row to the right, pivoting at the bottom of the glyph.
+            let shear = self.get_italics_shear(state, character_data.height, y);
 
+            for x in 0..character_data.width {
                 let alpha = character_data.data[x + y * character_data.width] as f32 / 255.0;
                 if alpha == 0.0 {
                     continue;
                 }
 
-                let mut color = color;
-                color[3] *= alpha;
-
-                let modified_pixel = &mut bitmap_data[x_offset + y_offset * request.resolution.width as usize];
-                let original_pixel = Vec4::from([
-                    modified_pixel[0] as f32 / 255.0,
-                    modified_pixel[1] as f32 / 255.0,
-                    modified_pixel[2] as f32 / 255.0,
-                    modified_pixel[3] as f32 / 255.0
-                ]);
-                let new_pixel = Vec4::from(color);
-
-                let result = original_pixel.lerp(new_pixel, color[3]).to_array();
-
-                *modified_pixel = [
-                    (result[0] * 255.0) as u8,
-                    (result[1] * 255.0) as u8,
-                    (result[2] * 255.0) as u8,
-                    (result[3] * 255.0) as u8,
-                ];
+                for bold in 0..=bold_offset {
+                    blend_pixel(request, bitmap_data, color, alpha, x_offset + x as i32 + shear + bold, y_offset + y as i32);
+                }
+            }
+        }
+
+        if state.underline {
+            let thickness = (self.line_height / UNDERLINE_DIVISOR).max(1) as i32;
+            let underline_y = y_offset + self.line_height as i32 - thickness;
+            for y in underline_y..underline_y + thickness {
+                for x in x_offset..x_offset + character_data.advance_x + bold_offset {
+                    blend_pixel(request, bitmap_data, color, 1.0, x, y);
+                }
+            }
+        }
+    }
+
+    fn generate_character_quads(
+        &self,
+        fallbacks: &[&Font],
+        quads: &mut Vec<CharacterQuad>,
+        character: &DrawableCharacter,
+        color: FloatColor,
+        x_offset: i32,
+        y_offset: i32,
+    ) {
+        let character_data = self.get_character(fallbacks, character);
+        let state = character.state;
+
+        // Bold is done by drawing the glyph again, shifted to the right.
+        let bold_offset = self.get_bold_offset(state);
+
+        // Italics shear the glyph to the right, pivoting at the bottom.
+        let shear = if state.italics { character_data.height as f32 * ITALICS_SLOPE } else { 0.0 };
+
+        if character_data.width > 0 && character_data.height > 0 {
+            for bold in 0..=bold_offset {
+                let left = (x_offset + bold) as f32;
+                let top = y_offset as f32;
+                quads.push(CharacterQuad {
+                    rect: [left, top, left + character_data.width as f32, top + character_data.height as f32],
+                    shear,
+                    texture_rect: character_data.texture_rect,
+                    color,
+                    vulkan: character_data.vulkan.clone()
+                });
             }
         }
+
+        if state.underline {
+            let thickness = (self.line_height / UNDERLINE_DIVISOR).max(1) as i32;
+            let underline_y = y_offset + self.line_height as i32 - thickness;
+            quads.push(CharacterQuad {
+                rect: [
+                    x_offset as f32,
+                    underline_y as f32,
+                    (x_offset + character_data.advance_x + bold_offset) as f32,
+                    (underline_y + thickness) as f32
+                ],
+                shear: 0.0,
+                texture_rect: self.solid.texture_rect,
+                color,
+                vulkan: self.solid.vulkan.clone()
+            });
+        }
     }
 
     fn handle_new_line(
         &self,
+        fallbacks: &[&Font],
         request: FontDrawRequest,
         characters: &[DrawableCharacter],
         current_line_range:
@@ -216,7 +556,7 @@ impl Font {
             TextAlignment::Right | TextAlignment::Center => {
                 let mut total_width = 0i32;
                 for i in current_line_range.clone() {
-                    total_width += self.characters[&characters[i].character].advance_x
+                    total_width += self.get_advance(fallbacks, characters, i, end)
                 }
 
                 let offset = (request.resolution.width as i32) - (total_width);
@@ -231,12 +571,14 @@ impl Font {
 
     fn iterate_characters<'font, 'string>(
         &'font self,
+        fallbacks: &'font [&'font Font],
         string: &'string str,
         color: FloatColor,
         text_position: TextState,
     ) -> FontCharacterIterator<'font, 'string> {
         FontCharacterIterator {
             font: self,
+            fallbacks,
             string: string.chars(),
             color_code_entry: false,
             pipe_entry: false,
@@ -248,6 +590,42 @@ impl Font {
     }
 }
 
+/// Horizontal offset per pixel of height for italic text.
+const ITALICS_SLOPE: f32 = 0.2;
+
+/// Line height divided by this is the extra width of bold text.
+const BOLD_DIVISOR: u32 = 12;
+
+/// Line height divided by this is the thickness of underlines.
+const UNDERLINE_DIVISOR: u32 = 12;
+
+fn blend_pixel(request: FontDrawRequest, bitmap_data: &mut [[u8; 4]], color: FloatColor, alpha: f32, x: i32, y: i32) {
+    if x < 0 || y < 0 || x >= request.resolution.width as i32 || y >= request.resolution.height as i32 {
+        return
+    }
+
+    let mut color = color;
+    color[3] *= alpha;
+
+    let modified_pixel = &mut bitmap_data[x as usize + y as usize * request.resolution.width as usize];
+    let original_pixel = Vec4::from([
+        modified_pixel[0] as f32 / 255.0,
+        modified_pixel[1] as f32 / 255.0,
+        modified_pixel[2] as f32 / 255.0,
+        modified_pixel[3] as f32 / 255.0
+    ]);
+    let new_pixel = Vec4::from(color);
+
+    let result = original_pixel.lerp(new_pixel, color[3]).to_array();
+
+    *modified_pixel = [
+        (result[0] * 255.0) as u8,
+        (result[1] * 255.0) as u8,
+        (result[2] * 255.0) as u8,
+        (result[3] * 255.0) as u8,
+    ];
+}
+
 #[derive(Default, Copy, Clone, Debug)]
 pub struct TextState {
     pub alignment: TextAlignment,
@@ -269,6 +647,7 @@ pub enum TextAlignment {
 
 struct FontCharacterIterator<'font, 'string> {
     font: &'font Font,
+    fallbacks: &'font [&'font Font],
     string: Chars<'string>,
     color_code_entry: bool,
     pipe_entry: bool,
@@ -278,8 +657,28 @@ struct FontCharacterIterator<'font, 'string> {
     text_state: TextState,
 }
 
+/// A textured quad of a character, in pixels relative to the top-left of the text.
+#[derive(Clone)]
+pub struct CharacterQuad {
+    /// Left, top, right, bottom
+    pub rect: [f32; 4],
+
+    /// Horizontal offset of the top edge from the bottom edge, used for italics.
+    pub shear: f32,
+
+    /// Left, top, right, bottom
+    pub texture_rect: [f32; 4],
+
+    pub color: FloatColor,
+    pub vulkan: VulkanCharacterData
+}
+
 pub struct DrawableCharacter {
     pub character: char,
+
+    /// Index of the font containing the character (0 = primary font, 1+ = fallbacks), or `None` if `.notdef`
+    pub font_index: Option<usize>,
+
     pub color: FloatColor,
     pub state: TextState,
     pub alignment_changed: bool,
@@ -294,7 +693,7 @@ impl<'font, 'string> Iterator for FontCharacterIterator<'font, 'string> {
     fn next(&mut self) -> Option<Self::Item> {
         let mut alignment_changed = false;
 
-        let character = loop {
+        let (character, font_index) = loop {
             let next = self.string.next()?;
             if next.is_ascii_control() {
                 self.color_code_entry = false;
@@ -360,15 +759,16 @@ impl<'font, 'string> Iterator for FontCharacterIterator<'font, 'string> {
                 continue
             }
 
-            let Some(as_char) = self.font.characters.get(&next) else {
-                continue;
-            };
+            let font_index = core::iter::once(self.font)
+                .chain(self.fallbacks.iter().copied())
+                .position(|f| f.characters.contains_key(&next));
 
-            break as_char;
+            break (next, font_index);
         };
 
         Some(DrawableCharacter {
-            character: character.character,
+            character,
+            font_index,
             color: self.modified_color,
             state: self.text_state,
             alignment_changed,
@@ -386,3 +786,78 @@ impl<'font, 'string> FontCharacterIterator<'font, 'string> {
 }
 
 impl<'font, 'string> FusedIterator for FontCharacterIterator<'font, 'string> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::RendererParameters;
+
+    fn make_character(character: char, advance_x: i32, kerning: Vec<(char, i32)>) -> AddFontParameterCharacter {
+        AddFontParameterCharacter {
+            character,
+            data: vec![0xFF; 4 * 8],
+            width: 4,
+            height: 8,
+            advance_x,
+            kerning
+        }
+    }
+
+    /// Load a font with 'A' kerned against 'V', or `None` if no Vulkan device is available.
+    fn load_kerned_font() -> Option<Font> {
+        let Ok(mut renderer) = Renderer::new_headless(RendererParameters::default()) else {
+            return None
+        };
+
+        let parameter = AddFontParameter {
+            characters: vec![
+                make_character('A', 10, vec![('V', -2)]),
+                make_character('V', 9, Vec::new())
+            ],
+            line_height: 8
+        };
+        Some(Font::load_from_parameters(&mut renderer, parameter).unwrap())
+    }
+
+    /// Lay out `string` with [`Font::generate_string_draws`], returning the x of each character.
+    fn layout(font: &Font, string: &str) -> Vec<i32> {
+        let mut characters = Vec::new();
+        font.generate_string_draws(&[], string, FontDrawRequest::default(), &mut characters);
+        characters.iter().map(|c| c.x).collect()
+    }
+
+    #[test]
+    fn kerning_pair_tightens_av() {
+        let Some(font) = load_kerned_font() else { return };
+        assert_eq!(layout(&font, "AV"), [0, 8]);
+    }
+
+    #[test]
+    fn kerning_only_applies_to_its_pair() {
+        let Some(font) = load_kerned_font() else { return };
+        assert_eq!(layout(&font, "VA"), [0, 9]);
+        assert_eq!(layout(&font, "AA"), [0, 10]);
+        assert_eq!(layout(&font, "AVA"), [0, 8, 17]);
+    }
+
+    #[test]
+    fn kerning_does_not_apply_across_lines() {
+        let Some(font) = load_kerned_font() else { return };
+        assert_eq!(layout(&font, "A\nV"), [0, 0]);
+    }
+
+    #[test]
+    fn collect_kerning_keys_by_following_character() {
+        let characters = [
+            make_character('A', 10, vec![('V', -2), ('T', -1)]),
+            make_character('T', 8, vec![('A', -1)])
+        ];
+
+        let kerning = collect_kerning(&characters);
+        assert_eq!(kerning.len(), 3);
+        assert_eq!(kerning[&('A', 'V')], -2);
+        assert_eq!(kerning[&('A', 'T')], -1);
+        assert_eq!(kerning[&('T', 'A')], -1);
+        assert!(!kerning.contains_key(&('V', 'A')));
+    }
+}
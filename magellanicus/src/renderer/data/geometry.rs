@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use glam::{Mat4, Vec3};
+use crate::error::MResult;
 use crate::renderer::vulkan::VulkanMaterialData;
+use crate::renderer::{AddGeometryParameter, AddGeometryParameterNode, Renderer};
 use crate::vertex::{ModelVertex, VertexOffsets};
 
 #[derive(Copy, Clone, Debug)]
@@ -37,9 +40,108 @@ impl<T: Sized + 'static> GeometryDetailData<T> {
 pub struct Geometry {
     pub nodes: HashMap<Arc<String>, GeometryNode>,
     pub geometries: Vec<GeometryGeometry>,
+    pub regions: Vec<GeometryRegion>,
     pub cutoff: GeometryDetailData<f32>,
     pub base_uv: [f32; 2],
     pub vulkan: VulkanMaterialData,
+
+    /// Distance from the origin to the furthest vertex; used for selecting a detail level.
+    pub bounding_radius: f32
+}
+
+impl Geometry {
+    pub fn load_from_parameters(renderer: &mut Renderer, add_geometry_parameter: AddGeometryParameter) -> MResult<Self> {
+        let mut nodes = HashMap::new();
+        for node in &add_geometry_parameter.nodes {
+            GeometryNode::insert_from_parameters(&mut nodes, node);
+        }
+
+        let mut vertex_offset = 0i32;
+        let mut index_offset = 0u32;
+
+        let bounding_radius = add_geometry_parameter
+            .geometries
+            .iter()
+            .map(|g| g.parts.iter())
+            .flatten()
+            .map(|p| p.vertices.iter())
+            .flatten()
+            .map(|v| Vec3::from(v.vertex_data.position).length())
+            .fold(0.0f32, f32::max);
+
+        let regions = add_geometry_parameter.regions.iter().map(|r| GeometryRegion {
+            name: Arc::new(r.name.clone()),
+            cannot_be_chosen_randomly: r.cannot_be_chosen_randomly,
+            geometry_indices: r.geometry_indices
+        }).collect();
+
+        let geometries = add_geometry_parameter.geometries.iter().map(|geometry| {
+            GeometryGeometry {
+                parts: geometry.parts.iter().map(|part| {
+                    let index_count = part.indices.len() as u32;
+                    let result = GeometryPart {
+                        shader: renderer.shaders.get_key_value(&part.shader).unwrap().0.clone(),
+                        offsets: VertexOffsets {
+                            index_count,
+                            vertex_offset,
+                            index_offset
+                        },
+                        centroid: part.centroid,
+                        previous_filthy_part_index: part.previous_filthy_part_index,
+                        next_filthy_part_index: part.next_filthy_part_index
+                    };
+
+                    vertex_offset += part.vertices.len() as i32;
+                    index_offset += index_count;
+
+                    result
+                }).collect()
+            }
+        }).collect();
+
+        let vulkan = VulkanMaterialData::new(renderer, &add_geometry_parameter)?;
+
+        Ok(Self {
+            nodes,
+            geometries,
+            regions,
+            cutoff: add_geometry_parameter.cutoff,
+            base_uv: add_geometry_parameter.base_uv,
+            vulkan,
+            bounding_radius
+        })
+    }
+
+    /// Get the indices of the geometries to draw, given the approximate height of the geometry on
+    /// screen in pixels.
+    pub fn get_geometries_for_screen_size(&self, pixels: f32) -> impl Iterator<Item = usize> + '_ {
+        let detail_level = self
+            .cutoff
+            .as_arr()
+            .iter()
+            .rposition(|cutoff| pixels >= **cutoff)
+            .unwrap_or(0);
+
+        let all_geometries = if self.regions.is_empty() { 0..self.geometries.len() } else { 0..0 };
+        self.regions
+            .iter()
+            .map(move |r| *r.geometry_indices.as_arr()[detail_level])
+            .chain(all_geometries)
+    }
+}
+
+/// Describes a geometry to be drawn for the current frame.
+#[derive(Clone, Debug)]
+pub struct GeometryInstance {
+    pub geometry: Arc<String>,
+    pub transform: Mat4
+}
+
+/// Describes a geometry to be drawn many times in one draw for the current frame.
+#[derive(Clone, Debug)]
+pub struct InstancedGeometry {
+    pub geometry: Arc<String>,
+    pub transforms: Vec<Mat4>
 }
 
 #[derive(Clone, Debug)]
@@ -75,6 +177,21 @@ pub struct GeometryNode {
     pub node_distance_from_parent: f32
 }
 
+impl GeometryNode {
+    /// Convert `node` and insert it and all of its children into `nodes`, keyed by name.
+    fn insert_from_parameters(nodes: &mut HashMap<Arc<String>, GeometryNode>, node: &AddGeometryParameterNode) -> GeometryNode {
+        let result = GeometryNode {
+            name: Arc::new(node.name.clone()),
+            children: node.children.iter().map(|c| Self::insert_from_parameters(nodes, c)).collect(),
+            default_translation: node.default_translation,
+            default_rotation: node.default_rotation,
+            node_distance_from_parent: node.node_distance_from_parent
+        };
+        nodes.insert(result.name.clone(), result.clone());
+        result
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GeometryRegion {
     pub name: Arc<String>,
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::types::String32;
+
+/// Hands out shared paths so each path is only allocated once, no matter how many times it is referenced.
+///
+/// Paths that fit in a [`String32`] are looked up without allocating.
+#[derive(Default)]
+pub struct PathInterner {
+    short_paths: HashMap<String32, Arc<String>>,
+    long_paths: HashMap<String, Arc<String>>
+}
+
+impl PathInterner {
+    /// Get the shared path for `path`, allocating it if it has not been interned yet.
+    pub fn intern(&mut self, path: &str) -> Arc<String> {
+        if let Ok(short_path) = String32::try_from(path) {
+            return self.short_paths
+                .entry(short_path)
+                .or_insert_with(|| Arc::new(path.to_owned()))
+                .clone()
+        }
+
+        if let Some(interned) = self.long_paths.get(path) {
+            return interned.clone()
+        }
+
+        let interned = Arc::new(path.to_owned());
+        self.long_paths.insert(path.to_owned(), interned.clone());
+        interned
+    }
+
+    /// Forget `path` if nothing else holds its shared copy.
+    pub fn release(&mut self, path: &str) {
+        if let Ok(short_path) = String32::try_from(path) {
+            if self.short_paths.get(&short_path).is_some_and(|p| Arc::strong_count(p) == 1) {
+                self.short_paths.remove(&short_path);
+            }
+            return
+        }
+
+        if self.long_paths.get(path).is_some_and(|p| Arc::strong_count(p) == 1) {
+            self.long_paths.remove(path);
+        }
+    }
+
+    /// Forget all interned paths.
+    ///
+    /// Paths handed out previously remain valid, but will no longer be shared with new ones.
+    pub fn clear(&mut self) {
+        self.short_paths.clear();
+        self.long_paths.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_only_forgets_unused_paths() {
+        let long_path = "a".repeat(64);
+        for path in ["short", long_path.as_str()] {
+            let mut paths = PathInterner::default();
+
+            let held = paths.intern(path);
+            paths.release(path);
+            assert!(Arc::ptr_eq(&held, &paths.intern(path)));
+
+            let weak = Arc::downgrade(&held);
+            drop(held);
+            paths.release(path);
+            assert!(weak.upgrade().is_none());
+            assert_eq!(paths.intern(path).as_str(), path);
+        }
+    }
+}
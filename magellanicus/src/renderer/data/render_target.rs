@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use crate::error::MResult;
+use crate::renderer::{BitmapFormat, Renderer, Resolution};
+use crate::renderer::data::{Bitmap, BitmapBitmap, BitmapSequence, BitmapType};
+use crate::renderer::vulkan::{VulkanBitmapData, VulkanRenderTarget};
+
+/// Handle to an offscreen render target created with [`Renderer::create_render_target`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderTargetId {
+    pub(crate) path: Arc<String>
+}
+
+impl RenderTargetId {
+    /// Get the path of the bitmap containing the render target's output.
+    ///
+    /// This can be referenced by shaders like any other bitmap, and removed with [`Renderer::remove_bitmap`].
+    pub fn bitmap_path(&self) -> &str {
+        self.path.as_str()
+    }
+}
+
+pub struct RenderTarget {
+    pub resolution: Resolution,
+    pub vulkan: VulkanRenderTarget
+}
+
+impl RenderTarget {
+    pub fn new(renderer: &mut Renderer, resolution: Resolution) -> MResult<Self> {
+        Ok(Self {
+            resolution,
+            vulkan: VulkanRenderTarget::new(&mut renderer.vulkan, resolution)?
+        })
+    }
+
+    /// Get a bitmap that samples the render target's output.
+    pub fn to_bitmap(&self) -> Bitmap {
+        Bitmap {
+            bitmaps: vec![BitmapBitmap {
+                vulkan: VulkanBitmapData { image: self.vulkan.image.clone() },
+                resolution: self.resolution,
+                bitmap_type: BitmapType::Dim2D,
+                format: BitmapFormat::A8B8G8R8
+            }],
+            sequences: vec![BitmapSequence::Bitmap { first: 0, count: 1 }]
+        }
+    }
+}
@@ -4,7 +4,13 @@ use crate::renderer::{AddShaderData, AddShaderParameter, Renderer};
 
 pub struct Shader {
     pub vulkan: VulkanMaterialShaderData,
-    pub shader_type: ShaderType
+    pub shader_type: ShaderType,
+
+    /// Paths of all bitmaps referenced by the shader.
+    pub bitmaps: Vec<String>,
+
+    /// The parameters the shader was loaded from, kept so its material can be rebuilt.
+    pub parameter: AddShaderParameter
 }
 
 impl Shader {
@@ -12,15 +18,20 @@ impl Shader {
         let shader_type = match &add_shader_parameter.data {
             AddShaderData::BasicShader(s) => s.shader_type,
             AddShaderData::ShaderEnvironment(_) => ShaderType::Environment,
-            AddShaderData::ShaderTransparentChicago(_) => ShaderType::TransparentChicago
+            AddShaderData::ShaderModel(_) => ShaderType::Model,
+            AddShaderData::ShaderTransparentChicago(_) => ShaderType::TransparentChicago,
+            AddShaderData::ShaderTransparentGeneric(_) => ShaderType::TransparentGeneric,
+            AddShaderData::ShaderTransparentWater(_) => ShaderType::TransparentWater
         };
 
+        let bitmaps = add_shader_parameter.data.referenced_bitmaps();
+
         let vulkan = VulkanMaterialShaderData::new_from_parameters(
             renderer,
-            add_shader_parameter
+            add_shader_parameter.clone()
         )?;
 
-        Ok(Self { vulkan, shader_type })
+        Ok(Self { vulkan, shader_type, bitmaps, parameter: add_shader_parameter })
     }
 }
 
@@ -7,7 +7,7 @@ pub struct Sky {
     pub indoor_fog: FogData
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct FogData {
     /// Current color in RGB.
     pub color: [f32; 3],
@@ -24,7 +24,26 @@ pub struct FogData {
     /// Maximum opacity of fog (from 0.0 - 1.0).
     ///
     /// At 1.0, the render distance is set to `distance_from`.
-    pub max_opacity: f32
+    pub max_opacity: f32,
+
+    /// How fog density increases with distance.
+    pub mode: FogMode,
+
+    /// Density of fog per world unit past `distance_from` (only used for exponential fog).
+    pub density: f32
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum FogMode {
+    /// Fog increases from `distance_from` to `distance_to`.
+    #[default]
+    Linear,
+
+    /// Fog is `1 - exp(-density * distance)`.
+    Exponential,
+
+    /// Fog is `1 - exp(-(density * distance)^2)`.
+    ExponentialSquared
 }
 
 impl FogData {
@@ -49,6 +68,10 @@ impl FogData {
             return Err(Error::from_data_error_string(format!("Invalid max opacity {}", self.max_opacity)))
         }
 
+        if self.density < 0.0 || !self.density.is_finite() {
+            return Err(Error::from_data_error_string(format!("Invalid density {}", self.density)))
+        }
+
         Ok(())
     }
 
@@ -60,6 +83,7 @@ impl FogData {
         self.distance_to = self.distance_to.clamp(self.distance_from, f32::MAX);
         self.min_opacity = self.min_opacity.clamp(0.0, 1.0);
         self.max_opacity = self.max_opacity.clamp(self.min_opacity, 1.0);
+        self.density = self.density.clamp(0.0, f32::MAX);
     }
 }
 
@@ -70,7 +94,10 @@ impl Default for FogData {
             distance_from: 0.0,
             distance_to: 1.0,
             min_opacity: 0.0,
-            max_opacity: 0.0
+            max_opacity: 0.0,
+            mode: FogMode::Linear,
+            density: 0.0
         }
     }
 }
+
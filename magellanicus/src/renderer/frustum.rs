@@ -0,0 +1,101 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// View frustum planes, extracted from a view-projection matrix.
+///
+/// Each plane is stored as `xyz` = normal (pointing inward) and `w` = distance, normalized such that
+/// `dot(normal, point) + distance` is the signed distance of `point` from the plane.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [Vec4; 6]
+}
+
+impl Frustum {
+    /// Extract the planes from a `proj * view` matrix.
+    ///
+    /// This assumes a depth range of 0 to 1 (as used by Vulkan).
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let row0 = view_projection.row(0);
+        let row1 = view_projection.row(1);
+        let row2 = view_projection.row(2);
+        let row3 = view_projection.row(3);
+
+        let planes = [
+            row3 + row0, // left
+            row3 - row0, // right
+            row3 + row1, // bottom
+            row3 - row1, // top
+            row2,        // near
+            row3 - row2, // far
+        ].map(|plane| {
+            let length = plane.truncate().length();
+            if length > 0.0 {
+                plane / length
+            }
+            else {
+                plane
+            }
+        });
+
+        Self { planes }
+    }
+
+    /// Return `true` if the sphere is at least partially inside the frustum.
+    pub fn contains_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 100.0;
+
+    /// Camera at the origin looking down +Y with +Z up, like the renderer's cameras.
+    fn make_frustum() -> Frustum {
+        let projection = Mat4::perspective_lh(90.0f32.to_radians(), 1.0, NEAR, FAR);
+        let view = Mat4::look_to_lh(Vec3::ZERO, Vec3::Y, Vec3::Z);
+        Frustum::from_view_projection(projection * view)
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for plane in make_frustum().planes {
+            assert!((plane.truncate().length() - 1.0).abs() < 1e-5, "{plane:?}");
+        }
+    }
+
+    #[test]
+    fn near_and_far_planes_give_distances() {
+        let frustum = make_frustum();
+        let point = Vec3::new(0.0, 10.0, 0.0);
+        let [.., near, far] = frustum.planes;
+        assert!((near.truncate().dot(point) + near.w - (10.0 - NEAR)).abs() < 1e-3, "{near:?}");
+        assert!((far.truncate().dot(point) + far.w - (FAR - 10.0)).abs() < 1e-3, "{far:?}");
+    }
+
+    #[test]
+    fn contains_sphere() {
+        let frustum = make_frustum();
+
+        assert!(frustum.contains_sphere(Vec3::new(0.0, 10.0, 0.0), 0.0));
+        assert!(frustum.contains_sphere(Vec3::new(5.0, 10.0, -5.0), 0.0));
+
+        // Behind the camera and past the far plane
+        assert!(!frustum.contains_sphere(Vec3::new(0.0, -10.0, 0.0), 1.0));
+        assert!(!frustum.contains_sphere(Vec3::new(0.0, FAR + 10.0, 0.0), 1.0));
+
+        // Outside each side of the 90 degree field of view
+        assert!(!frustum.contains_sphere(Vec3::new(20.0, 10.0, 0.0), 1.0));
+        assert!(!frustum.contains_sphere(Vec3::new(-20.0, 10.0, 0.0), 1.0));
+        assert!(!frustum.contains_sphere(Vec3::new(0.0, 10.0, 20.0), 1.0));
+        assert!(!frustum.contains_sphere(Vec3::new(0.0, 10.0, -20.0), 1.0));
+
+        // Spheres that are partially inside are kept
+        assert!(frustum.contains_sphere(Vec3::new(0.0, -10.0, 0.0), 15.0));
+        assert!(frustum.contains_sphere(Vec3::new(20.0, 10.0, 0.0), 10.0));
+    }
+}
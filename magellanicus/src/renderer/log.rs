@@ -0,0 +1,30 @@
+use std::sync::RwLock;
+
+/// Severity of a message logged by the renderer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error
+}
+
+/// Function that receives messages logged by the renderer.
+pub type LogCallback = Box<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/// This is set with [`Renderer::set_log_callback`](crate::renderer::Renderer::set_log_callback).
+static LOG_CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+
+pub(crate) fn set_log_callback(callback: Option<LogCallback>) {
+    *LOG_CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = callback;
+}
+
+/// Log a message with the current log callback, or print it if there is none.
+pub(crate) fn log(level: LogLevel, message: &str) {
+    let callback = LOG_CALLBACK.read().unwrap_or_else(|e| e.into_inner());
+    match (callback.as_ref(), level) {
+        (Some(callback), _) => callback(level, message),
+        (None, LogLevel::Info) => println!("{message}"),
+        (None, LogLevel::Warning) => eprintln!("Warning: {message}"),
+        (None, LogLevel::Error) => eprintln!("{message}")
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use crate::error::{Error, MResult};
+use crate::renderer::data::{CharacterQuad, FontDrawRequest, TextAlignment};
+use crate::renderer::Renderer;
+use crate::types::FloatColor;
+
+/// Function called for each player viewport while it is being drawn.
+///
+/// Set this with [`Renderer::set_overlay_callback`](crate::renderer::Renderer::set_overlay_callback).
+pub type OverlayCallback = Box<dyn FnMut(&mut OverlayContext) + Send>;
+
+/// Viewport being drawn and the draws queued over it by an [`OverlayCallback`].
+///
+/// Everything queued here is drawn over the viewport's geometry and debug lines, in the order it was queued, and it is
+/// clipped to the viewport.
+pub struct OverlayContext<'a> {
+    pub(crate) renderer: &'a Renderer,
+    pub(crate) viewport_index: usize,
+    pub(crate) rect: [f32; 4],
+    pub(crate) draws: Vec<OverlayDraw>
+}
+
+pub(crate) enum OverlayDraw {
+    Box { rect: [f32; 4], color: FloatColor },
+    Sprite { bitmap: Arc<String>, bitmap_index: usize, texture_rect: [f32; 4], rect: [f32; 4], color: FloatColor },
+    Text { quads: Vec<CharacterQuad> }
+}
+
+impl<'a> OverlayContext<'a> {
+    pub(crate) fn new(renderer: &'a Renderer, viewport_index: usize, rect: [f32; 4]) -> Self {
+        Self { renderer, viewport_index, rect, draws: Vec::new() }
+    }
+
+    /// Get the index of the viewport being drawn.
+    pub fn viewport_index(&self) -> usize {
+        self.viewport_index
+    }
+
+    /// Get the x, y, width, and height of the viewport in pixels at the render resolution.
+    pub fn rect(&self) -> [f32; 4] {
+        self.rect
+    }
+
+    /// Draw a solid box.
+    ///
+    /// `rect` is the x, y, width, and height of the box relative to the viewport, like the `rect` of
+    /// [`Renderer::draw_sprite`].
+    pub fn draw_box(&mut self, rect: [f32; 4], color: FloatColor) {
+        self.draws.push(OverlayDraw::Box { rect, color });
+    }
+
+    /// Draw a sprite, like [`Renderer::draw_sprite`].
+    ///
+    /// Returns `Err` if `path` refers to a bitmap that isn't loaded, or if the sequence or frame is invalid.
+    pub fn draw_sprite(&mut self, path: &str, sequence_index: usize, frame_index: usize, rect: [f32; 4], color: FloatColor) -> MResult<()> {
+        let (bitmap, bitmap_index, texture_rect) = self.renderer.get_sprite_frame(path, sequence_index, frame_index)?;
+        self.draws.push(OverlayDraw::Sprite { bitmap, bitmap_index, texture_rect, rect, color });
+        Ok(())
+    }
+
+    /// Draw left-aligned text with a loaded font.
+    ///
+    /// `position` is the top-left corner of the text in pixels relative to the top-left corner of the viewport. Text
+    /// is drawn at the font's native size, and color codes such as `^1` are supported.
+    ///
+    /// Returns `Err` if `font` is not loaded.
+    pub fn draw_text(&mut self, font: &str, text: &str, position: [f32; 2], color: FloatColor) -> MResult<()> {
+        let Some(font) = self.renderer.fonts.get(&font.to_owned()) else {
+            return Err(Error::from_data_error_string(format!("Can't draw text with font {font}: that font is not loaded")))
+        };
+
+        let request = FontDrawRequest {
+            alignment: TextAlignment::Left,
+            color,
+            ..FontDrawRequest::default()
+        };
+
+        let mut characters = Vec::new();
+        let mut quads = Vec::new();
+        font.generate_string_draws(&[], text, request, &mut characters);
+        font.generate_string_quads(&[], &characters, &mut quads);
+
+        let [x, y] = position;
+        for quad in &mut quads {
+            let [left, top, right, bottom] = quad.rect;
+            quad.rect = [left + x, top + y, right + x, bottom + y];
+        }
+
+        self.draws.push(OverlayDraw::Text { quads });
+        Ok(())
+    }
+}
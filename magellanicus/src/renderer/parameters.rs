@@ -12,6 +12,9 @@ pub use bsp::*;
 pub use sky::*;
 pub use font::*;
 
+use crate::error::{Error, MResult};
+use crate::types::FloatColor;
+
 /// Used for initializing a renderer.
 ///
 /// These fields can be changed later with their respective set_* methods.
@@ -24,22 +27,324 @@ pub struct RendererParameters {
     // TODO: Separate number_of_viewports from this?
     /// Number of viewports (must be 1-4)
     ///
+    /// These use the standard split screen layouts. Use `Renderer::set_viewport_layout` for other layouts.
+    ///
     /// Default = 1
     pub number_of_viewports: usize,
 
     /// Enable vSync.
     ///
+    /// This is ignored if `present_mode` is set.
+    ///
     /// Default = false
     pub vsync: bool,
 
+    /// How frames are presented to the window, falling back to other modes if the surface doesn't support it.
+    ///
+    /// If `None`, this is determined by `vsync`.
+    ///
+    /// Default = None
+    pub present_mode: Option<PresentModePreference>,
+
+    /// Render and present in HDR if the surface supports it.
+    ///
+    /// This renders to a 16-bit float image and presents with an extended sRGB (scRGB) color space. If the surface
+    /// does not support this, SDR is used instead. If HDR is used, [`srgb_correct`](Self::srgb_correct) is enabled, as
+    /// the extended sRGB color space is linear.
+    ///
+    /// Default = false
+    pub hdr: bool,
+
+    /// Treat color maps as sRGB and blend in linear space.
+    ///
+    /// Color maps are linearized when sampled, and the frame is gamma-corrected when written to an sRGB image. This
+    /// changes how fog, vertex colors, and blending look, as these are then treated as linear. If the surface has no
+    /// sRGB format, this is disabled.
+    ///
+    /// Default = false
+    pub srgb_correct: bool,
+
     /// Number of samples per pixel.
     pub msaa: MSAA,
 
+    /// Smooth edges with FXAA after the frame is drawn.
+    ///
+    /// This is cheaper than MSAA and also smooths aliasing from shaders, but it is blurrier. It can be used with or
+    /// without MSAA, and requires dynamic rendering support.
+    ///
+    /// Default = false
+    pub fxaa: bool,
+
+    /// Quality settings for FXAA, if enabled.
+    pub fxaa_quality: FXAAQuality,
+
     /// Anisotropic filtering.
     pub anisotropic_filtering: Option<f32>,
 
     /// Render scaling
     pub render_scale: f32,
+
+    /// Automatically adjust the render scale to keep the frame time near a target.
+    ///
+    /// This overrides `render_scale` after the first second of rendering.
+    ///
+    /// Default = None
+    pub dynamic_resolution: Option<DynamicResScale>,
+
+    /// Filter used when scaling the rendered frame to the window.
+    ///
+    /// Default = [`UpscaleFilter::Linear`]
+    pub upscale_filter: UpscaleFilter,
+
+    /// Abort the process when the validation layers report an error.
+    ///
+    /// This can be useful for debugging, as the backtrace is printed before aborting. This does nothing unless
+    /// [`enable_validation`](Self::enable_validation) is also set.
+    ///
+    /// Default = false
+    pub abort_on_validation_error: bool,
+
+    /// Enable the Vulkan validation layers, sending their messages to the log callback.
+    ///
+    /// This requires the `VK_LAYER_KHRONOS_validation` layer to be installed, and it makes rendering much slower.
+    ///
+    /// Default = false
+    pub enable_validation: bool,
+
+    /// Draw opaque BSP geometry to the depth buffer before shading it.
+    ///
+    /// This reduces overdraw of expensive shaders at the cost of drawing the geometry twice.
+    ///
+    /// Default = false
+    pub depth_prepass: bool,
+
+    /// Measure GPU frame time with timestamp queries.
+    ///
+    /// This is ignored if the GPU does not support timestamps. See [`Renderer::last_frame_timings`](crate::renderer::Renderer::last_frame_timings).
+    ///
+    /// Default = false
+    pub gpu_frame_timings: bool,
+
+    /// Determines what is drawn in place of missing maps that would otherwise default to white.
+    ///
+    /// Default = [`MissingTextureMode::Neutral`]
+    pub missing_texture_mode: MissingTextureMode,
+
+    /// GPU to render with.
+    ///
+    /// If `None`, the GPU is selected as with [`DeviceSelection::PreferDiscrete`].
+    ///
+    /// Default = None
+    pub preferred_device: Option<DeviceSelection>,
+
+    /// Approximate time in seconds for fog to transition when moving between indoor and outdoor areas.
+    ///
+    /// If 0, fog changes instantly.
+    ///
+    /// Default = 1.0
+    pub fog_transition_seconds: f32,
+
+    /// Number of primary command buffers allocated at a time.
+    ///
+    /// More are allocated as needed, so this only affects how often that happens.
+    ///
+    /// Default = 32
+    pub command_buffer_pool_size: u32,
+
+    /// Number of descriptor sets each descriptor pool can hold.
+    ///
+    /// A new pool is made whenever the current one is full, so large maps with thousands of shaders and lightmaps
+    /// end up with several pools. Raising this means fewer, larger pools, while lowering it saves memory for small
+    /// tools. If descriptor set allocation still fails (the error will mention descriptor pools or
+    /// `OutOfPoolMemory`), the GPU has run out of memory for descriptors and fewer assets need to be loaded.
+    ///
+    /// Default = 16384
+    pub descriptor_pool_size: u32,
+
+    /// Style of the bars drawn between split-screen viewports.
+    ///
+    /// If `None`, no bars are drawn.
+    ///
+    /// Default = `Some(SplitBarStyle::default())`
+    pub split_screen_bar: Option<SplitBarStyle>,
+
+    /// Give the depth image an 8-bit stencil buffer for masking sprites.
+    ///
+    /// This uses more memory per pixel. See [`Renderer::draw_stencil_mask`](crate::renderer::Renderer::draw_stencil_mask).
+    ///
+    /// Default = false
+    pub stencil: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceSelection {
+    /// Use the GPU at the given index of [`Renderer::list_physical_devices`](crate::renderer::Renderer::list_physical_devices).
+    Index(usize),
+
+    /// Use the first GPU whose name contains the given string.
+    Name(String),
+
+    /// Use the most capable suitable GPU, preferring discrete GPUs over integrated ones.
+    PreferDiscrete
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MissingTextureMode {
+    /// Use a neutral white texture.
+    #[default]
+    Neutral,
+
+    /// Use a magenta and black checkerboard so missing maps are easy to spot.
+    Checkerboard
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DynamicResScale {
+    /// Frame time to aim for in milliseconds.
+    ///
+    /// With vSync enabled, this should not be lower than the refresh interval, or the scale will only ever decrease.
+    pub target_ms: f32,
+
+    /// Lowest render scale that can be used.
+    pub min_scale: f32,
+
+    /// Highest render scale that can be used.
+    pub max_scale: f32
+}
+
+impl DynamicResScale {
+    pub(crate) fn validate(&self) -> MResult<()> {
+        if !(self.target_ms > 0.0 && self.target_ms.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Dynamic resolution target {} ms is not a positive number", self.target_ms)))
+        }
+        if !(self.min_scale > 0.0 && self.min_scale <= self.max_scale && self.max_scale.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Dynamic resolution scale range {}-{} is invalid", self.min_scale, self.max_scale)))
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplitBarStyle {
+    /// Color of the bars, which are alpha blended.
+    ///
+    /// Default = opaque black
+    pub color: FloatColor,
+
+    /// Thickness of the bars in pixels at 640x480.
+    ///
+    /// This is scaled up with the resolution.
+    ///
+    /// Default = 2.0
+    pub thickness: f32
+}
+
+impl Default for SplitBarStyle {
+    fn default() -> Self {
+        Self {
+            color: [0.0, 0.0, 0.0, 1.0],
+            thickness: 2.0
+        }
+    }
+}
+
+impl SplitBarStyle {
+    pub(crate) fn validate(&self) -> MResult<()> {
+        if !(self.thickness >= 0.0 && self.thickness.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Split-screen bar thickness {} is not a finite, non-negative number", self.thickness)))
+        }
+        if self.color.iter().any(|c| !c.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Split-screen bar color {:?} is not finite", self.color)))
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FXAAQuality {
+    /// Amount of sub-pixel aliasing removal, from 0.0 (off, sharpest) to 1.0 (softest).
+    ///
+    /// Default = 0.75
+    pub subpixel: f32,
+
+    /// Minimum local contrast, relative to the brightest nearby pixel, for a pixel to be considered an edge.
+    ///
+    /// Lower values smooth more edges but are slower. Default = 0.166
+    pub edge_threshold: f32,
+
+    /// Minimum local contrast for a pixel to be considered an edge, which prevents dark areas from being smoothed.
+    ///
+    /// Default = 0.0833
+    pub edge_threshold_min: f32
+}
+
+impl Default for FXAAQuality {
+    fn default() -> Self {
+        Self {
+            subpixel: 0.75,
+            edge_threshold: 0.166,
+            edge_threshold_min: 0.0833
+        }
+    }
+}
+
+impl FXAAQuality {
+    pub(crate) fn validate(&self) -> MResult<()> {
+        if !(0.0..=1.0).contains(&self.subpixel) {
+            return Err(Error::from_data_error_string(format!("FXAA subpixel {} is not between 0 and 1", self.subpixel)))
+        }
+        if !(self.edge_threshold > 0.0 && self.edge_threshold <= 1.0) {
+            return Err(Error::from_data_error_string(format!("FXAA edge threshold {} is not between 0 (exclusive) and 1", self.edge_threshold)))
+        }
+        if !(0.0..=1.0).contains(&self.edge_threshold_min) {
+            return Err(Error::from_data_error_string(format!("FXAA minimum edge threshold {} is not between 0 and 1", self.edge_threshold_min)))
+        }
+        Ok(())
+    }
+}
+
+/// Determines how colors are mapped to the displayable range after exposure is applied.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(u32)]
+pub enum TonemapOperator {
+    /// Leave colors as is, clipping anything too bright.
+    #[default]
+    Passthrough,
+
+    /// Map colors with `c / (1 + c)`, which never clips but desaturates highlights.
+    Reinhard,
+
+    /// Map colors with an approximation of the ACES filmic curve.
+    ACES
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum UpscaleFilter {
+    /// Smoothly interpolate between pixels.
+    #[default]
+    Linear,
+
+    /// Use the nearest pixel, keeping pixels crisp (e.g. for integer scaling).
+    Nearest
+}
+
+/// Determines the present mode of the swapchain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PresentModePreference {
+    /// Present the newest frame at each vertical blank without blocking rendering (`Mailbox`).
+    ///
+    /// This has low latency without tearing. Falls back to [`PresentModePreference::NoTearing`].
+    AutoVsync,
+
+    /// Present frames immediately (`Immediate`), which may tear.
+    ///
+    /// Falls back to `Mailbox`, then `Fifo`.
+    LowLatency,
+
+    /// Present frames in order at each vertical blank (`Fifo`).
+    ///
+    /// This is always supported, but rendering may block until a frame is presented.
+    NoTearing
 }
 
 #[derive(Copy, Clone, PartialEq, Default)]
@@ -60,9 +365,27 @@ impl Default for RendererParameters {
             resolution: Resolution::default(),
             number_of_viewports: 1,
             vsync: false,
+            present_mode: None,
+            hdr: false,
+            srgb_correct: false,
             msaa: Default::default(),
+            fxaa: false,
+            fxaa_quality: Default::default(),
             anisotropic_filtering: None,
-            render_scale: 1.0
+            render_scale: 1.0,
+            dynamic_resolution: None,
+            upscale_filter: UpscaleFilter::Linear,
+            abort_on_validation_error: false,
+            enable_validation: false,
+            depth_prepass: false,
+            gpu_frame_timings: false,
+            missing_texture_mode: MissingTextureMode::Neutral,
+            preferred_device: None,
+            fog_transition_seconds: 1.0,
+            command_buffer_pool_size: 32,
+            descriptor_pool_size: 16 * 1024,
+            split_screen_bar: Some(SplitBarStyle::default()),
+            stencil: false
         }
     }
 }
@@ -78,3 +401,15 @@ impl Default for Resolution {
         Self { width: 640, height: 480 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_match_shader_indices() {
+        assert_eq!(TonemapOperator::Passthrough as u32, 0);
+        assert_eq!(TonemapOperator::Reinhard as u32, 1);
+        assert_eq!(TonemapOperator::ACES as u32, 2);
+    }
+}
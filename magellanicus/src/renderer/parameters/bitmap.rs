@@ -7,10 +7,43 @@ use crate::renderer::Resolution;
 #[derive(Clone)]
 pub struct AddBitmapParameter {
     pub bitmaps: Vec<AddBitmapBitmapParameter>,
-    pub sequences: Vec<AddBitmapSequenceParameter>
+    pub sequences: Vec<AddBitmapSequenceParameter>,
+
+    /// Generate a full mipmap chain on the GPU for any bitmap that has no mipmaps.
+    ///
+    /// This is not supported for block-compressed formats (DXT/BCn).
+    pub generate_mipmaps: bool
 }
 
 impl AddBitmapParameter {
+    /// Create a parameter for a single 2D bitmap with no mipmaps from RGBA8 pixel data.
+    ///
+    /// Returns an error if `data` is not `width * height * 4` bytes long.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> MResult<Self> {
+        let expected_length = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|p| p.checked_mul(4))
+            .ok_or_else(|| Error::from_data_error_string(format!("Bitmap dimensions {width}x{height} are too large")))?;
+
+        if data.len() != expected_length {
+            return Err(Error::from_data_error_string(format!("RGBA8 bitmap ({width}x{height}) has an incorrect number of bytes (expected {expected_length}, got {})", data.len())))
+        }
+
+        Ok(Self {
+            bitmaps: vec![AddBitmapBitmapParameter {
+                format: BitmapFormat::A8B8G8R8,
+                bitmap_type: BitmapType::Dim2D,
+                resolution: Resolution { width, height },
+                mipmap_count: 0,
+                alpha_mode: AlphaMode::Straight,
+                color_key: None,
+                data
+            }],
+            sequences: vec![AddBitmapSequenceParameter::Bitmap { first: 0, count: 1 }],
+            generate_mipmaps: false
+        })
+    }
+
     pub(crate) fn validate(&self) -> MResult<()> {
         if self.sequences.is_empty() {
             return Err(Error::from_data_error_string("Bitmap has no sequences!".to_owned()))
@@ -88,6 +121,18 @@ impl AddBitmapParameter {
                     log_of_highest_dim + 1
                 };
 
+                if bitmap.alpha_mode == AlphaMode::Premultiplied && !bitmap.format.supports_unpremultiplying() {
+                    return Some(format!("Bitmap #{bitmap_index} is premultiplied, but {:?} bitmaps can't be un-premultiplied", bitmap.format))
+                }
+
+                if bitmap.color_key.is_some() && bitmap.format.is_block_compressed() {
+                    return Some(format!("Bitmap #{bitmap_index} is block-compressed ({:?}), so it can't use a color key", bitmap.format))
+                }
+
+                if self.generate_mipmaps && reported_mipmap_count == 0 && bitmap.format.is_block_compressed() {
+                    return Some(format!("Bitmap #{bitmap_index} is block-compressed ({:?}), so mipmaps cannot be generated for it", bitmap.format))
+                }
+
                 if reported_mipmap_count > highest_possible_mipmap_count {
                     return Some(format!("Bitmap #{bitmap_index} ({width}x{height}) reports a mipmap count of {reported_mipmap_count}, but the highest mipmap count possible is {highest_possible_mipmap_count}"))
                 }
@@ -96,18 +141,21 @@ impl AddBitmapParameter {
                     width_nz, height_nz, mipmap_type, block_length, Some(reported_mipmap_count as usize)
                 );
 
+                // Sum every face of every mipmap (cubemaps have 6 faces, 3D textures have one per depth level).
                 let bytes_per_block = bitmap.format.block_byte_size();
                 let Some((block_count, bytes_count)) = mipmaps
-                    .map(|b| (b.block_count as u64, b.block_count as u64 * bytes_per_block as u64))
-                    .reduce(|a, b| (a.0 + b.0, a.1 + b.1))
+                    .try_fold((0u64, 0u64), |(blocks, bytes), b| {
+                        let block_count = b.block_count as u64;
+                        Some((blocks.checked_add(block_count)?, bytes.checked_add(block_count.checked_mul(bytes_per_block as u64)?)?))
+                    })
                     .and_then(|(a,b)| Some((usize::try_from(a).ok()?, usize::try_from(b).ok()?)))
                 else {
-                    return Some(format!("Bitmap #{bitmap_index} can't get block count"))
+                    return Some(format!("Bitmap #{bitmap_index} ({width}x{height}) is too large to compute its size"))
                 };
 
                 let actual_length = bitmap.data.len();
                 if bytes_count != actual_length {
-                    return Some(format!("Bitmap #{bitmap_index} ({width}x{height}) has an incorrect number of bytes (expected {bytes_count} ({block_count} * {bytes_per_block}), got {actual_length})"))
+                    return Some(format!("Bitmap #{bitmap_index} ({width}x{height}, {:?}, {:?}, {reported_mipmap_count} mipmap(s)) has an incorrect number of bytes (expected {bytes_count} ({block_count} * {bytes_per_block}), got {actual_length})", bitmap.bitmap_type, bitmap.format))
                 }
 
                 None
@@ -131,14 +179,60 @@ pub struct AddBitmapBitmapParameter {
     pub bitmap_type: BitmapType,
     pub resolution: Resolution,
     pub mipmap_count: u32,
+
+    /// How the color channels of `data` relate to its alpha channel.
+    pub alpha_mode: AlphaMode,
+
+    /// If set, pixels matching this color are made fully transparent when loaded.
+    ///
+    /// This is only supported for formats that are not block-compressed.
+    pub color_key: Option<ColorKey>,
     pub data: Vec<u8>,
 }
 
+/// A color to treat as transparent, for bitmaps that use a color key (e.g. magenta) instead of an alpha channel.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ColorKey {
+    /// Red, green, and blue values of the key color.
+    pub color: [u8; 3],
+
+    /// Maximum difference allowed on each channel for a pixel to still match the key color.
+    pub tolerance: u8
+}
+
+/// Describes whether a bitmap's color channels are already multiplied by its alpha channel.
+///
+/// All of the alpha-blended pipelines (sprites, text, color boxes, water, and transparent generic shaders using alpha
+/// blending) expect straight alpha, so premultiplied bitmaps are un-premultiplied when they are uploaded. Additive
+/// and multiplicative pipelines don't use the alpha channel for blending and are not affected.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum AlphaMode {
+    /// Color channels are independent of the alpha channel.
+    #[default]
+    Straight,
+
+    /// Color channels have been multiplied by the alpha channel.
+    ///
+    /// This is only supported for formats that are not block-compressed and have at least 8 bits per channel.
+    Premultiplied
+}
+
+impl AddBitmapBitmapParameter {
+    /// Get the number of mipmaps (excluding the base level) in a full mipmap chain for this bitmap.
+    pub(crate) fn full_mipmap_count(&self) -> u32 {
+        let Resolution { width, height } = self.resolution;
+        let depth = match self.bitmap_type { BitmapType::Dim3D { depth } => depth, _ => 1 };
+        width.max(height).max(depth).max(1).ilog2()
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum BitmapFormat {
     DXT1,
     DXT3,
     DXT5,
+    BC4,
+    BC5,
     BC7,
     A8R8G8B8,
     X8R8G8B8,
@@ -162,6 +256,8 @@ impl BitmapFormat {
             Self::DXT1 => 4,
             Self::DXT3 => 4,
             Self::DXT5 => 4,
+            Self::BC4 => 4,
+            Self::BC5 => 4,
             Self::BC7 => 4,
             Self::A8R8G8B8 => 1,
             Self::X8R8G8B8 => 1,
@@ -183,6 +279,8 @@ impl BitmapFormat {
             Self::DXT1 => 8,
             Self::DXT3 => 16,
             Self::DXT5 => 16,
+            Self::BC4 => 8,
+            Self::BC5 => 16,
             Self::BC7 => 16,
             Self::R32G32B32A32SFloat => 4*4,
             Self::A8R8G8B8 => 4,
@@ -199,6 +297,32 @@ impl BitmapFormat {
             Self::A8B8G8R8 => 4,
         }
     }
+    pub fn is_block_compressed(self) -> bool {
+        self.block_pixel_length() != 1
+    }
+
+    /// Returns true if bitmaps of this format can be loaded with [`AlphaMode::Premultiplied`].
+    ///
+    /// [`BitmapFormat::X8R8G8B8`] has no alpha channel, so it can't be premultiplied.
+    pub fn supports_unpremultiplying(self) -> bool {
+        match self {
+            Self::A8R8G8B8
+            | Self::A8B8G8R8
+            | Self::A8
+            | Self::Y8
+            | Self::AY8
+            | Self::A8Y8
+            | Self::P8
+            | Self::R32G32B32A32SFloat => true,
+
+            // These are expanded to A8R8G8B8 before un-premultiplying.
+            Self::R5G6B5
+            | Self::A1R5G5B5
+            | Self::A4R4G4B4
+            | Self::B4G4R4A4 => true,
+            _ => false
+        }
+    }
 }
 
 pub fn decode_p8_to_a8r8g8b8le(p8: u8) -> [u8; 4] {
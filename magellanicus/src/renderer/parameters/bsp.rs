@@ -82,6 +82,10 @@ impl AddBSPParameter {
                     }
                 }
 
+                if let Some(index) = material.surfaces.iter().map(|s| s.indices.iter()).flatten().find(|i| **i as usize >= vertex_count) {
+                    return Err(Error::from_data_error_string(format!("BSP material #{material_index} of lightmap #{lightmap_index} has an out-of-bounds index {index} (vertex count is {vertex_count})")))
+                }
+
                 let shader_path = &material.shader;
                 let Some(Shader { shader_type, .. }) = renderer.shaders.get(shader_path) else {
                     return Err(Error::from_data_error_string(format!("BSP material #{material_index} of lightmap #{lightmap_index} references pipeline {shader_path} which is not loaded")))
@@ -107,7 +111,13 @@ pub struct BSPData {
     pub planes: Vec<BSP3DPlane>,
     pub leaves: Vec<BSPLeaf>,
     pub clusters: Vec<BSPCluster>,
-    pub portals: Vec<BSPPortal>
+    pub portals: Vec<BSPPortal>,
+
+    /// Potentially visible set (PVS) for each cluster.
+    ///
+    /// If `Some`, this must contain one entry per cluster, each listing the clusters that can be seen from that
+    /// cluster. If `None`, every cluster is considered visible from every other cluster.
+    pub cluster_pvs: Option<Vec<Vec<usize>>>
 }
 
 impl Default for BSPData {
@@ -117,7 +127,8 @@ impl Default for BSPData {
             planes: vec![BSP3DPlane { angle: [0.0, 1.0, 0.0], offset: 0.0 }],
             leaves: Vec::new(),
             clusters: Vec::new(),
-            portals: Vec::new()
+            portals: Vec::new(),
+            cluster_pvs: None
         }
     }
 }
@@ -182,6 +193,34 @@ pub struct BSP3DPlane {
 }
 
 impl BSPData {
+    /// Decode a PVS bit vector into a list of visible clusters for each cluster.
+    ///
+    /// Each cluster has a row of `ceil(cluster_count / 32)` little endian 32-bit words, where bit `n` is set if
+    /// cluster `n` is visible.
+    ///
+    /// Returns `None` if `data` is too small.
+    pub fn decode_cluster_pvs(cluster_count: usize, data: &[u8]) -> Option<Vec<Vec<usize>>> {
+        let row_length = (cluster_count + 31) / 32 * 4;
+        if data.len() < row_length * cluster_count {
+            return None
+        }
+
+        let pvs = data
+            .chunks_exact(row_length)
+            .take(cluster_count)
+            .map(|row| {
+                (0..cluster_count)
+                    .filter(|c| {
+                        let word = u32::from_le_bytes(row[c / 32 * 4..c / 32 * 4 + 4].try_into().unwrap());
+                        (word & (1 << (c % 32))) != 0
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Some(pvs)
+    }
+
     pub fn find_cluster(&self, position: [f32; 3]) -> Option<usize> {
         self.find_leaf(position).map(|l| self.leaves[l].cluster)
     }
@@ -249,6 +288,17 @@ impl BSPData {
             }
         }
 
+        if let Some(pvs) = self.cluster_pvs.as_ref() {
+            if pvs.len() != self.clusters.len() {
+                return Err(Error::from_data_error_string(format!("PVS has {} cluster(s), but there are {} cluster(s)", pvs.len(), self.clusters.len())))
+            }
+            for (index, visible) in pvs.iter().enumerate() {
+                if let Some(c) = visible.iter().find(|c| **c >= self.clusters.len()) {
+                    return Err(Error::from_data_error_string(format!("PVS of cluster #{index} points to cluster #{c} which does not exist")))
+                }
+            }
+        }
+
         for (p_index, portal) in self.portals.iter().enumerate() {
             if portal.front_cluster >= self.clusters.len() || portal.back_cluster >= self.clusters.len() {
                 return Err(Error::from_data_error_string(format!("Portal {p_index} points to an out-of-bounds cluster (there are {} surfaces)", self.clusters.len())))
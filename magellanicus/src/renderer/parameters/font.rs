@@ -19,7 +19,10 @@ pub struct AddFontParameterCharacter {
     pub data: Vec<u8>,
     pub width: usize,
     pub height: usize,
-    pub advance_x: i32
+    pub advance_x: i32,
+
+    /// Adjustments added to `advance_x` when this character is immediately followed by the given character.
+    pub kerning: Vec<(char, i32)>
 }
 
 impl AddFontParameterCharacter {
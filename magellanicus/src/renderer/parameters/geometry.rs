@@ -1,3 +1,6 @@
+use std::collections::HashSet;
+use crate::error::{Error, MResult};
+use crate::renderer::Renderer;
 use crate::vertex::ModelVertex;
 
 pub use crate::renderer::data::GeometryDetailData;
@@ -6,10 +9,78 @@ pub use crate::renderer::data::GeometryDetailData;
 pub struct AddGeometryParameter {
     pub nodes: Vec<AddGeometryParameterNode>,
     pub geometries: Vec<AddGeometryParameterGeometry>,
+
+    /// Describes which geometries are drawn for each detail level.
+    ///
+    /// Each region is drawn with the geometry selected for the current detail level. If there are
+    /// no regions, all geometries will be drawn regardless of detail level.
+    pub regions: Vec<AddGeometryParameterRegion>,
     pub cutoff: GeometryDetailData<f32>,
     pub base_uv: [f32; 2]
 }
 
+impl AddGeometryParameter {
+    pub(crate) fn validate(&self, renderer: &Renderer) -> MResult<()> {
+        let mut node_names = HashSet::new();
+        let mut nodes_to_check: Vec<&AddGeometryParameterNode> = self.nodes.iter().collect();
+        while let Some(node) = nodes_to_check.pop() {
+            if !node_names.insert(node.name.as_str()) {
+                return Err(Error::from_data_error_string(format!("Geometry has multiple nodes named {}", node.name)))
+            }
+            nodes_to_check.extend(node.children.iter());
+        }
+
+        let geometry_count = self.geometries.len();
+        for region in &self.regions {
+            if let Some(index) = region.geometry_indices.iter().find(|i| **i >= geometry_count) {
+                return Err(Error::from_data_error_string(format!("Geometry region {} references geometry #{index}, but there are only {geometry_count} geometries", region.name)))
+            }
+        }
+
+        for (geometry_index, geometry) in self.geometries.iter().enumerate() {
+            for (part_index, part) in geometry.parts.iter().enumerate() {
+                let shader_path = &part.shader;
+                if !renderer.shaders.contains_key(shader_path) {
+                    return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} references shader {shader_path} which is not loaded")))
+                }
+
+                let vertex_count = part.vertices.len();
+                if vertex_count > u16::MAX as usize {
+                    return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} has too many vertices ({vertex_count} > 65535)")))
+                }
+
+                let index_count = part.indices.len();
+                if index_count % 3 != 0 {
+                    return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} has an index count of {index_count}, which is not a multiple of 3")))
+                }
+
+                if let Some(index) = part.indices.iter().find(|i| **i as usize >= vertex_count) {
+                    return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} has an out-of-bounds index {index} (vertex count is {vertex_count})")))
+                }
+
+                for (vertex_index, vertex) in part.vertices.iter().enumerate() {
+                    for node in core::iter::once(&vertex.node0).chain(vertex.node1.iter()) {
+                        if !node_names.contains(node.as_str()) {
+                            return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} vertex #{vertex_index} references node {node} which does not exist")))
+                        }
+                    }
+                }
+
+                for (name, filthy_index) in [("previous", part.previous_filthy_part_index), ("next", part.next_filthy_part_index)] {
+                    if let Some(filthy_index) = filthy_index {
+                        let part_count = geometry.parts.len();
+                        if filthy_index >= part_count {
+                            return Err(Error::from_data_error_string(format!("Geometry #{geometry_index} part #{part_index} has an out-of-bounds {name} filthy part index {filthy_index} (part count is {part_count})")))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AddGeometryParameterVertex {
     pub vertex_data: ModelVertex,
@@ -22,6 +93,8 @@ pub struct AddGeometryParameterVertex {
 pub struct AddGeometryParameterPart {
     pub shader: String,
     pub vertices: Vec<AddGeometryParameterVertex>,
+
+    /// Triangle list indices into `vertices`.
     pub indices: Vec<u16>,
     pub centroid: [f32; 3],
     pub previous_filthy_part_index: Option<usize>,
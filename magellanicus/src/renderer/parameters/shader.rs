@@ -4,7 +4,10 @@ use crate::renderer::{BitmapType, Renderer};
 use crate::renderer::data::Bitmap;
 
 pub const MAX_SHADER_TRANSPARENT_CHICAGO_MAPS: usize = 4;
+pub const MAX_SHADER_TRANSPARENT_GENERIC_MAPS: usize = 4;
+pub const MAX_SHADER_TRANSPARENT_GENERIC_STAGES: usize = 7;
 
+#[derive(Clone)]
 pub struct AddShaderParameter {
     pub data: AddShaderData
 }
@@ -22,14 +25,52 @@ impl AddShaderParameter {
             AddShaderData::ShaderEnvironment(shader_data) => {
                 shader_data.validate(renderer)?;
             },
+            AddShaderData::ShaderModel(shader_data) => {
+                shader_data.validate(renderer)?;
+            },
             AddShaderData::ShaderTransparentChicago(shader_data) => {
                 shader_data.validate(renderer)?;
+            },
+            AddShaderData::ShaderTransparentGeneric(shader_data) => {
+                shader_data.validate(renderer)?;
+            },
+            AddShaderData::ShaderTransparentWater(shader_data) => {
+                shader_data.validate(renderer)?;
             }
         }
         Ok(())
     }
 }
 
+impl AddShaderData {
+    /// Get the paths of all bitmaps referenced by the shader.
+    pub(crate) fn referenced_bitmaps(&self) -> Vec<String> {
+        let references: Vec<&Option<String>> = match self {
+            AddShaderData::BasicShader(s) => vec![&s.bitmap],
+            AddShaderData::ShaderEnvironment(s) => vec![
+                &s.base_map,
+                &s.primary_detail_map,
+                &s.secondary_detail_map,
+                &s.micro_detail_map,
+                &s.bump_map,
+                &s.reflection_cube_map
+            ],
+            AddShaderData::ShaderModel(s) => vec![
+                &s.base_map,
+                &s.multipurpose_map,
+                &s.detail_map,
+                &s.reflection_cube_map
+            ],
+            AddShaderData::ShaderTransparentChicago(s) => s.maps.iter().map(|m| &m.bitmap).collect(),
+            AddShaderData::ShaderTransparentGeneric(s) => s.maps.iter().map(|m| &m.bitmap).collect(),
+            AddShaderData::ShaderTransparentWater(s) => vec![&s.base_map, &s.reflection_map, &s.ripple_map]
+        };
+
+        references.into_iter().filter_map(|r| r.clone()).collect()
+    }
+}
+
+#[derive(Clone)]
 pub enum AddShaderData {
     /// Basic pipeline that just renders a single texture. This does not map to an actual tag group
     /// and is to be removed once all shaders are implemented
@@ -38,14 +79,29 @@ pub enum AddShaderData {
     /// Renders a shader_environment texture.
     ShaderEnvironment(AddShaderEnvironmentShaderData),
 
+    /// Renders a shader_model texture.
+    ShaderModel(AddShaderModelShaderData),
+
     /// Renders a shader_transparent_chicago texture.
-    ShaderTransparentChicago(AddShaderTransparentChicagoShaderData)
+    ShaderTransparentChicago(AddShaderTransparentChicagoShaderData),
+
+    /// Renders a shader_transparent_generic texture.
+    ShaderTransparentGeneric(AddShaderTransparentGenericShaderData),
+
+    /// Renders a shader_transparent_water texture.
+    ShaderTransparentWater(AddShaderTransparentWaterShaderData)
 }
 
+#[derive(Clone)]
 pub struct AddShaderBasicShaderData {
     pub bitmap: Option<String>,
     pub shader_type: ShaderType,
-    pub alpha_tested: bool
+    pub alpha_tested: bool,
+
+    /// Depth of the slice to display, from 0.0 to 1.0, if `bitmap` is a 3D texture.
+    ///
+    /// This is ignored for 2D textures.
+    pub volume_slice: f32
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -90,6 +146,14 @@ pub struct AddShaderEnvironmentShaderData {
     pub micro_detail_map_scale: f32,
     pub micro_detail_map_function: ShaderEnvironmentMapFunction,
 
+    /// Distance from the camera at which detail maps start to fade out.
+    pub detail_fade_start: f32,
+
+    /// Distance from the camera at which detail maps are completely faded out.
+    ///
+    /// If this is not greater than `detail_fade_start`, detail maps are never faded.
+    pub detail_fade_end: f32,
+
     pub bump_map: Option<String>,
     pub bump_map_scale: f32,
 
@@ -109,10 +173,69 @@ impl AddShaderEnvironmentShaderData {
         check_bitmap(renderer, &self.micro_detail_map, BitmapType::Dim2D, "micro detail map")?;
         check_bitmap(renderer, &self.bump_map, BitmapType::Dim2D, "bump map")?;
         check_bitmap(renderer, &self.reflection_cube_map, BitmapType::Cubemap, "reflection cube map")?;
+        if !self.detail_fade_start.is_finite() || !self.detail_fade_end.is_finite() || self.detail_fade_start < 0.0 {
+            return Err(Error::from_data_error_string(format!("Detail fade distances ({}, {}) must be finite and non-negative", self.detail_fade_start, self.detail_fade_end)))
+        }
         Ok(())
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+#[repr(u32)]
+pub enum ShaderModelDetailMask {
+    None,
+    ReflectionMaskInverse,
+    ReflectionMask,
+    SelfIlluminationMaskInverse,
+    SelfIlluminationMask,
+    ChangeColorMaskInverse,
+    ChangeColorMask,
+    AuxiliaryMaskInverse,
+    AuxiliaryMask
+}
+
+#[derive(Clone)]
+pub struct AddShaderModelShaderData {
+    pub alpha_tested: bool,
+    pub two_sided: bool,
+
+    pub base_map: Option<String>,
+    pub map_uv_scale: [f32; 2],
+
+    /// Multipurpose map (PC channel layout)
+    ///
+    /// - R: auxiliary mask
+    /// - G: self-illumination mask
+    /// - B: reflection mask
+    /// - A: change color mask
+    pub multipurpose_map: Option<String>,
+
+    pub detail_map: Option<String>,
+    pub detail_map_function: ShaderEnvironmentMapFunction,
+    pub detail_mask: ShaderModelDetailMask,
+    pub detail_map_scale: f32,
+    pub detail_map_v_scale: f32,
+    pub detail_after_reflection: bool,
+
+    pub self_illumination_color: [f32; 3],
+
+    pub reflection_cube_map: Option<String>,
+    pub perpendicular_color: [f32; 3],
+    pub perpendicular_brightness: f32,
+    pub parallel_color: [f32; 3],
+    pub parallel_brightness: f32,
+}
+impl AddShaderModelShaderData {
+    pub(crate) fn validate(&self, renderer: &Renderer) -> MResult<()> {
+        check_bitmap(renderer, &self.base_map, BitmapType::Dim2D, "base map")?;
+        check_bitmap(renderer, &self.multipurpose_map, BitmapType::Dim2D, "multipurpose map")?;
+        check_bitmap(renderer, &self.detail_map, BitmapType::Dim2D, "detail map")?;
+        check_bitmap(renderer, &self.reflection_cube_map, BitmapType::Cubemap, "reflection cube map")?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
 pub struct AddShaderTransparentChicagoShaderData {
     pub two_sided: bool,
     pub first_map_type: ShaderTransparentChicagoFirstMapType,
@@ -152,10 +275,277 @@ pub struct AddShaderTransparentChicagoShaderMap {
     pub alpha_function: ShaderColorFunction,
     pub uv_scale: [f32; 2],
     pub uv_offset: [f32; 2],
-    pub alpha_replicate: bool
+
+    /// UV units scrolled per second, added to the offset.
+    pub uv_scroll_rate: [f32; 2],
+    pub alpha_replicate: bool,
+
+    /// Determines how UVs outside of 0-1 are handled.
+    pub address_mode: SamplerAddressMode
+}
+
+/// Determines how a map is sampled outside of its bounds.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum SamplerAddressMode {
+    /// Wrap around to the other side.
+    #[default]
+    Repeat,
+
+    /// Use the color at the nearest edge.
+    Clamp,
+
+    /// Wrap around, flipping every other repetition.
+    Mirror
 }
 
-#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct AddShaderTransparentGenericShaderData {
+    pub two_sided: bool,
+    pub first_map_type: ShaderTransparentChicagoFirstMapType,
+    pub framebuffer_method: ShaderTransparentChicagoFramebufferFunction,
+    pub maps: Vec<AddShaderTransparentGenericShaderMap>,
+
+    /// Stages to run in order.
+    ///
+    /// If there are no stages, the first map is output as-is.
+    pub stages: Vec<AddShaderTransparentGenericShaderStage>
+}
+
+impl AddShaderTransparentGenericShaderData {
+    pub(crate) fn validate(&self, renderer: &Renderer) -> MResult<()> {
+        if self.maps.len() > MAX_SHADER_TRANSPARENT_GENERIC_MAPS {
+            return Err(Error::from_data_error_string(format!("Maximum number of maps ({MAX_SHADER_TRANSPARENT_GENERIC_MAPS}) exceeded")))
+        }
+
+        if self.stages.len() > MAX_SHADER_TRANSPARENT_GENERIC_STAGES {
+            return Err(Error::from_data_error_string(format!("Maximum number of stages ({MAX_SHADER_TRANSPARENT_GENERIC_STAGES}) exceeded")))
+        }
+
+        for (index, map) in self.maps.iter().enumerate() {
+            let expected_type = if index != 0 || self.first_map_type == ShaderTransparentChicagoFirstMapType::Dim2D {
+                BitmapType::Dim2D
+            }
+            else {
+                BitmapType::Cubemap
+            };
+
+            check_bitmap(renderer, &map.bitmap, expected_type, &format!("map {index}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct AddShaderTransparentGenericShaderMap {
+    pub bitmap: Option<String>,
+    pub uv_scale: [f32; 2],
+    pub uv_offset: [f32; 2]
+}
+
+#[derive(Default, Clone)]
+pub struct AddShaderTransparentGenericShaderStage {
+    /// If true, the AB/CD color output selects CD if scratch alpha 0 is at least 0.5, otherwise AB.
+    ///
+    /// If false, AB and CD are summed.
+    pub color_mux: bool,
+
+    /// Same as `color_mux`, but for alpha.
+    pub alpha_mux: bool,
+
+    pub constant_color0: [f32; 4],
+    pub constant_color1: [f32; 4],
+
+    /// Color inputs A, B, C, and D.
+    pub color_inputs: [ShaderTransparentGenericColorInput; 4],
+    pub color_input_mappings: [ShaderTransparentGenericInputMapping; 4],
+    pub color_output_ab: ShaderTransparentGenericColorOutput,
+    pub color_output_ab_function: ShaderTransparentGenericOutputFunction,
+    pub color_output_cd: ShaderTransparentGenericColorOutput,
+    pub color_output_cd_function: ShaderTransparentGenericOutputFunction,
+    pub color_output_ab_cd_mux_sum: ShaderTransparentGenericColorOutput,
+    pub color_output_mapping: ShaderTransparentGenericOutputMapping,
+
+    /// Alpha inputs A, B, C, and D.
+    pub alpha_inputs: [ShaderTransparentGenericAlphaInput; 4],
+    pub alpha_input_mappings: [ShaderTransparentGenericInputMapping; 4],
+    pub alpha_output_ab: ShaderTransparentGenericAlphaOutput,
+    pub alpha_output_cd: ShaderTransparentGenericAlphaOutput,
+    pub alpha_output_ab_cd_mux_sum: ShaderTransparentGenericAlphaOutput,
+    pub alpha_output_mapping: ShaderTransparentGenericOutputMapping
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericColorInput {
+    #[default]
+    Zero,
+    One,
+    OneHalf,
+    NegativeOne,
+    NegativeOneHalf,
+    MapColor0,
+    MapColor1,
+    MapColor2,
+    MapColor3,
+    VertexColor0DiffuseLight,
+    VertexColor1FadePerpendicular,
+    ScratchColor0,
+    ScratchColor1,
+    ConstantColor0,
+    ConstantColor1,
+    MapAlpha0,
+    MapAlpha1,
+    MapAlpha2,
+    MapAlpha3,
+    VertexAlpha0FadeNone,
+    VertexAlpha1FadePerpendicular,
+    ScratchAlpha0,
+    ScratchAlpha1,
+    ConstantAlpha0,
+    ConstantAlpha1
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericAlphaInput {
+    #[default]
+    Zero,
+    One,
+    OneHalf,
+    NegativeOne,
+    NegativeOneHalf,
+    MapAlpha0,
+    MapAlpha1,
+    MapAlpha2,
+    MapAlpha3,
+    VertexAlpha0FadeNone,
+    VertexAlpha1FadePerpendicular,
+    ScratchAlpha0,
+    ScratchAlpha1,
+    ConstantAlpha0,
+    ConstantAlpha1,
+    MapBlue0,
+    MapBlue1,
+    MapBlue2,
+    MapBlue3,
+    VertexBlue0DiffuseLight,
+    VertexBlue1FadePerpendicular,
+    ScratchBlue0,
+    ScratchBlue1,
+    ConstantBlue0,
+    ConstantBlue1
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericInputMapping {
+    /// clamp(x, 0, 1)
+    #[default]
+    ClampX,
+
+    /// 1 - clamp(x, 0, 1)
+    OneMinusClampX,
+
+    /// 2 * clamp(x, 0, 1) - 1
+    Two,
+
+    /// 1 - 2 * clamp(x, 0, 1)
+    MinusTwo,
+
+    /// clamp(x, 0, 1) - 0.5
+    ClampXMinusHalf,
+
+    /// 0.5 - clamp(x, 0, 1)
+    HalfMinusClampX,
+
+    /// x
+    X,
+
+    /// -x
+    MinusX
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericColorOutput {
+    #[default]
+    Discard,
+    ScratchColor0FinalColor,
+    ScratchColor1,
+    VertexColor0,
+    VertexColor1,
+    MapColor0,
+    MapColor1,
+    MapColor2,
+    MapColor3
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericAlphaOutput {
+    #[default]
+    Discard,
+    ScratchAlpha0FinalAlpha,
+    ScratchAlpha1,
+    VertexAlpha0FadeNone,
+    VertexAlpha1FadePerpendicular,
+    MapAlpha0,
+    MapAlpha1,
+    MapAlpha2,
+    MapAlpha3
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericOutputFunction {
+    /// a * b
+    #[default]
+    Multiply,
+
+    /// dot(a, b)
+    DotProduct
+}
+
+#[derive(Default, Copy, Clone)]
+#[repr(u32)]
+pub enum ShaderTransparentGenericOutputMapping {
+    #[default]
+    Identity,
+    ScaleByHalf,
+    ScaleByTwo,
+    ScaleByFour,
+    BiasByMinusHalf,
+    ExpandNormal
+}
+
+#[derive(Clone)]
+pub struct AddShaderTransparentWaterShaderData {
+    pub base_map: Option<String>,
+    pub base_map_alpha_modulates_reflection: bool,
+    pub base_map_color_modulates_background: bool,
+
+    pub reflection_map: Option<String>,
+
+    pub ripple_map: Option<String>,
+    pub ripple_scale: f32,
+
+    pub perpendicular_color: [f32; 3],
+    pub perpendicular_brightness: f32,
+    pub parallel_color: [f32; 3],
+    pub parallel_brightness: f32,
+}
+
+impl AddShaderTransparentWaterShaderData {
+    pub(crate) fn validate(&self, renderer: &Renderer) -> MResult<()> {
+        check_bitmap(renderer, &self.base_map, BitmapType::Dim2D, "base map")?;
+        check_bitmap(renderer, &self.reflection_map, BitmapType::Cubemap, "reflection map")?;
+        check_bitmap(renderer, &self.ripple_map, BitmapType::Dim2D, "ripple map")?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 #[repr(u32)]
 pub enum ShaderTransparentChicagoFirstMapType {
     Dim2D,
@@ -164,6 +554,7 @@ pub enum ShaderTransparentChicagoFirstMapType {
     ViewerCenteredCubemap,
 }
 
+#[derive(Copy, Clone)]
 #[repr(u32)]
 pub enum ShaderTransparentChicagoFramebufferFunction {
     /// framebuffer.rgb = mix(framebuffer.rgb, pixel.rgb, pixel.a)
@@ -172,7 +563,7 @@ pub enum ShaderTransparentChicagoFramebufferFunction {
     /// framebuffer.rgb *= pixel.rgb
     Multiply,
 
-    /// framebuffer.rgb *= pixel.rgb * pixel.rgb (???)
+    /// framebuffer.rgb *= pixel.rgb * 2
     DoubleMultiply,
 
     /// framebuffer.rgb += pixel.rgb
@@ -1,7 +1,7 @@
 use crate::error::{Error, MResult};
 use crate::renderer::Renderer;
 
-pub use crate::renderer::data::FogData;
+pub use crate::renderer::data::{FogData, FogMode};
 
 pub struct AddSkyParameter {
     pub geometry: Option<String>,
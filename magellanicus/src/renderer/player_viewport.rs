@@ -1,6 +1,86 @@
-use glam::Vec3;
+use glam::{Quat, Vec3};
 use crate::renderer::data::{DRAW_DISTANCE_MINIMUM, MAX_DRAW_DISTANCE_LIMIT};
-use crate::renderer::FogData;
+use crate::renderer::{FogData, Resolution};
+use crate::error::{Error, MResult};
+use crate::types::FloatColor;
+
+/// Describes a world-space line to be drawn over a viewport for the current frame.
+#[derive(Copy, Clone, Debug)]
+pub struct DebugLineInstance {
+    pub viewport: usize,
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub color: FloatColor
+}
+
+/// Maximum number of viewports that can be set with [`Renderer::set_viewport_layout`](crate::renderer::Renderer::set_viewport_layout).
+pub const MAX_VIEWPORT_COUNT: usize = 64;
+
+/// Region of the screen covered by a viewport.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ViewportRect {
+    /// Relative X of the viewport (0.0-1.0)
+    pub x: f32,
+
+    /// Relative Y of the viewport (0.0-1.0)
+    pub y: f32,
+
+    /// Width of the viewport (0.0-1.0)
+    pub width: f32,
+
+    /// Height of the viewport (0.0-1.0)
+    pub height: f32
+}
+
+impl ViewportRect {
+    pub(crate) fn validate(&self) -> MResult<()> {
+        let ViewportRect { x, y, width, height } = *self;
+        if ![x, y, width, height].iter().all(|f| f.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Viewport rect {self:?} is not finite")))
+        }
+        if !(width > 0.0 && height > 0.0) {
+            return Err(Error::from_data_error_string(format!("Viewport rect {self:?} has no area")))
+        }
+        if x < 0.0 || y < 0.0 || x + width > 1.0 || y + height > 1.0 {
+            return Err(Error::from_data_error_string(format!("Viewport rect {self:?} is not within 0.0-1.0")))
+        }
+        Ok(())
+    }
+
+    /// Get the split screen layout for the given number of viewports, or `None` if there isn't one.
+    pub(crate) fn split_screen_layout(viewport_count: usize) -> Option<&'static [ViewportRect]> {
+        const fn rect(x: f32, y: f32, width: f32, height: f32) -> ViewportRect {
+            ViewportRect { x, y, width, height }
+        }
+
+        const ONE: [ViewportRect; 1] = [
+            rect(0.0, 0.0, 1.0, 1.0)
+        ];
+        const TWO: [ViewportRect; 2] = [
+            rect(0.0, 0.0, 1.0, 0.5),
+            rect(0.0, 0.5, 1.0, 0.5)
+        ];
+        const THREE: [ViewportRect; 3] = [
+            rect(0.0, 0.0, 1.0, 0.5),
+            rect(0.0, 0.5, 0.5, 0.5),
+            rect(0.5, 0.5, 0.5, 0.5)
+        ];
+        const FOUR: [ViewportRect; 4] = [
+            rect(0.0, 0.0, 0.5, 0.5),
+            rect(0.5, 0.0, 0.5, 0.5),
+            rect(0.0, 0.5, 0.5, 0.5),
+            rect(0.5, 0.5, 0.5, 0.5)
+        ];
+
+        match viewport_count {
+            1 => Some(&ONE),
+            2 => Some(&TWO),
+            3 => Some(&THREE),
+            4 => Some(&FOUR),
+            _ => None
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct PlayerViewport {
@@ -28,6 +108,14 @@ pub struct PlayerViewport {
     ///
     /// NOTE: This will be automatically modified to the correct value when a BSP is loaded.
     pub draw_distance: [f32; 2],
+
+    /// Horizontal FoV in radians, if set with `Renderer::set_camera_horizontal_fov`.
+    ///
+    /// If set, the camera's vertical FoV is recalculated from this when the swapchain is rebuilt.
+    pub horizontal_fov: Option<f32>,
+
+    /// If false, the viewport is filled with the background color instead of being drawn.
+    pub enabled: bool,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -41,11 +129,8 @@ pub struct ViewportFog {
     /// Current indoor fog.
     pub indoor_fog_data: FogData,
 
-    /// Target fog data (transitioned as the camera moves)
-    pub target_fog_data: FogData,
-
-    /// Pending transition amount.
-    pub transition_amount: f32
+    /// Target fog data (transitioned over time)
+    pub target_fog_data: FogData
 }
 
 impl Default for PlayerViewport {
@@ -58,6 +143,8 @@ impl Default for PlayerViewport {
             camera: Camera::default(),
             viewport_fog: None,
             draw_distance: [DRAW_DISTANCE_MINIMUM, MAX_DRAW_DISTANCE_LIMIT],
+            horizontal_fov: None,
+            enabled: true,
         }
     }
 }
@@ -65,33 +152,130 @@ impl Default for PlayerViewport {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Camera {
     /// Vertical FoV in radians
+    ///
+    /// This is ignored if `projection` is [`Projection::Orthographic`].
     pub fov: f32,
 
+    /// Projection used when drawing.
+    ///
+    /// This is ignored if `custom_projection` is set.
+    pub projection: Projection,
+
+    /// Column-major projection matrix to use instead of `projection`, such as for off-axis or VR projections.
+    ///
+    /// The matrix is used as is, so it must map to Vulkan clip space (left-handed, with depth from 0 to 1), while the
+    /// view matrix is still made from `position`, `rotation`, and `up`. `fov`, `z_near`, and `z_far` are ignored, so
+    /// geometry is clipped by the near and far planes of this matrix instead of the BSP's draw distance. Fog is
+    /// unaffected and still comes from the BSP. The sky is drawn with this matrix too, so it can be clipped by its far
+    /// plane.
+    ///
+    /// Default = None
+    pub custom_projection: Option<[[f32; 4]; 4]>,
+
+    /// Distance to the near plane, or `None` to use the default of 0.0625 world units.
+    ///
+    /// Depth precision depends mostly on this, so raising it reduces z-fighting in large scenes, while lowering it lets
+    /// the camera get closer to geometry at the cost of more z-fighting in the distance.
+    pub z_near: Option<f32>,
+
+    /// Distance to the far plane, or `None` to use the draw distance of the BSP (or the fog, if it is opaque).
+    ///
+    /// Geometry past this is not drawn. A tighter range also improves depth precision.
+    pub z_far: Option<f32>,
+
     /// Position in the map of the camera
     pub position: [f32; 3],
 
     /// Rotation of the camera
     pub rotation: [f32; 3],
 
+    /// Up vector of the camera, used for rolling the camera.
+    ///
+    /// This must not be parallel to `rotation`.
+    pub up: [f32; 3],
+
     /// Enable lightmap.
     pub lightmaps: bool,
 
     /// Enable fog.
-    pub fog: bool
+    pub fog: bool,
+
+    /// Enable frustum culling.
+    ///
+    /// Disabling this draws all geometry regardless of whether or not it is in view, which can be useful for debugging.
+    pub frustum_culling: bool,
+
+    /// Draw geometry as wireframe.
+    ///
+    /// This is ignored if the GPU does not support it.
+    pub wireframe: bool,
+
+    /// Show only part of the shading for debugging.
+    pub debug_view: DebugView
 }
 
 impl Default for Camera {
     fn default() -> Self {
         Self {
             fov: get_default_vertical_fov(),
+            projection: Projection::Perspective,
+            custom_projection: None,
+            z_near: None,
+            z_far: None,
             position: Vec3::default().to_array(),
             rotation: [0.0, 1.0, 0.0],
+            up: DEFAULT_CAMERA_UP,
             lightmaps: true,
-            fog: true
+            fog: true,
+            frustum_culling: true,
+            wireframe: false,
+            debug_view: DebugView::Normal
         }
     }
 }
 
+/// Determines how the scene is projected onto the viewport.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Projection {
+    /// Objects get smaller with distance, using the camera's FoV.
+    #[default]
+    Perspective,
+
+    /// Objects are the same size regardless of distance, which is useful for top-down and isometric views.
+    ///
+    /// The sky is not drawn in this mode.
+    Orthographic {
+        /// Height of the view in world units. The width is determined by the aspect ratio of the viewport.
+        height: f32
+    }
+}
+
+/// Determines what is shown when drawing geometry, which is useful for debugging lighting and performance.
+///
+/// Views other than [`DebugView::Normal`] and [`DebugView::Overdraw`] only affect `shader_environment`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(u32)]
+pub enum DebugView {
+    /// Draw everything as normal.
+    #[default]
+    Normal,
+
+    /// Only show the lightmap.
+    LightmapOnly,
+
+    /// Only show the diffuse color without lightmaps, specular, or fog.
+    DiffuseOnly,
+
+    /// Show how many times each pixel is drawn, with brighter pixels being drawn more.
+    Overdraw,
+
+    /// Show world space normals, including the bump map.
+    Normals
+}
+
+/// Default up vector for the camera.
+pub const DEFAULT_CAMERA_UP: [f32; 3] = [0.0, 0.0, -1.0];
+
 /// Default horizontal FoV to use.
 pub const DEFAULT_HORIZONTAL_FOV: f32 = 70.0;
 
@@ -100,8 +284,124 @@ pub fn get_default_vertical_fov() -> f32 {
     horizontal_to_vertical_fov(DEFAULT_HORIZONTAL_FOV.to_radians(), 640.0, 480.0)
 }
 
+impl PlayerViewport {
+    /// Set the region of the screen covered by this viewport.
+    pub fn set_rect(&mut self, rect: ViewportRect) {
+        self.rel_x = rect.x;
+        self.rel_y = rect.y;
+        self.rel_width = rect.width;
+        self.rel_height = rect.height;
+    }
+
+    /// Get the vertical FoV equivalent to `horizontal` for this viewport at the given resolution.
+    pub fn get_vertical_fov(&self, horizontal: f32, resolution: Resolution) -> f32 {
+        horizontal_to_vertical_fov(
+            horizontal,
+            self.rel_width * resolution.width as f32,
+            self.rel_height * resolution.height as f32
+        )
+    }
+}
+
 /// Calculate the vertical FoV given horizontal FoV and aspect ratio.
 #[inline(always)]
 pub fn horizontal_to_vertical_fov(horizontal: f32, width: f32, height: f32) -> f32 {
     2.0 * ((horizontal / 2.0).tan() * height / width).atan()
 }
+
+/// Get the forward and up vectors of a camera with the given orientation.
+///
+/// The identity quaternion faces +Y with the up vector being [`DEFAULT_CAMERA_UP`].
+pub(crate) fn get_camera_vectors_from_quat(orientation: Quat) -> (Vec3, Vec3) {
+    let orientation = orientation.normalize();
+    (orientation * Vec3::Y, orientation * Vec3::from(DEFAULT_CAMERA_UP))
+}
+
+/// Normalize `up`, replacing it if it is zero or parallel to `rotation`.
+///
+/// `rotation` must be normalized.
+pub(crate) fn get_camera_up(rotation: Vec3, up: Vec3) -> Vec3 {
+    let is_valid = |up: Vec3| rotation.cross(up).length_squared() > 0.00001;
+
+    if let Some(up) = up.try_normalize().filter(|u| is_valid(*u)) {
+        return up
+    }
+
+    let default_up = Vec3::from(DEFAULT_CAMERA_UP);
+    if is_valid(default_up) {
+        default_up
+    }
+    else {
+        rotation.any_orthonormal_vector()
+    }
+}
+
+/// Check that the near and far plane overrides of `camera`, if any, are usable.
+pub(crate) fn validate_clip_planes(camera: &Camera) -> Result<(), String> {
+    if let Some(z_near) = camera.z_near {
+        if !(z_near > 0.0 && z_near.is_finite()) {
+            return Err(format!("camera.z_near ({z_near}) is not a positive number"))
+        }
+    }
+    if let Some(z_far) = camera.z_far {
+        let z_near = camera.z_near.unwrap_or(DRAW_DISTANCE_MINIMUM);
+        if !(z_far > z_near && z_far.is_finite()) {
+            return Err(format!("camera.z_far ({z_far}) is not greater than the near plane ({z_near})"))
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_custom_projection(camera: &Camera) -> Result<(), String> {
+    if let Some(projection) = camera.custom_projection {
+        if projection.iter().flatten().any(|f| !f.is_finite()) {
+            return Err(format!("camera.custom_projection ({projection:?}) is not finite"))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Mat3;
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn identity_quat_faces_forward() {
+        let (forward, up) = get_camera_vectors_from_quat(Quat::IDENTITY);
+        assert_vec3_eq(forward, Vec3::Y);
+        assert_vec3_eq(up, Vec3::from(DEFAULT_CAMERA_UP));
+    }
+
+    #[test]
+    fn yaw_turns_forward() {
+        let (forward, _) = get_camera_vectors_from_quat(Quat::from_rotation_z(core::f32::consts::FRAC_PI_2));
+        assert_vec3_eq(forward, Vec3::NEG_X);
+    }
+
+    #[test]
+    fn quat_round_trips_to_the_same_forward_vector() {
+        let orientations = [
+            Quat::IDENTITY,
+            Quat::from_rotation_z(1.0),
+            Quat::from_euler(glam::EulerRot::ZXY, 0.3, -0.7, 1.2),
+            Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0).normalize(), 2.5)
+        ];
+
+        for orientation in orientations {
+            let (forward, up) = get_camera_vectors_from_quat(orientation);
+
+            // set_camera_for_viewport must keep both vectors as they are.
+            assert_vec3_eq(get_camera_up(forward, up), up);
+
+            // +Y maps to forward and -Z maps to up, so +X maps to forward x -up.
+            let rebuilt = Quat::from_mat3(&Mat3::from_cols(forward.cross(-up), forward, -up));
+            assert_vec3_eq(rebuilt * Vec3::Y, forward);
+            assert!(rebuilt.dot(orientation).abs() > 1.0 - 1e-5, "{rebuilt} != {orientation}");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::Device;
+use vulkano::image::view::ImageView;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use crate::error::MResult;
+
+/// Custom pass run on each frame after all viewports and overlays are drawn, but before it is copied to the window.
+///
+/// Set this with [`Renderer::set_post_process`](crate::renderer::Renderer::set_post_process). It must be [`Send`] so the
+/// renderer can be moved to another thread.
+pub trait PostProcess: Send {
+    /// Record the pass into `context.command_builder`.
+    ///
+    /// The result must be written back into `context.color`, as that is what gets copied to the window.
+    fn apply(&mut self, context: PostProcessContext) -> MResult<()>;
+}
+
+/// Everything a [`PostProcess`] needs to record its pass.
+pub struct PostProcessContext<'a> {
+    pub device: Arc<Device>,
+    pub memory_allocator: Arc<StandardMemoryAllocator>,
+    pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+
+    /// Resolved color image of the frame at the render resolution, with tonemapping and FXAA applied if enabled.
+    ///
+    /// This is single-sampled, and it can be sampled, rendered to, and used as a transfer source or destination.
+    /// Layout transitions are handled automatically.
+    ///
+    /// The format is `R8G8B8A8_UNORM`, or `R8G8B8A8_SRGB` if sRGB-correct rendering is enabled, or
+    /// `R16G16B16A16_SFLOAT` (linear, with values above 1.0 being brighter than SDR white) if HDR is enabled.
+    pub color: Arc<ImageView>,
+
+    /// Secondary command buffer that is executed before the frame is copied to the window.
+    ///
+    /// This does not inherit a render pass, so the pass must begin and end its own rendering.
+    pub command_builder: &'a mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>
+}
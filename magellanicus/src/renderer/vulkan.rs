@@ -8,78 +8,126 @@ mod player_viewport;
 mod vertex;
 mod material;
 mod font;
+mod sampler;
+mod timestamp;
+mod render_target;
 
-use std::collections::BTreeMap;
-use std::fmt::Display;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Duration;
 pub use bitmap::*;
 pub use bsp::*;
+pub use font::*;
+pub use render_target::*;
 pub use geometry::*;
 pub use material::*;
 pub use pipeline::*;
 
 use crate::error::{Error, MResult};
-use crate::renderer::data::{BSPGeometry, BSP, MAX_DRAW_DISTANCE_LIMIT};
+use crate::renderer::data::{BSPGeometry, CharacterQuad, Geometry, SpriteStencil, BSP, DRAW_DISTANCE_MINIMUM, MAX_DRAW_DISTANCE_LIMIT};
 use crate::renderer::player_viewport::PlayerViewport;
+use crate::renderer::frustum::Frustum;
+use crate::renderer::log::{log, LogLevel};
+use crate::renderer::overlay::{OverlayContext, OverlayDraw};
 use crate::renderer::vulkan::helper::{build_swapchain, LoadedVulkan};
-use crate::renderer::vulkan::vertex::{VulkanFogData, VulkanModelData, VulkanModelVertex};
-use crate::renderer::{Camera, FogData, Renderer, RendererParameters, Resolution, MSAA};
+use crate::renderer::vulkan::sampler::SamplerCache;
+use crate::renderer::vulkan::timestamp::VulkanFrameTimestamps;
+use crate::renderer::vulkan::vertex::{VulkanDebugLineVertex, VulkanFogData, VulkanModelData, VulkanModelInstance, VulkanModelVertex, VulkanTextVertex};
+use crate::renderer::{Camera, DebugView, Projection, FogData, DeviceSelection, FogMode, FrameTimings, FXAAQuality, PhysicalDeviceInfo, PostProcess, PostProcessContext, PresentModePreference, Renderer, RendererParameters, Resolution, SamplerAddressMode as MapAddressMode, SplitBarStyle, TonemapOperator, UpscaleFilter, MSAA};
 use crate::vertex::VertexOffsets;
 use crate::types::FloatColor;
 use glam::{Mat3, Mat4, Vec3};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
 use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, BlitImageInfo, ClearDepthStencilImageInfo, CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassType, CommandBufferInheritanceRenderingInfo, CommandBufferUsage, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo, RenderingAttachmentInfo, RenderingInfo, ResolveImageInfo, SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents, SubpassEndInfo};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, BlitImageInfo, ClearAttachment, ClearDepthStencilImageInfo, ClearRect, CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassType, CommandBufferInheritanceRenderingInfo, CommandBufferUsage, CopyImageToBufferInfo, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo, RenderingAttachmentInfo, RenderingInfo, ResolveImageInfo, SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents, SubpassEndInfo};
 use vulkano::descriptor_set::allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::{Device, DeviceOwned, Queue};
-use vulkano::format::{ClearDepthStencilValue, Format};
-use vulkano::image::sampler::{Filter, Sampler, SamplerCreateInfo};
-use vulkano::image::view::ImageView;
-use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
+use vulkano::format::{ClearDepthStencilValue, Format, NumericFormat};
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, LOD_CLAMP_NONE};
+use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::image::{Image, ImageAspects, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
 use vulkano::instance::Instance;
+use vulkano::instance::debug::{DebugUtilsLabel, DebugUtilsMessenger};
 use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
 use vulkano::padded::Padded;
+use vulkano::pipeline::graphics::depth_stencil::StencilFaces;
 use vulkano::pipeline::graphics::rasterization::CullMode;
-use vulkano::pipeline::graphics::viewport::Viewport;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
 use vulkano::pipeline::{Pipeline, PipelineBindPoint};
 use vulkano::render_pass::{AttachmentLoadOp, AttachmentStoreOp, Framebuffer, FramebufferCreateInfo};
-use vulkano::swapchain::{acquire_next_image, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainPresentInfo};
+use vulkano::swapchain::{acquire_next_image, ColorSpace, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainPresentInfo};
 use vulkano::sync::GpuFuture;
-use vulkano::{single_pass_renderpass, Validated, ValidationError, VulkanError};
+use vulkano::{single_pass_renderpass, DeviceSize, Validated, ValidationError, VulkanError};
 
 pub(crate) static OFFLINE_PIPELINE_COLOR_FORMAT: Format = Format::R8G8B8A8_UNORM;
+pub(crate) static HDR_OFFLINE_PIPELINE_COLOR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+pub(crate) static SRGB_OFFLINE_PIPELINE_COLOR_FORMAT: Format = Format::R8G8B8A8_SRGB;
 
 pub struct VulkanRenderer {
     current_resolution: Resolution,
     instance: Arc<Instance>,
+
+    /// Kept alive so validation messages keep going to the log callback.
+    _debug_messenger: Option<DebugUtilsMessenger>,
     device: Arc<Device>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: StandardCommandBufferAllocator,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    uniform_buffer_allocator: SubbufferAllocator,
+    vertex_buffer_allocator: SubbufferAllocator,
     queue: Arc<Queue>,
     future: Option<Box<dyn GpuFuture + Send + Sync>>,
     pipelines: BTreeMap<VulkanPipelineType, Arc<dyn VulkanPipelineData>>,
-    swapchain: Arc<Swapchain>,
-    surface: Arc<Surface>,
+    fog_descriptor_sets: HashMap<Option<usize>, (FogData, Arc<PersistentDescriptorSet>)>,
+    swapchain: Option<Arc<Swapchain>>,
+    surface: Option<Arc<Surface>>,
     swapchain_image_views: Vec<Arc<SwapchainImages>>,
     default_2d_sampler: Arc<Sampler>,
+    sampler_cache: SamplerCache,
+    lightmap_views: HashMap<(Arc<String>, usize), Arc<ImageView>>,
     samples_per_pixel: SampleCount,
-    default_box_indices: Subbuffer<[u16]>
+    color_format: Format,
+    depth_format: Format,
+    srgb_correct: bool,
+    default_box_indices: Subbuffer<[u16]>,
+    default_box_vertices: Subbuffer<[VulkanModelVertex]>,
+    default_instance: Subbuffer<[VulkanModelInstance]>,
+    last_image_index: Option<u32>,
+    render_scale: f32,
+    upscale_filter: UpscaleFilter,
+    vsync: bool,
+    present_mode: Option<PresentModePreference>,
+    fxaa: Option<FXAAQuality>,
+    debug_labels: bool,
+    exposure: f32,
+    tonemap: TonemapOperator,
+    last_frame_post_processed: bool,
+    device_lost: bool,
+    depth_prepass: bool,
+    frame_timestamps: Option<VulkanFrameTimestamps>,
+    last_frame_timings: Option<FrameTimings>,
+    bulk_load_commands: Option<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>
 }
 
 #[derive(Clone)]
 pub struct SwapchainImages {
-    output: Arc<ImageView>,
+    output: Option<Arc<ImageView>>,
     color: Arc<ImageView>,
     depth: Arc<ImageView>,
     resolve: Option<Arc<ImageView>>,
+    post_process: Option<Arc<ImageView>>,
     framebuffer: Option<Arc<Framebuffer>>
 }
 
 impl SwapchainImages {
+    /// Get whether the depth image has a stencil buffer.
+    fn has_stencil(&self) -> bool {
+        self.depth.format().aspects().intersects(ImageAspects::STENCIL)
+    }
+
     fn begin_rendering(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
         if let Some(n) = self.framebuffer.as_ref() {
             let begin_render_pass = RenderPassBeginInfo {
@@ -104,6 +152,11 @@ impl SwapchainImages {
                     store_op: AttachmentStoreOp::Store,
                     ..RenderingAttachmentInfo::image_view(self.depth.clone())
                 }),
+                stencil_attachment: self.has_stencil().then(|| RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::Load,
+                    store_op: AttachmentStoreOp::Store,
+                    ..RenderingAttachmentInfo::image_view(self.depth.clone())
+                }),
                 ..Default::default()
             }).expect("failed to begin rendering");
         }
@@ -116,6 +169,36 @@ impl SwapchainImages {
             command_builder.end_rendering().expect("failed to end rendering");
         }
     }
+
+    /// Resolve the color image if it is multisampled, then blit it to the output image, if any.
+    fn copy_to_output(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, filter: Filter) -> MResult<()> {
+        let staging_image = self.resolve_color(command_builder)?;
+        self.blit_to_output(command_builder, &staging_image, filter)
+    }
+
+    /// Resolve the color image if it is multisampled, returning the single-sampled color image.
+    fn resolve_color(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<Arc<ImageView>> {
+        if let Some(resolved_color_view) = self.resolve.as_ref() {
+            command_builder.resolve_image(
+                ResolveImageInfo::images(self.color.image().clone(), resolved_color_view.image().clone())
+            )?;
+            Ok(resolved_color_view.clone())
+        }
+        else {
+            Ok(self.color.clone())
+        }
+    }
+
+    fn blit_to_output(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, staging_image: &ImageView, filter: Filter) -> MResult<()> {
+        if let Some(output) = self.output.as_ref() {
+            command_builder.blit_image(BlitImageInfo {
+                filter,
+                ..BlitImageInfo::images(staging_image.image().clone(), output.image().clone())
+            })?;
+        }
+
+        Ok(())
+    }
 }
 
 impl VulkanRenderer {
@@ -123,41 +206,70 @@ impl VulkanRenderer {
         renderer_parameters: &RendererParameters,
         surface: &(impl HasRawWindowHandle + HasRawDisplayHandle)
     ) -> MResult<Self> {
-        let LoadedVulkan { device, instance, surface, queue} = helper::load_vulkan_and_get_queue(surface, renderer_parameters.anisotropic_filtering)?;
-
-        let samples_per_pixel = match renderer_parameters.msaa {
-            MSAA::NoMSAA => SampleCount::Sample1,
-            MSAA::MSAA2x => SampleCount::Sample2,
-            MSAA::MSAA4x => SampleCount::Sample4,
-            MSAA::MSAA8x => SampleCount::Sample8,
-            MSAA::MSAA16x => SampleCount::Sample16,
-            MSAA::MSAA32x => SampleCount::Sample32,
-            MSAA::MSAA64x => SampleCount::Sample64
+        let loaded_vulkan = helper::load_vulkan_and_get_queue(surface, renderer_parameters)?;
+        Self::new_from_loaded_vulkan(renderer_parameters, loaded_vulkan)
+    }
+
+    /// Get whether the depth image has a stencil buffer.
+    pub fn has_stencil(&self) -> bool {
+        self.depth_format.aspects().intersects(ImageAspects::STENCIL)
+    }
+
+    pub fn get_device_local_memory(&self) -> u64 {
+        helper::get_device_local_memory(self.device.physical_device().as_ref())
+    }
+
+    #[cfg(feature = "shader-hot-reload")]
+    pub fn reload_pipeline(&mut self, pipeline_type: VulkanPipelineType) -> MResult<()> {
+        let Some(pipeline) = hot_reload::reload_pipeline_type(pipeline_type, &self.swapchain_image_views[0], self.device.clone())? else {
+            return Err(Error::from_data_error_string(format!("{pipeline_type:?} is not supported by this device")))
         };
+        self.pipelines.insert(pipeline_type, pipeline);
+        self.fog_descriptor_sets.clear();
+        self.name_debug_objects();
+        Ok(())
+    }
 
-        if let Some(n) = renderer_parameters.anisotropic_filtering {
-            let max = device.physical_device().properties().max_sampler_anisotropy;
-            if max < n || n < 1.0 {
-                return Err(
-                    Error::from_vulkan_impl_error(format!("{n}x AF is unsupported by your device; supported values are 1-{max}"))
-                )
-            }
-        }
+    pub fn list_physical_devices() -> MResult<Vec<PhysicalDeviceInfo>> {
+        helper::list_physical_devices()
+    }
 
-        let color = device.physical_device().properties().sampled_image_color_sample_counts;
-        let depth = device.physical_device().properties().sampled_image_depth_sample_counts;
-        let intersection = color & depth;
-        if !intersection.contains_enum(samples_per_pixel) {
-            return Err(
-                Error::from_vulkan_impl_error(format!("{}x MSAA is unsupported by your device; only these are supported:{}",
-                                                      renderer_parameters.msaa as u32,
-                                                      intersection.into_iter().map(|s| format!(" {}", s as u32)).collect::<String>())));
-        }
+    pub unsafe fn supported_msaa(
+        surface: &(impl HasRawWindowHandle + HasRawDisplayHandle),
+        preferred_device: Option<&DeviceSelection>
+    ) -> MResult<Vec<MSAA>> {
+        let physical_device = helper::load_physical_device(surface, preferred_device)?;
+        Ok(helper::get_supported_msaa(physical_device.as_ref()))
+    }
+
+    pub unsafe fn supported_max_anisotropy(
+        surface: &(impl HasRawWindowHandle + HasRawDisplayHandle),
+        preferred_device: Option<&DeviceSelection>
+    ) -> MResult<Option<f32>> {
+        let physical_device = helper::load_physical_device(surface, preferred_device)?;
+        Ok(helper::get_supported_max_anisotropy(physical_device.as_ref()))
+    }
+
+    pub fn new_headless(renderer_parameters: &RendererParameters) -> MResult<Self> {
+        let loaded_vulkan = helper::load_vulkan_headless_and_get_queue(renderer_parameters)?;
+        Self::new_from_loaded_vulkan(renderer_parameters, loaded_vulkan)
+    }
+
+    fn new_from_loaded_vulkan(
+        renderer_parameters: &RendererParameters,
+        loaded_vulkan: LoadedVulkan
+    ) -> MResult<Self> {
+        let LoadedVulkan { device, instance, surface, queue, debug_messenger } = loaded_vulkan;
+
+        // MSAA support was already checked when the GPU was selected.
+        let samples_per_pixel = helper::get_sample_count(renderer_parameters.msaa);
+
+        check_anisotropic_filtering(device.as_ref(), renderer_parameters.anisotropic_filtering)?;
 
         let command_buffer_allocator = StandardCommandBufferAllocator::new(
             device.clone(),
             StandardCommandBufferAllocatorCreateInfo {
-                primary_buffer_count: 32,
+                primary_buffer_count: renderer_parameters.command_buffer_pool_size as usize,
                 secondary_buffer_count: 0,
                 ..Default::default()
             }
@@ -166,7 +278,7 @@ impl VulkanRenderer {
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
             device.clone(),
             StandardDescriptorSetAllocatorCreateInfo {
-                set_count: 16 * 1024,
+                set_count: renderer_parameters.descriptor_pool_size as usize,
                 ..Default::default()
             }
         ));
@@ -174,22 +286,63 @@ impl VulkanRenderer {
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
         let future = Some(vulkano::sync::now(device.clone()).boxed_send_sync());
 
-        let output_format = device
-            .physical_device()
-            .surface_formats(surface.as_ref(), Default::default())?[0]
-            .0;
+        let fxaa = renderer_parameters.fxaa.then_some(renderer_parameters.fxaa_quality);
+        let fxaa = if fxaa.is_some() && !device.enabled_extensions().khr_dynamic_rendering {
+            log(LogLevel::Warning, "FXAA requires dynamic rendering, which is not supported by this device; disabling FXAA");
+            None
+        }
+        else {
+            fxaa
+        };
+
+        let depth_format = helper::get_depth_format(device.as_ref(), renderer_parameters.stencil)?;
+
+        let mut srgb_correct = renderer_parameters.srgb_correct;
+        let mut color_format = if srgb_correct { SRGB_OFFLINE_PIPELINE_COLOR_FORMAT } else { OFFLINE_PIPELINE_COLOR_FORMAT };
+        let (swapchain, swapchain_image_views) = if let Some(surface) = surface.as_ref() {
+            let output_format = helper::get_surface_format(device.as_ref(), surface.as_ref(), renderer_parameters.hdr, srgb_correct)?;
+            if output_format.1 == ColorSpace::ExtendedSrgbLinear {
+                // scRGB is linear, so color maps have to be linearized, or the frame is presented too dark.
+                if !srgb_correct {
+                    log(LogLevel::Info, "HDR requires sRGB-correct rendering; enabling it");
+                    srgb_correct = true;
+                }
+                color_format = HDR_OFFLINE_PIPELINE_COLOR_FORMAT;
+            }
+            else if renderer_parameters.hdr {
+                log(LogLevel::Warning, "HDR is not supported by the surface; falling back to SDR");
+            }
 
-        let (swapchain, swapchain_images) = build_swapchain(device.clone(), surface.clone(), output_format, renderer_parameters)?;
+            // Blitting linear colors to a non-sRGB image would not gamma-correct them.
+            if srgb_correct && color_format == SRGB_OFFLINE_PIPELINE_COLOR_FORMAT && output_format.0.numeric_format_color() != Some(NumericFormat::SRGB) {
+                log(LogLevel::Warning, "sRGB output is not supported by the surface; disabling sRGB-correct rendering");
+                srgb_correct = false;
+                color_format = OFFLINE_PIPELINE_COLOR_FORMAT;
+            }
 
-        let swapchain_image_views = Self::make_swapchain_images(swapchain_images, memory_allocator.clone(), samples_per_pixel, renderer_parameters.render_scale);
+            let (swapchain, swapchain_images) = build_swapchain(device.clone(), surface.clone(), output_format, renderer_parameters)?;
+            (Some(swapchain), Self::make_swapchain_images(swapchain_images, memory_allocator.clone(), samples_per_pixel, color_format, depth_format, fxaa.is_some(), renderer_parameters.render_scale, renderer_parameters.upscale_filter))
+        }
+        else {
+            (None, vec![Self::make_headless_images(memory_allocator.clone(), samples_per_pixel, color_format, depth_format, fxaa.is_some(), renderer_parameters.resolution, renderer_parameters.render_scale, renderer_parameters.upscale_filter)])
+        };
         let pipelines = load_all_pipelines(&swapchain_image_views[0], device.clone())?;
 
-        let default_2d_sampler = Sampler::new(
-            device.clone(),
-            SamplerCreateInfo {
-                anisotropy: renderer_parameters.anisotropic_filtering,
-                ..SamplerCreateInfo::simple_repeat_linear()
+        let frame_timestamps = if renderer_parameters.gpu_frame_timings {
+            let timestamps = VulkanFrameTimestamps::new(device.clone(), queue.as_ref(), renderer_parameters.number_of_viewports);
+            if timestamps.is_none() {
+                log(LogLevel::Warning, "GPU frame timings are not supported on this device");
             }
+            timestamps
+        }
+        else {
+            None
+        };
+
+        let mut sampler_cache = SamplerCache::default();
+        let default_2d_sampler = sampler_cache.get(
+            device.clone(),
+            default_2d_sampler_create_info(renderer_parameters.anisotropic_filtering)
         )?;
 
         let default_box_indices = Buffer::from_iter(
@@ -202,183 +355,592 @@ impl VulkanRenderer {
             [0u16,1,2,0,2,3]
         )?;
 
-        Ok(Self {
+        // Boxes are scaled and positioned in the vertex shader, so one unit box can be used for all of them.
+        let default_box_vertices = generate_box(memory_allocator.clone(), 0.0, 0.0, 1.0, 1.0);
+
+        // Bound for anything that isn't instanced, so it can use the same pipelines as instanced geometry.
+        let default_instance = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            [VulkanModelInstance::from_transform(Mat4::IDENTITY)]
+        )?;
+
+        // Per-frame uniforms are suballocated from arenas that get reused once the GPU is done with them, rather than
+        // allocating a new buffer for each one.
+        let uniform_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::UNIFORM_BUFFER,
+                memory_type_filter: default_allocation_create_info().memory_type_filter,
+                ..Default::default()
+            }
+        );
+        let vertex_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::VERTEX_BUFFER,
+                memory_type_filter: default_allocation_create_info().memory_type_filter,
+                ..Default::default()
+            }
+        );
+
+        let renderer = Self {
             current_resolution: renderer_parameters.resolution,
             instance,
+            _debug_messenger: debug_messenger,
             command_buffer_allocator,
             descriptor_set_allocator,
+            uniform_buffer_allocator,
+            vertex_buffer_allocator,
             device,
             queue,
             future,
             pipelines,
+            fog_descriptor_sets: HashMap::new(),
             swapchain,
             surface,
             swapchain_image_views,
             memory_allocator,
             default_2d_sampler,
+            sampler_cache,
+            lightmap_views: HashMap::new(),
             samples_per_pixel,
-            default_box_indices
-        })
+            color_format,
+            depth_format,
+            srgb_correct,
+            default_box_indices,
+            default_box_vertices,
+            default_instance,
+            last_image_index: None,
+            render_scale: renderer_parameters.render_scale,
+            upscale_filter: renderer_parameters.upscale_filter,
+            vsync: renderer_parameters.vsync,
+            present_mode: renderer_parameters.present_mode,
+            fxaa,
+            debug_labels: instance.enabled_extensions().ext_debug_utils,
+            exposure: 1.0,
+            tonemap: TonemapOperator::Passthrough,
+            last_frame_post_processed: false,
+            device_lost: false,
+            depth_prepass: renderer_parameters.depth_prepass,
+            frame_timestamps,
+            last_frame_timings: None,
+            bulk_load_commands: None
+        };
+        renderer.name_debug_objects();
+
+        Ok(renderer)
     }
 
     pub fn draw_frame(renderer: &mut Renderer) -> MResult<bool> {
         let vulkan_renderer = &mut renderer.vulkan;
+        if vulkan_renderer.device_lost {
+            return Err(Error::DeviceLost)
+        }
+
+        // Anything drawn this frame may have been uploaded in the current bulk load.
+        vulkan_renderer.flush_bulk_load()?;
+
+        // Headless; there is nothing to acquire, so just draw to the offline images.
+        let Some(swapchain) = vulkan_renderer.swapchain.clone() else {
+            return Self::draw_frame_to_image(renderer, 0, None)
+        };
 
         let (image_index, suboptimal, acquire_future) =
-            match acquire_next_image(vulkan_renderer.swapchain.clone(), None).map_err(Validated::unwrap) {
+            match acquire_next_image(swapchain, None).map_err(Validated::unwrap) {
                 Ok(r) => r,
                 Err(VulkanError::OutOfDate) => return Ok(false),
-                Err(e) => panic!("failed to acquire next image: {e}"),
+                Err(VulkanError::DeviceLost) => {
+                    vulkan_renderer.device_lost = true;
+                    return Err(Error::DeviceLost)
+                },
+                Err(e) => return Err(Error::from_vulkan_error_with_source(format!("Failed to acquire next image: {e}"), e)),
             };
 
-        Ok(Self::draw_frame_infallible(renderer, image_index, acquire_future) && !suboptimal)
+        Ok(Self::draw_frame_to_image(renderer, image_index, Some(acquire_future))? && !suboptimal)
     }
 
-    pub fn rebuild_swapchain(&mut self, renderer_parameters: &RendererParameters) -> MResult<()> {
-        let (swapchain, swapchain_images) = self.swapchain.recreate(
-            SwapchainCreateInfo {
-                image_extent: [renderer_parameters.resolution.width, renderer_parameters.resolution.height],
-                ..self.swapchain.create_info()
-            }
+    pub fn read_frame_to_buffer(renderer: &mut Renderer) -> MResult<(Resolution, Vec<u8>)> {
+        let vulkan_renderer = &mut renderer.vulkan;
+
+        let Some(image_index) = vulkan_renderer.last_image_index else {
+            return Err(Error::from_data_error_string("No frame has been drawn yet".to_owned()))
+        };
+
+        let images = vulkan_renderer.swapchain_image_views[image_index as usize].clone();
+        let image = images.post_process
+            .as_ref()
+            .filter(|_| vulkan_renderer.last_frame_post_processed)
+            .or(images.resolve.as_ref())
+            .unwrap_or(&images.color)
+            .image()
+            .clone();
+        let [width, height, ..] = image.extent();
+
+        let buffer = Buffer::new_slice::<u8>(
+            vulkan_renderer.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::TRANSFER_DST, ..Default::default() },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (width as u64) * (height as u64) * (OFFLINE_PIPELINE_COLOR_FORMAT.block_size())
         )?;
 
-        self.swapchain = swapchain;
-        self.swapchain_image_views = Self::make_swapchain_images(swapchain_images, self.memory_allocator.clone(), self.samples_per_pixel, renderer_parameters.render_scale);
-        self.current_resolution = renderer_parameters.resolution;
-        self.pipelines = load_all_pipelines(&self.swapchain_image_views[0], self.device.clone()).expect("failed to reload pipelines...");
+        let mut command_builder = AutoCommandBufferBuilder::primary(
+            &vulkan_renderer.command_buffer_allocator,
+            vulkan_renderer.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        )?;
 
+        // HDR frames are converted to RGBA8 first. HDR always renders sRGB-correct, so these are linear and need to be
+        // gamma-corrected like sRGB frames.
+        let image = if image.format() == HDR_OFFLINE_PIPELINE_COLOR_FORMAT {
+            let converted = Image::new(
+                vulkan_renderer.memory_allocator.clone(),
+                ImageCreateInfo {
+                    extent: [width, height, 1],
+                    format: SRGB_OFFLINE_PIPELINE_COLOR_FORMAT,
+                    image_type: ImageType::Dim2d,
+                    usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default()
+            )?;
+            command_builder.blit_image(BlitImageInfo::images(image, converted.clone()))?;
+            converted
+        }
+        else {
+            image
+        };
+
+        command_builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))?;
+        let commands = command_builder.build()?;
+
+        let future = vulkan_renderer
+            .future
+            .take()
+            .expect("there's no future :(");
+
+        // Wait for the frame (and the copy) to finish so the data is valid.
+        let result = future
+            .then_execute(vulkan_renderer.queue.clone(), commands)
+            .map_err(|e| Error::from_vulkan_error(e.to_string()))
+            .and_then(|f| Ok(f.then_signal_fence_and_flush()?))
+            .and_then(|f| Ok(f.wait(None)?));
+
+        vulkan_renderer.future = Some(vulkano::sync::now(vulkan_renderer.device.clone()).boxed_send_sync());
+        result?;
+
+        let pixels = buffer
+            .read()
+            .map_err(|e| Error::from_vulkan_error(e.to_string()))?
+            .to_vec();
+
+        Ok((Resolution { width, height }, pixels))
+    }
+
+    /// Get a sampler matching `create_info`, reusing an existing one if possible.
+    pub fn get_sampler(&mut self, create_info: SamplerCreateInfo) -> MResult<Arc<Sampler>> {
+        self.sampler_cache.get(self.device.clone(), create_info)
+    }
+
+    /// Get a view of bitmap `index` of a lightmap bitmap, sharing it with any other BSP that uses the same lightmap.
+    pub fn get_lightmap_view(&mut self, bitmap_path: &Arc<String>, index: usize, image: &Arc<Image>) -> MResult<Arc<ImageView>> {
+        let key = (bitmap_path.clone(), index);
+        if let Some(view) = self.lightmap_views.get(&key) {
+            return Ok(view.clone())
+        }
+
+        let view = ImageView::new(image.clone(), ImageViewCreateInfo::from_image(image.as_ref()))?;
+        self.lightmap_views.insert(key, view.clone());
+        Ok(view)
+    }
+
+    /// Drop the shared lightmap views of a bitmap, such as when it is removed.
+    ///
+    /// If `bitmap_path` is `None`, all of them are dropped.
+    pub fn remove_lightmap_views(&mut self, bitmap_path: Option<&str>) {
+        match bitmap_path {
+            Some(path) => self.lightmap_views.retain(|(p, _), _| p.as_str() != path),
+            None => self.lightmap_views.clear()
+        }
+    }
+
+    /// Recreate the default 2D sampler with the given anisotropic filtering level.
+    ///
+    /// Descriptor sets holding the old sampler are not updated; they must be rebuilt by the caller. The old sampler is
+    /// returned so it can be put back with [`VulkanRenderer::restore_default_2d_sampler`] if that fails.
+    pub fn set_anisotropic_filtering(&mut self, anisotropic_filtering: Option<f32>) -> MResult<Arc<Sampler>> {
+        check_anisotropic_filtering(self.device.as_ref(), anisotropic_filtering)?;
+        let sampler = self.sampler_cache.get(
+            self.device.clone(),
+            default_2d_sampler_create_info(anisotropic_filtering)
+        )?;
+        Ok(std::mem::replace(&mut self.default_2d_sampler, sampler))
+    }
+
+    /// Put back a default 2D sampler returned by [`VulkanRenderer::set_anisotropic_filtering`].
+    pub fn restore_default_2d_sampler(&mut self, sampler: Arc<Sampler>) {
+        self.default_2d_sampler = sampler;
+    }
+
+    /// Get a sampler for shader maps like the default 2D sampler, but with the given address mode.
+    pub fn get_map_sampler(&mut self, address_mode: MapAddressMode) -> MResult<Arc<Sampler>> {
+        let address_mode = match address_mode {
+            MapAddressMode::Repeat => return Ok(self.default_2d_sampler.clone()),
+            MapAddressMode::Clamp => SamplerAddressMode::ClampToEdge,
+            MapAddressMode::Mirror => SamplerAddressMode::MirroredRepeat
+        };
+        self.get_sampler(SamplerCreateInfo {
+            address_mode: [address_mode; 3],
+            anisotropy: self.default_2d_sampler.anisotropy(),
+            lod: 0.0..=LOD_CLAMP_NONE,
+            ..SamplerCreateInfo::simple_repeat_linear()
+        })
+    }
+
+    pub fn get_last_frame_timings(&self) -> Option<FrameTimings> {
+        self.last_frame_timings.clone()
+    }
+
+    pub fn get_current_resolution(&self) -> Resolution {
+        self.current_resolution
+    }
+
+    pub fn set_viewport_count(&mut self, viewport_count: usize) {
+        if let Some(t) = self.frame_timestamps.as_mut() {
+            t.set_viewport_count(viewport_count);
+        }
+    }
+
+    pub fn rebuild_swapchain(&mut self, renderer_parameters: &RendererParameters) -> MResult<()> {
+        self.render_scale = renderer_parameters.render_scale;
+        self.upscale_filter = renderer_parameters.upscale_filter;
+        self.vsync = renderer_parameters.vsync;
+        self.present_mode = renderer_parameters.present_mode;
+        if self.device.enabled_extensions().khr_dynamic_rendering {
+            self.fxaa = renderer_parameters.fxaa.then_some(renderer_parameters.fxaa_quality);
+        }
+        self.recreate_swapchain(renderer_parameters.resolution)
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.color_format == HDR_OFFLINE_PIPELINE_COLOR_FORMAT
+    }
+
+    pub fn is_srgb_correct(&self) -> bool {
+        self.srgb_correct
+    }
+
+    pub fn get_render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: f32) -> MResult<()> {
+        if !(render_scale > 0.0 && render_scale.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Render scale {render_scale} is not a positive number")))
+        }
+        if self.render_scale == render_scale {
+            return Ok(())
+        }
+
+        self.render_scale = render_scale;
+        self.recreate_offline_images();
         Ok(())
     }
 
-    fn make_swapchain_images(swapchain_images: Vec<Arc<Image>>, memory_allocator: Arc<StandardMemoryAllocator>, samples_per_pixel: SampleCount, render_scale: f32) -> Vec<Arc<SwapchainImages>> {
-        assert!(render_scale > 0.0);
+    /// Recreate the images that are rendered to at the current render scale.
+    ///
+    /// The swapchain and pipelines are kept, as the formats and sample count do not change.
+    fn recreate_offline_images(&mut self) {
+        self.swapchain_image_views = self.swapchain_image_views.iter().map(|images| {
+            let Some(output) = images.output.as_ref() else {
+                return Self::make_headless_images(self.memory_allocator.clone(), self.samples_per_pixel, self.color_format, self.depth_format, self.needs_post_process_image(), self.current_resolution, self.render_scale, self.upscale_filter)
+            };
+            let [native_width, native_height, _] = output.image().extent();
+            let (width, height) = Self::get_render_resolution(self.memory_allocator.device(), native_width, native_height, self.render_scale, self.upscale_filter);
+            Self::make_offline_images(self.memory_allocator.clone(), self.samples_per_pixel, self.color_format, self.depth_format, self.needs_post_process_image(), width, height, Some(output.clone()))
+        }).collect();
+    }
 
-        let device = memory_allocator.device();
+    pub fn set_fxaa(&mut self, fxaa: Option<FXAAQuality>) -> MResult<()> {
+        if fxaa.is_some() && !self.device.enabled_extensions().khr_dynamic_rendering {
+            return Err(Error::from_data_error_string("FXAA requires dynamic rendering, which is not supported by this device".to_owned()))
+        }
+
+        let needed_post_process_image = self.needs_post_process_image();
+        self.fxaa = fxaa;
+        if needed_post_process_image != self.needs_post_process_image() {
+            self.recreate_offline_images();
+        }
+        Ok(())
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: TonemapOperator, exposure: f32) -> MResult<()> {
+        if !(exposure > 0.0 && exposure.is_finite()) {
+            return Err(Error::from_data_error_string(format!("Exposure {exposure} is not a positive number")))
+        }
+        if (tonemap != TonemapOperator::Passthrough || exposure != 1.0) && !self.device.enabled_extensions().khr_dynamic_rendering {
+            return Err(Error::from_data_error_string("Tonemapping requires dynamic rendering, which is not supported by this device".to_owned()))
+        }
+
+        let needed_post_process_image = self.needs_post_process_image();
+        self.tonemap = tonemap;
+        self.exposure = exposure;
+        if needed_post_process_image != self.needs_post_process_image() {
+            self.recreate_offline_images();
+        }
+        Ok(())
+    }
+
+    pub fn get_tonemap(&self) -> (TonemapOperator, f32) {
+        (self.tonemap, self.exposure)
+    }
+
+    /// Return `true` if tonemapping does anything, as otherwise the pass can be skipped.
+    fn tonemap_enabled(&self) -> bool {
+        self.tonemap != TonemapOperator::Passthrough || self.exposure != 1.0
+    }
+
+    fn needs_post_process_image(&self) -> bool {
+        self.fxaa.is_some() || self.tonemap_enabled()
+    }
+
+    pub fn set_upscale_filter(&mut self, upscale_filter: UpscaleFilter) {
+        self.upscale_filter = upscale_filter;
+    }
 
+    pub fn set_vsync(&mut self, vsync: bool) -> MResult<()> {
+        if self.vsync == vsync {
+            return Ok(())
+        }
+
+        if let Some(surface) = self.surface.as_ref() {
+            // Check this before changing anything so the current swapchain stays intact on failure.
+            helper::get_present_mode(self.device.as_ref(), surface.as_ref(), self.present_mode, vsync)?;
+        }
+
+        self.vsync = vsync;
+        self.recreate_swapchain(self.current_resolution)
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: Option<PresentModePreference>) -> MResult<()> {
+        if self.present_mode == present_mode {
+            return Ok(())
+        }
+
+        if let Some(surface) = self.surface.as_ref() {
+            // Check this before changing anything so the current swapchain stays intact on failure.
+            helper::get_present_mode(self.device.as_ref(), surface.as_ref(), present_mode, self.vsync)?;
+        }
+
+        self.present_mode = present_mode;
+        self.recreate_swapchain(self.current_resolution)
+    }
+
+    fn recreate_swapchain(&mut self, resolution: Resolution) -> MResult<()> {
+        if let (Some(swapchain), Some(surface)) = (self.swapchain.as_ref(), self.surface.as_ref()) {
+            let (swapchain, swapchain_images) = swapchain.recreate(
+                SwapchainCreateInfo {
+                    image_extent: [resolution.width, resolution.height],
+                    present_mode: helper::get_present_mode(self.device.as_ref(), surface.as_ref(), self.present_mode, self.vsync)?,
+                    ..swapchain.create_info()
+                }
+            )?;
+
+            self.swapchain = Some(swapchain);
+            self.swapchain_image_views = Self::make_swapchain_images(swapchain_images, self.memory_allocator.clone(), self.samples_per_pixel, self.color_format, self.depth_format, self.needs_post_process_image(), self.render_scale, self.upscale_filter);
+        }
+        else {
+            self.swapchain_image_views = vec![Self::make_headless_images(self.memory_allocator.clone(), self.samples_per_pixel, self.color_format, self.depth_format, self.needs_post_process_image(), resolution, self.render_scale, self.upscale_filter)];
+        }
+        self.current_resolution = resolution;
+        if let Some(t) = self.frame_timestamps.as_mut() {
+            t.clear();
+        }
+        self.last_image_index = None;
+        self.pipelines = load_all_pipelines(&self.swapchain_image_views[0], self.device.clone())?;
+        self.fog_descriptor_sets.clear();
+        self.name_debug_objects();
+
+        Ok(())
+    }
+
+    fn make_swapchain_images(swapchain_images: Vec<Arc<Image>>, memory_allocator: Arc<StandardMemoryAllocator>, samples_per_pixel: SampleCount, color_format: Format, depth_format: Format, post_process_image: bool, render_scale: f32, upscale_filter: UpscaleFilter) -> Vec<Arc<SwapchainImages>> {
         swapchain_images.iter().map(|i| {
             let native_width = i.extent()[0];
             let native_height = i.extent()[1];
+            let (width, height) = Self::get_render_resolution(memory_allocator.device(), native_width, native_height, render_scale, upscale_filter);
 
-            let width;
-            let height;
-            if render_scale != 1.0 {
-                let max_width = device.physical_device().properties().max_framebuffer_width;
-                let max_height = device.physical_device().properties().max_framebuffer_height;
+            let output = ImageView::new_default(i.clone()).unwrap();
+            Self::make_offline_images(memory_allocator.clone(), samples_per_pixel, color_format, depth_format, post_process_image, width, height, Some(output))
+        }).collect()
+    }
 
-                let attempted_width = ((native_width as f32) * render_scale.sqrt()) as u32;
-                let attempted_height = ((native_height as f32) * render_scale.sqrt()) as u32;
+    fn make_headless_images(memory_allocator: Arc<StandardMemoryAllocator>, samples_per_pixel: SampleCount, color_format: Format, depth_format: Format, post_process_image: bool, resolution: Resolution, render_scale: f32, upscale_filter: UpscaleFilter) -> Arc<SwapchainImages> {
+        let (width, height) = Self::get_render_resolution(memory_allocator.device(), resolution.width, resolution.height, render_scale, upscale_filter);
+        Self::make_offline_images(memory_allocator, samples_per_pixel, color_format, depth_format, post_process_image, width, height, None)
+    }
 
-                width = attempted_width.clamp(1, max_width);
-                height = attempted_height.clamp(1, max_height);
+    fn get_render_resolution(device: &Arc<Device>, native_width: u32, native_height: u32, render_scale: f32, upscale_filter: UpscaleFilter) -> (u32, u32) {
+        assert!(render_scale > 0.0);
 
-                if width != attempted_width || height != attempted_height {
-                    eprintln!("Resolution {attempted_width}x{attempted_height} is not supported by the GPU... resizing");
-                }
-            }
-            else {
-                width = native_width;
-                height = native_height;
+        let width;
+        let height;
+        if render_scale != 1.0 {
+            let max_width = device.physical_device().properties().max_framebuffer_width;
+            let max_height = device.physical_device().properties().max_framebuffer_height;
+
+            let attempted_width = ((native_width as f32) * render_scale.sqrt()) as u32;
+            let attempted_height = ((native_height as f32) * render_scale.sqrt()) as u32;
+
+            width = attempted_width.clamp(1, max_width);
+            height = attempted_height.clamp(1, max_height);
+
+            if width != attempted_width || height != attempted_height {
+                log(LogLevel::Warning, &format!("Resolution {attempted_width}x{attempted_height} is not supported by the GPU... resizing"));
             }
+        }
+        else {
+            width = native_width;
+            height = native_height;
+        }
 
-            println!("Render resolution: {width}x{height} ({native_width}x{native_height}x{:.02}%)", render_scale * 100.0);
+        log(LogLevel::Info, &format!("Render resolution: {width}x{height} ({native_width}x{native_height}x{:.02}%, {upscale_filter:?} upscaling)", render_scale * 100.0));
 
-            let output = ImageView::new_default(i.clone()).unwrap();
-            let color = ImageView::new_default(Image::new(
+        (width, height)
+    }
+
+    /// Make the images that are actually rendered to.
+    ///
+    /// If `output` is `None`, the frame is not copied anywhere after rendering (i.e. headless).
+    fn make_offline_images(memory_allocator: Arc<StandardMemoryAllocator>, samples_per_pixel: SampleCount, color_format: Format, depth_format: Format, post_process_image: bool, width: u32, height: u32, output: Option<Arc<ImageView>>) -> Arc<SwapchainImages> {
+        let device = memory_allocator.device();
+
+        let color = ImageView::new_default(Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                extent: [width, height, 1],
+                format: color_format,
+                image_type: ImageType::Dim2d,
+                samples: samples_per_pixel,
+                usage: if samples_per_pixel == SampleCount::Sample1 {
+                    // This is also the resolved image, so post-processing needs to be able to sample and write to it.
+                    ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED
+                }
+                else {
+                    ImageUsage::TRANSFER_SRC | ImageUsage::COLOR_ATTACHMENT
+                },
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        ).unwrap()).unwrap();
+
+        let depth = ImageView::new_default(Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                extent: [width, height, 1],
+                format: depth_format,
+                image_type: ImageType::Dim2d,
+                samples: samples_per_pixel,
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        ).unwrap()).unwrap();
+
+        let resolve = if samples_per_pixel != SampleCount::Sample1 {
+            Some(ImageView::new_default(Image::new(
                 memory_allocator.clone(),
                 ImageCreateInfo {
                     extent: [width, height, 1],
-                    format: OFFLINE_PIPELINE_COLOR_FORMAT,
+                    format: color_format,
                     image_type: ImageType::Dim2d,
-                    samples: samples_per_pixel,
-                    usage: ImageUsage::TRANSFER_SRC | ImageUsage::COLOR_ATTACHMENT,
+                    samples: SampleCount::Sample1,
+                    usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
                     ..Default::default()
                 },
                 AllocationCreateInfo::default(),
-            ).unwrap()).unwrap();
+            ).unwrap()).unwrap())
+        } else {
+            None
+        };
 
-            let depth = ImageView::new_default(Image::new(
+        // Built-in post-processing passes alternate between this and the resolved image.
+        let post_process = if post_process_image {
+            Some(ImageView::new_default(Image::new(
                 memory_allocator.clone(),
                 ImageCreateInfo {
                     extent: [width, height, 1],
-                    format: Format::D32_SFLOAT,
+                    format: color_format,
                     image_type: ImageType::Dim2d,
-                    samples: samples_per_pixel,
-                    usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSFER_DST,
+                    samples: SampleCount::Sample1,
+                    usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
                     ..Default::default()
                 },
                 AllocationCreateInfo::default(),
-            ).unwrap()).unwrap();
-
-            let resolve = if samples_per_pixel != SampleCount::Sample1 {
-                Some(ImageView::new_default(Image::new(
-                    memory_allocator.clone(),
-                    ImageCreateInfo {
-                        extent: [width, height, 1],
-                        format: OFFLINE_PIPELINE_COLOR_FORMAT,
-                        image_type: ImageType::Dim2d,
-                        samples: SampleCount::Sample1,
-                        usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo::default(),
-                ).unwrap()).unwrap())
-            } else {
-                None
-            };
+            ).unwrap()).unwrap())
+        } else {
+            None
+        };
 
-            let framebuffer = if !device.enabled_extensions().khr_dynamic_rendering {
-                let color_format = color.image().format();
-                let depth_format = depth.image().format();
-                let samples = color.image().samples();
-
-                let render_pass = single_pass_renderpass!(
-                    device.clone(),
-                    attachments: {
-                        color: {
-                            format: color_format,
-                            samples: samples,
-                            load_op: Load,
-                            store_op: Store,
-                        },
-                        depth_stencil: {
-                            format: depth_format,
-                            samples: samples,
-                            load_op: Load,
-                            store_op: DontCare,
-                        }
-                    },
-                    pass: {
-                        color: [color],
-                        depth_stencil: {depth_stencil},
+        let framebuffer = if !device.enabled_extensions().khr_dynamic_rendering {
+            let color_format = color.image().format();
+            let samples = color.image().samples();
+
+            let render_pass = single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    color: {
+                        format: color_format,
+                        samples: samples,
+                        load_op: Load,
+                        store_op: Store,
                     },
-                ).expect("failed to make render pass");
-
-                let framebuffer = Framebuffer::new(render_pass, FramebufferCreateInfo {
-                    attachments: vec![
-                        color.clone(),
-                        depth.clone()
-                    ],
-                    extent: [width, height],
-                    ..Default::default()
-                }).expect("failed to make framebuffer");
+                    depth_stencil: {
+                        format: depth_format,
+                        samples: samples,
+                        load_op: Load,
+                        store_op: DontCare,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth_stencil},
+                },
+            ).expect("failed to make render pass");
+
+            let framebuffer = Framebuffer::new(render_pass, FramebufferCreateInfo {
+                attachments: vec![
+                    color.clone(),
+                    depth.clone()
+                ],
+                extent: [width, height],
+                ..Default::default()
+            }).expect("failed to make framebuffer");
 
-                Some(framebuffer)
-            }
-            else {
-                None
-            };
+            Some(framebuffer)
+        }
+        else {
+            None
+        };
 
-            Arc::new(SwapchainImages {
-                output,
-                color,
-                depth,
-                resolve,
-                framebuffer
-            })
-        }).collect()
+        Arc::new(SwapchainImages {
+            output,
+            color,
+            depth,
+            resolve,
+            post_process,
+            framebuffer
+        })
     }
 
-    fn draw_frame_infallible(renderer: &mut Renderer, image_index: u32, image_future: SwapchainAcquireFuture) -> bool {
+    fn draw_frame_to_image(renderer: &mut Renderer, image_index: u32, image_future: Option<SwapchainAcquireFuture>) -> MResult<bool> {
         let currently_loaded_bsp = renderer
             .current_bsp
             .as_ref()
@@ -389,19 +951,30 @@ impl VulkanRenderer {
             &renderer.vulkan.command_buffer_allocator,
             renderer.vulkan.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit
-        ).expect("failed to init command builder");
+        ).map_err(|e| renderer.vulkan.flush_error(e))?;
 
         let images = renderer.vulkan.swapchain_image_views[image_index as usize].clone();
-        image_future.wait(Some(Duration::from_millis(5000))).expect("waited too long");
+        if let Some(image_future) = image_future.as_ref() {
+            image_future
+                .wait(Some(Duration::from_millis(5000)))
+                .map_err(|e| renderer.vulkan.flush_error(Validated::Error(e)))?;
+        }
         renderer.vulkan.future.as_mut().unwrap().cleanup_finished();
 
+        if let Some(timestamps) = renderer.vulkan.frame_timestamps.as_mut() {
+            if let Some(timings) = timestamps.get_results(image_index) {
+                renderer.vulkan.last_frame_timings = Some(timings);
+            }
+            timestamps.begin(image_index, &mut command_builder)?;
+        }
+
         let [width, height, ..] = images.color.image().extent();
         let (width, height) = (width as f32, height as f32);
 
         command_builder.clear_depth_stencil_image(ClearDepthStencilImageInfo {
             clear_value: ClearDepthStencilValue::from(1.0),
             ..ClearDepthStencilImageInfo::image(images.depth.clone().image().clone())
-        }).expect("failed to clear depth image");
+        })?;
 
         for i in 0..renderer.player_viewports.len() {
             let player_viewport = renderer.player_viewports[i];
@@ -412,60 +985,158 @@ impl VulkanRenderer {
                 depth_range: 0.0..=1.0,
             };
 
-            Self::draw_viewport(
-                renderer,
-                &images,
-                viewport,
-                &currently_loaded_bsp,
-                &mut command_builder,
-                &player_viewport,
-                player_viewport.camera.clone()
-            );
+            renderer.vulkan.begin_debug_label(&mut command_builder, &format!("Viewport #{i}"));
+            if player_viewport.enabled {
+                Self::draw_viewport(
+                    renderer,
+                    Some(i),
+                    &images,
+                    viewport,
+                    &currently_loaded_bsp,
+                    &mut command_builder,
+                    &player_viewport,
+                    player_viewport.camera.clone()
+                )?;
+            }
+            else {
+                command_builder.set_viewport(0, [viewport.clone()].into_iter().collect())?;
+                command_builder.set_scissor(0, [scissor_for_viewport(&viewport)].into_iter().collect())?;
+                images.begin_rendering(&mut command_builder);
+                draw_box(renderer, 0.0, 0.0, 1.0, 1.0, renderer.background_color, &mut command_builder)?;
+                images.end_rendering(&mut command_builder);
+            }
+            renderer.vulkan.end_debug_label(&mut command_builder);
+
+            if let Some(timestamps) = renderer.vulkan.frame_timestamps.as_ref() {
+                timestamps.write_viewport_end(image_index, i, &mut command_builder)?;
+            }
         }
 
-        if renderer.player_viewports.len() > 1 {
+        if renderer.player_viewports.len() > 1 && !renderer.custom_viewport_layout && renderer.split_screen_bar.is_some() {
+            renderer.vulkan.begin_debug_label(&mut command_builder, "Split screen bars");
             images.begin_rendering(&mut command_builder);
             Self::draw_split_screen_bars(renderer, &mut command_builder, width, height);
             images.end_rendering(&mut command_builder);
+            renderer.vulkan.end_debug_label(&mut command_builder);
         }
 
-        if renderer.debug_font.is_some() {
-            let debug_data = renderer.debug_text.iter().last().expect("where????");
+        if !renderer.sprite_instances.is_empty() {
+            renderer.vulkan.begin_debug_label(&mut command_builder, "Sprites");
             images.begin_rendering(&mut command_builder);
-            draw_sprite(renderer, 0.0, 0.0, (renderer.vulkan.current_resolution.height as f32) / 480.0, &debug_data.bitmaps[0].vulkan.image, &mut command_builder).expect("could not draw debug shit");
+            Self::draw_sprite_instances(renderer, &mut command_builder, width, height)?;
             images.end_rendering(&mut command_builder);
+            renderer.vulkan.end_debug_label(&mut command_builder);
         }
 
-        let staging_image = if let Some(resolved_color_view) = images.resolve.as_ref().map(|iv| iv.image()) {
-            command_builder.resolve_image(
-                ResolveImageInfo::images(images.color.image().clone(), resolved_color_view.clone())
-            ).expect("resolve fail");
-            resolved_color_view
+        if !renderer.debug_text.is_empty() {
+            // Debug text is scaled as if the screen were 480 pixels tall.
+            let scale = (renderer.vulkan.current_resolution.height as f32) / 480.0;
+            let scale = [
+                scale / (renderer.vulkan.current_resolution.width as f32),
+                scale / (renderer.vulkan.current_resolution.height as f32)
+            ];
+
+            // The last viewport drawn may not cover the whole screen.
+            let viewport = Viewport {
+                offset: [0.0, 0.0],
+                extent: [width, height],
+                depth_range: 0.0..=1.0,
+            };
+
+            renderer.vulkan.begin_debug_label(&mut command_builder, "Debug text");
+            command_builder.set_scissor(0, [scissor_for_viewport(&viewport)].into_iter().collect())?;
+            command_builder.set_viewport(0, [viewport].into_iter().collect())?;
+            images.begin_rendering(&mut command_builder);
+            draw_character_quads(renderer, &renderer.debug_text, scale, &mut command_builder)?;
+            images.end_rendering(&mut command_builder);
+            renderer.vulkan.end_debug_label(&mut command_builder);
         }
-        else {
-            images.color.image()
+
+        if let Some(timestamps) = renderer.vulkan.frame_timestamps.as_ref() {
+            timestamps.write_overlays_end(image_index, &mut command_builder)?;
+        }
+
+        let filter = match renderer.vulkan.upscale_filter {
+            UpscaleFilter::Linear => Filter::Linear,
+            UpscaleFilter::Nearest => Filter::Nearest
         };
+        renderer.vulkan.begin_debug_label(&mut command_builder, "Post processing");
+        let resolved_image = images.resolve_color(&mut command_builder)?;
+        let mut staging_image = resolved_image.clone();
+        if let Some(post_process_image) = images.post_process.as_ref() {
+            // Tonemap first so FXAA works on displayable colors.
+            if renderer.vulkan.tonemap_enabled() {
+                let destination = if Arc::ptr_eq(&staging_image, post_process_image) { &resolved_image } else { post_process_image };
+                let data = pipeline::tonemap::TonemapData {
+                    exposure: renderer.vulkan.exposure,
+                    tonemap_operator: renderer.vulkan.tonemap as u32
+                };
+                Self::draw_fullscreen_pass(renderer, VulkanPipelineType::Tonemap, data, &staging_image, destination, &mut command_builder)?;
+                staging_image = destination.clone();
+            }
+            if let Some(quality) = renderer.vulkan.fxaa {
+                let destination = if Arc::ptr_eq(&staging_image, post_process_image) { &resolved_image } else { post_process_image };
+                let [width, height, ..] = staging_image.image().extent();
+                let data = pipeline::fxaa::FXAAData {
+                    inverse_resolution: [1.0 / (width as f32), 1.0 / (height as f32)],
+                    subpixel: quality.subpixel,
+                    edge_threshold: quality.edge_threshold,
+                    edge_threshold_min: quality.edge_threshold_min
+                };
+                Self::draw_fullscreen_pass(renderer, VulkanPipelineType::FXAA, data, &staging_image, destination, &mut command_builder)?;
+                staging_image = destination.clone();
+            }
+        }
+        renderer.vulkan.last_frame_post_processed = !Arc::ptr_eq(&staging_image, &resolved_image);
+        if let Some(mut post_process) = renderer.post_process.take() {
+            let result = Self::run_post_process(renderer, post_process.as_mut(), &staging_image, &mut command_builder);
+            renderer.post_process = Some(post_process);
+            result?;
+        }
+        renderer.vulkan.end_debug_label(&mut command_builder);
+
+        renderer.vulkan.begin_debug_label(&mut command_builder, "Blit to output");
+        images.blit_to_output(&mut command_builder, &staging_image, filter)?;
+        renderer.vulkan.end_debug_label(&mut command_builder);
 
-        command_builder.blit_image(BlitImageInfo {
-            filter: Filter::Linear,
-            ..BlitImageInfo::images(staging_image.clone(), images.output.image().clone())
-        }).unwrap();
+        if let Some(timestamps) = renderer.vulkan.frame_timestamps.as_ref() {
+            timestamps.write_blit_end(image_index, &mut command_builder)?;
+        }
 
-        let commands = command_builder.build().expect("failed to build command builder");
+        let commands = command_builder.build()?;
 
         let future = renderer.vulkan
             .future
             .take()
             .expect("there's no future :(");
 
-        let swapchain_present = SwapchainPresentInfo::swapchain_image_index(renderer.vulkan.swapchain.clone(), image_index);
+        let future = match (renderer.vulkan.swapchain.clone(), image_future) {
+            (Some(swapchain), Some(image_future)) => {
+                let swapchain_present = SwapchainPresentInfo::swapchain_image_index(swapchain, image_index);
+                future
+                    .join(image_future)
+                    .then_execute(renderer.vulkan.queue.clone(), commands.clone())
+                    .map(|f| f
+                        .then_swapchain_present(renderer.vulkan.queue.clone(), swapchain_present)
+                        .then_signal_fence()
+                        .boxed_send_sync())
+            },
+            _ => {
+                future
+                    .then_execute(renderer.vulkan.queue.clone(), commands.clone())
+                    .map(|f| f
+                        .then_signal_fence()
+                        .boxed_send_sync())
+            }
+        };
 
-        let future = future
-            .join(image_future)
-            .then_execute(renderer.vulkan.queue.clone(), commands.clone())
-            .expect("can't execute commands")
-            .then_swapchain_present(renderer.vulkan.queue.clone(), swapchain_present)
-            .then_signal_fence();
+        let future = match future {
+            Ok(n) => n,
+            Err(e) => {
+                renderer.vulkan.future = Some(vulkano::sync::now(renderer.vulkan.device.clone()).boxed_send_sync());
+                return Err(Error::from_vulkan_error(format!("Failed to execute commands: {e}")))
+            }
+        };
 
         loop {
             match future.flush() {
@@ -480,35 +1151,78 @@ impl VulkanRenderer {
                 },
                 Err(Validated::Error(VulkanError::OutOfDate)) => {
                     renderer.vulkan.future = Some(vulkano::sync::now(renderer.vulkan.device.clone()).boxed_send_sync());
-                    return false
+                    return Ok(false)
                 },
                 Err(e) => {
-                    panic!("Oh, shit! Some bullshit just happened: {e:?}")
+                    renderer.vulkan.future = Some(vulkano::sync::now(renderer.vulkan.device.clone()).boxed_send_sync());
+                    return Err(renderer.vulkan.flush_error(e))
                 }
             }
         }
 
-        renderer.vulkan.future = Some(future.boxed_send_sync());
-        true
+        renderer.vulkan.future = Some(future);
+        renderer.vulkan.last_image_index = Some(image_index);
+        Ok(true)
     }
 
+    /// Draw a viewport to a render target and submit it.
+    pub fn draw_to_render_target(renderer: &mut Renderer, target: &VulkanRenderTarget, player_viewport: &PlayerViewport, bsp: &Option<Arc<BSP>>) -> MResult<()> {
+        renderer.vulkan.flush_bulk_load()?;
+
+        let images = target.images.clone();
+        let mut command_builder = renderer.vulkan.generate_upload_command_builder()?;
+
+        command_builder.clear_depth_stencil_image(ClearDepthStencilImageInfo {
+            clear_value: ClearDepthStencilValue::from(1.0),
+            ..ClearDepthStencilImageInfo::image(images.depth.image().clone())
+        })?;
+
+        let [width, height, ..] = images.color.image().extent();
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [width as f32, height as f32],
+            depth_range: 0.0..=1.0,
+        };
+
+        Self::draw_viewport(
+            renderer,
+            None,
+            &images,
+            viewport,
+            bsp,
+            &mut command_builder,
+            player_viewport,
+            player_viewport.camera
+        )?;
+
+        images.copy_to_output(&mut command_builder, Filter::Linear)?;
+        renderer.vulkan.execute_command_list(command_builder.build()?)?;
+        Ok(())
+    }
+
+    /// Draw a viewport.
+    ///
+    /// `viewport_index` is the index of the player viewport being drawn, if any, for drawing its debug lines.
     fn draw_viewport(
         renderer: &mut Renderer,
+        viewport_index: Option<usize>,
         images: &Arc<SwapchainImages>,
         viewport: Viewport,
         currently_loaded_bsp: &Option<Arc<BSP>>,
         command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         player_viewport: &PlayerViewport,
         camera: Camera
-    ) {
+    ) -> MResult<()> {
         command_builder.set_viewport(0, [viewport.clone()].into_iter().collect()).unwrap();
+        command_builder.set_scissor(0, [scissor_for_viewport(&viewport)].into_iter().collect()).unwrap();
         images.begin_rendering(command_builder);
+        command_builder.bind_vertex_buffers(3, renderer.vulkan.default_instance.clone()).unwrap();
 
         let aspect_ratio = viewport.extent[0] / viewport.extent[1];
         let [z_near, mut z_far] = player_viewport.draw_distance;
 
-        let mut fog_data = player_viewport
-            .viewport_fog
+        let viewport_fog = player_viewport.viewport_fog.filter(|_| currently_loaded_bsp.is_some());
+        let mut fog_data = viewport_fog
             .map(|f| f.current_fog_data)
             .unwrap_or_default();
 
@@ -518,7 +1232,18 @@ impl VulkanRenderer {
             z_far = MAX_DRAW_DISTANCE_LIMIT;
         }
 
-        let sky_color = [fog_data.color[0], fog_data.color[1], fog_data.color[2], 1.0];
+        let z_near = camera.z_near.unwrap_or(z_near);
+        let z_far = match camera.z_far {
+            Some(z_far) => z_far,
+            // Only the near plane was overridden, so make sure it's still in front of the far plane.
+            None => z_far.max(z_near * 2.0)
+        };
+
+        let sky_color = match viewport_fog {
+            Some(f) => [f.current_fog_data.color[0], f.current_fog_data.color[1], f.current_fog_data.color[2], 1.0],
+            None => renderer.background_color
+        };
+        renderer.vulkan.begin_debug_label(command_builder, "Clear");
         draw_box(
             renderer,
             0.0,
@@ -528,85 +1253,542 @@ impl VulkanRenderer {
             sky_color,
             command_builder
         ).unwrap();
+        renderer.vulkan.end_debug_label(command_builder);
+
+        let proj = match (camera.custom_projection, camera.projection) {
+            (Some(projection), _) => Mat4::from_cols_array_2d(&projection),
+            (None, Projection::Perspective) => Mat4::perspective_lh(
+                camera.fov,
+                aspect_ratio,
+                z_near,
+                z_far
+            ),
+            (None, Projection::Orthographic { height }) => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic_lh(-half_width, half_width, -half_height, half_height, z_near, z_far)
+            }
+        };
+        let view = Mat4::look_to_lh(
+            camera.position.into(),
+            camera.rotation.into(),
+            camera.up.into()
+        );
+
+        let frustum = Frustum::from_view_projection(proj * view);
+        let is_visible = |center: Vec3, radius: f32| !camera.frustum_culling || frustum.contains_sphere(center, radius);
+
+        // Get the approximate height in pixels of a sphere, for picking a level of detail.
+        let screen_size = |radius: f32, distance: f32| match (camera.custom_projection, camera.projection) {
+            // Use the vertical scale and the clip-space W of the custom matrix at this distance.
+            (Some(_), _) => {
+                let w = proj.z_axis.w * distance + proj.w_axis.w;
+                if w > 0.0 { radius * viewport.extent[1] * proj.y_axis.y.abs() / w } else { f32::INFINITY }
+            }
+            // Size on screen does not depend on distance with an orthographic projection.
+            (None, Projection::Orthographic { height }) => radius * viewport.extent[1] / (height / 2.0),
+            (None, Projection::Perspective) if distance > 0.0 => radius * viewport.extent[1] / (distance * (camera.fov / 2.0).tan()),
+            (None, Projection::Perspective) => f32::INFINITY
+        };
+
+        let fog = get_fog_descriptor_set(renderer, viewport_index, &fog_data);
+
+        // Transparent BSP geometry and transparent parts of geometries are all queued here with their distance and
+        // shader, then drawn together back-to-front.
+        let mut transparent_geometries: Vec<(TransparentGeometry, f32, &Arc<String>)> = Vec::with_capacity(256);
+        let mut last_shader = None;
+        let mut bound_buffers = None;
+
+        let camera_position = Vec3::from(camera.position);
+        let bsp_mvp = make_model_view_uniform(renderer, camera_position, Mat4::IDENTITY, Vec3::default(), Mat3::IDENTITY, view, proj, camera.debug_view);
+
+        renderer.vulkan.begin_debug_label(command_builder, "Sky");
+        Self::draw_sky(renderer, currently_loaded_bsp, command_builder, &camera, &viewport, view, &mut last_shader, &mut bound_buffers)?;
+        renderer.vulkan.end_debug_label(command_builder);
+
+        renderer.vulkan.begin_debug_label(command_builder, "Opaque");
+
+        let bsp = currently_loaded_bsp.as_ref().and_then(|bsp| {
+            Some((bsp, bsp.vulkan.subbuffers.as_ref()?))
+        });
+
+        if let Some((bsp, buffers)) = bsp {
+            let visible_geometries = bsp.get_visible_geometries(camera.position);
+            let is_geometry_visible = |index: usize| {
+                let geometry = &bsp.geometries[index];
+                visible_geometries.as_ref().map_or(true, |v| v[index]) && is_visible(Vec3::from(geometry.centroid), geometry.radius)
+            };
+
+            buffers.bind(command_builder);
+            bound_buffers = Some(BoundVertexBuffers::BSP);
+
+            if renderer.vulkan.depth_prepass && !camera.wireframe {
+                Self::draw_depth_prepass(renderer, bsp, command_builder, bsp_mvp.clone(), &is_geometry_visible);
+
+                // The main pipelines need to be rebound.
+                last_shader = None;
+            }
+
+            // Draw non-transparent shaders first
+            //
+            // Visible geometries next to each other in the buffers with the same shader, lightmap, and base vertex
+            // are merged into one draw.
+            let mut runs: Vec<(usize, VertexOffsets)> = Vec::with_capacity(bsp.vulkan.opaque_geometries.len());
+            for index in bsp.vulkan.opaque_geometries.iter().copied().filter(|i| is_geometry_visible(*i)) {
+                let geometry = &bsp.geometries[index];
+                if let Some((last_index, offset)) = runs.last_mut() {
+                    let last = &bsp.geometries[*last_index];
+                    if index == *last_index + 1
+                        && last.shader == geometry.shader
+                        && last.lightmap_index == geometry.lightmap_index
+                        && last.offset.vertex_offset == geometry.offset.vertex_offset {
+                        offset.index_count += geometry.offset.index_count;
+                        *last_index = index;
+                        continue
+                    }
+                }
+                runs.push((index, geometry.offset));
+            }
+            for (index, offset) in &runs {
+                Self::draw_bsp_geometry(renderer, bsp, command_builder, &camera, &mut last_shader, &bsp.geometries[*index], offset, fog.clone(), bsp_mvp.clone());
+            }
+
+            transparent_geometries.extend(bsp
+                .vulkan
+                .transparent_geometries
+                .iter()
+                .filter(|i| is_geometry_visible(**i))
+                .map(|i| (TransparentGeometry::BSP(*i), camera_position.distance_squared(Vec3::from(bsp.geometries[*i].centroid)), &bsp.geometries[*i].shader))
+            );
+        }
+
+        let instances: Vec<GeometryInstanceDraw> = renderer
+            .geometry_instances
+            .iter()
+            .filter_map(|instance| {
+                let (path, geometry) = renderer.geometries.get_key_value(&instance.geometry)?;
+                let buffers = geometry.vulkan.subbuffers.as_ref()?;
+
+                let (scale, _, translation) = instance.transform.to_scale_rotation_translation();
+                let distance = camera_position.distance(translation);
+                let radius = geometry.bounding_radius * scale.max_element();
+                let pixels = screen_size(radius, distance);
+
+                let rotation = Mat3::from_mat4(instance.transform).inverse().transpose();
+                let mvp = make_model_view_uniform(renderer, camera_position, instance.transform, Vec3::default(), rotation, view, proj, camera.debug_view);
+
+                Some(GeometryInstanceDraw {
+                    path,
+                    geometry,
+                    buffers,
+                    mvp,
+                    transform: instance.transform,
+                    geometry_indices: geometry.get_geometries_for_screen_size(pixels).collect()
+                })
+            })
+            .collect();
+
+        for (instance_index, instance) in instances.iter().enumerate() {
+            for geometry_index in instance.geometry_indices.iter().copied() {
+                for (part_index, part) in instance.geometry.geometries[geometry_index].parts.iter().enumerate() {
+                    if renderer.shaders[&part.shader].vulkan.pipeline_data.is_transparent() {
+                        let centroid = instance.transform.transform_point3(Vec3::from(part.centroid));
+                        transparent_geometries.push((TransparentGeometry::Geometry { instance_index, geometry_index, part_index }, camera_position.distance_squared(centroid), &part.shader));
+                        continue;
+                    }
+                    Self::draw_geometry_instance_part(renderer, command_builder, &camera, &mut last_shader, &mut bound_buffers, instance, geometry_index, part_index, fog.clone());
+                }
+            }
+        }
+
+        let instanced_geometries: Vec<InstancedGeometryDraw> = renderer
+            .instanced_geometries
+            .iter()
+            .filter_map(|instanced| {
+                let (path, geometry) = renderer.geometries.get_key_value(&instanced.geometry)?;
+                let buffers = geometry.vulkan.subbuffers.as_ref()?;
+
+                let mut closest: Option<(Mat4, f32)> = None;
+                let mut pixels = 0.0f32;
+                let instances: Vec<VulkanModelInstance> = instanced
+                    .transforms
+                    .iter()
+                    .filter(|transform| {
+                        let (scale, _, translation) = transform.to_scale_rotation_translation();
+                        let radius = geometry.bounding_radius * scale.max_element();
+                        if !is_visible(translation, radius) {
+                            return false
+                        }
+
+                        let distance = camera_position.distance(translation);
+                        pixels = pixels.max(screen_size(radius, distance));
+                        if closest.map_or(true, |(_, d)| distance < d) {
+                            closest = Some((**transform, distance));
+                        }
+                        true
+                    })
+                    .map(|transform| VulkanModelInstance::from_transform(*transform))
+                    .collect();
+                let (closest_transform, _) = closest?;
+
+                let instances = match make_frame_vertex_buffer(renderer, &instances) {
+                    Ok(n) => n,
+                    Err(e) => return Some(Err(e))
+                };
+
+                Some(Ok(InstancedGeometryDraw {
+                    path,
+                    geometry,
+                    buffers,
+                    instances,
+                    closest_transform,
+                    geometry_indices: geometry.get_geometries_for_screen_size(pixels).collect()
+                }))
+            })
+            .collect::<MResult<_>>()?;
+
+        // Instance transforms are in the instance buffer, so these use the same identity world transform as the BSP.
+        for (instanced_index, instanced) in instanced_geometries.iter().enumerate() {
+            for geometry_index in instanced.geometry_indices.iter().copied() {
+                for (part_index, part) in instanced.geometry.geometries[geometry_index].parts.iter().enumerate() {
+                    if renderer.shaders[&part.shader].vulkan.pipeline_data.is_transparent() {
+                        let centroid = instanced.closest_transform.transform_point3(Vec3::from(part.centroid));
+                        transparent_geometries.push((TransparentGeometry::InstancedGeometry { instanced_index, geometry_index, part_index }, camera_position.distance_squared(centroid), &part.shader));
+                        continue;
+                    }
+                    Self::draw_instanced_geometry_part(renderer, command_builder, &camera, &mut last_shader, &mut bound_buffers, instanced, geometry_index, part_index, fog.clone(), bsp_mvp.clone());
+                }
+            }
+        }
+
+        renderer.vulkan.end_debug_label(command_builder);
+
+        // Geometries at the same distance are grouped by shader so descriptors can be reused between them.
+        transparent_geometries
+            .sort_by(|a,b| b.1.total_cmp(&a.1).then_with(|| a.2.cmp(b.2)));
+
+        renderer.vulkan.begin_debug_label(command_builder, "Transparent");
+
+        for (transparent_geometry, _, _) in transparent_geometries {
+            match transparent_geometry {
+                TransparentGeometry::BSP(index) => {
+                    let (bsp, buffers) = bsp.expect("BSP transparent geometry without a BSP?");
+                    let geometry = &bsp.geometries[index];
+                    if bound_buffers != Some(BoundVertexBuffers::BSP) {
+                        buffers.bind(command_builder);
+                        bound_buffers = Some(BoundVertexBuffers::BSP);
+                    }
+                    Self::draw_bsp_geometry(renderer, bsp, command_builder, &camera, &mut last_shader, geometry, &geometry.offset, fog.clone(), bsp_mvp.clone());
+                }
+                TransparentGeometry::Geometry { instance_index, geometry_index, part_index } => {
+                    Self::draw_geometry_instance_part(renderer, command_builder, &camera, &mut last_shader, &mut bound_buffers, &instances[instance_index], geometry_index, part_index, fog.clone());
+                }
+                TransparentGeometry::InstancedGeometry { instanced_index, geometry_index, part_index } => {
+                    Self::draw_instanced_geometry_part(renderer, command_builder, &camera, &mut last_shader, &mut bound_buffers, &instanced_geometries[instanced_index], geometry_index, part_index, fog.clone(), bsp_mvp.clone());
+                }
+            }
+        }
+        renderer.vulkan.end_debug_label(command_builder);
+
+        if let Some(viewport_index) = viewport_index {
+            Self::draw_debug_lines(renderer, viewport_index, command_builder, bsp_mvp);
+            Self::draw_overlay(renderer, viewport_index, &viewport, command_builder)?;
+        }
+
+        images.end_rendering(command_builder);
+        Ok(())
+    }
+
+    /// Call the overlay callback for the viewport, if any, and draw everything it queued.
+    fn draw_overlay(
+        renderer: &mut Renderer,
+        viewport_index: usize,
+        viewport: &Viewport,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        let Some(mut callback) = renderer.overlay_callback.take() else {
+            return Ok(())
+        };
+
+        let rect = [viewport.offset[0], viewport.offset[1], viewport.extent[0], viewport.extent[1]];
+        let mut context = OverlayContext::new(renderer, viewport_index, rect);
+        callback(&mut context);
+        let draws = context.draws;
+        renderer.overlay_callback = Some(callback);
+
+        if draws.is_empty() {
+            return Ok(())
+        }
+
+        renderer.vulkan.begin_debug_label(command_builder, "Overlay");
+        let text_scale = [1.0 / viewport.extent[0], 1.0 / viewport.extent[1]];
+        let result = draws.iter().try_for_each(|draw| match draw {
+            OverlayDraw::Box { rect, color } => {
+                let [x, y, width, height] = *rect;
+                draw_box(renderer, x, y, width, height, *color, command_builder)
+            }
+            OverlayDraw::Sprite { bitmap, bitmap_index, texture_rect, rect, color } => {
+                let Some(bitmap) = renderer.bitmaps.get(bitmap) else {
+                    return Ok(())
+                };
+                draw_sprite(renderer, *rect, *texture_rect, *color, &bitmap.bitmaps[*bitmap_index].vulkan.image, command_builder)
+            }
+            OverlayDraw::Text { quads } => draw_character_quads(renderer, quads, text_scale, command_builder)
+        });
+        renderer.vulkan.end_debug_label(command_builder);
+
+        result
+    }
+
+    /// Draw all debug lines for the viewport in one draw.
+    fn draw_debug_lines(
+        renderer: &Renderer,
+        viewport_index: usize,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        mvp: Arc<PersistentDescriptorSet>
+    ) {
+        let vertices: Vec<VulkanDebugLineVertex> = renderer
+            .debug_lines
+            .iter()
+            .filter(|l| l.viewport == viewport_index)
+            .flat_map(|l| [
+                VulkanDebugLineVertex { position: l.start, color: l.color },
+                VulkanDebugLineVertex { position: l.end, color: l.color }
+            ])
+            .collect();
+
+        if vertices.is_empty() {
+            return
+        }
+
+        let pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::DebugLine].clone();
+
+        let vertex_buffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::VERTEX_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            vertices
+        ).unwrap();
+        let vertex_count = vertex_buffer.len() as u32;
+
+        command_builder.bind_pipeline_graphics(pipeline.get_pipeline()).unwrap();
+        command_builder.set_cull_mode(CullMode::None).unwrap();
+        upload_main_material_uniform(command_builder, pipeline, mvp);
+        command_builder.bind_vertex_buffers(0, vertex_buffer).unwrap();
+        command_builder.draw(vertex_count, 1, 0, 0).unwrap();
+    }
+
+    /// Draw all visible opaque BSP geometry to the depth buffer only.
+    ///
+    /// When the opaque geometry is drawn afterward, any fragment that is hidden fails the depth test before it is shaded.
+    fn draw_depth_prepass(
+        renderer: &Renderer,
+        bsp: &BSP,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        mvp: Arc<PersistentDescriptorSet>,
+        is_geometry_visible: &dyn Fn(usize) -> bool
+    ) {
+        let pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::DepthPrepass].clone();
+
+        command_builder
+            .bind_pipeline_graphics(pipeline.get_pipeline())
+            .expect("tried to bind depth prepass pipeline");
+        command_builder.set_cull_mode(CullMode::Back)
+            .expect("tried to set cull mode back to Back");
+        upload_main_material_uniform(command_builder, pipeline, mvp);
+
+        for geometry in bsp
+            .vulkan
+            .opaque_geometries
+            .iter()
+            .filter(|i| is_geometry_visible(**i))
+            .map(|i| &bsp.geometries[*i])
+            .filter(|g| renderer.shaders[&g.shader].vulkan.pipeline_data.can_depth_prepass()) {
+            geometry.offset
+                .make_vulkan_draw_command(1, command_builder)
+                .expect("can't draw depth prepass geometry");
+        }
+    }
+
+    /// Draw the sky of the cluster the camera is in, if any.
+    ///
+    /// The depth buffer is cleared afterwards so the sky is always behind everything else.
+    fn draw_sky<'a>(
+        renderer: &'a Renderer,
+        currently_loaded_bsp: &Option<Arc<BSP>>,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: &Camera,
+        viewport: &Viewport,
+        view: Mat4,
+        last_shader: &mut Option<&'a Arc<String>>,
+        bound_buffers: &mut Option<BoundVertexBuffers<'a>>
+    ) -> MResult<()> {
+        // A sky at infinity can't be seen without perspective.
+        if camera.projection != Projection::Perspective && camera.custom_projection.is_none() {
+            return Ok(())
+        }
+
+        let Some(bsp) = currently_loaded_bsp.as_ref() else { return Ok(()) };
+        let Some(cluster) = bsp.bsp_data.find_cluster(camera.position) else { return Ok(()) };
+        let Some(sky) = bsp.bsp_data.clusters[cluster].sky.as_ref().and_then(|s| renderer.skies.get(s)) else { return Ok(()) };
+        let Some((path, geometry)) = sky.geometry.as_ref().and_then(|g| renderer.geometries.get_key_value(g)) else { return Ok(()) };
+        let Some(buffers) = geometry.vulkan.subbuffers.as_ref() else { return Ok(()) };
+
+        // The sky is not limited by draw distance, and it is centered on the camera so it can never be reached.
+        let aspect_ratio = viewport.extent[0] / viewport.extent[1];
+        let proj = match camera.custom_projection {
+            Some(projection) => Mat4::from_cols_array_2d(&projection),
+            None => Mat4::perspective_infinite_lh(camera.fov, aspect_ratio, DRAW_DISTANCE_MINIMUM)
+        };
+        let camera_position = Vec3::from(camera.position);
+        let transform = Mat4::from_translation(camera_position);
+
+        let instance = GeometryInstanceDraw {
+            path,
+            geometry,
+            buffers,
+            mvp: make_model_view_uniform(renderer, camera_position, transform, Vec3::default(), Mat3::IDENTITY, view, proj, camera.debug_view),
+            transform,
+            geometry_indices: geometry.get_geometries_for_screen_size(f32::INFINITY).collect()
+        };
 
-        let proj = Mat4::perspective_lh(
-            camera.fov,
-            aspect_ratio,
-            z_near,
-            z_far
-        );
-        let view = Mat4::look_to_lh(
-            camera.position.into(),
-            camera.rotation.into(),
-            Vec3::new(0.0, 0.0, -1.0)
-        );
+        // Skies are not fogged.
+        let fog = make_fog_uniform(renderer, &FogData::default());
 
-        let fog = make_fog_uniform(renderer, &fog_data);
+        for transparent in [false, true] {
+            for geometry_index in instance.geometry_indices.iter().copied() {
+                for (part_index, part) in instance.geometry.geometries[geometry_index].parts.iter().enumerate() {
+                    if renderer.shaders[&part.shader].vulkan.pipeline_data.is_transparent() != transparent {
+                        continue;
+                    }
+                    Self::draw_geometry_instance_part(renderer, command_builder, camera, last_shader, bound_buffers, &instance, geometry_index, part_index, fog.clone());
+                }
+            }
+        }
 
-        let mut transparent_geometries: Vec<(usize, f32)> = Vec::with_capacity(256);
+        command_builder.clear_attachments(
+            [ClearAttachment::Depth(1.0)].into_iter().collect(),
+            [ClearRect {
+                offset: [viewport.offset[0] as u32, viewport.offset[1] as u32],
+                extent: [viewport.extent[0] as u32, viewport.extent[1] as u32],
+                array_layers: 0..1
+            }].into_iter().collect()
+        )?;
 
-        if let Some((bsp, buffers)) = currently_loaded_bsp.as_ref().and_then(|bsp| {
-            Some((bsp, bsp.vulkan.subbuffers.as_ref()?))
-        }) {
-            command_builder.bind_index_buffer(buffers.index_subbuffer.clone()).expect("failed to bind indices");
-            command_builder.bind_vertex_buffers(0, (
-                buffers.vertex_data_subbuffer.clone(),
-                buffers.texture_coords_subbuffer.clone(),
-                buffers.lightmap_texture_coords_subbuffer.clone()
-            )).expect("failed to bind vertex data");
+        Ok(())
+    }
 
-            let mvp = make_model_view_uniform(renderer, camera.position.into(), Vec3::default(), Mat3::IDENTITY, view, proj);
+    fn draw_bsp_geometry<'a>(
+        renderer: &'a Renderer,
+        currently_loaded_bsp: &'a BSP,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: &Camera,
+        last_shader: &mut Option<&'a Arc<String>>,
+        geometry: &'a BSPGeometry,
+        offset: &VertexOffsets,
+        fog_data: Arc<PersistentDescriptorSet>,
+        mvp: Arc<PersistentDescriptorSet>
+    ) {
+        let mut desired_lightmap = geometry.lightmap_index;
+        if !camera.lightmaps {
+            desired_lightmap = None;
+        }
 
-            // Draw non-transparent shaders first
-            let mut last_shader = None;
+        let lightmap = desired_lightmap
+            .and_then(|i| currently_loaded_bsp.vulkan.lightmap_images.get(&i))
+            .map(|b| b.clone())
+            .unwrap_or_else(|| currently_loaded_bsp.vulkan.null_lightmaps.clone());
 
-            let get_geometry_shader = |f: &usize| (&bsp.geometries[*f], &renderer.shaders[&bsp.geometries[*f].shader].vulkan.pipeline_data);
+        Self::draw_material(renderer, command_builder, camera, last_shader, &geometry.shader, lightmap, fog_data, mvp, offset, 1);
+    }
 
-            for (geometry, shader) in bsp
-                .vulkan
-                .opaque_geometries
-                .iter()
-                .map(get_geometry_shader) {
-                Self::draw_bsp_geometry(renderer, bsp, command_builder, &camera, &mut last_shader, geometry, fog.clone(), mvp.clone(), shader, &geometry.offset);
-            }
+    fn draw_geometry_instance_part<'a>(
+        renderer: &'a Renderer,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: &Camera,
+        last_shader: &mut Option<&'a Arc<String>>,
+        bound_buffers: &mut Option<BoundVertexBuffers<'a>>,
+        instance: &GeometryInstanceDraw<'a>,
+        geometry_index: usize,
+        part_index: usize,
+        fog_data: Arc<PersistentDescriptorSet>
+    ) {
+        let part = &instance.geometry.geometries[geometry_index].parts[part_index];
 
-            transparent_geometries.extend(bsp
-                .vulkan
-                .transparent_geometries
-                .iter()
-                .map(|i| (*i, Vec3::from(camera.position).distance_squared(Vec3::from(bsp.geometries[*i].centroid))))
-            );
-            transparent_geometries
-                .sort_by(|a,b| b.1.total_cmp(&a.1));
+        let buffers = Some(BoundVertexBuffers::Geometry(instance.path));
+        if *bound_buffers != buffers {
+            instance.buffers.bind(command_builder);
+            *bound_buffers = buffers;
+        }
 
-            for (geometry, shader) in transparent_geometries
-                .iter()
-                .map(|b| &b.0)
-                .map(get_geometry_shader) {
-                if geometry.shader.ends_with("water") {
-                    // FIXME: water is not yet supported and the fallback shader is broken for it; should be fixed later
-                    continue;
-                }
-                Self::draw_bsp_geometry(renderer, bsp, command_builder, &camera, &mut last_shader, geometry, fog.clone(), mvp.clone(), shader, &geometry.offset);
-            }
+        Self::draw_material(renderer, command_builder, camera, last_shader, &part.shader, instance.geometry.vulkan.null_lightmaps.clone(), fog_data, instance.mvp.clone(), &part.offsets, 1);
+    }
+
+    fn draw_instanced_geometry_part<'a>(
+        renderer: &'a Renderer,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: &Camera,
+        last_shader: &mut Option<&'a Arc<String>>,
+        bound_buffers: &mut Option<BoundVertexBuffers<'a>>,
+        instanced: &InstancedGeometryDraw<'a>,
+        geometry_index: usize,
+        part_index: usize,
+        fog_data: Arc<PersistentDescriptorSet>,
+        mvp: Arc<PersistentDescriptorSet>
+    ) {
+        let part = &instanced.geometry.geometries[geometry_index].parts[part_index];
+
+        let buffers = Some(BoundVertexBuffers::Geometry(instanced.path));
+        if *bound_buffers != buffers {
+            instanced.buffers.bind(command_builder);
+            *bound_buffers = buffers;
         }
 
-        images.end_rendering(command_builder);
+        command_builder.bind_vertex_buffers(3, instanced.instances.clone()).unwrap();
+        Self::draw_material(renderer, command_builder, camera, last_shader, &part.shader, instanced.geometry.vulkan.null_lightmaps.clone(), fog_data, mvp, &part.offsets, instanced.instances.len() as u32);
+        command_builder.bind_vertex_buffers(3, renderer.vulkan.default_instance.clone()).unwrap();
     }
 
-    fn draw_bsp_geometry<'a, 'b>(
-        renderer: &Renderer,
-        currently_loaded_bsp: &'a BSP,
+    fn draw_material<'a>(
+        renderer: &'a Renderer,
         mut command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         camera: &Camera,
-        last_shader: &'b mut Option<&'a Arc<String>>,
-        geometry: &'a BSPGeometry,
+        last_shader: &mut Option<&'a Arc<String>>,
+        this_shader: &'a Arc<String>,
+        lightmap: Arc<PersistentDescriptorSet>,
         fog_data: Arc<PersistentDescriptorSet>,
         mvp: Arc<PersistentDescriptorSet>,
-        shader: &Arc<dyn VulkanMaterial>,
-        vertices: &VertexOffsets
+        vertices: &VertexOffsets,
+        instance_count: u32
     ) {
-        let this_shader = &geometry.shader;
+        // Wireframe and overdraw replace the material's pipeline. Falls back to regular rendering if the wireframe
+        // pipeline is unsupported.
+        let (debug_pipeline, debug_cull_mode) = if camera.wireframe {
+            (renderer.vulkan.pipelines.get(&VulkanPipelineType::Wireframe), CullMode::None)
+        }
+        else if camera.debug_view == DebugView::Overdraw {
+            (renderer.vulkan.pipelines.get(&VulkanPipelineType::Overdraw), CullMode::Back)
+        }
+        else {
+            (None, CullMode::None)
+        };
+        if let Some(debug_pipeline) = debug_pipeline {
+            command_builder
+                .bind_pipeline_graphics(debug_pipeline.get_pipeline())
+                .expect("tried to bind debug pipeline");
+            command_builder.set_cull_mode(debug_cull_mode)
+                .expect("tried to set debug cull mode");
+            upload_main_material_uniform(&mut command_builder, debug_pipeline.clone(), mvp);
+            vertices
+                .make_vulkan_draw_command(instance_count, &mut command_builder)
+                .expect("can't draw debug geometry");
+
+            // The material's pipeline needs to be rebound if the debug view is turned off.
+            *last_shader = None;
+            return;
+        }
+
+        let shader = &renderer.shaders[this_shader].vulkan.pipeline_data;
         let repeat_shader = if *last_shader != Some(this_shader) && shader.can_reuse_descriptors() {
             false
         }
@@ -616,10 +1798,6 @@ impl VulkanRenderer {
         *last_shader = Some(this_shader);
 
         let main_pipeline = renderer.vulkan.pipelines.get(&shader.get_main_pipeline()).unwrap();
-        let mut desired_lightmap = geometry.lightmap_index;
-        if !camera.lightmaps {
-            desired_lightmap = None;
-        }
 
         if !repeat_shader {
             command_builder
@@ -629,12 +1807,12 @@ impl VulkanRenderer {
                 .expect("tried to set cull mode back to Back");
         }
 
-        upload_main_material_uniform(&mut command_builder, main_pipeline.clone(), mvp.clone());
-        upload_fog_uniform(&mut command_builder, main_pipeline.clone(), fog_data.clone());
-        upload_lightmap_descriptor_set(desired_lightmap, &currently_loaded_bsp, &mut command_builder, main_pipeline.clone());
+        upload_main_material_uniform(&mut command_builder, main_pipeline.clone(), mvp);
+        upload_fog_uniform(&mut command_builder, main_pipeline.clone(), fog_data);
+        upload_lightmap_descriptor_set(lightmap, &mut command_builder, main_pipeline.clone());
 
         shader
-            .generate_commands(renderer, &vertices, repeat_shader, &mut command_builder)
+            .generate_commands(renderer, &vertices, instance_count, repeat_shader, &mut command_builder)
             .expect("can't generate stage commands");
     }
 
@@ -643,18 +1821,24 @@ impl VulkanRenderer {
             return;
         }
 
-        let color = [0.0, 0.0, 0.0, 1.0];
+        let Some(SplitBarStyle { color, thickness }) = renderer.split_screen_bar else {
+            return;
+        };
+        if thickness == 0.0 || color[3] <= 0.0 {
+            return;
+        }
+
         let viewport = Viewport {
             offset: [0.0, 0.0],
             extent: [width, height],
             depth_range: 0.0..=1.0,
         };
+        command_builder.set_scissor(0, [scissor_for_viewport(&viewport)].into_iter().collect()).unwrap();
         command_builder.set_viewport(0, [viewport].into_iter().collect()).unwrap();
 
-        let base_thickness = 2.0;
         let scale = (width / 640.0).min(height / 480.0).max(1.0);
-        let line_thickness_horizontal = base_thickness / height * scale;
-        let line_thickness_vertical = base_thickness / width * scale;
+        let line_thickness_horizontal = thickness / height * scale;
+        let line_thickness_vertical = thickness / width * scale;
 
         draw_box(renderer, 0.0, 0.5 - line_thickness_horizontal / 2.0, 1.0, line_thickness_horizontal, color, command_builder)
             .expect("can't draw split screen vertical bar");
@@ -676,18 +1860,298 @@ impl VulkanRenderer {
         }
     }
 
-    fn execute_command_list(&mut self, command_buffer: Arc<impl PrimaryCommandBufferAbstract + 'static>) {
-        let execution = command_buffer.execute(self.queue.clone()).unwrap();
+    fn draw_sprite_instances(renderer: &Renderer, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, width: f32, height: f32) -> MResult<()> {
+        // Masks are only valid for this pass, so clear anything left over from drawing the viewports.
+        if renderer.sprite_instances.iter().any(|s| matches!(s.stencil, SpriteStencil::Mask(_))) {
+            command_builder.clear_attachments(
+                [ClearAttachment::Stencil(0)].into_iter().collect(),
+                [ClearRect {
+                    offset: [0, 0],
+                    extent: [width as u32, height as u32],
+                    array_layers: 0..1
+                }].into_iter().collect()
+            )?;
+        }
+
+        for sprite in &renderer.sprite_instances {
+            let Some(bitmap) = renderer.bitmaps.get(&sprite.bitmap) else {
+                continue
+            };
+
+            let player_viewport = &renderer.player_viewports[sprite.viewport];
+            if !player_viewport.enabled {
+                continue
+            }
+            let viewport = Viewport {
+                offset: [player_viewport.rel_x * width, player_viewport.rel_y * height],
+                extent: [player_viewport.rel_width * width, player_viewport.rel_height * height],
+                depth_range: 0.0..=1.0,
+            };
+            let [scissor_x, scissor_y, scissor_width, scissor_height] = sprite.scissor;
+            let scissor = scissor_for_viewport(&Viewport {
+                offset: [viewport.offset[0] + scissor_x * viewport.extent[0], viewport.offset[1] + scissor_y * viewport.extent[1]],
+                extent: [scissor_width * viewport.extent[0], scissor_height * viewport.extent[1]],
+                depth_range: 0.0..=1.0,
+            });
+            if scissor.extent[0] == 0 || scissor.extent[1] == 0 {
+                continue
+            }
+
+            command_builder.set_viewport(0, [viewport].into_iter().collect())?;
+            command_builder.set_scissor(0, [scissor].into_iter().collect())?;
+
+            let image = &bitmap.bitmaps[sprite.bitmap_index].vulkan.image;
+            match sprite.stencil {
+                SpriteStencil::None => draw_sprite(renderer, sprite.rect, sprite.texture_rect, sprite.color, image, command_builder)?,
+                SpriteStencil::Test(value) => draw_sprite_with_stencil_test(renderer, Some(value), sprite.rect, sprite.texture_rect, sprite.color, image, command_builder)?,
+                SpriteStencil::Mask(value) => {
+                    let [x, y, width, height] = sprite.rect;
+                    draw_box_with_stencil_mask(renderer, Some(value), x, y, width, height, sprite.color, command_builder)?;
+                }
+            }
+        }
+
+        // Restore the full viewport for anything drawn afterwards
+        let full_viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [width, height],
+            depth_range: 0.0..=1.0,
+        };
+        command_builder.set_scissor(0, [scissor_for_viewport(&full_viewport)].into_iter().collect())?;
+        command_builder.set_viewport(0, [full_viewport].into_iter().collect())?;
+
+        Ok(())
+    }
+
+    pub fn begin_bulk_load(&mut self) -> MResult<()> {
+        if self.bulk_load_commands.is_some() {
+            return Err(Error::from_data_error_string("A bulk load is already in progress".to_owned()))
+        }
+        self.bulk_load_commands = Some(self.generate_upload_command_builder()?);
+        Ok(())
+    }
+
+    pub fn end_bulk_load(&mut self) -> MResult<()> {
+        let Some(commands) = self.bulk_load_commands.take() else {
+            return Err(Error::from_data_error_string("No bulk load is in progress".to_owned()))
+        };
+        self.execute_command_list(commands.build()?)?;
+        Ok(())
+    }
+
+    /// Submit all uploads recorded so far in the current bulk load, if any, without ending it.
+    fn flush_bulk_load(&mut self) -> MResult<()> {
+        if self.bulk_load_commands.is_none() {
+            return Ok(())
+        }
+        self.end_bulk_load()?;
+        self.begin_bulk_load()
+    }
+
+    /// Record upload commands.
+    ///
+    /// If a bulk load is in progress, the commands are deferred until it ends. Otherwise, they are submitted
+    /// immediately.
+    pub(crate) fn record_upload(&mut self, record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()>) -> MResult<()> {
+        if let Some(commands) = self.bulk_load_commands.as_mut() {
+            return record(commands)
+        }
+
+        let mut commands = self.generate_upload_command_builder()?;
+        record(&mut commands)?;
+        self.execute_command_list(commands.build()?)?;
+        Ok(())
+    }
+
+    fn generate_upload_command_builder(&self) -> MResult<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>> {
+        let result = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        Ok(result)
+    }
+
+    fn execute_command_list(&mut self, command_buffer: Arc<impl PrimaryCommandBufferAbstract + 'static>) -> MResult<()> {
+        let execution = command_buffer.execute(self.queue.clone())?;
 
         let future = self.future
             .take()
             .expect("no future?")
             .join(execution)
-            .then_signal_fence_and_flush()
-            .expect("failed to signal/flush")
-            .boxed_send_sync();
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                self.future = Some(future.boxed_send_sync());
+                Ok(())
+            }
+            Err(e) => {
+                self.future = Some(vulkano::sync::now(self.device.clone()).boxed_send_sync());
+                Err(self.flush_error(e))
+            }
+        }
+    }
+
+    /// Begin a labeled region of commands, shown by graphics debuggers such as RenderDoc.
+    ///
+    /// Does nothing if debug utils are unavailable. Every call must be matched by [`VulkanRenderer::end_debug_label`]
+    /// in the same command buffer.
+    fn begin_debug_label(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, name: &str) {
+        if !self.debug_labels {
+            return
+        }
+        command_builder.begin_debug_utils_label(DebugUtilsLabel {
+            label_name: name.to_owned(),
+            ..Default::default()
+        }).expect("failed to begin debug label");
+    }
+
+    /// End the region started with [`VulkanRenderer::begin_debug_label`].
+    fn end_debug_label(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if !self.debug_labels {
+            return
+        }
+        // SAFETY: Every label is begun earlier in the same command buffer.
+        unsafe { command_builder.end_debug_utils_label() }.expect("failed to end debug label");
+    }
+
+    /// Name the swapchain images and pipelines for graphics debuggers.
+    ///
+    /// Does nothing if debug utils are unavailable. This needs to be done again whenever they are recreated.
+    fn name_debug_objects(&self) {
+        if !self.debug_labels {
+            return
+        }
+
+        // Names are only a debugging aid, so failing to set one is not an error.
+        for (index, images) in self.swapchain_image_views.iter().enumerate() {
+            let _ = self.device.set_debug_utils_object_name(images.color.image().as_ref(), Some(&format!("Color #{index}")));
+            let _ = self.device.set_debug_utils_object_name(images.depth.image().as_ref(), Some(&format!("Depth #{index}")));
+            if let Some(resolve) = images.resolve.as_ref() {
+                let _ = self.device.set_debug_utils_object_name(resolve.image().as_ref(), Some(&format!("Resolve #{index}")));
+            }
+            if let Some(post_process) = images.post_process.as_ref() {
+                let _ = self.device.set_debug_utils_object_name(post_process.image().as_ref(), Some(&format!("Post process #{index}")));
+            }
+        }
+        for (pipeline_type, pipeline) in &self.pipelines {
+            let _ = self.device.set_debug_utils_object_name(pipeline.get_pipeline().as_ref(), Some(&format!("{pipeline_type:?}")));
+        }
+    }
+
+    /// Convert an error from flushing, noting if the device was lost so later frames fail fast.
+    fn flush_error(&mut self, error: Validated<VulkanError>) -> Error {
+        if let Validated::Error(VulkanError::DeviceLost) = error {
+            self.device_lost = true;
+            return Error::DeviceLost
+        }
+        error.into()
+    }
+
+    /// Draw `source` into `destination` with a fullscreen pass, such as FXAA.
+    ///
+    /// The pipeline takes `source` in set 0 and `data` in set 1.
+    fn draw_fullscreen_pass<T: BufferContents>(
+        renderer: &mut Renderer,
+        pipeline_type: VulkanPipelineType,
+        data: T,
+        source: &Arc<ImageView>,
+        destination: &Arc<ImageView>,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        let pipeline = renderer
+            .vulkan
+            .pipelines[&pipeline_type]
+            .get_pipeline();
+
+        let sampler = renderer.vulkan.get_sampler(SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..SamplerCreateInfo::default()
+        })?;
+
+        let [width, height, ..] = source.image().extent();
+        let uniform_buffer = Buffer::from_data(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            data
+        )?;
+
+        let image_set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::sampler(0, sampler),
+                WriteDescriptorSet::image_view(1, source.clone()),
+            ],
+            []
+        )?;
+
+        let data_set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::buffer(0, uniform_buffer),
+            ],
+            []
+        )?;
+
+        command_builder.begin_rendering(RenderingInfo {
+            color_attachments: vec![Some(RenderingAttachmentInfo {
+                load_op: AttachmentLoadOp::DontCare,
+                store_op: AttachmentStoreOp::Store,
+                ..RenderingAttachmentInfo::image_view(destination.clone())
+            })],
+            ..Default::default()
+        })?;
+
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [width as f32, height as f32],
+            depth_range: 0.0..=1.0,
+        };
+        command_builder.set_scissor(0, [scissor_for_viewport(&viewport)].into_iter().collect())?;
+        command_builder.set_viewport(0, [viewport].into_iter().collect())?;
+        command_builder.set_cull_mode(CullMode::None)?;
+        command_builder.bind_pipeline_graphics(pipeline.clone())?;
+        command_builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            (image_set, data_set)
+        )?;
+        command_builder.draw(3, 1, 0, 0)?;
+        command_builder.end_rendering()?;
+
+        Ok(())
+    }
+
+    fn run_post_process(
+        renderer: &Renderer,
+        post_process: &mut dyn PostProcess,
+        color: &Arc<ImageView>,
+        command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        let mut post_process_builder = AutoCommandBufferBuilder::secondary(
+            &renderer.vulkan.command_buffer_allocator,
+            renderer.vulkan.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+            CommandBufferInheritanceInfo::default()
+        )?;
 
-        self.future = Some(future)
+        post_process.apply(PostProcessContext {
+            device: renderer.vulkan.device.clone(),
+            memory_allocator: renderer.vulkan.memory_allocator.clone(),
+            descriptor_set_allocator: renderer.vulkan.descriptor_set_allocator.clone(),
+            color: color.clone(),
+            command_builder: &mut post_process_builder
+        })?;
+
+        command_builder.execute_commands(post_process_builder.build()?)?;
+        Ok(())
     }
 
     fn generate_secondary_buffer_builder(&self) -> MResult<AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>> {
@@ -697,8 +2161,9 @@ impl VulkanRenderer {
             CommandBufferUsage::MultipleSubmit,
             CommandBufferInheritanceInfo {
                 render_pass: Some(CommandBufferInheritanceRenderPassType::BeginRendering(CommandBufferInheritanceRenderingInfo {
-                    color_attachment_formats: vec![Some(OFFLINE_PIPELINE_COLOR_FORMAT)],
-                    depth_attachment_format: Some(Format::D32_SFLOAT),
+                    color_attachment_formats: vec![Some(self.color_format)],
+                    depth_attachment_format: Some(self.depth_format),
+                    stencil_attachment_format: self.has_stencil().then_some(self.depth_format),
                     ..CommandBufferInheritanceRenderingInfo::default()
                 })),
                 ..CommandBufferInheritanceInfo::default()
@@ -708,8 +2173,68 @@ impl VulkanRenderer {
     }
 }
 
-extern "C" {
-    fn exit(code: i32) -> !;
+/// Describes a geometry instance being drawn in a viewport.
+struct GeometryInstanceDraw<'a> {
+    path: &'a Arc<String>,
+    geometry: &'a Geometry,
+    buffers: &'a VulkanMaterialDataBuffers,
+    mvp: Arc<PersistentDescriptorSet>,
+    transform: Mat4,
+    geometry_indices: Vec<usize>
+}
+
+/// Describes an instanced geometry being drawn in a viewport.
+struct InstancedGeometryDraw<'a> {
+    path: &'a Arc<String>,
+    geometry: &'a Geometry,
+    buffers: &'a VulkanMaterialDataBuffers,
+    instances: Subbuffer<[VulkanModelInstance]>,
+
+    /// Transform of the closest visible instance, used for depth sorting
+    closest_transform: Mat4,
+    geometry_indices: Vec<usize>
+}
+
+/// Describes a transparent geometry to be depth sorted.
+#[derive(Copy, Clone)]
+enum TransparentGeometry {
+    /// Index of a BSP geometry
+    BSP(usize),
+
+    /// Part of a geometry instance
+    Geometry { instance_index: usize, geometry_index: usize, part_index: usize },
+
+    /// Part of an instanced geometry
+    InstancedGeometry { instanced_index: usize, geometry_index: usize, part_index: usize }
+}
+
+/// Describes which vertex buffers are currently bound.
+#[derive(Copy, Clone, PartialEq)]
+enum BoundVertexBuffers<'a> {
+    BSP,
+    Geometry(&'a Arc<String>)
+}
+
+/// Check that the device supports the given anisotropic filtering level.
+fn check_anisotropic_filtering(device: &Device, anisotropic_filtering: Option<f32>) -> MResult<()> {
+    if let Some(n) = anisotropic_filtering {
+        let max = device.physical_device().properties().max_sampler_anisotropy;
+        if !device.enabled_features().sampler_anisotropy || max < n || n < 1.0 || n.is_nan() {
+            return Err(
+                Error::from_vulkan_impl_error(format!("{n}x AF is unsupported by your device; supported values are 1-{max}"))
+            )
+        }
+    }
+    Ok(())
+}
+
+fn default_2d_sampler_create_info(anisotropic_filtering: Option<f32>) -> SamplerCreateInfo {
+    SamplerCreateInfo {
+        anisotropy: anisotropic_filtering,
+        // Sample every mip level, including generated ones.
+        lod: 0.0..=LOD_CLAMP_NONE,
+        ..SamplerCreateInfo::simple_repeat_linear()
+    }
 }
 
 fn default_allocation_create_info() -> AllocationCreateInfo {
@@ -719,41 +2244,42 @@ fn default_allocation_create_info() -> AllocationCreateInfo {
     }
 }
 
-impl<T: Display> From<Validated<T>> for Error {
+impl<T: std::error::Error + Send + Sync + 'static> From<Validated<T>> for Error {
     fn from(value: Validated<T>) -> Self {
         match value {
             Validated::ValidationError(v) => v.into(),
-            Validated::Error(e) => Self::from_vulkan_error(format!("Vulkan error! {e}"))
+            Validated::Error(e) => Self::from_vulkan_error_with_source(format!("Vulkan error! {e}"), e)
         }
     }
 }
 
 impl From<Box<ValidationError>> for Error {
     fn from(value: Box<ValidationError>) -> Self {
-        // FIXME: figure out a more graceful way to do this
-        eprintln!("Validation error! {value:?}\n\n-----------\n\nBACKTRACE:\n\n{}\n\n-----------\n\n", std::backtrace::Backtrace::force_capture());
-        std::process::abort();
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        Self::from_vulkan_error_with_source(format!("Validation error! {value}\n\nBACKTRACE:\n\n{backtrace}"), *value)
     }
 }
 
 impl From<vulkano::LoadingError> for Error {
     fn from(value: vulkano::LoadingError) -> Self {
-        Self::from_vulkan_error(format!("Loading error! {value:?}"))
+        Self::from_vulkan_error_with_source(format!("Loading error! {value:?}"), value)
     }
 }
 
 impl Error {
     fn from_vulkan_error(error: String) -> Self {
-        Self::GraphicsAPIError { backend: "Vulkan", error }
+        Self::GraphicsAPIError { backend: "Vulkan", error, source: None }
+    }
+    fn from_vulkan_error_with_source(error: String, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Self::GraphicsAPIError { backend: "Vulkan", error, source: Some(Arc::new(source)) }
     }
     fn from_vulkan_impl_error(error: String) -> Self {
-        Self::GraphicsAPIError { backend: "Vulkan-IMPL", error }
+        Self::GraphicsAPIError { backend: "Vulkan-IMPL", error, source: None }
     }
 }
 
 fn upload_lightmap_descriptor_set(
-    lightmap_index: Option<usize>,
-    bsp: &BSP,
+    set: Arc<PersistentDescriptorSet>,
     builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     pipeline: Arc<dyn VulkanPipelineData>
 ) {
@@ -761,10 +2287,6 @@ fn upload_lightmap_descriptor_set(
         return;
     }
 
-    let set = lightmap_index
-        .and_then(|i| bsp.vulkan.lightmap_images.get(&i))
-        .map(|b| b.clone())
-        .unwrap_or_else(|| bsp.vulkan.null_lightmaps.clone());
     builder.bind_descriptor_sets(
         PipelineBindPoint::Graphics,
         pipeline.get_pipeline().layout().clone(),
@@ -817,15 +2339,16 @@ fn make_fog_uniform(
         sky_fog_from: fog.distance_from,
         sky_fog_min_opacity: fog.min_opacity,
         sky_fog_max_opacity: fog.max_opacity,
+        sky_fog_mode: match fog.mode {
+            FogMode::Linear => 0,
+            FogMode::Exponential => 1,
+            FogMode::ExponentialSquared => 2
+        },
+        sky_fog_density: Padded::from(fog.density),
         sky_fog_color: [fog.color[0], fog.color[1], fog.color[2], 1.0]
     };
 
-    let fog_uniform_buffer = Buffer::from_data(
-        renderer.vulkan.memory_allocator.clone(),
-        BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
-        default_allocation_create_info(),
-        fog_data
-    ).unwrap();
+    let fog_uniform_buffer = make_frame_uniform(renderer, fog_data);
 
     PersistentDescriptorSet::new(
         renderer.vulkan.descriptor_set_allocator.as_ref(),
@@ -837,20 +2360,64 @@ fn make_fog_uniform(
     ).unwrap()
 }
 
+/// Get the fog descriptor set for a viewport, reusing the one from the last frame if the fog has not changed.
+fn get_fog_descriptor_set(
+    renderer: &mut Renderer,
+    viewport_index: Option<usize>,
+    fog: &FogData
+) -> Arc<PersistentDescriptorSet> {
+    if let Some((cached_fog, set)) = renderer.vulkan.fog_descriptor_sets.get(&viewport_index) {
+        if cached_fog == fog {
+            return set.clone()
+        }
+    }
+
+    let set = make_fog_uniform(renderer, fog);
+    renderer.vulkan.fog_descriptor_sets.insert(viewport_index, (*fog, set.clone()));
+    set
+}
+
+/// Allocate a vertex buffer containing `data` that is only used for the current frame.
+fn make_frame_vertex_buffer<T: BufferContents + Copy>(renderer: &Renderer, data: &[T]) -> MResult<Subbuffer<[T]>> {
+    let buffer = renderer
+        .vulkan
+        .vertex_buffer_allocator
+        .allocate_slice(data.len() as DeviceSize)
+        .map_err(|e| Error::from_vulkan_error_with_source(format!("Failed to allocate frame vertex buffer: {e}"), e))?;
+    buffer
+        .write()
+        .map_err(|e| Error::from_vulkan_error_with_source(format!("Failed to write frame vertex buffer: {e}"), e))?
+        .copy_from_slice(data);
+    Ok(buffer)
+}
+
+/// Allocate a uniform buffer containing `data` that is only used for the current frame.
+fn make_frame_uniform<T: BufferContents>(renderer: &Renderer, data: T) -> Subbuffer<T> {
+    let buffer = renderer
+        .vulkan
+        .uniform_buffer_allocator
+        .allocate_sized()
+        .expect("failed to allocate frame uniform");
+    *buffer.write().expect("failed to write frame uniform") = data;
+    buffer
+}
+
 fn make_model_view_uniform(
     renderer: &Renderer,
     camera: Vec3,
+    world: Mat4,
     offset: Vec3,
     rotation: Mat3,
     view: Mat4,
     proj: Mat4,
+    debug_view: DebugView,
 ) -> Arc<PersistentDescriptorSet> {
     let pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::ShaderEnvironment].get_pipeline();
-    let model = Mat4::IDENTITY;
 
     let model_data = VulkanModelData {
-        camera: Padded::from(camera.to_array()),
-        world: model.to_cols_array_2d(),
+        camera: camera.to_array(),
+        time: renderer.time,
+        world: world.to_cols_array_2d(),
         view: view.to_cols_array_2d(),
         proj: proj.to_cols_array_2d(),
         offset: Padded::from(offset.to_array()),
@@ -859,14 +2426,10 @@ fn make_model_view_uniform(
             Padded::from(rotation.y_axis.to_array()),
             Padded::from(rotation.z_axis.to_array())
         ],
+        debug_view: debug_view as u32,
     };
 
-    let model_uniform_buffer = Buffer::from_data(
-        renderer.vulkan.memory_allocator.clone(),
-        BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
-        default_allocation_create_info(),
-        model_data
-    ).unwrap();
+    let model_uniform_buffer = make_frame_uniform(renderer, model_data);
 
     PersistentDescriptorSet::new(
         renderer.vulkan.descriptor_set_allocator.as_ref(),
@@ -878,19 +2441,39 @@ fn make_model_view_uniform(
     ).unwrap()
 }
 
+/// Get a scissor covering `viewport`, rounded to the nearest pixel.
+fn scissor_for_viewport(viewport: &Viewport) -> Scissor {
+    let left = viewport.offset[0].round().max(0.0);
+    let top = viewport.offset[1].round().max(0.0);
+    let right = (viewport.offset[0] + viewport.extent[0]).round().max(left);
+    let bottom = (viewport.offset[1] + viewport.extent[1]).round().max(top);
+
+    Scissor {
+        offset: [left as u32, top as u32],
+        extent: [(right - left) as u32, (bottom - top) as u32]
+    }
+}
+
 fn draw_box(renderer: &Renderer, x: f32, y: f32, width: f32, height: f32, color: FloatColor, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
-    let vertices = generate_box(renderer, x, y, width, height);
+    draw_box_with_stencil_mask(renderer, None, x, y, width, height, color, command_builder)
+}
 
+/// Draw a box, or if `stencil_mask` is set, write it to the stencil buffer instead of drawing anything.
+fn draw_box_with_stencil_mask(renderer: &Renderer, stencil_mask: Option<u8>, x: f32, y: f32, width: f32, height: f32, color: FloatColor, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+    let pipeline_type = if stencil_mask.is_some() { VulkanPipelineType::StencilMask } else { VulkanPipelineType::ColorBox };
     let pipeline = renderer
         .vulkan
-        .pipelines[&VulkanPipelineType::ColorBox]
+        .pipelines[&pipeline_type]
         .get_pipeline();
 
     let uniform_buffer = Buffer::from_data(
         renderer.vulkan.memory_allocator.clone(),
         BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
         default_allocation_create_info(),
-        color
+        pipeline::color_box::ColorBoxData {
+            color,
+            rect: [x, y, width, height]
+        }
     ).unwrap();
 
     let set = PersistentDescriptorSet::new(
@@ -911,34 +2494,59 @@ fn draw_box(renderer: &Renderer, x: f32, y: f32, width: f32, height: f32, color:
 
     command_builder.set_cull_mode(CullMode::None).unwrap();
     command_builder.bind_index_buffer(renderer.vulkan.default_box_indices.clone()).unwrap();
-    command_builder.bind_vertex_buffers(0, vertices).unwrap();
+    command_builder.bind_vertex_buffers(0, renderer.vulkan.default_box_vertices.clone()).unwrap();
     command_builder.bind_pipeline_graphics(pipeline).unwrap();
+    if let Some(value) = stencil_mask {
+        command_builder.set_stencil_reference(StencilFaces::FrontAndBack, value as u32)?;
+    }
     command_builder.draw_indexed(6, 1, 0, 0, 0).unwrap();
 
     Ok(())
 }
 
-fn draw_sprite(renderer: &Renderer, x: f32, y: f32, scale: f32, bitmap: &Arc<Image>, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+fn draw_sprite(renderer: &Renderer, rect: [f32; 4], texture_rect: [f32; 4], color: FloatColor, bitmap: &Arc<Image>, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+    draw_sprite_with_stencil_test(renderer, None, rect, texture_rect, color, bitmap, command_builder)
+}
+
+/// Draw a sprite, only where the stencil buffer equals `stencil_test` if it is set.
+fn draw_sprite_with_stencil_test(renderer: &Renderer, stencil_test: Option<u8>, rect: [f32; 4], texture_rect: [f32; 4], color: FloatColor, bitmap: &Arc<Image>, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+    let pipeline_type = if stencil_test.is_some() { VulkanPipelineType::DrawSpriteStencilTest } else { VulkanPipelineType::DrawSprite };
     let pipeline = renderer
         .vulkan
-        .pipelines[&VulkanPipelineType::DrawSprite]
+        .pipelines[&pipeline_type]
         .get_pipeline();
 
+    let uniform_buffer = Buffer::from_data(
+        renderer.vulkan.memory_allocator.clone(),
+        BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+        default_allocation_create_info(),
+        pipeline::draw_sprite::DrawSpriteData {
+            color,
+            texture_rect
+        }
+    )?;
+
+    let sprite_data_set = PersistentDescriptorSet::new(
+        renderer.vulkan.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts()[1].clone(),
+        [
+            WriteDescriptorSet::buffer(0, uniform_buffer),
+        ],
+        []
+    )?;
+
     let set = PersistentDescriptorSet::new(
         renderer.vulkan.descriptor_set_allocator.as_ref(),
         pipeline.layout().set_layouts()[0].clone(),
         [
             WriteDescriptorSet::sampler(0, renderer.vulkan.default_2d_sampler.clone()),
-            WriteDescriptorSet::image_view(1, ImageView::new_default(bitmap.clone())?),
+            WriteDescriptorSet::image_view(1, color_map_view(&renderer.vulkan, bitmap.clone(), ImageViewType::Dim2d)?),
         ],
         []
     ).unwrap();
 
-    let [width, height, _] = bitmap.extent();
-    let width = width as f32 * scale / (renderer.vulkan.current_resolution.width as f32);
-    let height = height as f32 * scale / (renderer.vulkan.current_resolution.height as f32);
-
-    let vertices = generate_box(renderer, x, y, width, height);
+    let [x, y, width, height] = rect;
+    let vertices = generate_box(renderer.vulkan.memory_allocator.clone(), x, y, width, height);
 
     command_builder.set_cull_mode(CullMode::None).unwrap();
     command_builder.bind_index_buffer(renderer.vulkan.default_box_indices.clone()).unwrap();
@@ -947,16 +2555,83 @@ fn draw_sprite(renderer: &Renderer, x: f32, y: f32, scale: f32, bitmap: &Arc<Ima
         PipelineBindPoint::Graphics,
         pipeline.layout().clone(),
         0,
-        set
+        (set, sprite_data_set)
     ).unwrap();
     command_builder.bind_pipeline_graphics(pipeline).unwrap();
+    if let Some(value) = stencil_test {
+        command_builder.set_stencil_reference(StencilFaces::FrontAndBack, value as u32)?;
+    }
     command_builder.draw_indexed(6, 1, 0, 0, 0).unwrap();
     Ok(())
 }
 
-fn generate_box(renderer: &Renderer, x: f32, y: f32, width: f32, height: f32) -> Subbuffer<[VulkanModelVertex]> {
+/// Draw character quads, converting pixels to screen coordinates with `scale`.
+///
+/// Consecutive quads from the same atlas page are drawn together in one draw call.
+fn draw_character_quads(renderer: &Renderer, quads: &[CharacterQuad], scale: [f32; 2], command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+    let pipeline = renderer
+        .vulkan
+        .pipelines[&VulkanPipelineType::DrawText]
+        .get_pipeline();
+
+    command_builder.set_cull_mode(CullMode::None)?;
+    command_builder.bind_pipeline_graphics(pipeline.clone())?;
+
+    for batch in quads.chunk_by(|a, b| Arc::ptr_eq(&a.vulkan.image, &b.vulkan.image)) {
+        let mut vertices = Vec::with_capacity(batch.len() * 6);
+        for quad in batch {
+            let [left, top, right, bottom] = quad.rect;
+            let [texture_left, texture_top, texture_right, texture_bottom] = quad.texture_rect;
+            let vertex = |x: f32, y: f32, texture_x: f32, texture_y: f32| VulkanTextVertex {
+                position: [x * scale[0], y * scale[1]],
+                texture_coords: [texture_x, texture_y],
+                color: quad.color
+            };
+
+            let top_left = vertex(left + quad.shear, top, texture_left, texture_top);
+            let top_right = vertex(right + quad.shear, top, texture_right, texture_top);
+            let bottom_left = vertex(left, bottom, texture_left, texture_bottom);
+            let bottom_right = vertex(right, bottom, texture_right, texture_bottom);
+            vertices.extend_from_slice(&[top_left, bottom_left, bottom_right, top_left, bottom_right, top_right]);
+        }
+
+        let vertex_count = vertices.len() as u32;
+        let vertex_buffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            vertices
+        )?;
+
+        let set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::sampler(0, renderer.vulkan.default_2d_sampler.clone()),
+                WriteDescriptorSet::image_view(1, batch[0].vulkan.image.clone()),
+            ],
+            []
+        )?;
+
+        command_builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            set
+        )?;
+        command_builder.bind_vertex_buffers(0, vertex_buffer)?;
+        command_builder.draw(vertex_count, 1, 0, 0)?;
+    }
+
+    Ok(())
+}
+
+fn generate_box(memory_allocator: Arc<StandardMemoryAllocator>, x: f32, y: f32, width: f32, height: f32) -> Subbuffer<[VulkanModelVertex]> {
     Buffer::from_iter(
-        renderer.vulkan.memory_allocator.clone(),
+        memory_allocator,
         BufferCreateInfo {
             usage: BufferUsage::VERTEX_BUFFER,
             ..Default::default()
@@ -1,35 +1,60 @@
 use crate::error::{Error, MResult};
 use crate::renderer::mipmap_iterator::{MipmapFaceIterator, MipmapMetadata, MipmapTextureIterator, MipmapType};
 use crate::renderer::vulkan::{default_allocation_create_info, VulkanRenderer};
-use crate::renderer::{decode_p8_to_a8r8g8b8le, AddBitmapBitmapParameter, BitmapFormat, BitmapType};
+use crate::renderer::{decode_p8_to_a8r8g8b8le, AddBitmapBitmapParameter, AlphaMode, BitmapFormat, BitmapType, ColorKey};
 use std::num::NonZeroUsize;
 use std::string::ToString;
 use std::sync::Arc;
 use std::vec::Vec;
 use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer};
-use vulkano::format::Format;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo, ImageBlit, PrimaryAutoCommandBuffer};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::sampler::Filter;
+use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
 use vulkano::image::{Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage};
 use vulkano::memory::allocator::{AllocationCreateInfo, MemoryAllocatePreference, MemoryTypeFilter};
 use vulkano::DeviceSize;
 
+/// Maps each cubemap face index in Halo bitmap data to its Vulkan array layer.
+///
+/// Halo stores faces going around the horizon first (+X, +Y, -X, -Y) and then the top and bottom (+Z, -Z), whereas
+/// Vulkan expects +X, -X, +Y, -Y, +Z, -Z, so only the second and third faces need to be swapped. This is the swap
+/// the renderer has always done when uploading cubemaps; the tests read back an uploaded cubemap to check that each
+/// face lands on its layer.
+const HALO_TO_VULKAN_CUBEMAP_FACES: [u32; 6] = [0, 2, 1, 3, 4, 5];
+
 pub struct VulkanBitmapData {
     pub image: Arc<Image>
 }
 
 impl VulkanBitmapData {
-    pub fn new(vulkan_renderer: &mut VulkanRenderer, parameter: &AddBitmapBitmapParameter) -> MResult<Self> {
+    /// Get the size of the image in device memory in bytes.
+    pub fn memory_size(&self) -> DeviceSize {
+        self.image
+            .memory_requirements()
+            .iter()
+            .map(|r| r.layout.size())
+            .sum()
+    }
+
+    pub fn new(vulkan_renderer: &mut VulkanRenderer, parameter: &AddBitmapBitmapParameter, generate_mipmaps: bool) -> MResult<Self> {
         let (image_type, depth) = match parameter.bitmap_type {
             BitmapType::Dim3D { depth } => (ImageType::Dim3d, depth),
             _ => (ImageType::Dim2d, 1)
         };
 
+        if parameter.format.is_block_compressed() && !vulkan_renderer.device.enabled_features().texture_compression_bc {
+            return Err(Error::from_data_error_string(format!("Cannot load {:?} bitmaps; BC texture compression is not supported on this device", parameter.format)))
+        }
+
         let mut transcoded_pixels: Vec<u8> = Vec::new();
 
         let (bitmap_format, format, bytes) = match parameter.format {
             BitmapFormat::DXT1 => (parameter.format, Format::BC1_RGBA_UNORM_BLOCK, &parameter.data),
             BitmapFormat::DXT3 => (parameter.format, Format::BC2_UNORM_BLOCK, &parameter.data),
             BitmapFormat::DXT5 => (parameter.format, Format::BC3_UNORM_BLOCK, &parameter.data),
+            BitmapFormat::BC4 => (parameter.format, Format::BC4_UNORM_BLOCK, &parameter.data),
+            BitmapFormat::BC5 => (parameter.format, Format::BC5_UNORM_BLOCK, &parameter.data),
             BitmapFormat::BC7 => (parameter.format, Format::BC7_UNORM_BLOCK, &parameter.data),
 
             BitmapFormat::A8B8G8R8 => (parameter.format, Format::R8G8B8A8_UNORM, &parameter.data),
@@ -114,21 +139,74 @@ impl VulkanBitmapData {
             }
         };
 
+        let processed_pixels: Vec<u8>;
+        let (bitmap_format, format, bytes) = if parameter.color_key.is_some() || parameter.alpha_mode == AlphaMode::Premultiplied {
+            let (bitmap_format, format, mut pixels) = match bitmap_format {
+                // Expand packed formats so keyed pixels can be given a full alpha channel, and so colors keep their
+                // precision when un-premultiplied.
+                BitmapFormat::R5G6B5 | BitmapFormat::A1R5G5B5 | BitmapFormat::A4R4G4B4 | BitmapFormat::B4G4R4A4 => {
+                    (BitmapFormat::A8R8G8B8, Format::B8G8R8A8_UNORM, decode_pack16_to_b8g8r8a8(bitmap_format, bytes))
+                },
+                _ => (bitmap_format, format, bytes.clone())
+            };
+
+            if let Some(color_key) = parameter.color_key {
+                apply_color_key(bitmap_format, color_key, &mut pixels)?;
+            }
+
+            // Blending expects straight alpha, so undo premultiplication here.
+            if parameter.alpha_mode == AlphaMode::Premultiplied {
+                unpremultiply_alpha(bitmap_format, &mut pixels)?;
+            }
+
+            processed_pixels = pixels;
+            (bitmap_format, format, &processed_pixels)
+        }
+        else {
+            (bitmap_format, format, bytes)
+        };
+
+        // Only generate mipmaps if none are provided.
+        let generate_mipmaps = generate_mipmaps && parameter.mipmap_count == 0;
+        let mip_levels = if generate_mipmaps {
+            let required_features = FormatFeatures::BLIT_SRC | FormatFeatures::BLIT_DST | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR;
+            let format_features = vulkan_renderer.device.physical_device().format_properties(format)?.optimal_tiling_features;
+            if !format_features.contains(required_features) {
+                return Err(Error::from_data_error_string(format!("Cannot generate mipmaps for {format:?} bitmaps on this device")))
+            }
+            parameter.full_mipmap_count() + 1
+        }
+        else {
+            parameter.mipmap_count + 1
+        };
+
+        let mut usage = ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED;
+        if generate_mipmaps {
+            usage |= ImageUsage::TRANSFER_SRC;
+        }
+
+        let mut flags = if parameter.bitmap_type == BitmapType::Cubemap {
+            ImageCreateFlags::CUBE_COMPATIBLE
+        }
+        else {
+            ImageCreateFlags::empty()
+        };
+
+        // Allow color maps to be viewed as sRGB.
+        if vulkan_renderer.srgb_correct && srgb_format(format).is_some() {
+            flags |= ImageCreateFlags::MUTABLE_FORMAT;
+        }
+
         let image = Image::new(
             vulkan_renderer.memory_allocator.clone(),
             ImageCreateInfo {
                 image_type,
                 format,
                 extent: [parameter.resolution.width, parameter.resolution.height, depth],
-                mip_levels: parameter.mipmap_count + 1,
+                mip_levels,
                 array_layers: if parameter.bitmap_type == BitmapType::Cubemap { 6 } else { 1 },
-                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
-                flags: if parameter.bitmap_type == BitmapType::Cubemap {
-                    ImageCreateFlags::CUBE_COMPATIBLE
-                }
-                else {
-                    ImageCreateFlags::empty()
-                },
+                usage,
+                flags,
                 ..Default::default()
             },
             AllocationCreateInfo {
@@ -153,105 +231,283 @@ impl VulkanBitmapData {
             .map_err(|e| Error::from_vulkan_error(e.to_string()))?
             .copy_from_slice(bytes);
 
-        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
-            &vulkan_renderer.command_buffer_allocator,
-            vulkan_renderer.queue.queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )?;
+        vulkan_renderer.record_upload(|command_buffer_builder| {
+            record_upload_commands(parameter, bitmap_format, &image, &upload_buffer, command_buffer_builder)?;
+            if generate_mipmaps {
+                record_mipmap_generation_commands(&image, command_buffer_builder)?;
+            }
+            Ok(())
+        })?;
 
-        // Simple bitmaps don't need iterated.
-        if parameter.bitmap_type == BitmapType::Dim2D
-            && parameter.mipmap_count == 0
-            && parameter.format.block_pixel_length() == 1 {
-            upload_image(
-                &image,
-                &upload_buffer,
-                &mut command_buffer_builder,
-                0,
-                0,
-                parameter.resolution.width,
-                parameter.resolution.height,
-                0,
-                parameter.resolution.width,
-                parameter.resolution.height,
-                1
-            )?;
-            let buffer = command_buffer_builder.build()?;
-            vulkan_renderer.execute_command_list(buffer);
-            return Ok(Self { image })
-        }
+        Ok(Self { image })
+    }
+}
 
-        let width_nzus = NonZeroUsize::new(parameter.resolution.width as usize).unwrap();
-        let height_nzus = NonZeroUsize::new(parameter.resolution.height as usize).unwrap();
-        let bitmap_type = match parameter.bitmap_type {
-            BitmapType::Cubemap => MipmapType::Cubemap,
-            BitmapType::Dim2D => MipmapType::TwoDimensional,
-            BitmapType::Dim3D { depth } => MipmapType::ThreeDimensional(NonZeroUsize::new(depth as usize).unwrap())
-        };
-        let block_pixel_length_nzus = NonZeroUsize::new(bitmap_format.block_pixel_length()).unwrap();
-        let mipmap_count = Some(parameter.mipmap_count as usize);
-
-        let mut mipmap_face_iterator = MipmapFaceIterator::new(
-            width_nzus,
-            height_nzus,
-            bitmap_type,
-            block_pixel_length_nzus,
-            mipmap_count,
-        );
-
-        let mut mipmap_texture_iterator = MipmapTextureIterator::new(
-            width_nzus,
-            height_nzus,
-            bitmap_type,
-            block_pixel_length_nzus,
-            mipmap_count,
-        );
-
-        let iterator_to_use: &mut dyn Iterator<Item = MipmapMetadata> = if parameter.bitmap_type != BitmapType::Cubemap {
-            &mut mipmap_texture_iterator
-        }
-        else {
-            &mut mipmap_face_iterator
+/// Expand 16-bit packed pixels into B8G8R8A8 pixels.
+fn decode_pack16_to_b8g8r8a8(bitmap_format: BitmapFormat, pixels: &[u8]) -> Vec<u8> {
+    let expand = |value: u16, shift: u16, bits: u16| -> u8 {
+        let max = (1u32 << bits) - 1;
+        let value = ((value >> shift) as u32) & max;
+        ((value * 255 + max / 2) / max) as u8
+    };
+
+    let mut result = Vec::with_capacity(pixels.len() * 2);
+    for color in pixels.chunks_exact(2).map(|c| u16::from_le_bytes(c.try_into().unwrap())) {
+        let [b, g, r, a] = match bitmap_format {
+            BitmapFormat::R5G6B5 => [expand(color, 0, 5), expand(color, 5, 6), expand(color, 11, 5), 0xFF],
+            BitmapFormat::A1R5G5B5 => [expand(color, 0, 5), expand(color, 5, 5), expand(color, 10, 5), expand(color, 15, 1)],
+            BitmapFormat::A4R4G4B4 => [expand(color, 0, 4), expand(color, 4, 4), expand(color, 8, 4), expand(color, 12, 4)],
+            BitmapFormat::B4G4R4A4 => [expand(color, 12, 4), expand(color, 8, 4), expand(color, 4, 4), expand(color, 0, 4)],
+            n => unreachable!("{n:?} is not a 16-bit packed format")
         };
+        result.extend_from_slice(&[b, g, r, a]);
+    }
+    result
+}
+
+/// Make every pixel that matches the color key fully transparent black.
+fn apply_color_key(bitmap_format: BitmapFormat, color_key: ColorKey, pixels: &mut [u8]) -> MResult<()> {
+    let [key_r, key_g, key_b] = color_key.color;
+    let matches = |r: u8, g: u8, b: u8| {
+        r.abs_diff(key_r) <= color_key.tolerance
+            && g.abs_diff(key_g) <= color_key.tolerance
+            && b.abs_diff(key_b) <= color_key.tolerance
+    };
+
+    match bitmap_format {
+        BitmapFormat::A8R8G8B8 => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                if matches(pixel[2], pixel[1], pixel[0]) {
+                    pixel.fill(0);
+                }
+            }
+        },
+        BitmapFormat::X8R8G8B8 => {
+            // The unused byte becomes alpha here, so everything not keyed out has to be opaque.
+            for pixel in pixels.chunks_exact_mut(4) {
+                if matches(pixel[2], pixel[1], pixel[0]) {
+                    pixel.fill(0);
+                }
+                else {
+                    pixel[3] = 0xFF;
+                }
+            }
+        },
+        BitmapFormat::A8B8G8R8 => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                if matches(pixel[0], pixel[1], pixel[2]) {
+                    pixel.fill(0);
+                }
+            }
+        },
+        BitmapFormat::R32G32B32A32SFloat => {
+            let to_u8 = |bytes: &[u8]| (f32::from_le_bytes(bytes.try_into().unwrap()).clamp(0.0, 1.0) * 255.0).round() as u8;
+            for pixel in pixels.chunks_exact_mut(16) {
+                if matches(to_u8(&pixel[0..4]), to_u8(&pixel[4..8]), to_u8(&pixel[8..12])) {
+                    pixel.fill(0);
+                }
+            }
+        },
+        n => return Err(Error::from_data_error_string(format!("Cannot apply a color key to {n:?} bitmaps")))
+    }
+    Ok(())
+}
 
-        let mut offset = 0;
-        let block_size = bitmap_format.block_byte_size();
-        let pixel_size = bitmap_format.block_pixel_length();
-        for i in iterator_to_use {
-            let size = block_size * i.block_count;
-            let actual_face_index = if parameter.bitmap_type != BitmapType::Cubemap {
-                0
+/// Divide the color channels of each pixel by its alpha channel.
+///
+/// Fully transparent pixels are left as-is, since their color can't be recovered.
+fn unpremultiply_alpha(bitmap_format: BitmapFormat, pixels: &mut [u8]) -> MResult<()> {
+    match bitmap_format {
+        // Alpha is the last byte for both BGRA and RGBA.
+        BitmapFormat::A8R8G8B8 | BitmapFormat::A8B8G8R8 => {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let alpha = pixel[3] as u32;
+                if alpha == 0 {
+                    continue;
+                }
+                for channel in &mut pixel[0..3] {
+                    *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+                }
             }
-            else {
-                // TODO: IS THIS RIGHT?????? I THINK IT IS BUT IDK :(
-                match i.face_index {
-                    0 => 0,
-                    1 => 2,
-                    2 => 1,
-                    3 => 3,
-                    4 => 4,
-                    5 => 5,
-                    _ => continue
+        },
+        BitmapFormat::R32G32B32A32SFloat => {
+            for pixel in pixels.chunks_exact_mut(16) {
+                let mut rgba = [0.0f32; 4];
+                for (c, bytes) in rgba.iter_mut().zip(pixel.chunks_exact(4)) {
+                    *c = f32::from_le_bytes(bytes.try_into().unwrap());
                 }
-            };
+                let alpha = rgba[3];
+                if alpha <= 0.0 {
+                    continue;
+                }
+                for (c, bytes) in rgba[0..3].iter().zip(pixel.chunks_exact_mut(4)) {
+                    bytes.copy_from_slice(&(*c / alpha).to_le_bytes());
+                }
+            }
+        },
+        n => return Err(Error::from_data_error_string(format!("Cannot un-premultiply {n:?} bitmaps")))
+    }
+    Ok(())
+}
+
+/// Make a view of a color map (e.g. a base map or detail map) for sampling.
+///
+/// If sRGB-correct rendering is enabled, the image is viewed as sRGB so it is linearized when sampled. Images that
+/// hold non-color data such as bump maps should be viewed normally instead.
+pub fn color_map_view(vulkan_renderer: &VulkanRenderer, image: Arc<Image>, view_type: ImageViewType) -> MResult<Arc<ImageView>> {
+    let mut create_info = ImageViewCreateInfo {
+        view_type,
+        ..ImageViewCreateInfo::from_image(&image)
+    };
+
+    if vulkan_renderer.srgb_correct && image.flags().intersects(ImageCreateFlags::MUTABLE_FORMAT) {
+        if let Some(format) = srgb_format(image.format()) {
+            create_info.format = format;
+        }
+    }
+
+    Ok(ImageView::new(image, create_info)?)
+}
+
+/// Get the sRGB equivalent of a UNORM color format, if any.
+fn srgb_format(format: Format) -> Option<Format> {
+    match format {
+        Format::BC1_RGBA_UNORM_BLOCK => Some(Format::BC1_RGBA_SRGB_BLOCK),
+        Format::BC2_UNORM_BLOCK => Some(Format::BC2_SRGB_BLOCK),
+        Format::BC3_UNORM_BLOCK => Some(Format::BC3_SRGB_BLOCK),
+        Format::BC7_UNORM_BLOCK => Some(Format::BC7_SRGB_BLOCK),
+        Format::R8G8B8A8_UNORM => Some(Format::R8G8B8A8_SRGB),
+        Format::B8G8R8A8_UNORM => Some(Format::B8G8R8A8_SRGB),
+        _ => None
+    }
+}
+
+fn record_upload_commands(
+    parameter: &AddBitmapBitmapParameter,
+    bitmap_format: BitmapFormat,
+    image: &Arc<Image>,
+    upload_buffer: &Subbuffer<[u8]>,
+    command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+) -> MResult<()> {
+    // Simple bitmaps don't need iterated.
+    if parameter.bitmap_type == BitmapType::Dim2D
+        && parameter.mipmap_count == 0
+        && parameter.format.block_pixel_length() == 1 {
+        return upload_image(
+            image,
+            upload_buffer,
+            command_buffer_builder,
+            0,
+            0,
+            parameter.resolution.width,
+            parameter.resolution.height,
+            0,
+            parameter.resolution.width,
+            parameter.resolution.height,
+            1
+        )
+    }
+
+    let width_nzus = NonZeroUsize::new(parameter.resolution.width as usize).unwrap();
+    let height_nzus = NonZeroUsize::new(parameter.resolution.height as usize).unwrap();
+    let bitmap_type = match parameter.bitmap_type {
+        BitmapType::Cubemap => MipmapType::Cubemap,
+        BitmapType::Dim2D => MipmapType::TwoDimensional,
+        BitmapType::Dim3D { depth } => MipmapType::ThreeDimensional(NonZeroUsize::new(depth as usize).unwrap())
+    };
+    let block_pixel_length_nzus = NonZeroUsize::new(bitmap_format.block_pixel_length()).unwrap();
+    let mipmap_count = Some(parameter.mipmap_count as usize);
+
+    let mut mipmap_face_iterator = MipmapFaceIterator::new(
+        width_nzus,
+        height_nzus,
+        bitmap_type,
+        block_pixel_length_nzus,
+        mipmap_count,
+    );
 
-            let mip_height_physical = (i.block_height * pixel_size) as u32;
-            let mip_width_physical = (i.block_width * pixel_size) as u32;
-            let mip_level = i.mipmap_index as u32;
-            let mip_width_logical = i.width as u32;
-            let mip_height_logical = i.height as u32;
-            let mip_depth_logical = i.depth as u32;
+    let mut mipmap_texture_iterator = MipmapTextureIterator::new(
+        width_nzus,
+        height_nzus,
+        bitmap_type,
+        block_pixel_length_nzus,
+        mipmap_count,
+    );
 
-            upload_image(&image, &upload_buffer, &mut command_buffer_builder, offset, actual_face_index, mip_width_physical, mip_height_physical, mip_level, mip_width_logical, mip_height_logical, mip_depth_logical)?;
+    let iterator_to_use: &mut dyn Iterator<Item = MipmapMetadata> = if parameter.bitmap_type != BitmapType::Cubemap {
+        &mut mipmap_texture_iterator
+    }
+    else {
+        &mut mipmap_face_iterator
+    };
 
-            offset += size as DeviceSize;
+    let mut offset = 0;
+    let block_size = bitmap_format.block_byte_size();
+    let pixel_size = bitmap_format.block_pixel_length();
+    for i in iterator_to_use {
+        let size = block_size * i.block_count;
+        let actual_face_index = if parameter.bitmap_type != BitmapType::Cubemap {
+            0
         }
+        else {
+            match HALO_TO_VULKAN_CUBEMAP_FACES.get(i.face_index) {
+                Some(&f) => f,
+                None => continue
+            }
+        };
 
-        let buffer = command_buffer_builder.build()?;
-        vulkan_renderer.execute_command_list(buffer);
+        let mip_height_physical = (i.block_height * pixel_size) as u32;
+        let mip_width_physical = (i.block_width * pixel_size) as u32;
+        let mip_level = i.mipmap_index as u32;
+        let mip_width_logical = i.width as u32;
+        let mip_height_logical = i.height as u32;
+        let mip_depth_logical = i.depth as u32;
 
-        Ok(Self { image })
+        upload_image(image, upload_buffer, command_buffer_builder, offset, actual_face_index, mip_width_physical, mip_height_physical, mip_level, mip_width_logical, mip_height_logical, mip_depth_logical)?;
+
+        offset += size as DeviceSize;
+    }
+
+    Ok(())
+}
+
+/// Fill each mip level of the image by downscaling the previous one, assuming level 0 has been uploaded.
+fn record_mipmap_generation_commands(
+    image: &Arc<Image>,
+    command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+) -> MResult<()> {
+    let [width, height, depth] = image.extent();
+    let array_layers = image.array_layers();
+    let mip_extent = |level: u32| [(width >> level).max(1), (height >> level).max(1), (depth >> level).max(1)];
+
+    for mip_level in 1..image.mip_levels() {
+        let src_extent = mip_extent(mip_level - 1);
+        let dst_extent = mip_extent(mip_level);
+
+        command_buffer_builder.blit_image(BlitImageInfo {
+            regions: [
+                ImageBlit {
+                    src_subresource: ImageSubresourceLayers {
+                        aspects: ImageAspects::COLOR,
+                        mip_level: mip_level - 1,
+                        array_layers: 0..array_layers,
+                    },
+                    src_offsets: [[0, 0, 0], src_extent],
+                    dst_subresource: ImageSubresourceLayers {
+                        aspects: ImageAspects::COLOR,
+                        mip_level,
+                        array_layers: 0..array_layers,
+                    },
+                    dst_offsets: [[0, 0, 0], dst_extent],
+                    ..Default::default()
+                }
+            ].into(),
+            filter: Filter::Linear,
+            ..BlitImageInfo::images(image.clone(), image.clone())
+        })?;
     }
+
+    Ok(())
 }
 
 fn upload_image(image: &Arc<Image>, upload_buffer: &Subbuffer<[u8]>, command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, offset: DeviceSize, actual_face_index: u32, mip_width_physical: u32, mip_height_physical: u32, mip_level: u32, mip_width_logical: u32, mip_height_logical: u32, mip_depth_logical: u32) -> Result<(), Error> {
@@ -278,3 +534,115 @@ fn upload_image(image: &Arc<Image>, upload_buffer: &Subbuffer<[u8]>, command_buf
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{AddBitmapParameter, AddBitmapSequenceParameter, Renderer, RendererParameters, Resolution};
+    use vulkano::command_buffer::{CommandBufferUsage, CopyImageToBufferInfo};
+    use vulkano::sync::GpuFuture;
+
+    /// Upload a 1x1 cubemap whose red channel is its Halo face index times 40, then read back each array layer, or
+    /// return `None` if no Vulkan device is available.
+    fn read_back_cubemap_layers() -> Option<Vec<u8>> {
+        let Ok(mut renderer) = Renderer::new_headless(RendererParameters::default()) else {
+            return None
+        };
+
+        renderer.add_bitmap("cubemap", AddBitmapParameter {
+            bitmaps: vec![AddBitmapBitmapParameter {
+                format: BitmapFormat::A8B8G8R8,
+                bitmap_type: BitmapType::Cubemap,
+                resolution: Resolution { width: 1, height: 1 },
+                mipmap_count: 0,
+                alpha_mode: AlphaMode::Straight,
+                color_key: None,
+                data: (0..6u8).flat_map(|face| [face * 40, 0, 0, 0xFF]).collect()
+            }],
+            sequences: vec![AddBitmapSequenceParameter::Bitmap { first: 0, count: 1 }],
+            generate_mipmaps: false
+        }).unwrap();
+
+        let image = renderer.bitmaps
+            .iter()
+            .find(|(path, _)| path.as_str() == "cubemap")
+            .unwrap()
+            .1
+            .bitmaps[0]
+            .vulkan
+            .image
+            .clone();
+
+        let vulkan_renderer = &mut renderer.vulkan;
+        let buffer = Buffer::new_slice::<u8>(
+            vulkan_renderer.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::TRANSFER_DST, ..Default::default() },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            6 * 4
+        ).unwrap();
+
+        let mut command_builder = AutoCommandBufferBuilder::primary(
+            &vulkan_renderer.command_buffer_allocator,
+            vulkan_renderer.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        ).unwrap();
+        command_builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone())).unwrap();
+
+        vulkan_renderer.future
+            .take()
+            .expect("there's no future :(")
+            .then_execute(vulkan_renderer.queue.clone(), command_builder.build().unwrap())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let pixels = buffer.read().unwrap().to_vec();
+        Some(pixels)
+    }
+
+    #[test]
+    fn cubemap_faces_are_uploaded_to_vulkan_layers() {
+        let Some(pixels) = read_back_cubemap_layers() else { return };
+
+        // Vulkan array layers are +X, -X, +Y, -Y, +Z, -Z.
+        let halo_faces_per_layer: Vec<u8> = pixels.chunks(4).map(|pixel| pixel[0] / 40).collect();
+        assert_eq!(halo_faces_per_layer, [0, 2, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn unpremultiply_edge_pixel() {
+        // Straight (255, 125, 0) at 20% alpha, premultiplied, in BGRA
+        let mut pixels = vec![0, 25, 51, 51];
+        unpremultiply_alpha(BitmapFormat::A8R8G8B8, &mut pixels).unwrap();
+        assert_eq!(pixels, [0, 125, 255, 51]);
+
+        // Opaque pixels are unchanged, and transparent pixels are left alone.
+        let mut pixels = vec![10, 20, 30, 255, 10, 20, 30, 0];
+        unpremultiply_alpha(BitmapFormat::A8B8G8R8, &mut pixels).unwrap();
+        assert_eq!(pixels, [10, 20, 30, 255, 10, 20, 30, 0]);
+
+        let mut pixels: Vec<u8> = [0.25f32, 0.5, 0.1, 0.5].iter().flat_map(|c| c.to_le_bytes()).collect();
+        unpremultiply_alpha(BitmapFormat::R32G32B32A32SFloat, &mut pixels).unwrap();
+        let rgba: Vec<f32> = pixels.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+        assert_eq!(rgba, [0.5, 1.0, 0.2, 0.5]);
+    }
+
+    #[test]
+    fn color_key_makes_x8r8g8b8_opaque() {
+        let color_key = ColorKey { color: [255, 0, 255], tolerance: 0 };
+        let mut pixels = vec![255, 0, 255, 0x12, 1, 2, 3, 0x34];
+        apply_color_key(BitmapFormat::X8R8G8B8, color_key, &mut pixels).unwrap();
+        assert_eq!(pixels, [0, 0, 0, 0, 1, 2, 3, 0xFF]);
+    }
+
+    #[test]
+    fn x8r8g8b8_is_not_unpremultiplied() {
+        assert!(!BitmapFormat::X8R8G8B8.supports_unpremultiplying());
+        assert!(unpremultiply_alpha(BitmapFormat::X8R8G8B8, &mut [0, 25, 51, 51]).is_err());
+    }
+}
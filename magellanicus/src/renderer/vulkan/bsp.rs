@@ -1,22 +1,27 @@
 use crate::error::MResult;
 use crate::renderer::{AddBSPParameter, DefaultType, Renderer};
 
-use crate::renderer::data::BSPGeometry;
+use crate::renderer::data::{BSPGeometry, BSPIndexWidth, BSPIndices, Shader};
 use crate::renderer::vulkan::vertex::{VulkanModelVertex, VulkanModelVertexLightmapTextureCoords, VulkanModelVertexTextureCoords};
 use crate::renderer::vulkan::{default_allocation_create_info, VulkanPipelineType};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::vec::Vec;
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer};
+use vulkano::DeviceSize;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::image::sampler::{Sampler, SamplerAddressMode, SamplerCreateInfo};
-use vulkano::image::view::{ImageView, ImageViewCreateInfo};
+use vulkano::image::sampler::{SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
 use vulkano::pipeline::Pipeline;
 
+/// Lightmap descriptor sets for each lightmap index.
+pub type LightmapDescriptorSets = BTreeMap<usize, Arc<PersistentDescriptorSet>>;
+
 pub struct VulkanBSPData {
     pub subbuffers: Option<VulkanBSPVertexDataBuffers>,
 
-    pub lightmap_images: BTreeMap<usize, Arc<PersistentDescriptorSet>>,
+    pub lightmap_images: LightmapDescriptorSets,
     pub null_lightmaps: Arc<PersistentDescriptorSet>,
 
     pub transparent_geometries: Vec<usize>,
@@ -24,86 +29,104 @@ pub struct VulkanBSPData {
 }
 
 impl VulkanBSPData {
+    /// Make a descriptor set for each of the given lightmap indices of `lightmap_bitmap`.
+    ///
+    /// This is also used to rebuild them when the lightmap sampler changes.
+    pub fn make_lightmap_descriptor_sets(
+        renderer: &mut Renderer,
+        lightmap_bitmap: Option<&String>,
+        lightmap_indices: impl Iterator<Item = usize>
+    ) -> MResult<LightmapDescriptorSets> {
+        let mut images = BTreeMap::new();
+        let Some(n) = lightmap_bitmap else {
+            return Ok(images)
+        };
+
+        let shader_environment_pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::ShaderEnvironment].get_pipeline();
+        let (bitmap_path, bitmap) = renderer
+            .bitmaps
+            .get_key_value(n)
+            .unwrap();
+
+        let sampler = renderer.vulkan.get_sampler(
+            SamplerCreateInfo {
+                address_mode: [
+                    SamplerAddressMode::ClampToEdge,
+                    SamplerAddressMode::ClampToEdge,
+                    SamplerAddressMode::ClampToEdge
+                ],
+                anisotropy: renderer.vulkan.default_2d_sampler.anisotropy(),
+                ..SamplerCreateInfo::simple_repeat_linear_no_mipmap()
+            }
+        )?;
+
+        for i in lightmap_indices {
+            if images.contains_key(&i) {
+                continue;
+            }
+
+            // Views are shared so BSPs with the same lightmaps don't each make their own.
+            let lightmap = renderer.vulkan.get_lightmap_view(bitmap_path, i, &bitmap.bitmaps[i].vulkan.image)?;
+
+            let descriptor_set = PersistentDescriptorSet::new(
+                renderer.vulkan.descriptor_set_allocator.as_ref(),
+                shader_environment_pipeline.layout().set_layouts()[1].clone(),
+                [
+                    WriteDescriptorSet::sampler(0, sampler.clone()),
+                    WriteDescriptorSet::image_view(1, lightmap),
+                ],
+                []
+            )?;
+
+            images.insert(i, descriptor_set);
+        }
+
+        Ok(images)
+    }
+
     pub fn new(
         renderer: &mut Renderer,
         param: &AddBSPParameter,
-        geometries: &Vec<BSPGeometry>
+        geometries: &Vec<BSPGeometry>,
+        index_width: BSPIndexWidth
     ) -> MResult<Self> {
         let mut vertex_data: Vec<VulkanModelVertex> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
         let mut texture_coords_data: Vec<VulkanModelVertexTextureCoords> = Vec::new();
         let mut lightmap_texture_coords_data: Vec<VulkanModelVertexLightmapTextureCoords> = Vec::new();
 
-        for l in &param.lightmap_sets {
-            for m in &l.materials {
-                indices.extend(m.surfaces.iter().map(|m| m.indices.iter()).flatten());
-                vertex_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertex {
-                    position: s.position,
-                    normal: s.normal,
-                    binormal: s.binormal,
-                    tangent: s.tangent
+        let materials = param.lightmap_sets.iter().map(|l| l.materials.iter()).flatten();
+        for (m, geometry) in materials.zip(geometries.iter()) {
+            // Indices may be relative to an earlier geometry's vertices so draws can be merged.
+            let rebase = (vertex_data.len() as i32 - geometry.offset.vertex_offset) as u32;
+            indices.extend(m.surfaces.iter().map(|m| m.indices.iter()).flatten().map(|i| i + rebase));
+            vertex_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertex {
+                position: s.position,
+                normal: s.normal,
+                binormal: s.binormal,
+                tangent: s.tangent
+            }));
+            texture_coords_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertexTextureCoords {
+                texture_coords: s.texture_coords
+            }));
+            if let Some(n) = m.lightmap_vertices.as_ref() {
+                lightmap_texture_coords_data.extend(n.iter().map(|s| VulkanModelVertexLightmapTextureCoords {
+                    lightmap_texture_coords: s.lightmap_texture_coords
                 }));
-                texture_coords_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertexTextureCoords {
-                    texture_coords: s.texture_coords
+            }
+            else {
+                lightmap_texture_coords_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertexLightmapTextureCoords {
+                    lightmap_texture_coords: s.texture_coords
                 }));
-                if let Some(n) = m.lightmap_vertices.as_ref() {
-                    lightmap_texture_coords_data.extend(n.iter().map(|s| VulkanModelVertexLightmapTextureCoords {
-                        lightmap_texture_coords: s.lightmap_texture_coords
-                    }));
-                }
-                else {
-                    lightmap_texture_coords_data.extend(m.shader_vertices.iter().map(|s| VulkanModelVertexLightmapTextureCoords {
-                        lightmap_texture_coords: s.texture_coords
-                    }));
-                }
             }
         }
 
         let shader_environment_pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::ShaderEnvironment].get_pipeline();
-        let mut images = BTreeMap::new();
-        if let Some(n) = &param.lightmap_bitmap {
-            let image = renderer
-                .bitmaps
-                .get(n)
-                .unwrap();
-
-            for i in param.lightmap_sets.iter().filter_map(|b| b.lightmap_index) {
-                if images.contains_key(&i) {
-                    continue;
-                }
-
-                let image = image.bitmaps[i].vulkan.image.clone();
-
-                let lightmap = ImageView::new(
-                    image.clone(),
-                    ImageViewCreateInfo::from_image(image.as_ref())
-                )?;
-
-                let sampler = Sampler::new(
-                    renderer.vulkan.device.clone(),
-                    SamplerCreateInfo {
-                        address_mode: [
-                            SamplerAddressMode::ClampToEdge,
-                            SamplerAddressMode::ClampToEdge,
-                            SamplerAddressMode::ClampToEdge
-                        ],
-                        ..SamplerCreateInfo::simple_repeat_linear_no_mipmap()
-                    }
-                )?;
-
-                let descriptor_set = PersistentDescriptorSet::new(
-                    renderer.vulkan.descriptor_set_allocator.as_ref(),
-                    shader_environment_pipeline.layout().set_layouts()[1].clone(),
-                    [
-                        WriteDescriptorSet::sampler(0, sampler),
-                        WriteDescriptorSet::image_view(1, lightmap),
-                    ],
-                    []
-                )?;
-
-                images.insert(i, descriptor_set);
-            }
-        }
+        let images = Self::make_lightmap_descriptor_sets(
+            renderer,
+            param.lightmap_bitmap.as_ref(),
+            param.lightmap_sets.iter().filter_map(|b| b.lightmap_index)
+        )?;
 
         let null_set = PersistentDescriptorSet::new(
             renderer.vulkan.descriptor_set_allocator.as_ref(),
@@ -115,28 +138,7 @@ impl VulkanBSPData {
             []
         ).unwrap();
 
-        let mut transparent_geometries: Vec<usize> = geometries
-            .iter()
-            .enumerate()
-            .filter_map(|f| if renderer.shaders[&f.1.shader].vulkan.pipeline_data.is_transparent() {
-                Some(f.0)
-            }
-            else {
-                None
-            }).collect();
-
-        let mut opaque_geometries: Vec<usize> = geometries
-            .iter()
-            .enumerate()
-            .filter_map(|f| if !renderer.shaders[&f.1.shader].vulkan.pipeline_data.is_transparent() {
-                Some(f.0)
-            }
-            else {
-                None
-            }).collect();
-
-        transparent_geometries.sort_by(|a,b| geometries[*a].shader.cmp(&geometries[*b].shader));
-        opaque_geometries.sort_by(|a,b| geometries[*a].shader.cmp(&geometries[*b].shader));
+        let (opaque_geometries, transparent_geometries) = Self::sort_geometries(&renderer.shaders, geometries);
 
         let subbuffers = if !indices.is_empty() {
             let vertex_data_subbuffer = Buffer::from_iter(
@@ -169,15 +171,25 @@ impl VulkanBSPData {
                 lightmap_texture_coords_data.into_iter()
             )?;
 
-            let index_subbuffer = Buffer::from_iter(
-                renderer.vulkan.memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER,
-                    ..Default::default()
-                },
-                default_allocation_create_info(),
-                indices.into_iter()
-            )?;
+            let index_buffer_create_info = BufferCreateInfo {
+                usage: BufferUsage::INDEX_BUFFER,
+                ..Default::default()
+            };
+
+            let index_subbuffer = match index_width.narrow_indices(indices) {
+                BSPIndices::U16(indices) => IndexBuffer::U16(Buffer::from_iter(
+                    renderer.vulkan.memory_allocator.clone(),
+                    index_buffer_create_info,
+                    default_allocation_create_info(),
+                    indices.into_iter()
+                )?),
+                BSPIndices::U32(indices) => IndexBuffer::U32(Buffer::from_iter(
+                    renderer.vulkan.memory_allocator.clone(),
+                    index_buffer_create_info,
+                    default_allocation_create_info(),
+                    indices.into_iter()
+                )?)
+            };
 
             Some(VulkanBSPVertexDataBuffers {
                 vertex_data_subbuffer,
@@ -198,11 +210,65 @@ impl VulkanBSPData {
             transparent_geometries
         })
     }
+
+    /// Recalculate which geometries are opaque and which are transparent.
+    ///
+    /// This needs to be done if a shader used by the BSP is replaced.
+    pub fn resort_geometries(&mut self, shaders: &HashMap<Arc<String>, Shader>, geometries: &[BSPGeometry]) {
+        (self.opaque_geometries, self.transparent_geometries) = Self::sort_geometries(shaders, geometries);
+    }
+
+    /// Split geometries into opaque and transparent geometries, each sorted by shader.
+    fn sort_geometries(shaders: &HashMap<Arc<String>, Shader>, geometries: &[BSPGeometry]) -> (Vec<usize>, Vec<usize>) {
+        let mut transparent_geometries: Vec<usize> = geometries
+            .iter()
+            .enumerate()
+            .filter_map(|f| if shaders[&f.1.shader].vulkan.pipeline_data.is_transparent() {
+                Some(f.0)
+            }
+            else {
+                None
+            }).collect();
+
+        let mut opaque_geometries: Vec<usize> = geometries
+            .iter()
+            .enumerate()
+            .filter_map(|f| if !shaders[&f.1.shader].vulkan.pipeline_data.is_transparent() {
+                Some(f.0)
+            }
+            else {
+                None
+            }).collect();
+
+        transparent_geometries.sort_by(|a,b| geometries[*a].shader.cmp(&geometries[*b].shader));
+        opaque_geometries.sort_by(|a,b| geometries[*a].shader.cmp(&geometries[*b].shader));
+
+        (opaque_geometries, transparent_geometries)
+    }
 }
 
 pub struct VulkanBSPVertexDataBuffers {
     pub vertex_data_subbuffer: Subbuffer<[VulkanModelVertex]>,
     pub texture_coords_subbuffer: Subbuffer<[VulkanModelVertexTextureCoords]>,
     pub lightmap_texture_coords_subbuffer: Subbuffer<[VulkanModelVertexLightmapTextureCoords]>,
-    pub index_subbuffer: Subbuffer<[u16]>,
+    pub index_subbuffer: IndexBuffer,
+}
+
+impl VulkanBSPVertexDataBuffers {
+    /// Get the size of all buffers in bytes.
+    pub fn memory_size(&self) -> DeviceSize {
+        self.vertex_data_subbuffer.size()
+            + self.texture_coords_subbuffer.size()
+            + self.lightmap_texture_coords_subbuffer.size()
+            + self.index_subbuffer.as_bytes().size()
+    }
+
+    pub fn bind(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        command_builder.bind_index_buffer(self.index_subbuffer.clone()).expect("failed to bind indices");
+        command_builder.bind_vertex_buffers(0, (
+            self.vertex_data_subbuffer.clone(),
+            self.texture_coords_subbuffer.clone(),
+            self.lightmap_texture_coords_subbuffer.clone()
+        )).expect("failed to bind vertex data");
+    }
 }
@@ -1,6 +1,16 @@
 use vulkano::image::view::ImageView;
 use std::sync::Arc;
+use crate::error::MResult;
+use crate::renderer::data::BitmapBitmap;
 
+#[derive(Clone)]
 pub struct VulkanCharacterData {
+    /// Atlas page containing the character.
     pub image: Arc<ImageView>
 }
+
+impl VulkanCharacterData {
+    pub fn new(atlas_page: &BitmapBitmap) -> MResult<Self> {
+        Ok(Self { image: ImageView::new_default(atlas_page.vulkan.image.clone())? })
+    }
+}
@@ -1,10 +1,142 @@
 use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::view::ImageView;
+use vulkano::pipeline::Pipeline;
+use vulkano::DeviceSize;
 use crate::vertex::{LightmapVertex, ModelVertex, ModelTriangle};
 use crate::error::{Error, MResult};
+use crate::renderer::{AddGeometryParameter, DefaultType, Renderer};
+use crate::renderer::vulkan::{default_allocation_create_info, VulkanPipelineType};
 use crate::renderer::vulkan::vertex::*;
 
 pub struct VulkanMaterialData {
-    pub buffers: Arc<VulkanMaterialVertexBuffers>
+    pub subbuffers: Option<VulkanMaterialDataBuffers>,
+
+    /// Geometries are not lightmapped, but pipelines that use lightmaps still need something bound.
+    pub null_lightmaps: Arc<PersistentDescriptorSet>
+}
+
+impl VulkanMaterialData {
+    pub fn new(
+        renderer: &mut Renderer,
+        param: &AddGeometryParameter
+    ) -> MResult<Self> {
+        let mut vertex_data: Vec<VulkanModelVertex> = Vec::new();
+        let mut indices: Vec<u16> = Vec::new();
+        let mut texture_coords_data: Vec<VulkanModelVertexTextureCoords> = Vec::new();
+        let mut lightmap_texture_coords_data: Vec<VulkanModelVertexLightmapTextureCoords> = Vec::new();
+
+        for part in param.geometries.iter().map(|g| g.parts.iter()).flatten() {
+            indices.extend(part.indices.iter());
+            vertex_data.extend(part.vertices.iter().map(|v| VulkanModelVertex {
+                position: v.vertex_data.position,
+                normal: v.vertex_data.normal,
+                binormal: v.vertex_data.binormal,
+                tangent: v.vertex_data.tangent
+            }));
+            texture_coords_data.extend(part.vertices.iter().map(|v| VulkanModelVertexTextureCoords {
+                texture_coords: v.vertex_data.texture_coords
+            }));
+
+            // Models do not have lightmaps, but binding 2 still needs to be populated for pipelines that use it.
+            lightmap_texture_coords_data.extend(part.vertices.iter().map(|v| VulkanModelVertexLightmapTextureCoords {
+                lightmap_texture_coords: v.vertex_data.texture_coords
+            }));
+        }
+
+        let shader_environment_pipeline = renderer.vulkan.pipelines[&VulkanPipelineType::ShaderEnvironment].get_pipeline();
+        let null_lightmaps = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            shader_environment_pipeline.layout().set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::sampler(0, renderer.vulkan.default_2d_sampler.clone()),
+                WriteDescriptorSet::image_view(1, ImageView::new_default(renderer.get_default_2d(DefaultType::White).vulkan.image.clone())?),
+            ],
+            []
+        )?;
+
+        if indices.is_empty() {
+            return Ok(Self { subbuffers: None, null_lightmaps })
+        }
+
+        let vertex_data_subbuffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            vertex_data.into_iter()
+        )?;
+
+        let texture_coords_subbuffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            texture_coords_data.into_iter()
+        )?;
+
+        let lightmap_texture_coords_subbuffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            lightmap_texture_coords_data.into_iter()
+        )?;
+
+        let index_subbuffer = Buffer::from_iter(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::INDEX_BUFFER,
+                ..Default::default()
+            },
+            default_allocation_create_info(),
+            indices.into_iter()
+        )?;
+
+        Ok(Self {
+            subbuffers: Some(VulkanMaterialDataBuffers {
+                vertex_data_subbuffer,
+                texture_coords_subbuffer,
+                lightmap_texture_coords_subbuffer,
+                index_subbuffer
+            }),
+            null_lightmaps
+        })
+    }
+}
+
+pub struct VulkanMaterialDataBuffers {
+    pub vertex_data_subbuffer: Subbuffer<[VulkanModelVertex]>,
+    pub texture_coords_subbuffer: Subbuffer<[VulkanModelVertexTextureCoords]>,
+    pub lightmap_texture_coords_subbuffer: Subbuffer<[VulkanModelVertexLightmapTextureCoords]>,
+    pub index_subbuffer: Subbuffer<[u16]>,
+}
+
+impl VulkanMaterialDataBuffers {
+    /// Get the size of all buffers in bytes.
+    pub fn memory_size(&self) -> DeviceSize {
+        self.vertex_data_subbuffer.size()
+            + self.texture_coords_subbuffer.size()
+            + self.lightmap_texture_coords_subbuffer.size()
+            + self.index_subbuffer.size()
+    }
+
+    pub fn bind(&self, command_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        command_builder.bind_index_buffer(self.index_subbuffer.clone()).expect("failed to bind indices");
+        command_builder.bind_vertex_buffers(0, (
+            self.vertex_data_subbuffer.clone(),
+            self.texture_coords_subbuffer.clone(),
+            self.lightmap_texture_coords_subbuffer.clone()
+        )).expect("failed to bind vertex data");
+    }
 }
 
 pub struct VulkanMaterialVertexBuffers {
@@ -91,9 +223,10 @@ impl VulkanMaterialVertexBuffers {
             if a as usize >= vertex_count || b as usize >= vertex_count || c as usize >= vertex_count {
                 return Err(Error::DataError { error: std::format!("triangle {a},{b},{c} out-of-bounds (at least one index was >= {vertex_count})") })
             }
-            indices_buf.push(a);
-            indices_buf.push(b);
-            indices_buf.push(c);
+            // vertex_count is at most 65535, so these will fit
+            indices_buf.push(a as u16);
+            indices_buf.push(b as u16);
+            indices_buf.push(c as u16);
         }
         indices_buf.shrink_to_fit();
 
@@ -1,5 +1,6 @@
 use crate::error::{Error, MResult};
-use crate::renderer::RendererParameters;
+use crate::renderer::log::{log, LogLevel};
+use crate::renderer::{DeviceSelection, GPUType, PhysicalDeviceInfo, PresentModePreference, RendererParameters, MSAA};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use std::string::ToString;
 use std::borrow::ToOwned;
@@ -7,29 +8,171 @@ use std::sync::Arc;
 use std::vec::Vec;
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags};
-use vulkano::format::Format;
-use vulkano::image::{Image, ImageUsage};
-use vulkano::instance::{Instance, InstanceCreateInfo};
-use vulkano::swapchain::{PresentMode, Surface, Swapchain, SwapchainCreateInfo};
+use vulkano::format::{Format, FormatFeatures, NumericFormat};
+use vulkano::image::{Image, ImageUsage, SampleCount, SampleCounts};
+use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
+use vulkano::instance::debug::{DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger, DebugUtilsMessengerCallback, DebugUtilsMessengerCreateInfo};
+use vulkano::memory::MemoryHeapFlags;
+use vulkano::swapchain::{ColorSpace, PresentMode, Surface, Swapchain, SwapchainCreateInfo};
 use vulkano::{Validated, Version, VulkanError, VulkanLibrary};
 
 pub struct LoadedVulkan {
     pub instance: Arc<Instance>,
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
-    pub surface: Arc<Surface>,
+    pub surface: Option<Arc<Surface>>,
+    pub debug_messenger: Option<DebugUtilsMessenger>,
+}
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Create an instance, enabling the validation layers and a debug messenger for them if requested.
+fn create_instance(
+    library: Arc<VulkanLibrary>,
+    mut enabled_extensions: InstanceExtensions,
+    enable_validation: bool,
+    abort_on_validation_error: bool
+) -> MResult<(Arc<Instance>, Option<DebugUtilsMessenger>)> {
+    // Lets graphics debuggers such as RenderDoc show command buffer labels and object names.
+    enabled_extensions.ext_debug_utils = library.supported_extensions().ext_debug_utils;
+
+    if !enable_validation {
+        let instance = Instance::new(library, InstanceCreateInfo {
+            enabled_extensions,
+            ..Default::default()
+        })?;
+        return Ok((instance, None))
+    }
+
+    if !library.layer_properties()?.any(|l| l.name() == VALIDATION_LAYER) {
+        return Err(Error::from_vulkan_error(format!("Validation was requested, but {VALIDATION_LAYER} is not installed")))
+    }
+    enabled_extensions.ext_debug_utils = true;
+
+    let instance = Instance::new(library, InstanceCreateInfo {
+        enabled_extensions,
+        enabled_layers: vec![VALIDATION_LAYER.to_owned()],
+        ..Default::default()
+    })?;
+
+    // SAFETY: The callback does not call into Vulkan.
+    let callback = unsafe {
+        DebugUtilsMessengerCallback::new(move |severity, message_type, data| {
+            let level = if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+                LogLevel::Error
+            }
+            else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+                LogLevel::Warning
+            }
+            else {
+                LogLevel::Info
+            };
+            let id = data.message_id_name.unwrap_or("unknown");
+            log(level, &format!("Vulkan {message_type:?} message ({id}): {}", data.message));
+
+            if abort_on_validation_error && level == LogLevel::Error && message_type.intersects(DebugUtilsMessageType::VALIDATION) {
+                let backtrace = std::backtrace::Backtrace::force_capture();
+                log(LogLevel::Error, &format!("Aborting on validation error!\n\n-----------\n\nBACKTRACE:\n\n{backtrace}\n\n-----------\n\n"));
+                std::process::abort();
+            }
+        })
+    };
+
+    let debug_messenger = DebugUtilsMessenger::new(instance.clone(), DebugUtilsMessengerCreateInfo {
+        message_severity: DebugUtilsMessageSeverity::ERROR | DebugUtilsMessageSeverity::WARNING | DebugUtilsMessageSeverity::INFO,
+        message_type: DebugUtilsMessageType::GENERAL | DebugUtilsMessageType::VALIDATION | DebugUtilsMessageType::PERFORMANCE,
+        ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+    })?;
+
+    Ok((instance, Some(debug_messenger)))
 }
 
 pub unsafe fn load_vulkan_and_get_queue(
     surface: &(impl HasRawWindowHandle + HasRawDisplayHandle),
-    anisotropic_filtering: Option<f32>
+    renderer_parameters: &RendererParameters
+) -> MResult<LoadedVulkan> {
+    let library = VulkanLibrary::new()?;
+
+    let mut enabled_extensions = Surface::required_extensions(surface);
+
+    // Needed for surfaces to report HDR color spaces.
+    if renderer_parameters.hdr {
+        enabled_extensions |= library.supported_extensions() & InstanceExtensions {
+            ext_swapchain_colorspace: true,
+            ..InstanceExtensions::empty()
+        };
+    }
+
+    let (instance, debug_messenger) = create_instance(library, enabled_extensions, renderer_parameters.enable_validation, renderer_parameters.abort_on_validation_error)?;
+
+    let surface = Surface::from_window_ref(instance.clone(), surface)?;
+
+    load_device_and_get_queue(instance, Some(surface), debug_messenger, renderer_parameters)
+}
+
+pub fn load_vulkan_headless_and_get_queue(
+    renderer_parameters: &RendererParameters
 ) -> MResult<LoadedVulkan> {
     let library = VulkanLibrary::new()?;
 
+    let (instance, debug_messenger) = create_instance(library, InstanceExtensions::empty(), renderer_parameters.enable_validation, renderer_parameters.abort_on_validation_error)?;
+
+    load_device_and_get_queue(instance, None, debug_messenger, renderer_parameters)
+}
+
+/// Get the GPU that would be selected for the surface with the given preference, ignoring MSAA and anisotropic
+/// filtering requirements.
+pub unsafe fn load_physical_device(
+    surface: &(impl HasRawWindowHandle + HasRawDisplayHandle),
+    preferred_device: Option<&DeviceSelection>
+) -> MResult<Arc<PhysicalDevice>> {
+    let library = VulkanLibrary::new()?;
+
     let enabled_extensions = Surface::required_extensions(surface);
+
+    let instance = Instance::new(library.clone(), InstanceCreateInfo {
+        enabled_extensions,
+        ..Default::default()
+    })?;
+
+    let surface = Surface::from_window_ref(instance.clone(), surface)?;
+    let requirements = get_gpu_requirements(Some(surface), false, SampleCount::Sample1);
+    let (physical_device, ..) = find_best_gpu(instance, &requirements, preferred_device)?;
+    Ok(physical_device)
+}
+
+fn load_device_and_get_queue(
+    instance: Arc<Instance>,
+    surface: Option<Arc<Surface>>,
+    debug_messenger: Option<DebugUtilsMessenger>,
+    renderer_parameters: &RendererParameters
+) -> MResult<LoadedVulkan> {
+    let requirements = get_gpu_requirements(
+        surface.clone(),
+        renderer_parameters.anisotropic_filtering.is_some(),
+        get_sample_count(renderer_parameters.msaa)
+    );
+
+    let (physical_device, queue_family_index, device_extensions) = find_best_gpu(
+        instance.clone(),
+        &requirements,
+        renderer_parameters.preferred_device.as_ref()
+    )?;
+
+    let (device, mut queues) = create_device_and_queues(
+        physical_device,
+        device_extensions,
+        queue_family_index
+    )?;
+    let queue = queues.next().ok_or_else(|| Error::from_vulkan_error("Unable to make a device queue".to_string()))?;
+
+    Ok(LoadedVulkan { instance, device, queue, surface, debug_messenger })
+}
+
+fn get_gpu_requirements(surface: Option<Arc<Surface>>, anisotropic_filtering: bool, samples: SampleCount) -> GPURequirements {
     let device_extensions_all = DeviceExtensions {
-        // Non-negotiable; required to do swapchains
-        khr_swapchain: true,
+        // Non-negotiable; required to do swapchains (unless we're headless)
+        khr_swapchain: surface.is_some(),
         ..DeviceExtensions::empty()
     };
 
@@ -40,7 +183,7 @@ pub unsafe fn load_vulkan_and_get_queue(
     }.clone();
 
     let required_device_features = Features {
-        sampler_anisotropy: anisotropic_filtering.is_some(),
+        sampler_anisotropy: anisotropic_filtering,
         ..Features::empty()
     };
 
@@ -52,34 +195,24 @@ pub unsafe fn load_vulkan_and_get_queue(
         ..optional_extensions_all
     };
 
-    let instance = Instance::new(library.clone(), InstanceCreateInfo {
-        enabled_extensions,
-        ..Default::default()
-    })?;
-
-    let surface = Surface::from_window_ref(instance.clone(), surface)?;
-
-    let (physical_device, queue_family_index, device_extensions) = find_best_gpu(
-        instance.clone(),
+    GPURequirements {
         device_extensions_12,
-        device_extensions_all,
+        device_extensions_13: device_extensions_all,
         optional_extensions_12,
-        optional_extensions_all,
+        optional_extensions_13: optional_extensions_all,
         required_device_features,
-        surface.clone()
-    ).ok_or_else(|| Error::from_vulkan_error("No suitable Vulkan-compatible GPUs found".to_string()))?;
-
-    let (device, mut queues) = create_device_and_queues(
-        physical_device,
-        device_extensions,
-        queue_family_index
-    )?;
-    let queue = queues.next().ok_or_else(|| Error::from_vulkan_error("Unable to make a device queue".to_string()))?;
-
-    Ok(LoadedVulkan { instance, device, queue, surface })
+        surface,
+        samples
+    }
 }
 
 fn create_device_and_queues(physical_device: Arc<PhysicalDevice>, device_extensions: DeviceExtensions, queue_family_index: u32) -> Result<(Arc<Device>, impl ExactSizeIterator<Item=Arc<Queue>> + Sized), Validated<VulkanError>> {
+    // Optional; used for wireframe rendering
+    let fill_mode_non_solid = physical_device.supported_features().fill_mode_non_solid;
+
+    // Optional; used for DXT/BCn bitmaps
+    let texture_compression_bc = physical_device.supported_features().texture_compression_bc;
+
     Device::new(
         physical_device,
         DeviceCreateInfo {
@@ -92,6 +225,8 @@ fn create_device_and_queues(physical_device: Arc<PhysicalDevice>, device_extensi
                 dynamic_rendering: device_extensions.khr_dynamic_rendering,
                 extended_dynamic_state: true,
                 sampler_anisotropy: true,
+                fill_mode_non_solid,
+                texture_compression_bc,
                 ..Features::default()
             },
             ..Default::default()
@@ -99,7 +234,7 @@ fn create_device_and_queues(physical_device: Arc<PhysicalDevice>, device_extensi
     )
 }
 
-pub fn build_swapchain(device: Arc<Device>, surface: Arc<Surface>, image_format: Format, renderer_parameters: &RendererParameters) -> MResult<(Arc<Swapchain>, Vec<Arc<Image>>)> {
+pub fn build_swapchain(device: Arc<Device>, surface: Arc<Surface>, (image_format, image_color_space): (Format, ColorSpace), renderer_parameters: &RendererParameters) -> MResult<(Arc<Swapchain>, Vec<Arc<Image>>)> {
     let surface_capabilities = device
         .physical_device()
         .surface_capabilities(surface.as_ref(), Default::default())
@@ -107,19 +242,14 @@ pub fn build_swapchain(device: Arc<Device>, surface: Arc<Surface>, image_format:
 
     let result = Swapchain::new(
         device.clone(),
-        surface,
+        surface.clone(),
         SwapchainCreateInfo {
             min_image_count: surface_capabilities.min_image_count.max(2),
             image_format,
+            image_color_space,
             image_extent: [renderer_parameters.resolution.width, renderer_parameters.resolution.height],
             image_usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST,
-            present_mode: if renderer_parameters.vsync {
-                // This is guaranteed to be supported as per the Vulkan standard.
-                PresentMode::Fifo
-            } else {
-                // This should be supported, but it is not technically required.
-                PresentMode::Immediate
-            },
+            present_mode: get_present_mode(device.as_ref(), surface.as_ref(), renderer_parameters.present_mode, renderer_parameters.vsync)?,
 
             // The alpha mode indicates how the alpha value of the final image will behave. For
             // example, you can choose whether the window will be opaque or transparent.
@@ -136,50 +266,137 @@ pub fn build_swapchain(device: Arc<Device>, surface: Arc<Surface>, image_format:
     Ok(result)
 }
 
-fn find_best_gpu(
-    instance: Arc<Instance>,
+/// Get the format and color space to present to the surface with.
+///
+/// If `hdr` is true, an extended sRGB (scRGB) format is preferred, as this can be blitted to from
+/// `HDR_OFFLINE_PIPELINE_COLOR_FORMAT` without any conversion. Otherwise, if `srgb` is true, an sRGB format is
+/// preferred. If the surface supports neither, the first supported format is used.
+pub fn get_surface_format(device: &Device, surface: &Surface, hdr: bool, srgb: bool) -> MResult<(Format, ColorSpace)> {
+    let surface_formats = device
+        .physical_device()
+        .surface_formats(surface, Default::default())?;
+
+    if hdr {
+        let hdr_format = surface_formats
+            .iter()
+            .find(|(format, color_space)| *format == Format::R16G16B16A16_SFLOAT && *color_space == ColorSpace::ExtendedSrgbLinear);
+        if let Some(&format) = hdr_format {
+            return Ok(format)
+        }
+    }
+
+    if srgb {
+        let srgb_format = surface_formats
+            .iter()
+            .find(|(format, color_space)| format.numeric_format_color() == Some(NumericFormat::SRGB) && *color_space == ColorSpace::SrgbNonLinear);
+        if let Some(&format) = srgb_format {
+            return Ok(format)
+        }
+    }
+
+    surface_formats
+        .first()
+        .copied()
+        .ok_or_else(|| Error::from_data_error_string("no formats are supported for the surface".to_owned()))
+}
+
+/// Get the format of the depth image.
+///
+/// If `stencil` is true, a combined depth/stencil format is used, preferring 32-bit float depth.
+///
+/// Errors if `stencil` is true and the device supports no depth/stencil format.
+pub fn get_depth_format(device: &Device, stencil: bool) -> MResult<Format> {
+    if !stencil {
+        return Ok(Format::D32_SFLOAT)
+    }
+
+    for format in [Format::D32_SFLOAT_S8_UINT, Format::D24_UNORM_S8_UINT] {
+        let format_features = device.physical_device().format_properties(format)?.optimal_tiling_features;
+        if format_features.contains(FormatFeatures::DEPTH_STENCIL_ATTACHMENT | FormatFeatures::TRANSFER_DST) {
+            return Ok(format)
+        }
+    }
+
+    Err(Error::from_data_error_string("no depth/stencil format is supported by this device".to_owned()))
+}
+
+/// Get the present mode to use for the surface.
+///
+/// `vsync` is only used if `present_mode` is `None`.
+///
+/// Errors if no suitable present mode is supported by the surface.
+pub fn get_present_mode(device: &Device, surface: &Surface, present_mode: Option<PresentModePreference>, vsync: bool) -> MResult<PresentMode> {
+    let supported_present_modes: Vec<PresentMode> = device
+        .physical_device()
+        .surface_present_modes(surface, Default::default())?
+        .into_iter()
+        .collect();
+
+    // Fifo is guaranteed to be supported as per the Vulkan standard. The others should be supported, but they are not
+    // technically required.
+    let preferred_present_modes: &[PresentMode] = match present_mode {
+        Some(PresentModePreference::AutoVsync) => &[PresentMode::Mailbox, PresentMode::Fifo],
+        Some(PresentModePreference::LowLatency) => &[PresentMode::Immediate, PresentMode::Mailbox, PresentMode::Fifo],
+        Some(PresentModePreference::NoTearing) => &[PresentMode::Fifo],
+        None if vsync => &[PresentMode::Fifo],
+        None => &[PresentMode::Mailbox, PresentMode::Immediate, PresentMode::Fifo]
+    };
+
+    preferred_present_modes
+        .iter()
+        .find(|p| supported_present_modes.contains(p))
+        .copied()
+        .ok_or_else(|| Error::from_data_error_string(format!("no suitable present mode is supported (present mode = {present_mode:?}, vsync = {vsync})")))
+}
+
+struct GPURequirements {
     device_extensions_12: DeviceExtensions,
     device_extensions_13: DeviceExtensions,
     optional_extensions_12: DeviceExtensions,
     optional_extensions_13: DeviceExtensions,
     required_device_features: Features,
-    surface: Arc<Surface>
-) -> Option<(Arc<PhysicalDevice>, u32, DeviceExtensions)> {
-    instance
-        .enumerate_physical_devices()
-        .unwrap()
-        .filter(|device| device.supported_features().contains(&required_device_features))
-        .filter_map(|device| {
-            let supported_extensions = device.supported_extensions().to_owned();
-            if device.api_version() >= Version::V1_3 {
-                if supported_extensions.contains(&device_extensions_13) {
-                    Some((device, device_extensions_13 | (supported_extensions & optional_extensions_13)))
-                }
-                else {
-                    None
-                }
-            }
-            else if device.api_version() >= Version::V1_2 {
-                if supported_extensions.contains(&device_extensions_12) {
-                    Some((device, device_extensions_12 | (supported_extensions & optional_extensions_12)))
-                }
-                else {
-                    None
-                }
-            }
-            else {
+    surface: Option<Arc<Surface>>,
+    samples: SampleCount
+}
+
+fn find_best_gpu(
+    instance: Arc<Instance>,
+    requirements: &GPURequirements,
+    preferred_device: Option<&DeviceSelection>
+) -> MResult<(Arc<PhysicalDevice>, u32, DeviceExtensions)> {
+    let devices: Vec<Arc<PhysicalDevice>> = instance.enumerate_physical_devices()?.collect();
+
+    let requested_device = match preferred_device {
+        Some(DeviceSelection::Index(index)) => Some(
+            devices
+                .get(*index)
+                .ok_or_else(|| Error::from_vulkan_error(format!("Requested GPU #{index} does not exist ({} found)", devices.len())))?
+        ),
+        Some(DeviceSelection::Name(name)) => Some(
+            devices
+                .iter()
+                .find(|d| d.properties().device_name.contains(name.as_str()))
+                .ok_or_else(|| Error::from_vulkan_error(format!("No GPU found with a name containing \"{name}\"")))?
+        ),
+        Some(DeviceSelection::PreferDiscrete) | None => None
+    };
+
+    if let Some(device) = requested_device {
+        return check_gpu(device, requirements)
+            .map(|(queue_family_index, extensions)| (device.clone(), queue_family_index, extensions))
+            .map_err(|e| Error::from_vulkan_error(format!("Requested GPU {} cannot be used: {e}", device.properties().device_name)))
+    }
+
+    let mut reasons = String::new();
+    devices
+        .iter()
+        .filter_map(|device| match check_gpu(device, requirements) {
+            Ok((queue_family_index, extensions)) => Some((device.clone(), queue_family_index, extensions)),
+            Err(e) => {
+                reasons += &format!("\n  {}: {e}", device.properties().device_name);
                 None
             }
         })
-        .filter_map(|(device, extensions)| {
-            device.queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.intersects(QueueFlags::GRAPHICS) && (device.surface_support(i as u32, surface.as_ref()).unwrap_or(false))
-                })
-                .map(|i| (device, i as u32, extensions))
-        })
         .min_by_key(|(p, ..)| match p.properties().device_type {
             PhysicalDeviceType::DiscreteGpu => 0,
             PhysicalDeviceType::IntegratedGpu => 1,
@@ -187,4 +404,137 @@ fn find_best_gpu(
             PhysicalDeviceType::Cpu => 3,
             _ => u32::MAX,
         })
+        .ok_or_else(|| Error::from_vulkan_error(format!("No suitable Vulkan-compatible GPUs found{reasons}")))
+}
+
+/// Check that the device meets all requirements, returning the queue family index and extensions to enable if so.
+fn check_gpu(device: &PhysicalDevice, requirements: &GPURequirements) -> Result<(u32, DeviceExtensions), String> {
+    if !device.supported_features().contains(&requirements.required_device_features) {
+        return Err("required features are unsupported".to_owned())
+    }
+
+    let supported_extensions = device.supported_extensions().to_owned();
+    let extensions = if device.api_version() >= Version::V1_3 {
+        if !supported_extensions.contains(&requirements.device_extensions_13) {
+            return Err("required extensions are unsupported".to_owned())
+        }
+        requirements.device_extensions_13 | (supported_extensions & requirements.optional_extensions_13)
+    }
+    else if device.api_version() >= Version::V1_2 {
+        if !supported_extensions.contains(&requirements.device_extensions_12) {
+            return Err("required extensions are unsupported".to_owned())
+        }
+        requirements.device_extensions_12 | (supported_extensions & requirements.optional_extensions_12)
+    }
+    else {
+        return Err(format!("Vulkan {} is unsupported (1.2 or newer is required)", device.api_version()))
+    };
+
+    let intersection = get_supported_sample_counts(device);
+    if !intersection.contains_enum(requirements.samples) {
+        return Err(format!("{}x MSAA is unsupported; only these are supported:{}",
+                           requirements.samples as u32,
+                           intersection.into_iter().map(|s| format!(" {}", s as u32)).collect::<String>()))
+    }
+
+    if let Some(surface) = requirements.surface.as_ref() {
+        let has_formats = device
+            .surface_formats(surface.as_ref(), Default::default())
+            .is_ok_and(|f| !f.is_empty());
+        if !has_formats {
+            return Err("no formats are supported for the surface".to_owned())
+        }
+    }
+
+    device.queue_family_properties()
+        .iter()
+        .enumerate()
+        .position(|(i, q)| {
+            q.queue_flags.intersects(QueueFlags::GRAPHICS) && requirements.surface
+                .as_ref()
+                .map_or(true, |surface| device.surface_support(i as u32, surface.as_ref()).unwrap_or(false))
+        })
+        .map(|i| (i as u32, extensions))
+        .ok_or_else(|| if requirements.surface.is_some() {
+            "cannot present to the surface".to_owned()
+        }
+        else {
+            "no graphics queue is available".to_owned()
+        })
+}
+
+/// Get all MSAA levels supported by the device.
+pub fn get_supported_msaa(device: &PhysicalDevice) -> Vec<MSAA> {
+    let supported = get_supported_sample_counts(device);
+    [MSAA::NoMSAA, MSAA::MSAA2x, MSAA::MSAA4x, MSAA::MSAA8x, MSAA::MSAA16x, MSAA::MSAA32x, MSAA::MSAA64x]
+        .into_iter()
+        .filter(|m| supported.contains_enum(get_sample_count(*m)))
+        .collect()
+}
+
+/// Get the maximum anisotropic filtering level supported by the device, or `None` if it is unsupported.
+pub fn get_supported_max_anisotropy(device: &PhysicalDevice) -> Option<f32> {
+    device
+        .supported_features()
+        .sampler_anisotropy
+        .then(|| device.properties().max_sampler_anisotropy)
+}
+
+/// Get the total size of all device-local memory heaps in bytes.
+pub fn get_device_local_memory(device: &PhysicalDevice) -> u64 {
+    device
+        .memory_properties()
+        .memory_heaps
+        .iter()
+        .filter(|h| h.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|h| h.size)
+        .sum()
+}
+
+/// Get the sample counts supported for both color and depth images.
+pub fn get_supported_sample_counts(device: &PhysicalDevice) -> SampleCounts {
+    let properties = device.properties();
+    properties.sampled_image_color_sample_counts & properties.sampled_image_depth_sample_counts
+}
+
+pub fn get_sample_count(msaa: MSAA) -> SampleCount {
+    match msaa {
+        MSAA::NoMSAA => SampleCount::Sample1,
+        MSAA::MSAA2x => SampleCount::Sample2,
+        MSAA::MSAA4x => SampleCount::Sample4,
+        MSAA::MSAA8x => SampleCount::Sample8,
+        MSAA::MSAA16x => SampleCount::Sample16,
+        MSAA::MSAA32x => SampleCount::Sample32,
+        MSAA::MSAA64x => SampleCount::Sample64
+    }
+}
+
+/// List all physical devices in the order used by [`DeviceSelection::Index`].
+pub fn list_physical_devices() -> MResult<Vec<PhysicalDeviceInfo>> {
+    let library = VulkanLibrary::new()?;
+    let instance = Instance::new(library, InstanceCreateInfo::default())?;
+
+    let devices = instance
+        .enumerate_physical_devices()?
+        .enumerate()
+        .map(|(index, device)| {
+            let properties = device.properties();
+            let vram = get_device_local_memory(device.as_ref());
+
+            PhysicalDeviceInfo {
+                index,
+                name: properties.device_name.clone(),
+                device_type: match properties.device_type {
+                    PhysicalDeviceType::DiscreteGpu => GPUType::Discrete,
+                    PhysicalDeviceType::IntegratedGpu => GPUType::Integrated,
+                    PhysicalDeviceType::VirtualGpu => GPUType::Virtual,
+                    PhysicalDeviceType::Cpu => GPUType::CPU,
+                    _ => GPUType::Other
+                },
+                vram
+            }
+        })
+        .collect();
+
+    Ok(devices)
 }
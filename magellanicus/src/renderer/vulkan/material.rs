@@ -1,10 +1,16 @@
 mod simple_shader;
 mod shader_environment;
+mod shader_model;
 mod shader_transparent_chicago;
+mod shader_transparent_generic;
+mod shader_transparent_water;
 
 use crate::error::MResult;
 use crate::renderer::vulkan::material::shader_environment::VulkanShaderEnvironmentMaterial;
+use crate::renderer::vulkan::material::shader_model::VulkanShaderModelMaterial;
 use crate::renderer::vulkan::material::shader_transparent_chicago::VulkanShaderTransparentChicagoMaterial;
+use crate::renderer::vulkan::material::shader_transparent_generic::VulkanShaderTransparentGenericMaterial;
+use crate::renderer::vulkan::material::shader_transparent_water::VulkanShaderTransparentWaterMaterial;
 use crate::renderer::vulkan::material::simple_shader::VulkanSimpleShaderMaterial;
 use crate::renderer::vulkan::VulkanPipelineType;
 use crate::renderer::{AddShaderData, AddShaderParameter, Renderer};
@@ -19,6 +25,7 @@ use crate::vertex::VertexOffsets;
 /// - layout 0, location 0 is vertex data, defined as [`VulkanModelVertex`](crate::renderer::vulkan::vertex::VulkanModelVertex)
 /// - layout 0, location 1 is texture coordinates, defined as [`VulkanModelVertexTextureCoords`](crate::renderer::vulkan::vertex::VulkanModelVertexTextureCoords)
 /// - layout 0, location 2 is lightmap texture coordinates, defined as [`VulkanModelVertexTextureCoords`](crate::renderer::vulkan::vertex::VulkanModelVertexTextureCoords)
+/// - layout 0, location 3 is the per-instance transform, defined as [`VulkanModelInstance`](crate::renderer::vulkan::vertex::VulkanModelInstance)
 ///
 /// Descriptor sets are bound like this:
 ///
@@ -43,17 +50,29 @@ impl VulkanMaterialShaderData {
                 let shader = Arc::new(VulkanShaderEnvironmentMaterial::new(renderer, shader)?);
                 Ok(Self { pipeline_data: shader })
             }
+            AddShaderData::ShaderModel(shader) => {
+                let shader = Arc::new(VulkanShaderModelMaterial::new(renderer, shader)?);
+                Ok(Self { pipeline_data: shader })
+            }
             AddShaderData::ShaderTransparentChicago(shader) => {
                 let shader = Arc::new(VulkanShaderTransparentChicagoMaterial::new(renderer, shader)?);
                 Ok(Self { pipeline_data: shader })
             }
+            AddShaderData::ShaderTransparentGeneric(shader) => {
+                let shader = Arc::new(VulkanShaderTransparentGenericMaterial::new(renderer, shader)?);
+                Ok(Self { pipeline_data: shader })
+            }
+            AddShaderData::ShaderTransparentWater(shader) => {
+                let shader = Arc::new(VulkanShaderTransparentWaterMaterial::new(renderer, shader)?);
+                Ok(Self { pipeline_data: shader })
+            }
         }
     }
 }
 
 impl VertexOffsets {
-    pub fn make_vulkan_draw_command(&self, to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
-        to.draw_indexed(self.index_count, 1, self.index_offset, self.vertex_offset, 0)?;
+    pub fn make_vulkan_draw_command(&self, instance_count: u32, to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        to.draw_indexed(self.index_count, instance_count, self.index_offset, self.vertex_offset, 0)?;
         Ok(())
     }
 }
@@ -61,12 +80,13 @@ impl VertexOffsets {
 pub trait VulkanMaterial: Send + Sync + 'static {
     /// Generate rendering commands.
     ///
-    /// All vertex buffers (vertices, texture coords, lightmap texture coords) will be bound before
-    /// this is called.
+    /// All vertex buffers (vertices, texture coords, lightmap texture coords, instances) will be bound before
+    /// this is called, with `instance_count` instances in the instance buffer.
     fn generate_commands(
         &self,
         renderer: &Renderer,
         vertices: &VertexOffsets,
+        instance_count: u32,
         repeat_shader: bool,
         to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     ) -> MResult<()>;
@@ -80,6 +100,16 @@ pub trait VulkanMaterial: Send + Sync + 'static {
         false
     }
 
+    /// Return `true` if the material can be drawn in the depth pre-pass.
+    ///
+    /// This requires the material to be opaque, not alpha tested, and to calculate its vertex positions
+    /// the same way as the depth pre-pass.
+    ///
+    /// Default: `false`
+    fn can_depth_prepass(&self) -> bool {
+        false
+    }
+
     /// Get the main graphics pipeline that will be used for drawing.
     fn get_main_pipeline(&self) -> VulkanPipelineType;
 
@@ -1,15 +1,36 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::{default_allocation_create_info, VertexOffsets, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VertexOffsets, VulkanMaterial, VulkanPipelineType};
 use crate::renderer::{AddShaderEnvironmentShaderData, DefaultType, Renderer};
 use std::sync::Arc;
 use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewType};
 use vulkano::pipeline::{Pipeline, PipelineBindPoint};
 
+// These must match shader_environment_data.glsl.
+const SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST: u32 = 1;
+const SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK: u32 = 2;
+const SHADER_ENVIRONMENT_FLAGS_BUMPMAP_TWO_CHANNEL: u32 = 4;
+
+fn pack_flags(alpha_tested: bool, bump_map_is_specular_mask: bool, two_channel_bump_map: bool) -> u32 {
+    let mut flags = 0;
+    if alpha_tested {
+        flags |= SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST;
+    }
+    if bump_map_is_specular_mask {
+        flags |= SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK;
+    }
+    if two_channel_bump_map {
+        flags |= SHADER_ENVIRONMENT_FLAGS_BUMPMAP_TWO_CHANNEL;
+    }
+    flags
+}
+
 pub struct VulkanShaderEnvironmentMaterial {
-    descriptor_set: Arc<PersistentDescriptorSet>
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    alpha_tested: bool
 }
 
 impl VulkanShaderEnvironmentMaterial {
@@ -60,32 +81,26 @@ impl VulkanShaderEnvironmentMaterial {
             secondary_detail_map_scale: add_shader_parameter.secondary_detail_map_scale,
             bump_map_scale: add_shader_parameter.bump_map_scale,
             micro_detail_map_scale: add_shader_parameter.micro_detail_map_scale,
-            flags: {
-                let mut flags = 0;
-                flags |= (add_shader_parameter.alpha_tested as u32) << 0;
-                flags |= (add_shader_parameter.bump_map_is_specular_mask as u32) << 1;
-                flags
-            },
+            flags: pack_flags(
+                add_shader_parameter.alpha_tested,
+                add_shader_parameter.bump_map_is_specular_mask,
+                bump_map.format() == Format::BC5_UNORM_BLOCK
+            ),
             shader_environment_type: add_shader_parameter.shader_environment_type as u32,
             detail_map_function: add_shader_parameter.detail_map_function as u32,
             micro_detail_map_function: add_shader_parameter.micro_detail_map_function as u32,
+            detail_fade: [add_shader_parameter.detail_fade_start, add_shader_parameter.detail_fade_end, 0.0, 0.0],
             parallel_color: [add_shader_parameter.parallel_color[0], add_shader_parameter.parallel_color[1], add_shader_parameter.parallel_color[2], add_shader_parameter.parallel_brightness],
             perpendicular_color: [add_shader_parameter.perpendicular_color[0], add_shader_parameter.perpendicular_color[1], add_shader_parameter.perpendicular_color[2], add_shader_parameter.perpendicular_brightness],
         };
 
         let map_sampler = renderer.vulkan.default_2d_sampler.clone();
-        let base_map = ImageView::new_default(base_map)?;
-        let primary_detail_map = ImageView::new_default(primary_detail_map)?;
-        let secondary_detail_map = ImageView::new_default(secondary_detail_map)?;
-        let micro_detail_map = ImageView::new_default(micro_detail_map)?;
+        let base_map = color_map_view(&renderer.vulkan, base_map, ImageViewType::Dim2d)?;
+        let primary_detail_map = color_map_view(&renderer.vulkan, primary_detail_map, ImageViewType::Dim2d)?;
+        let secondary_detail_map = color_map_view(&renderer.vulkan, secondary_detail_map, ImageViewType::Dim2d)?;
+        let micro_detail_map = color_map_view(&renderer.vulkan, micro_detail_map, ImageViewType::Dim2d)?;
         let bump_map = ImageView::new_default(bump_map)?;
-        let cubemap = ImageView::new(
-            cubemap.clone(),
-            ImageViewCreateInfo {
-                view_type: ImageViewType::Cube,
-                ..ImageViewCreateInfo::from_image(&cubemap)
-            }
-        )?;
+        let cubemap = color_map_view(&renderer.vulkan, cubemap, ImageViewType::Cube)?;
 
         let uniform_buffer = Buffer::from_data(
             renderer.vulkan.memory_allocator.clone(),
@@ -111,7 +126,8 @@ impl VulkanShaderEnvironmentMaterial {
         )?;
 
         let shader_data = Self {
-            descriptor_set
+            descriptor_set,
+            alpha_tested: add_shader_parameter.alpha_tested
         };
 
         Ok(shader_data)
@@ -123,6 +139,7 @@ impl VulkanMaterial for VulkanShaderEnvironmentMaterial {
         &self,
         renderer: &Renderer,
         vertices: &VertexOffsets,
+        instance_count: u32,
         repeat_shader: bool,
         to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
     ) -> MResult<()> {
@@ -135,10 +152,14 @@ impl VulkanMaterial for VulkanShaderEnvironmentMaterial {
                 self.descriptor_set.clone()
             )?;
         }
-        vertices.make_vulkan_draw_command(to)?;
+        vertices.make_vulkan_draw_command(instance_count, to)?;
         Ok(())
     }
 
+    fn can_depth_prepass(&self) -> bool {
+        !self.alpha_tested
+    }
+
     fn get_main_pipeline(&self) -> VulkanPipelineType {
         VulkanPipelineType::ShaderEnvironment
     }
@@ -147,3 +168,17 @@ impl VulkanMaterial for VulkanShaderEnvironmentMaterial {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_packed() {
+        assert_eq!(pack_flags(false, false, false), 0);
+        assert_eq!(pack_flags(true, false, false), SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST);
+        assert_eq!(pack_flags(false, true, false), SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK);
+        assert_eq!(pack_flags(false, false, true), SHADER_ENVIRONMENT_FLAGS_BUMPMAP_TWO_CHANNEL);
+        assert_eq!(pack_flags(true, true, true), 7);
+    }
+}
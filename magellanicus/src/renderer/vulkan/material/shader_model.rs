@@ -0,0 +1,137 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VertexOffsets, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::{AddShaderModelShaderData, DefaultType, Renderer};
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::pipeline::graphics::rasterization::CullMode;
+
+pub struct VulkanShaderModelMaterial {
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    two_sided: bool
+}
+
+impl VulkanShaderModelMaterial {
+    pub fn new(renderer: &mut Renderer, add_shader_parameter: AddShaderModelShaderData) -> MResult<Self> {
+        let base_map = renderer
+            .get_or_default_2d(&add_shader_parameter.base_map, 0, DefaultType::White)
+            .vulkan
+            .image
+            .clone();
+
+        let multipurpose_map = renderer
+            .get_or_default_2d(&add_shader_parameter.multipurpose_map, 0, DefaultType::Null)
+            .vulkan
+            .image
+            .clone();
+
+        let detail_map = renderer
+            .get_or_default_2d(&add_shader_parameter.detail_map, 0, DefaultType::Gray)
+            .vulkan
+            .image
+            .clone();
+
+        let cubemap = renderer
+            .get_or_default_cubemap(&add_shader_parameter.reflection_cube_map, 0, DefaultType::Null)
+            .vulkan
+            .image
+            .clone();
+
+        let pipeline = renderer
+            .vulkan
+            .pipelines[&VulkanPipelineType::ShaderModel]
+            .clone();
+
+        let uniform = super::super::pipeline::shader_model::ShaderModelData {
+            parallel_color: [add_shader_parameter.parallel_color[0], add_shader_parameter.parallel_color[1], add_shader_parameter.parallel_color[2], add_shader_parameter.parallel_brightness],
+            perpendicular_color: [add_shader_parameter.perpendicular_color[0], add_shader_parameter.perpendicular_color[1], add_shader_parameter.perpendicular_color[2], add_shader_parameter.perpendicular_brightness],
+            self_illumination_color: [add_shader_parameter.self_illumination_color[0], add_shader_parameter.self_illumination_color[1], add_shader_parameter.self_illumination_color[2], 1.0],
+            map_scale: add_shader_parameter.map_uv_scale,
+            detail_map_scale: add_shader_parameter.detail_map_scale,
+            detail_map_v_scale: add_shader_parameter.detail_map_v_scale,
+            flags: {
+                let mut flags = 0;
+                flags |= (add_shader_parameter.alpha_tested as u32) << 0;
+                flags |= (add_shader_parameter.detail_after_reflection as u32) << 1;
+                flags
+            },
+            detail_map_function: add_shader_parameter.detail_map_function as u32,
+            detail_mask: add_shader_parameter.detail_mask as u32,
+        };
+
+        let map_sampler = renderer.vulkan.default_2d_sampler.clone();
+        let base_map = color_map_view(&renderer.vulkan, base_map, ImageViewType::Dim2d)?;
+        let multipurpose_map = ImageView::new_default(multipurpose_map)?;
+        let detail_map = color_map_view(&renderer.vulkan, detail_map, ImageViewType::Dim2d)?;
+        let cubemap = color_map_view(&renderer.vulkan, cubemap, ImageViewType::Cube)?;
+
+        let uniform_buffer = Buffer::from_data(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            uniform
+        )?;
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            pipeline.get_pipeline().layout().set_layouts()[3].clone(),
+            [
+                WriteDescriptorSet::buffer(0, uniform_buffer),
+                WriteDescriptorSet::sampler(1, map_sampler),
+                WriteDescriptorSet::image_view(2, base_map),
+                WriteDescriptorSet::image_view(3, multipurpose_map),
+                WriteDescriptorSet::image_view(4, detail_map),
+                WriteDescriptorSet::image_view(5, cubemap),
+            ],
+            []
+        )?;
+
+        let shader_data = Self {
+            descriptor_set,
+            two_sided: add_shader_parameter.two_sided
+        };
+
+        Ok(shader_data)
+    }
+}
+
+impl VulkanMaterial for VulkanShaderModelMaterial {
+    fn generate_commands(
+        &self,
+        renderer: &Renderer,
+        vertices: &VertexOffsets,
+        instance_count: u32,
+        repeat_shader: bool,
+        to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        if !repeat_shader {
+            let pipeline = renderer.vulkan.pipelines.get(&self.get_main_pipeline()).unwrap();
+            to.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.get_pipeline().layout().clone(),
+                3,
+                self.descriptor_set.clone()
+            )?;
+            if self.two_sided {
+                to.set_cull_mode(CullMode::None)?;
+            }
+        }
+        vertices.make_vulkan_draw_command(instance_count, to)?;
+        Ok(())
+    }
+
+    fn is_transparent(&self) -> bool {
+        false
+    }
+
+    fn get_main_pipeline(&self) -> VulkanPipelineType {
+        VulkanPipelineType::ShaderModel
+    }
+
+    fn can_reuse_descriptors(&self) -> bool {
+        true
+    }
+}
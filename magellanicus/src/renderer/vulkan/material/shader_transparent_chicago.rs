@@ -1,12 +1,12 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::{default_allocation_create_info, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VulkanMaterial, VulkanPipelineType};
 use crate::renderer::{AddShaderTransparentChicagoShaderData, AddShaderTransparentChicagoShaderMap, DefaultType, Renderer, ShaderTransparentChicagoFirstMapType, ShaderTransparentChicagoFramebufferFunction};
 use std::sync::Arc;
 use std::borrow::ToOwned;
 use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::image::view::ImageViewType;
 use vulkano::pipeline::{Pipeline, PipelineBindPoint};
 use vulkano::pipeline::graphics::rasterization::CullMode;
 use crate::vertex::VertexOffsets;
@@ -40,17 +40,11 @@ impl VulkanShaderTransparentChicagoMaterial {
         else {
             (renderer.get_default_2d(default_map), renderer.get_or_default_cubemap(&map0.bitmap, 0, default_map))
         };
-        let map0_2d = ImageView::new_default(map0_2d.vulkan.image.clone())?;
-        let map0_cubemap = ImageView::new(
-            map0_cubemap.vulkan.image.clone(),
-            ImageViewCreateInfo {
-                view_type: ImageViewType::Cube,
-                ..ImageViewCreateInfo::from_image(&map0_cubemap.vulkan.image)
-            }
-        )?;
-        let map1_2d = ImageView::new_default(renderer.get_or_default_2d(&map1.bitmap, 0, default_map).vulkan.image.clone())?;
-        let map2_2d = ImageView::new_default(renderer.get_or_default_2d(&map2.bitmap, 0, default_map).vulkan.image.clone())?;
-        let map3_2d = ImageView::new_default(renderer.get_or_default_2d(&map3.bitmap, 0, default_map).vulkan.image.clone())?;
+        let map0_2d = color_map_view(&renderer.vulkan, map0_2d.vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map0_cubemap = color_map_view(&renderer.vulkan, map0_cubemap.vulkan.image.clone(), ImageViewType::Cube)?;
+        let map1_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map1.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map2_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map2.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map3_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map3.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
 
         let premultiply = match add_shader_parameter.framebuffer_method {
             ShaderTransparentChicagoFramebufferFunction::Add => 1,
@@ -68,21 +62,25 @@ impl VulkanShaderTransparentChicagoMaterial {
             map0_scale: map0.uv_scale,
             map0_color_function: map0.color_function as u32,
             map0_alpha_function: map0.alpha_function as u32,
+            map0_scroll: map0.uv_scroll_rate,
 
             map1_uv: map1.uv_offset,
             map1_scale: map1.uv_scale,
             map1_color_function: map1.color_function as u32,
             map1_alpha_function: map1.alpha_function as u32,
+            map1_scroll: map1.uv_scroll_rate,
 
             map2_uv: map2.uv_offset,
             map2_scale: map2.uv_scale,
             map2_color_function: map2.color_function as u32,
             map2_alpha_function: map2.alpha_function as u32,
+            map2_scroll: map2.uv_scroll_rate,
 
             map3_uv: map3.uv_offset,
             map3_scale: map3.uv_scale,
             map3_color_function: map3.color_function as u32,
             map3_alpha_function: map3.alpha_function as u32,
+            map3_scroll: map3.uv_scroll_rate,
 
             first_map_type: add_shader_parameter.first_map_type as u32,
             map_count: add_shader_parameter.maps.len() as u32,
@@ -98,17 +96,22 @@ impl VulkanShaderTransparentChicagoMaterial {
             uniform
         )?;
 
-        let map_sampler = renderer.vulkan.default_2d_sampler.clone();
+        let map_samplers = [
+            renderer.vulkan.get_map_sampler(map0.address_mode)?,
+            renderer.vulkan.get_map_sampler(map1.address_mode)?,
+            renderer.vulkan.get_map_sampler(map2.address_mode)?,
+            renderer.vulkan.get_map_sampler(map3.address_mode)?,
+        ];
 
         let pipeline = match add_shader_parameter.framebuffer_method {
             ShaderTransparentChicagoFramebufferFunction::Add => VulkanPipelineType::ShaderTransparentChicagoAdd,
             ShaderTransparentChicagoFramebufferFunction::AlphaBlend => VulkanPipelineType::ShaderTransparentChicagoAlphaBlend,
             ShaderTransparentChicagoFramebufferFunction::Multiply => VulkanPipelineType::ShaderTransparentChicagoMultiply,
-            ShaderTransparentChicagoFramebufferFunction::DoubleMultiply => VulkanPipelineType::ShaderTransparentChicagoMultiply, // FIXME
+            ShaderTransparentChicagoFramebufferFunction::DoubleMultiply => VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply,
             ShaderTransparentChicagoFramebufferFunction::Subtract => VulkanPipelineType::ShaderTransparentChicagoSubtract,
             ShaderTransparentChicagoFramebufferFunction::ComponentMin => VulkanPipelineType::ShaderTransparentChicagoComponentMin,
             ShaderTransparentChicagoFramebufferFunction::ComponentMax => VulkanPipelineType::ShaderTransparentChicagoComponentMax,
-            ShaderTransparentChicagoFramebufferFunction::AlphaMultiplyAdd => VulkanPipelineType::ShaderTransparentChicagoAlphaBlend // FIXME
+            ShaderTransparentChicagoFramebufferFunction::AlphaMultiplyAdd => VulkanPipelineType::ShaderTransparentChicagoAlphaMultiplyAdd
         };
 
         let descriptor_set = PersistentDescriptorSet::new(
@@ -116,7 +119,7 @@ impl VulkanShaderTransparentChicagoMaterial {
             renderer.vulkan.pipelines[&pipeline].get_pipeline().layout().set_layouts()[3].clone(),
             [
                 WriteDescriptorSet::buffer(0, uniform_buffer),
-                WriteDescriptorSet::sampler(1, map_sampler),
+                WriteDescriptorSet::sampler_array(1, 0, map_samplers),
                 WriteDescriptorSet::image_view(2, map0_cubemap),
                 WriteDescriptorSet::image_view(3, map0_2d),
                 WriteDescriptorSet::image_view(4, map1_2d),
@@ -141,6 +144,7 @@ impl VulkanMaterial for VulkanShaderTransparentChicagoMaterial {
         &self,
         renderer: &Renderer,
         vertices: &VertexOffsets,
+        instance_count: u32,
         repeat_shader: bool,
         to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
     ) -> MResult<()> {
@@ -156,7 +160,7 @@ impl VulkanMaterial for VulkanShaderTransparentChicagoMaterial {
                 to.set_cull_mode(CullMode::None)?;
             }
         }
-        vertices.make_vulkan_draw_command(to)?;
+        vertices.make_vulkan_draw_command(instance_count, to)?;
         Ok(())
     }
 
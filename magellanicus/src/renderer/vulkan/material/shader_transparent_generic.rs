@@ -0,0 +1,188 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::{AddShaderTransparentGenericShaderData, AddShaderTransparentGenericShaderMap, AddShaderTransparentGenericShaderStage, DefaultType, Renderer, ShaderTransparentChicagoFirstMapType, ShaderTransparentChicagoFramebufferFunction};
+use std::sync::Arc;
+use std::borrow::ToOwned;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::view::ImageViewType;
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::pipeline::graphics::rasterization::CullMode;
+use crate::renderer::vulkan::pipeline::shader_transparent_generic::{ShaderTransparentGenericData, ShaderTransparentGenericStage};
+use crate::vertex::VertexOffsets;
+
+pub struct VulkanShaderTransparentGenericMaterial {
+    pipeline: VulkanPipelineType,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    two_sided: bool
+}
+
+impl VulkanShaderTransparentGenericMaterial {
+    pub fn new(renderer: &mut Renderer, add_shader_parameter: AddShaderTransparentGenericShaderData) -> MResult<Self> {
+        let get_map = |index: usize| -> AddShaderTransparentGenericShaderMap {
+            add_shader_parameter
+                .maps
+                .get(index)
+                .map(|f| f.to_owned())
+                .unwrap_or_default()
+        };
+
+        let map0 = get_map(0);
+        let map1 = get_map(1);
+        let map2 = get_map(2);
+        let map3 = get_map(3);
+
+        let default_map = DefaultType::Null;
+
+        let (map0_2d, map0_cubemap) = if add_shader_parameter.first_map_type == ShaderTransparentChicagoFirstMapType::Dim2D {
+            (renderer.get_or_default_2d(&map0.bitmap, 0, default_map), renderer.get_default_cubemap(default_map))
+        }
+        else {
+            (renderer.get_default_2d(default_map), renderer.get_or_default_cubemap(&map0.bitmap, 0, default_map))
+        };
+        let map0_2d = color_map_view(&renderer.vulkan, map0_2d.vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map0_cubemap = color_map_view(&renderer.vulkan, map0_cubemap.vulkan.image.clone(), ImageViewType::Cube)?;
+        let map1_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map1.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map2_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map2.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
+        let map3_2d = color_map_view(&renderer.vulkan, renderer.get_or_default_2d(&map3.bitmap, 0, default_map).vulkan.image.clone(), ImageViewType::Dim2d)?;
+
+        let premultiply = match add_shader_parameter.framebuffer_method {
+            ShaderTransparentChicagoFramebufferFunction::Add => 1,
+            ShaderTransparentChicagoFramebufferFunction::Subtract => 1,
+            _ => 0
+        };
+
+        let map_uv = |map: &AddShaderTransparentGenericShaderMap| -> [f32; 4] {
+            [map.uv_offset[0], map.uv_offset[1], map.uv_scale[0], map.uv_scale[1]]
+        };
+
+        let uniform = ShaderTransparentGenericData {
+            map_uv: [map_uv(&map0), map_uv(&map1), map_uv(&map2), map_uv(&map3)],
+            stages: std::array::from_fn(|index| {
+                let stage = add_shader_parameter
+                    .stages
+                    .get(index)
+                    .map(|f| f.to_owned())
+                    .unwrap_or_default();
+                stage_to_uniform(&stage)
+            }),
+            first_map_type: add_shader_parameter.first_map_type as u32,
+            stage_count: add_shader_parameter.stages.len() as u32,
+            premultiply
+        };
+
+        let uniform_buffer = Buffer::from_data(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            uniform
+        )?;
+
+        let map_sampler = renderer.vulkan.default_2d_sampler.clone();
+
+        let pipeline = match add_shader_parameter.framebuffer_method {
+            ShaderTransparentChicagoFramebufferFunction::Add => VulkanPipelineType::ShaderTransparentGenericAdd,
+            ShaderTransparentChicagoFramebufferFunction::AlphaBlend => VulkanPipelineType::ShaderTransparentGenericAlphaBlend,
+            ShaderTransparentChicagoFramebufferFunction::Multiply => VulkanPipelineType::ShaderTransparentGenericMultiply,
+            ShaderTransparentChicagoFramebufferFunction::DoubleMultiply => VulkanPipelineType::ShaderTransparentGenericDoubleMultiply,
+            ShaderTransparentChicagoFramebufferFunction::Subtract => VulkanPipelineType::ShaderTransparentGenericSubtract,
+            ShaderTransparentChicagoFramebufferFunction::ComponentMin => VulkanPipelineType::ShaderTransparentGenericComponentMin,
+            ShaderTransparentChicagoFramebufferFunction::ComponentMax => VulkanPipelineType::ShaderTransparentGenericComponentMax,
+            ShaderTransparentChicagoFramebufferFunction::AlphaMultiplyAdd => VulkanPipelineType::ShaderTransparentGenericAlphaMultiplyAdd
+        };
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            renderer.vulkan.pipelines[&pipeline].get_pipeline().layout().set_layouts()[3].clone(),
+            [
+                WriteDescriptorSet::buffer(0, uniform_buffer),
+                WriteDescriptorSet::sampler(1, map_sampler),
+                WriteDescriptorSet::image_view(2, map0_cubemap),
+                WriteDescriptorSet::image_view(3, map0_2d),
+                WriteDescriptorSet::image_view(4, map1_2d),
+                WriteDescriptorSet::image_view(5, map2_2d),
+                WriteDescriptorSet::image_view(6, map3_2d),
+            ],
+            []
+        )?;
+
+        let shader_data = Self {
+            pipeline,
+            descriptor_set,
+            two_sided: add_shader_parameter.two_sided
+        };
+
+        Ok(shader_data)
+    }
+}
+
+fn stage_to_uniform(stage: &AddShaderTransparentGenericShaderStage) -> ShaderTransparentGenericStage {
+    ShaderTransparentGenericStage {
+        constant_color0: stage.constant_color0,
+        constant_color1: stage.constant_color1,
+
+        color_inputs: stage.color_inputs.map(|i| i as u32),
+        color_input_mappings: stage.color_input_mappings.map(|i| i as u32),
+        color_outputs: [
+            stage.color_output_ab as u32,
+            stage.color_output_cd as u32,
+            stage.color_output_ab_cd_mux_sum as u32,
+            stage.color_output_mapping as u32
+        ],
+
+        alpha_inputs: stage.alpha_inputs.map(|i| i as u32),
+        alpha_input_mappings: stage.alpha_input_mappings.map(|i| i as u32),
+        alpha_outputs: [
+            stage.alpha_output_ab as u32,
+            stage.alpha_output_cd as u32,
+            stage.alpha_output_ab_cd_mux_sum as u32,
+            stage.alpha_output_mapping as u32
+        ],
+
+        functions: [
+            stage.color_output_ab_function as u32,
+            stage.color_output_cd_function as u32,
+            stage.color_mux as u32,
+            stage.alpha_mux as u32
+        ]
+    }
+}
+
+impl VulkanMaterial for VulkanShaderTransparentGenericMaterial {
+    fn generate_commands(
+        &self,
+        renderer: &Renderer,
+        vertices: &VertexOffsets,
+        instance_count: u32,
+        repeat_shader: bool,
+        to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        if !repeat_shader {
+            let pipeline = renderer.vulkan.pipelines[&self.get_main_pipeline()].clone();
+            to.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.get_pipeline().layout().clone(),
+                3,
+                self.descriptor_set.clone()
+            )?;
+            if self.two_sided {
+                to.set_cull_mode(CullMode::None)?;
+            }
+        }
+        vertices.make_vulkan_draw_command(instance_count, to)?;
+        Ok(())
+    }
+
+    fn is_transparent(&self) -> bool {
+        true
+    }
+
+    fn get_main_pipeline(&self) -> VulkanPipelineType {
+        self.pipeline
+    }
+
+    fn can_reuse_descriptors(&self) -> bool {
+        true
+    }
+}
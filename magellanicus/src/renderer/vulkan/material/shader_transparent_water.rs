@@ -0,0 +1,119 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::{AddShaderTransparentWaterShaderData, DefaultType, Renderer};
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use crate::vertex::VertexOffsets;
+
+pub struct VulkanShaderTransparentWaterMaterial {
+    descriptor_set: Arc<PersistentDescriptorSet>
+}
+
+impl VulkanShaderTransparentWaterMaterial {
+    pub fn new(renderer: &mut Renderer, add_shader_parameter: AddShaderTransparentWaterShaderData) -> MResult<Self> {
+        let base_map = renderer
+            .get_or_default_2d(&add_shader_parameter.base_map, 0, DefaultType::White)
+            .vulkan
+            .image
+            .clone();
+
+        let ripple_map = renderer
+            .get_or_default_2d(&add_shader_parameter.ripple_map, 0, DefaultType::Vector)
+            .vulkan
+            .image
+            .clone();
+
+        let reflection_map = renderer
+            .get_or_default_cubemap(&add_shader_parameter.reflection_map, 0, DefaultType::Null)
+            .vulkan
+            .image
+            .clone();
+
+        let pipeline = renderer
+            .vulkan
+            .pipelines[&VulkanPipelineType::ShaderTransparentWater]
+            .clone();
+
+        let uniform = super::super::pipeline::shader_transparent_water::ShaderTransparentWaterData {
+            perpendicular_color: [add_shader_parameter.perpendicular_color[0], add_shader_parameter.perpendicular_color[1], add_shader_parameter.perpendicular_color[2], add_shader_parameter.perpendicular_brightness],
+            parallel_color: [add_shader_parameter.parallel_color[0], add_shader_parameter.parallel_color[1], add_shader_parameter.parallel_color[2], add_shader_parameter.parallel_brightness],
+            ripple_scale: add_shader_parameter.ripple_scale,
+            flags: {
+                let mut flags = 0;
+                flags |= (add_shader_parameter.base_map_alpha_modulates_reflection as u32) << 0;
+                flags |= (add_shader_parameter.base_map_color_modulates_background as u32) << 1;
+                flags
+            }
+        };
+
+        let map_sampler = renderer.vulkan.default_2d_sampler.clone();
+        let base_map = color_map_view(&renderer.vulkan, base_map, ImageViewType::Dim2d)?;
+        let ripple_map = ImageView::new_default(ripple_map)?;
+        let reflection_map = color_map_view(&renderer.vulkan, reflection_map, ImageViewType::Cube)?;
+
+        let uniform_buffer = Buffer::from_data(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            uniform
+        )?;
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            renderer.vulkan.descriptor_set_allocator.as_ref(),
+            pipeline.get_pipeline().layout().set_layouts()[3].clone(),
+            [
+                WriteDescriptorSet::buffer(0, uniform_buffer),
+                WriteDescriptorSet::sampler(1, map_sampler),
+                WriteDescriptorSet::image_view(2, base_map),
+                WriteDescriptorSet::image_view(3, ripple_map),
+                WriteDescriptorSet::image_view(4, reflection_map),
+            ],
+            []
+        )?;
+
+        let shader_data = Self {
+            descriptor_set
+        };
+
+        Ok(shader_data)
+    }
+}
+
+impl VulkanMaterial for VulkanShaderTransparentWaterMaterial {
+    fn generate_commands(
+        &self,
+        renderer: &Renderer,
+        vertices: &VertexOffsets,
+        instance_count: u32,
+        repeat_shader: bool,
+        to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
+    ) -> MResult<()> {
+        if !repeat_shader {
+            let pipeline = renderer.vulkan.pipelines.get(&self.get_main_pipeline()).unwrap();
+            to.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.get_pipeline().layout().clone(),
+                3,
+                self.descriptor_set.clone()
+            )?;
+        }
+        vertices.make_vulkan_draw_command(instance_count, to)?;
+        Ok(())
+    }
+
+    fn is_transparent(&self) -> bool {
+        true
+    }
+
+    fn get_main_pipeline(&self) -> VulkanPipelineType {
+        VulkanPipelineType::ShaderTransparentWater
+    }
+
+    fn can_reuse_descriptors(&self) -> bool {
+        true
+    }
+}
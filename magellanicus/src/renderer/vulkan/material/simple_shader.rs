@@ -1,13 +1,14 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::{VertexOffsets, VulkanMaterial, VulkanPipelineType};
+use crate::renderer::vulkan::{color_map_view, default_allocation_create_info, VertexOffsets, VulkanMaterial, VulkanPipelineType};
 use crate::renderer::{AddShaderBasicShaderData, DefaultType, Renderer};
-use std::eprintln;
+use crate::renderer::log::{log, LogLevel};
 use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::image::sampler::Sampler;
-use vulkano::image::view::{ImageView, ImageViewCreateInfo};
-use vulkano::image::{ImageAspects, ImageSubresourceRange, ImageType};
+use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::image::ImageType;
 use vulkano::pipeline::{Pipeline, PipelineBindPoint};
 
 pub struct VulkanSimpleShaderMaterial {
@@ -24,23 +25,34 @@ impl VulkanSimpleShaderMaterial {
             .image
             .clone();
 
-        if diffuse.array_layers() != 1 || diffuse.image_type() != ImageType::Dim2d {
-            eprintln!("Warning: Can't display {} in a simple shader material. Using fallback...", add_shader_parameter.bitmap.as_ref().unwrap());
+        if diffuse.array_layers() != 1 || diffuse.image_type() == ImageType::Dim1d {
+            log(LogLevel::Warning, &format!("Can't display {} in a simple shader material. Using fallback...", add_shader_parameter.bitmap.as_ref().unwrap()));
             return VulkanSimpleShaderMaterial::new(renderer, AddShaderBasicShaderData {
                 bitmap: None,
                 ..add_shader_parameter
             })
         }
 
-        let diffuse = ImageView::new(diffuse.clone(), ImageViewCreateInfo {
-            subresource_range: ImageSubresourceRange {
-                aspects: ImageAspects::COLOR,
-                mip_levels: 0..diffuse.mip_levels(),
-                array_layers: 0..diffuse.array_layers()
-            },
-            format: diffuse.format(),
-            ..Default::default()
-        })?;
+        // 3D textures are bound in their own slot and sampled at `volume_slice`.
+        let use_volume = diffuse.image_type() == ImageType::Dim3d;
+        let (diffuse, volume) = if use_volume {
+            let placeholder = renderer.get_default_2d(DefaultType::White).vulkan.image.clone();
+            (color_map_view(&renderer.vulkan, placeholder, ImageViewType::Dim2d)?, color_map_view(&renderer.vulkan, diffuse, ImageViewType::Dim3d)?)
+        }
+        else {
+            let placeholder = renderer.get_or_default_3d(&None, 0, DefaultType::White).vulkan.image.clone();
+            (color_map_view(&renderer.vulkan, diffuse, ImageViewType::Dim2d)?, color_map_view(&renderer.vulkan, placeholder, ImageViewType::Dim3d)?)
+        };
+
+        let uniform_buffer = Buffer::from_data(
+            renderer.vulkan.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::UNIFORM_BUFFER, ..Default::default() },
+            default_allocation_create_info(),
+            super::super::pipeline::simple_texture::SimpleTextureData {
+                volume_slice: add_shader_parameter.volume_slice.clamp(0.0, 1.0),
+                use_volume: use_volume as u32
+            }
+        )?;
 
         let diffuse_sampler = renderer.vulkan.default_2d_sampler.clone();
 
@@ -52,6 +64,8 @@ impl VulkanSimpleShaderMaterial {
             [
                 WriteDescriptorSet::sampler(0, diffuse_sampler.clone()),
                 WriteDescriptorSet::image_view(1, diffuse.clone()),
+                WriteDescriptorSet::image_view(2, volume),
+                WriteDescriptorSet::buffer(3, uniform_buffer),
             ],
             []
         )?;
@@ -65,6 +79,7 @@ impl VulkanMaterial for VulkanSimpleShaderMaterial {
         &self,
         renderer: &Renderer,
         vertices: &VertexOffsets,
+        instance_count: u32,
         repeat_shader: bool,
         to: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>
     ) -> MResult<()> {
@@ -77,7 +92,7 @@ impl VulkanMaterial for VulkanSimpleShaderMaterial {
                 self.descriptor_set.clone()
             )?;
         }
-        vertices.make_vulkan_draw_command(to)?;
+        vertices.make_vulkan_draw_command(instance_count, to)?;
         Ok(())
     }
 
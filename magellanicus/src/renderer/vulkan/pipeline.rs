@@ -5,14 +5,28 @@ use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, BlendFactor, Ble
 use vulkano::pipeline::GraphicsPipeline;
 use crate::error::MResult;
 use crate::renderer::vulkan::SwapchainImages;
+use crate::renderer::vulkan::pipeline::pipeline_loader::ShaderModules;
 
 pub mod solid_color;
 pub mod simple_texture;
 mod pipeline_loader;
-mod color_box;
+pub mod color_box;
 pub mod shader_environment;
+pub mod shader_model;
 pub mod shader_transparent_chicago;
-mod draw_sprite;
+pub mod shader_transparent_generic;
+pub mod shader_transparent_water;
+pub mod draw_sprite;
+mod depth_prepass;
+mod wireframe;
+mod overdraw;
+mod debug_line;
+mod draw_text;
+pub mod fxaa;
+pub mod tonemap;
+
+#[cfg(feature = "shader-hot-reload")]
+pub mod hot_reload;
 
 pub trait VulkanPipelineData: Send + Sync + 'static {
     fn get_pipeline(&self) -> Arc<GraphicsPipeline>;
@@ -23,59 +37,177 @@ pub trait VulkanPipelineData: Send + Sync + 'static {
 pub fn load_all_pipelines(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<BTreeMap<VulkanPipelineType, Arc<dyn VulkanPipelineData>>> {
     let mut pipelines: BTreeMap<VulkanPipelineType, Arc<dyn VulkanPipelineData>> = BTreeMap::new();
 
-    pipelines.insert(VulkanPipelineType::SolidColor, Arc::new(solid_color::SolidColorShader::new(swapchain_images, device.clone())?));
-    pipelines.insert(VulkanPipelineType::SimpleTexture, Arc::new(simple_texture::SimpleTextureShader::new(swapchain_images, device.clone())?));
-    pipelines.insert(VulkanPipelineType::ColorBox, Arc::new(color_box::ColorBox::new(swapchain_images, device.clone())?));
-    pipelines.insert(VulkanPipelineType::ShaderEnvironment, Arc::new(shader_environment::ShaderEnvironment::new(swapchain_images, device.clone())?));
-
-    let add = AttachmentBlend::additive();
-    let alpha_blend = AttachmentBlend::alpha();
-    let subtract = AttachmentBlend {
-        src_color_blend_factor: BlendFactor::One,
-        dst_color_blend_factor: BlendFactor::One,
-        color_blend_op: BlendOp::Subtract,
-        src_alpha_blend_factor: BlendFactor::One,
-        dst_alpha_blend_factor: BlendFactor::One,
-        alpha_blend_op: BlendOp::Subtract,
-    };
-    let component_min = AttachmentBlend {
-        src_color_blend_factor: BlendFactor::One,
-        dst_color_blend_factor: BlendFactor::One,
-        color_blend_op: BlendOp::Min,
-        src_alpha_blend_factor: BlendFactor::One,
-        dst_alpha_blend_factor: BlendFactor::One,
-        alpha_blend_op: BlendOp::Min,
-    };
-    let component_max = AttachmentBlend {
-        src_color_blend_factor: BlendFactor::One,
-        dst_color_blend_factor: BlendFactor::One,
-        color_blend_op: BlendOp::Max,
-        src_alpha_blend_factor: BlendFactor::One,
-        dst_alpha_blend_factor: BlendFactor::One,
-        alpha_blend_op: BlendOp::Max,
-    };
-    let multiply = AttachmentBlend {
-        src_color_blend_factor: BlendFactor::SrcColor,
-        dst_color_blend_factor: BlendFactor::OneMinusSrcColor,
-        color_blend_op: BlendOp::Add,
-        src_alpha_blend_factor: BlendFactor::SrcAlpha,
-        dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
-        alpha_blend_op: BlendOp::Add,
+    for pipeline_type in VulkanPipelineType::ALL {
+        if let Some(pipeline) = load_pipeline_type(pipeline_type, swapchain_images, device.clone(), None)? {
+            pipelines.insert(pipeline_type, pipeline);
+        }
+    }
+
+    Ok(pipelines)
+}
+
+/// Load a single pipeline.
+///
+/// If `shaders` is set, they are used instead of the pipeline's built-in shaders.
+///
+/// Returns `None` if the pipeline is unsupported by the device.
+pub fn load_pipeline_type(pipeline_type: VulkanPipelineType, swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Option<Arc<dyn VulkanPipelineData>>> {
+    let pipeline: Arc<dyn VulkanPipelineData> = match pipeline_type {
+        VulkanPipelineType::SolidColor => Arc::new(solid_color::SolidColorShader::new(swapchain_images, device, shaders)?),
+        VulkanPipelineType::SimpleTexture => Arc::new(simple_texture::SimpleTextureShader::new(swapchain_images, device, shaders)?),
+        VulkanPipelineType::ColorBox => Arc::new(color_box::ColorBox::new(swapchain_images, device, false, shaders)?),
+        VulkanPipelineType::ShaderEnvironment => Arc::new(shader_environment::ShaderEnvironment::new(swapchain_images, device, shaders)?),
+        VulkanPipelineType::ShaderModel => Arc::new(shader_model::ShaderModel::new(swapchain_images, device, shaders)?),
+
+        VulkanPipelineType::ShaderTransparentChicagoAdd
+        | VulkanPipelineType::ShaderTransparentChicagoAlphaBlend
+        | VulkanPipelineType::ShaderTransparentChicagoSubtract
+        | VulkanPipelineType::ShaderTransparentChicagoComponentMin
+        | VulkanPipelineType::ShaderTransparentChicagoComponentMax
+        | VulkanPipelineType::ShaderTransparentChicagoMultiply
+        | VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply
+        | VulkanPipelineType::ShaderTransparentChicagoAlphaMultiplyAdd => {
+            let blend = get_transparent_blend(pipeline_type);
+            Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device, blend, shaders)?)
+        }
+
+        VulkanPipelineType::ShaderTransparentGenericAdd
+        | VulkanPipelineType::ShaderTransparentGenericAlphaBlend
+        | VulkanPipelineType::ShaderTransparentGenericSubtract
+        | VulkanPipelineType::ShaderTransparentGenericComponentMin
+        | VulkanPipelineType::ShaderTransparentGenericComponentMax
+        | VulkanPipelineType::ShaderTransparentGenericMultiply
+        | VulkanPipelineType::ShaderTransparentGenericDoubleMultiply
+        | VulkanPipelineType::ShaderTransparentGenericAlphaMultiplyAdd => {
+            let blend = get_transparent_blend(pipeline_type);
+            Arc::new(shader_transparent_generic::ShaderTransparentGeneric::new(swapchain_images, device, blend, shaders)?)
+        }
+
+        VulkanPipelineType::ShaderTransparentWater => Arc::new(shader_transparent_water::ShaderTransparentWater::new(swapchain_images, device, shaders)?),
+        VulkanPipelineType::DrawSprite => Arc::new(draw_sprite::DrawSprite::new(swapchain_images, device, false, shaders)?),
+        VulkanPipelineType::DepthPrepass => Arc::new(depth_prepass::DepthPrepass::new(swapchain_images, device, shaders)?),
+
+        VulkanPipelineType::Wireframe => {
+            if !device.enabled_features().fill_mode_non_solid {
+                return Ok(None)
+            }
+            Arc::new(wireframe::Wireframe::new(swapchain_images, device, shaders)?)
+        }
+
+        VulkanPipelineType::Overdraw => Arc::new(overdraw::Overdraw::new(swapchain_images, device, shaders)?),
+
+        VulkanPipelineType::DebugLine => Arc::new(debug_line::DebugLine::new(swapchain_images, device, shaders)?),
+        VulkanPipelineType::DrawText => Arc::new(draw_text::DrawText::new(swapchain_images, device, shaders)?),
+
+        VulkanPipelineType::FXAA => {
+            if !device.enabled_extensions().khr_dynamic_rendering {
+                return Ok(None)
+            }
+            Arc::new(fxaa::FXAA::new(swapchain_images, device, shaders)?)
+        }
+
+        VulkanPipelineType::Tonemap => {
+            if !device.enabled_extensions().khr_dynamic_rendering {
+                return Ok(None)
+            }
+            Arc::new(tonemap::Tonemap::new(swapchain_images, device, shaders)?)
+        }
+
+        VulkanPipelineType::StencilMask => {
+            if !swapchain_images.has_stencil() {
+                return Ok(None)
+            }
+            Arc::new(color_box::ColorBox::new(swapchain_images, device, true, shaders)?)
+        }
+
+        VulkanPipelineType::DrawSpriteStencilTest => {
+            if !swapchain_images.has_stencil() {
+                return Ok(None)
+            }
+            Arc::new(draw_sprite::DrawSprite::new(swapchain_images, device, true, shaders)?)
+        }
     };
 
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoAdd, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(add))?));
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoAlphaBlend, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(alpha_blend))?));
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoSubtract, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(subtract))?));
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoComponentMin, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(component_min))?));
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoComponentMax, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(component_max))?));
-    pipelines.insert(VulkanPipelineType::ShaderTransparentChicagoMultiply, Arc::new(shader_transparent_chicago::ShaderTransparentChicago::new(swapchain_images, device.clone(), Some(multiply))?));
+    Ok(Some(pipeline))
+}
+
+/// Get the blending for a shader_transparent_chicago or shader_transparent_generic pipeline.
+fn get_transparent_blend(pipeline_type: VulkanPipelineType) -> Option<AttachmentBlend> {
+    let blend = match pipeline_type {
+        VulkanPipelineType::ShaderTransparentChicagoAdd
+        | VulkanPipelineType::ShaderTransparentGenericAdd => AttachmentBlend::additive(),
 
-    pipelines.insert(VulkanPipelineType::DrawSprite, Arc::new(draw_sprite::DrawSprite::new(swapchain_images, device.clone())?));
+        VulkanPipelineType::ShaderTransparentChicagoAlphaBlend
+        | VulkanPipelineType::ShaderTransparentGenericAlphaBlend => AttachmentBlend::alpha(),
 
-    Ok(pipelines)
+        VulkanPipelineType::ShaderTransparentChicagoSubtract
+        | VulkanPipelineType::ShaderTransparentGenericSubtract => AttachmentBlend {
+            src_color_blend_factor: BlendFactor::One,
+            dst_color_blend_factor: BlendFactor::One,
+            color_blend_op: BlendOp::Subtract,
+            src_alpha_blend_factor: BlendFactor::One,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Subtract,
+        },
+
+        VulkanPipelineType::ShaderTransparentChicagoComponentMin
+        | VulkanPipelineType::ShaderTransparentGenericComponentMin => AttachmentBlend {
+            src_color_blend_factor: BlendFactor::One,
+            dst_color_blend_factor: BlendFactor::One,
+            color_blend_op: BlendOp::Min,
+            src_alpha_blend_factor: BlendFactor::One,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Min,
+        },
+
+        VulkanPipelineType::ShaderTransparentChicagoComponentMax
+        | VulkanPipelineType::ShaderTransparentGenericComponentMax => AttachmentBlend {
+            src_color_blend_factor: BlendFactor::One,
+            dst_color_blend_factor: BlendFactor::One,
+            color_blend_op: BlendOp::Max,
+            src_alpha_blend_factor: BlendFactor::One,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Max,
+        },
+
+        VulkanPipelineType::ShaderTransparentChicagoMultiply
+        | VulkanPipelineType::ShaderTransparentGenericMultiply => AttachmentBlend {
+            src_color_blend_factor: BlendFactor::SrcColor,
+            dst_color_blend_factor: BlendFactor::OneMinusSrcColor,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::SrcAlpha,
+            dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+            alpha_blend_op: BlendOp::Add,
+        },
+
+        VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply
+        | VulkanPipelineType::ShaderTransparentGenericDoubleMultiply => AttachmentBlend {
+            // src * dst + dst * src = 2 * src * dst
+            src_color_blend_factor: BlendFactor::DstColor,
+            dst_color_blend_factor: BlendFactor::SrcColor,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::DstAlpha,
+            dst_alpha_blend_factor: BlendFactor::SrcAlpha,
+            alpha_blend_op: BlendOp::Add,
+        },
+
+        VulkanPipelineType::ShaderTransparentChicagoAlphaMultiplyAdd
+        | VulkanPipelineType::ShaderTransparentGenericAlphaMultiplyAdd => AttachmentBlend {
+            src_color_blend_factor: BlendFactor::SrcAlpha,
+            dst_color_blend_factor: BlendFactor::One,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::Zero,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Add,
+        },
+
+        _ => return None
+    };
+
+    Some(blend)
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 #[repr(u32)]
 pub enum VulkanPipelineType {
     /// Writes a solid color.
@@ -92,6 +224,9 @@ pub enum VulkanPipelineType {
     /// shader_environment
     ShaderEnvironment,
 
+    /// shader_model
+    ShaderModel,
+
     /// shader_transparent_chicago + Add
     ShaderTransparentChicagoAdd,
     /// shader_transparent_chicago + Alpha Blend
@@ -104,7 +239,225 @@ pub enum VulkanPipelineType {
     ShaderTransparentChicagoComponentMax,
     /// shader_transparent_chicago + Multiply
     ShaderTransparentChicagoMultiply,
+    /// shader_transparent_chicago + Double Multiply
+    ShaderTransparentChicagoDoubleMultiply,
+    /// shader_transparent_chicago + Alpha Multiply Add
+    ShaderTransparentChicagoAlphaMultiplyAdd,
+
+    /// shader_transparent_generic + Add
+    ShaderTransparentGenericAdd,
+    /// shader_transparent_generic + Alpha Blend
+    ShaderTransparentGenericAlphaBlend,
+    /// shader_transparent_generic + Subtract
+    ShaderTransparentGenericSubtract,
+    /// shader_transparent_generic + Component Min
+    ShaderTransparentGenericComponentMin,
+    /// shader_transparent_generic + Component Max
+    ShaderTransparentGenericComponentMax,
+    /// shader_transparent_generic + Multiply
+    ShaderTransparentGenericMultiply,
+    /// shader_transparent_generic + Double Multiply
+    ShaderTransparentGenericDoubleMultiply,
+    /// shader_transparent_generic + Alpha Multiply Add
+    ShaderTransparentGenericAlphaMultiplyAdd,
+
+    /// shader_transparent_water
+    ShaderTransparentWater,
 
     /// Draws a sprite to the screen.
-    DrawSprite
+    DrawSprite,
+
+    /// Writes only depth for opaque geometry.
+    DepthPrepass,
+
+    /// Draws geometry as white lines.
+    ///
+    /// Only loaded if the device supports `fill_mode_non_solid`.
+    Wireframe,
+
+    /// Draws geometry additively with a constant color to visualize overdraw.
+    Overdraw,
+
+    /// Draws colored lines in world space.
+    DebugLine,
+
+    /// Draws text from a font's glyph atlas to the screen.
+    DrawText,
+
+    /// Applies FXAA to the resolved frame.
+    ///
+    /// Only loaded if the device supports dynamic rendering.
+    FXAA,
+
+    /// Applies exposure and tonemapping to the resolved frame.
+    ///
+    /// Only loaded if the device supports dynamic rendering.
+    Tonemap,
+
+    /// Writes the stencil reference in a box without drawing any color.
+    ///
+    /// Only loaded if the depth image has a stencil buffer.
+    StencilMask,
+
+    /// Draws a sprite only where the stencil buffer equals the stencil reference.
+    ///
+    /// Only loaded if the depth image has a stencil buffer.
+    DrawSpriteStencilTest
+}
+
+impl VulkanPipelineType {
+    pub const ALL: [VulkanPipelineType; 32] = [
+        VulkanPipelineType::SolidColor,
+        VulkanPipelineType::SimpleTexture,
+        VulkanPipelineType::ColorBox,
+        VulkanPipelineType::ShaderEnvironment,
+        VulkanPipelineType::ShaderModel,
+        VulkanPipelineType::ShaderTransparentChicagoAdd,
+        VulkanPipelineType::ShaderTransparentChicagoAlphaBlend,
+        VulkanPipelineType::ShaderTransparentChicagoSubtract,
+        VulkanPipelineType::ShaderTransparentChicagoComponentMin,
+        VulkanPipelineType::ShaderTransparentChicagoComponentMax,
+        VulkanPipelineType::ShaderTransparentChicagoMultiply,
+        VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply,
+        VulkanPipelineType::ShaderTransparentChicagoAlphaMultiplyAdd,
+        VulkanPipelineType::ShaderTransparentGenericAdd,
+        VulkanPipelineType::ShaderTransparentGenericAlphaBlend,
+        VulkanPipelineType::ShaderTransparentGenericSubtract,
+        VulkanPipelineType::ShaderTransparentGenericComponentMin,
+        VulkanPipelineType::ShaderTransparentGenericComponentMax,
+        VulkanPipelineType::ShaderTransparentGenericMultiply,
+        VulkanPipelineType::ShaderTransparentGenericDoubleMultiply,
+        VulkanPipelineType::ShaderTransparentGenericAlphaMultiplyAdd,
+        VulkanPipelineType::ShaderTransparentWater,
+        VulkanPipelineType::DrawSprite,
+        VulkanPipelineType::DepthPrepass,
+        VulkanPipelineType::Wireframe,
+        VulkanPipelineType::Overdraw,
+        VulkanPipelineType::DebugLine,
+        VulkanPipelineType::DrawText,
+        VulkanPipelineType::FXAA,
+        VulkanPipelineType::Tonemap,
+        VulkanPipelineType::StencilMask,
+        VulkanPipelineType::DrawSpriteStencilTest,
+    ];
+
+    /// Get the directory containing the pipeline's `vertex.vert` and `fragment.frag`, relative to the crate root.
+    #[cfg(feature = "shader-hot-reload")]
+    pub fn get_shader_directory(self) -> &'static str {
+        match self {
+            VulkanPipelineType::SolidColor => "src/renderer/vulkan/pipeline/solid_color",
+            VulkanPipelineType::SimpleTexture => "src/renderer/vulkan/pipeline/simple_texture",
+            VulkanPipelineType::ColorBox
+            | VulkanPipelineType::StencilMask => "src/renderer/vulkan/pipeline/color_box",
+            VulkanPipelineType::ShaderEnvironment => "src/renderer/vulkan/pipeline/shader_environment",
+            VulkanPipelineType::ShaderModel => "src/renderer/vulkan/pipeline/shader_model",
+            VulkanPipelineType::ShaderTransparentChicagoAdd
+            | VulkanPipelineType::ShaderTransparentChicagoAlphaBlend
+            | VulkanPipelineType::ShaderTransparentChicagoSubtract
+            | VulkanPipelineType::ShaderTransparentChicagoComponentMin
+            | VulkanPipelineType::ShaderTransparentChicagoComponentMax
+            | VulkanPipelineType::ShaderTransparentChicagoMultiply
+            | VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply
+            | VulkanPipelineType::ShaderTransparentChicagoAlphaMultiplyAdd => "src/renderer/vulkan/pipeline/shader_transparent_chicago",
+            VulkanPipelineType::ShaderTransparentGenericAdd
+            | VulkanPipelineType::ShaderTransparentGenericAlphaBlend
+            | VulkanPipelineType::ShaderTransparentGenericSubtract
+            | VulkanPipelineType::ShaderTransparentGenericComponentMin
+            | VulkanPipelineType::ShaderTransparentGenericComponentMax
+            | VulkanPipelineType::ShaderTransparentGenericMultiply
+            | VulkanPipelineType::ShaderTransparentGenericDoubleMultiply
+            | VulkanPipelineType::ShaderTransparentGenericAlphaMultiplyAdd => "src/renderer/vulkan/pipeline/shader_transparent_generic",
+            VulkanPipelineType::ShaderTransparentWater => "src/renderer/vulkan/pipeline/shader_transparent_water",
+            VulkanPipelineType::DrawSprite
+            | VulkanPipelineType::DrawSpriteStencilTest => "src/renderer/vulkan/pipeline/draw_sprite",
+            VulkanPipelineType::DepthPrepass => "src/renderer/vulkan/pipeline/depth_prepass",
+            VulkanPipelineType::Wireframe => "src/renderer/vulkan/pipeline/wireframe",
+            VulkanPipelineType::Overdraw => "src/renderer/vulkan/pipeline/overdraw",
+            VulkanPipelineType::DebugLine => "src/renderer/vulkan/pipeline/debug_line",
+            VulkanPipelineType::DrawText => "src/renderer/vulkan/pipeline/draw_text",
+            VulkanPipelineType::FXAA => "src/renderer/vulkan/pipeline/fxaa",
+            VulkanPipelineType::Tonemap => "src/renderer/vulkan/pipeline/tonemap",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blend `src` over `dst` the same way the fixed-function blend stage does for a float target.
+    ///
+    /// Returns `None` if the blend uses a factor or op this doesn't evaluate.
+    fn blend(blend: AttachmentBlend, src: [f32; 4], dst: [f32; 4]) -> Option<[f32; 4]> {
+        let factor = |factor: BlendFactor, channel: usize| match factor {
+            BlendFactor::Zero => Some(0.0),
+            BlendFactor::One => Some(1.0),
+            BlendFactor::SrcColor => Some(src[channel]),
+            BlendFactor::OneMinusSrcColor => Some(1.0 - src[channel]),
+            BlendFactor::DstColor => Some(dst[channel]),
+            BlendFactor::OneMinusDstColor => Some(1.0 - dst[channel]),
+            BlendFactor::SrcAlpha => Some(src[3]),
+            BlendFactor::OneMinusSrcAlpha => Some(1.0 - src[3]),
+            BlendFactor::DstAlpha => Some(dst[3]),
+            BlendFactor::OneMinusDstAlpha => Some(1.0 - dst[3]),
+            _ => None
+        };
+
+        let mut result = [0.0; 4];
+        for (channel, out) in result.iter_mut().enumerate() {
+            let (src_factor, dst_factor, op) = if channel == 3 {
+                (blend.src_alpha_blend_factor, blend.dst_alpha_blend_factor, blend.alpha_blend_op)
+            }
+            else {
+                (blend.src_color_blend_factor, blend.dst_color_blend_factor, blend.color_blend_op)
+            };
+
+            let s = src[channel] * factor(src_factor, channel)?;
+            let d = dst[channel] * factor(dst_factor, channel)?;
+            *out = match op {
+                BlendOp::Add => s + d,
+                BlendOp::Subtract => s - d,
+                BlendOp::ReverseSubtract => d - s,
+                BlendOp::Min => src[channel].min(dst[channel]),
+                BlendOp::Max => src[channel].max(dst[channel]),
+                _ => return None
+            };
+        }
+        Some(result)
+    }
+
+    #[test]
+    fn every_transparent_blend_can_be_evaluated() {
+        for pipeline_type in VulkanPipelineType::ALL {
+            let Some(attachment_blend) = get_transparent_blend(pipeline_type) else {
+                continue
+            };
+            assert!(blend(attachment_blend, [0.5; 4], [0.5; 4]).is_some(), "{pipeline_type:?}");
+        }
+    }
+
+    #[test]
+    fn double_multiply_is_twice_multiply() {
+        let pixel = [0.25, 0.5, 0.75, 0.5];
+        let framebuffer = [0.5, 0.25, 0.5, 1.0];
+
+        // framebuffer.rgb *= pixel.rgb
+        let multiply: [f32; 3] = std::array::from_fn(|i| framebuffer[i] * pixel[i]);
+
+        for pipeline_type in [VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply, VulkanPipelineType::ShaderTransparentGenericDoubleMultiply] {
+            let double_multiply = blend(get_transparent_blend(pipeline_type).unwrap(), pixel, framebuffer).unwrap();
+            for i in 0..3 {
+                assert!((double_multiply[i] - multiply[i] * 2.0).abs() < 1e-6, "{pipeline_type:?} channel {i}: {double_multiply:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn double_multiply_by_half_gray_is_identity() {
+        let framebuffer = [0.2, 0.4, 0.6, 1.0];
+        let result = blend(get_transparent_blend(VulkanPipelineType::ShaderTransparentChicagoDoubleMultiply).unwrap(), [0.5, 0.5, 0.5, 0.5], framebuffer).unwrap();
+        for i in 0..3 {
+            assert!((result[i] - framebuffer[i]).abs() < 1e-6, "channel {i}: {result:?}");
+        }
+    }
 }
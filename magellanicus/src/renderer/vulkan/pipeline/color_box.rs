@@ -1,11 +1,11 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, DepthAccess, PipelineSettings, StencilAccess};
 use crate::renderer::vulkan::vertex::VulkanModelVertex;
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 use std::sync::Arc;
 use std::vec;
 use vulkano::device::Device;
-use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState};
+use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorComponents};
 use vulkano::pipeline::graphics::vertex_input::Vertex;
 use vulkano::pipeline::GraphicsPipeline;
 
@@ -23,20 +23,35 @@ mod fragment {
     }
 }
 
+pub use fragment::ColorBoxData;
+
 pub struct ColorBox {
     pub pipeline: Arc<GraphicsPipeline>
 }
 
 impl ColorBox {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
-            depth_access: DepthAccess::NoDepth,
-            vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex()],
-            samples: swapchain_images.color.image().samples(),
-            color_blend_attachment_state: ColorBlendAttachmentState {
+    /// Load the pipeline.
+    ///
+    /// If `stencil_mask` is true, the box writes the stencil reference instead of its color.
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, stencil_mask: bool, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let color_blend_attachment_state = if stencil_mask {
+            ColorBlendAttachmentState {
+                color_write_mask: ColorComponents::empty(),
+                ..ColorBlendAttachmentState::default()
+            }
+        }
+        else {
+            ColorBlendAttachmentState {
                 blend: Some(AttachmentBlend::alpha()),
                 ..ColorBlendAttachmentState::default()
-            },
+            }
+        };
+
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
+            depth_access: DepthAccess::NoDepth,
+            stencil_access: if stencil_mask { StencilAccess::StencilWrite } else { StencilAccess::NoStencil },
+            vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex()],
+            color_blend_attachment_state,
             ..Default::default()
         })?;
 
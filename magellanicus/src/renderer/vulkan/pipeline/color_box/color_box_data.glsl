@@ -0,0 +1,6 @@
+layout(set = 1, binding = 0) uniform ColorBoxData {
+    vec4 color;
+
+    // x, y, width, height
+    vec4 rect;
+} box_data;
@@ -1,7 +1,10 @@
 #version 450
 
-#include "../include/material.vert"
+#include "color_box_data.glsl"
+
+layout(location = 0) in vec3 position;
 
 void main() {
-    gl_Position = vec4((position * 2.0) - 1.0, 1.0);
+    vec3 box_position = vec3(box_data.rect.xy + position.xy * box_data.rect.zw, position.z);
+    gl_Position = vec4((box_position * 2.0) - 1.0, 1.0);
 }
@@ -0,0 +1,58 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::vertex::VulkanDebugLineVertex;
+use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
+use std::sync::Arc;
+use std::vec;
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState};
+use vulkano::pipeline::graphics::input_assembly::PrimitiveTopology;
+use vulkano::pipeline::graphics::vertex_input::Vertex;
+use vulkano::pipeline::GraphicsPipeline;
+
+mod vertex {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/renderer/vulkan/pipeline/debug_line/vertex.vert"
+    }
+}
+
+mod fragment {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/renderer/vulkan/pipeline/debug_line/fragment.frag"
+    }
+}
+
+pub struct DebugLine {
+    pub pipeline: Arc<GraphicsPipeline>
+}
+
+impl DebugLine {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
+            depth_access: DepthAccess::DepthReadOnlyTransparent,
+            vertex_buffer_descriptions: vec![VulkanDebugLineVertex::per_vertex()],
+            color_blend_attachment_state: ColorBlendAttachmentState {
+                blend: Some(AttachmentBlend::alpha()),
+                ..ColorBlendAttachmentState::default()
+            },
+            topology: PrimitiveTopology::LineList,
+            ..Default::default()
+        })?;
+
+        Ok(Self { pipeline })
+    }
+}
+
+impl VulkanPipelineData for DebugLine {
+    fn get_pipeline(&self) -> Arc<GraphicsPipeline> {
+        self.pipeline.clone()
+    }
+    fn has_lightmaps(&self) -> bool {
+        false
+    }
+    fn has_fog(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,11 @@
+#version 450
+
+#include "../include/material.vert"
+
+layout(location = 1) in vec4 color;
+layout(location = 0) out vec4 line_color;
+
+void main() {
+    gl_Position = uniforms.proj * uniforms.view * vec4(position, 1.0);
+    line_color = color;
+}
@@ -0,0 +1,53 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, material_vertex_buffer_descriptions, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorComponents};
+use vulkano::pipeline::GraphicsPipeline;
+
+mod vertex {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/renderer/vulkan/pipeline/depth_prepass/vertex.vert"
+    }
+}
+
+mod fragment {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/renderer/vulkan/pipeline/depth_prepass/fragment.frag"
+    }
+}
+
+pub struct DepthPrepass {
+    pub pipeline: Arc<GraphicsPipeline>
+}
+
+impl DepthPrepass {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
+            depth_access: DepthAccess::DepthWrite,
+            vertex_buffer_descriptions: material_vertex_buffer_descriptions(),
+            color_blend_attachment_state: ColorBlendAttachmentState {
+                color_write_mask: ColorComponents::empty(),
+                ..ColorBlendAttachmentState::default()
+            },
+            ..Default::default()
+        })?;
+
+        Ok(Self { pipeline })
+    }
+}
+
+impl VulkanPipelineData for DepthPrepass {
+    fn get_pipeline(&self) -> Arc<GraphicsPipeline> {
+        self.pipeline.clone()
+    }
+    fn has_lightmaps(&self) -> bool {
+        false
+    }
+    fn has_fog(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,12 @@
+#version 450
+
+#define USE_INSTANCES
+#include "../include/material.vert"
+
+// Must match the depth calculated by the opaque shaders exactly, or the main pass will fail the depth test.
+invariant gl_Position;
+
+void main() {
+    vec3 vertex_position = (uniforms.world * instance_world * vec4(position.xyz + uniforms.offset.xyz, 1.0)).xyz;
+    gl_Position = uniforms.proj * uniforms.view * vec4(vertex_position, 1.0);
+}
@@ -1,5 +1,5 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, DepthAccess, PipelineSettings, StencilAccess};
 use crate::renderer::vulkan::vertex::VulkanModelVertex;
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 use std::sync::Arc;
@@ -23,16 +23,21 @@ mod fragment {
     }
 }
 
+pub use fragment::DrawSpriteData;
+
 pub struct DrawSprite {
     pub pipeline: Arc<GraphicsPipeline>
 }
 
 impl DrawSprite {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
+    /// Load the pipeline.
+    ///
+    /// If `stencil_test` is true, the sprite is only drawn where the stencil buffer equals the stencil reference.
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, stencil_test: bool, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
             depth_access: DepthAccess::NoDepth,
+            stencil_access: if stencil_test { StencilAccess::StencilTestEqual } else { StencilAccess::NoStencil },
             vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex()],
-            samples: swapchain_images.color.image().samples(),
             color_blend_attachment_state: ColorBlendAttachmentState {
                 blend: Some(AttachmentBlend::alpha()),
                 ..ColorBlendAttachmentState::default()
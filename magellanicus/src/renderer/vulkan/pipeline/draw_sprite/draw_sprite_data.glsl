@@ -0,0 +1,7 @@
+layout(set = 1, binding = 0) uniform DrawSpriteData {
+    // Multiplied with the texture
+    vec4 color;
+
+    // left, top, right, bottom
+    vec4 texture_rect;
+} sprite_data;
@@ -1,5 +1,7 @@
 #version 450
 
+#include "draw_sprite_data.glsl"
+
 layout(location = 0) out vec4 f_color;
 layout(location = 0) in vec2 texture_coords;
 
@@ -7,5 +9,5 @@ layout(set = 0, binding = 0) uniform sampler s;
 layout(set = 0, binding = 1) uniform texture2D tex;
 
 void main() {
-    f_color = texture(sampler2D(tex, s), texture_coords);
+    f_color = texture(sampler2D(tex, s), texture_coords) * sprite_data.color;
 }
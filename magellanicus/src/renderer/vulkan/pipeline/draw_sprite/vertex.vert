@@ -1,14 +1,19 @@
 #version 450
 
+#include "draw_sprite_data.glsl"
+
 layout(location = 0) in vec3 position;
 layout(location = 0) out vec2 texture_coords;
 
 void main() {
     gl_Position = vec4((position * 2.0) - 1.0, 1.0);
+
+    vec2 corner;
     switch(gl_VertexIndex) {
-        case 0: texture_coords = vec2(0.0, 0.0); break;
-        case 1: texture_coords = vec2(0.0, 1.0); break;
-        case 2: texture_coords = vec2(1.0, 1.0); break;
-        case 3: texture_coords = vec2(1.0, 0.0); break;
+        case 0: corner = vec2(0.0, 0.0); break;
+        case 1: corner = vec2(0.0, 1.0); break;
+        case 2: corner = vec2(1.0, 1.0); break;
+        case 3: corner = vec2(1.0, 0.0); break;
     }
+    texture_coords = mix(sprite_data.texture_rect.xy, sprite_data.texture_rect.zw, corner);
 }
@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 character_texture_coords;
+layout(location = 1) in vec4 character_color;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler s;
+layout(set = 0, binding = 1) uniform texture2D tex;
+
+void main() {
+    f_color = texture(sampler2D(tex, s), character_texture_coords) * character_color;
+}
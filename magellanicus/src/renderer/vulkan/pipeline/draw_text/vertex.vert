@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 texture_coords;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 character_texture_coords;
+layout(location = 1) out vec4 character_color;
+
+void main() {
+    gl_Position = vec4((position * 2.0) - 1.0, 0.5, 1.0);
+    character_texture_coords = texture_coords;
+    character_color = color;
+}
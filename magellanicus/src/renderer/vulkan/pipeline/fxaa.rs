@@ -0,0 +1,50 @@
+use crate::error::MResult;
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::pipeline::GraphicsPipeline;
+
+mod vertex {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/renderer/vulkan/pipeline/fxaa/vertex.vert"
+    }
+}
+
+mod fragment {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/renderer/vulkan/pipeline/fxaa/fragment.frag"
+    }
+}
+
+pub use fragment::FXAAData;
+
+pub struct FXAA {
+    pub pipeline: Arc<GraphicsPipeline>
+}
+
+impl FXAA {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
+            depth_access: DepthAccess::NoDepth,
+            fullscreen_pass: true,
+            ..Default::default()
+        })?;
+
+        Ok(Self { pipeline })
+    }
+}
+
+impl VulkanPipelineData for FXAA {
+    fn get_pipeline(&self) -> Arc<GraphicsPipeline> {
+        self.pipeline.clone()
+    }
+    fn has_lightmaps(&self) -> bool {
+        false
+    }
+    fn has_fog(&self) -> bool {
+        false
+    }
+}
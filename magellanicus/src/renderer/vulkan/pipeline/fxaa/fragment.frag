@@ -0,0 +1,148 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+layout(location = 0) in vec2 texture_coords;
+
+layout(set = 0, binding = 0) uniform sampler s;
+layout(set = 0, binding = 1) uniform texture2D tex;
+
+layout(set = 1, binding = 0) uniform FXAAData {
+    vec2 inverse_resolution;
+    float subpixel;
+    float edge_threshold;
+    float edge_threshold_min;
+} fxaa_data;
+
+#define SEARCH_STEPS 12
+
+float luma(vec3 color) {
+    // Approximate perceptual luma so that linear (sRGB-correct or HDR) images are handled similarly.
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+vec4 sample_offset(vec2 uv, vec2 offset) {
+    return texture(sampler2D(tex, s), uv + offset * fxaa_data.inverse_resolution);
+}
+
+void main() {
+    vec2 uv = texture_coords;
+    vec4 center = sample_offset(uv, vec2(0.0, 0.0));
+
+    float luma_m = luma(center.rgb);
+    float luma_n = luma(sample_offset(uv, vec2(0.0, -1.0)).rgb);
+    float luma_s = luma(sample_offset(uv, vec2(0.0, 1.0)).rgb);
+    float luma_w = luma(sample_offset(uv, vec2(-1.0, 0.0)).rgb);
+    float luma_e = luma(sample_offset(uv, vec2(1.0, 0.0)).rgb);
+
+    float luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_w, luma_e)));
+    float luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_w, luma_e)));
+    float luma_range = luma_max - luma_min;
+
+    // Skip pixels that aren't on an edge.
+    if(luma_range < max(fxaa_data.edge_threshold_min, luma_max * fxaa_data.edge_threshold)) {
+        f_color = center;
+        return;
+    }
+
+    float luma_nw = luma(sample_offset(uv, vec2(-1.0, -1.0)).rgb);
+    float luma_ne = luma(sample_offset(uv, vec2(1.0, -1.0)).rgb);
+    float luma_sw = luma(sample_offset(uv, vec2(-1.0, 1.0)).rgb);
+    float luma_se = luma(sample_offset(uv, vec2(1.0, 1.0)).rgb);
+
+    // Determine whether the edge is horizontal or vertical.
+    float edge_horizontal = abs(luma_nw + luma_ne - 2.0 * luma_n)
+        + 2.0 * abs(luma_w + luma_e - 2.0 * luma_m)
+        + abs(luma_sw + luma_se - 2.0 * luma_s);
+    float edge_vertical = abs(luma_nw + luma_sw - 2.0 * luma_w)
+        + 2.0 * abs(luma_n + luma_s - 2.0 * luma_m)
+        + abs(luma_ne + luma_se - 2.0 * luma_e);
+    bool horizontal = edge_horizontal >= edge_vertical;
+
+    // Pick the side of the edge with the steepest gradient.
+    float luma_negative = horizontal ? luma_n : luma_w;
+    float luma_positive = horizontal ? luma_s : luma_e;
+    float gradient_negative = abs(luma_negative - luma_m);
+    float gradient_positive = abs(luma_positive - luma_m);
+
+    float step_length = horizontal ? fxaa_data.inverse_resolution.y : fxaa_data.inverse_resolution.x;
+    float luma_local_average;
+    float gradient;
+    if(gradient_negative >= gradient_positive) {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma_negative + luma_m);
+        gradient = gradient_negative;
+    }
+    else {
+        luma_local_average = 0.5 * (luma_positive + luma_m);
+        gradient = gradient_positive;
+    }
+    float gradient_scaled = gradient * 0.25;
+
+    // Walk along the edge in both directions until its ends are found.
+    vec2 edge_uv = uv;
+    if(horizontal) {
+        edge_uv.y += step_length * 0.5;
+    }
+    else {
+        edge_uv.x += step_length * 0.5;
+    }
+
+    vec2 edge_step = horizontal ? vec2(fxaa_data.inverse_resolution.x, 0.0) : vec2(0.0, fxaa_data.inverse_resolution.y);
+    vec2 uv_negative = edge_uv - edge_step;
+    vec2 uv_positive = edge_uv + edge_step;
+    float luma_end_negative = 0.0;
+    float luma_end_positive = 0.0;
+    bool reached_negative = false;
+    bool reached_positive = false;
+
+    for(int i = 0; i < SEARCH_STEPS; i++) {
+        if(!reached_negative) {
+            luma_end_negative = luma(texture(sampler2D(tex, s), uv_negative).rgb) - luma_local_average;
+            reached_negative = abs(luma_end_negative) >= gradient_scaled;
+        }
+        if(!reached_positive) {
+            luma_end_positive = luma(texture(sampler2D(tex, s), uv_positive).rgb) - luma_local_average;
+            reached_positive = abs(luma_end_positive) >= gradient_scaled;
+        }
+        if(reached_negative && reached_positive) {
+            break;
+        }
+
+        // Take bigger steps the further out the search goes.
+        float step_scale = i < 4 ? 1.0 : 2.0;
+        if(!reached_negative) {
+            uv_negative -= edge_step * step_scale;
+        }
+        if(!reached_positive) {
+            uv_positive += edge_step * step_scale;
+        }
+    }
+
+    float distance_negative = horizontal ? (uv.x - uv_negative.x) : (uv.y - uv_negative.y);
+    float distance_positive = horizontal ? (uv_positive.x - uv.x) : (uv_positive.y - uv.y);
+    bool negative_closer = distance_negative < distance_positive;
+    float distance_closest = min(distance_negative, distance_positive);
+    float edge_length = distance_negative + distance_positive;
+
+    // Only blend if the center is on the correct side of the edge end.
+    bool center_smaller = luma_m < luma_local_average;
+    bool correct_variation = ((negative_closer ? luma_end_negative : luma_end_positive) < 0.0) != center_smaller;
+    float edge_offset = correct_variation ? (0.5 - distance_closest / edge_length) : 0.0;
+
+    // Also blend based on the average of all neighbors to smooth sub-pixel aliasing.
+    float luma_average = (1.0 / 12.0) * (2.0 * (luma_n + luma_s + luma_w + luma_e) + luma_nw + luma_ne + luma_sw + luma_se);
+    float subpixel_offset = clamp(abs(luma_average - luma_m) / luma_range, 0.0, 1.0);
+    subpixel_offset = (-2.0 * subpixel_offset + 3.0) * subpixel_offset * subpixel_offset;
+    subpixel_offset = subpixel_offset * subpixel_offset * fxaa_data.subpixel;
+
+    float final_offset = max(edge_offset, subpixel_offset);
+    vec2 final_uv = uv;
+    if(horizontal) {
+        final_uv.y += final_offset * step_length;
+    }
+    else {
+        final_uv.x += final_offset * step_length;
+    }
+
+    f_color = texture(sampler2D(tex, s), final_uv);
+}
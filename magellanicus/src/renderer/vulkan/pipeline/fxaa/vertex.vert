@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) out vec2 texture_coords;
+
+// Covers the whole screen with one triangle, so no vertex buffer is needed.
+void main() {
+    texture_coords = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(texture_coords * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind};
+use vulkano::device::Device;
+use vulkano::shader::{ShaderModule, ShaderModuleCreateInfo};
+use crate::error::{Error, MResult};
+use crate::renderer::vulkan::pipeline::{load_pipeline_type, VulkanPipelineData, VulkanPipelineType};
+use crate::renderer::vulkan::SwapchainImages;
+
+/// Compile the pipeline's shaders from disk and load the pipeline with them.
+///
+/// The returned error contains the compiler output if compilation failed.
+pub fn reload_pipeline_type(pipeline_type: VulkanPipelineType, swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Option<Arc<dyn VulkanPipelineData>>> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(pipeline_type.get_shader_directory());
+    let vertex = compile_shader(device.clone(), &directory.join("vertex.vert"), ShaderKind::Vertex)?;
+    let fragment = compile_shader(device.clone(), &directory.join("fragment.frag"), ShaderKind::Fragment)?;
+
+    load_pipeline_type(pipeline_type, swapchain_images, device, Some((vertex, fragment)))
+}
+
+fn compile_shader(device: Arc<Device>, path: &Path, kind: ShaderKind) -> MResult<Arc<ShaderModule>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| Error::from_data_error_string(format!("Failed to read {}: {e}", path.display())))?;
+
+    let compiler = Compiler::new()
+        .ok_or_else(|| Error::from_data_error_string("Failed to initialize the shader compiler".to_owned()))?;
+    let mut options = CompileOptions::new()
+        .ok_or_else(|| Error::from_data_error_string("Failed to initialize the shader compiler options".to_owned()))?;
+
+    options.set_include_callback(|name, include_type, source, _depth| {
+        let base = match include_type {
+            IncludeType::Relative => Path::new(source).parent().map(Path::to_path_buf).unwrap_or_default(),
+            IncludeType::Standard => PathBuf::new()
+        };
+        let resolved_name = base.join(name);
+        let content = std::fs::read_to_string(&resolved_name)
+            .map_err(|e| format!("Failed to read {}: {e}", resolved_name.display()))?;
+        Ok(ResolvedInclude { resolved_name: resolved_name.to_string_lossy().into_owned(), content })
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(&source, kind, &path.to_string_lossy(), "main", Some(&options))
+        .map_err(|e| Error::from_data_error_string(format!("Failed to compile {}: {e}", path.display())))?;
+
+    // SAFETY: shaderc outputs valid SPIR-V.
+    let module = unsafe { ShaderModule::new(device, ShaderModuleCreateInfo::new(artifact.as_binary()))? };
+    Ok(module)
+}
@@ -0,0 +1,6 @@
+// Must match DebugView.
+#define DEBUG_VIEW_NORMAL 0u
+#define DEBUG_VIEW_LIGHTMAP_ONLY 1u
+#define DEBUG_VIEW_DIFFUSE_ONLY 2u
+#define DEBUG_VIEW_OVERDRAW 3u
+#define DEBUG_VIEW_NORMALS 4u
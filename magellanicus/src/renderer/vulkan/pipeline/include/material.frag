@@ -10,9 +10,23 @@ layout(set = 2, binding = 0) uniform FogData {
     float sky_fog_to;
     float min_opacity;
     float max_opacity;
+    uint mode;
+    float density;
 } sky_fog_data;
 
+#define FOG_MODE_LINEAR 0u
+#define FOG_MODE_EXPONENTIAL 1u
+#define FOG_MODE_EXPONENTIAL_SQUARED 2u
+
 float calculate_fog_density(float distance_from_camera) {
+    if(sky_fog_data.mode != FOG_MODE_LINEAR) {
+        float distance = max(distance_from_camera - sky_fog_data.sky_fog_from, 0.0) * sky_fog_data.density;
+        if(sky_fog_data.mode == FOG_MODE_EXPONENTIAL_SQUARED) {
+            distance *= distance;
+        }
+        return mix(sky_fog_data.min_opacity, sky_fog_data.max_opacity, 1.0 - exp(-distance));
+    }
+
     float clamped = clamp(distance_from_camera, sky_fog_data.sky_fog_from, sky_fog_data.sky_fog_to);
 
     // This is a pretty close approximation of the algorithm used for fog from planar fog density
@@ -27,7 +41,8 @@ float calculate_fog_density(float distance_from_camera) {
     // This can be slightly outside of 0.0-1.0, however, when x is close to 0.0 or 1.0.
     float interpolation = clamp(y, 0.0, 1.0);
 
-    return interpolation * sky_fog_data.max_opacity;
+    // Fog never drops below the minimum opacity, even right in front of the camera.
+    return mix(sky_fog_data.min_opacity, sky_fog_data.max_opacity, interpolation);
 }
 
 vec3 apply_fog(float distance_from_camera, vec3 color) {
@@ -1,3 +1,5 @@
+#include "debug_view.glsl"
+
 layout(location = 0) in vec3 position;
 
 #ifdef USE_TEXTURE_COORDS
@@ -6,12 +8,19 @@ layout(location = 1) in vec2 texture_coords;
 #ifdef USE_LIGHTMAPS
 layout(location = 2) in vec2 lightmap_texture_coords;
 #endif
+#ifdef USE_INSTANCES
+// Applied on top of the world transform. Draws that aren't instanced have one identity instance bound.
+layout(location = 8) in mat4 instance_world;
+layout(location = 12) in mat3 instance_rotation;
+#endif
 
 layout(set = 0, binding = 0) uniform ModelData {
     vec3 camera;
+    float time;
     mat4 world;
     mat4 view;
     mat4 proj;
     vec3 offset;
     mat3 rotation;
+    uint debug_view;
 } uniforms;
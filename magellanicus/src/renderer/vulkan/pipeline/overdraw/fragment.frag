@@ -0,0 +1,8 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+// Each layer adds this much, so the brightness shows how many times a pixel was drawn.
+void main() {
+    f_color = vec4(0.1, 0.05, 0.025, 1.0);
+}
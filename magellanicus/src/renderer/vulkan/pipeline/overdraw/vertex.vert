@@ -0,0 +1,9 @@
+#version 450
+
+#define USE_INSTANCES
+#include "../include/material.vert"
+
+void main() {
+    vec3 vertex_position = (uniforms.world * instance_world * vec4(position.xyz + uniforms.offset.xyz, 1.0)).xyz;
+    gl_Position = uniforms.proj * uniforms.view * vec4(vertex_position, 1.0);
+}
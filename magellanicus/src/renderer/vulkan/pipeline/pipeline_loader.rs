@@ -1,23 +1,24 @@
-use crate::error::MResult;
+use crate::error::{Error, MResult};
 use std::sync::Arc;
 use std::vec::Vec;
 use std::vec;
 use vulkano::device::Device;
-use vulkano::format::Format;
-use vulkano::image::SampleCount;
 use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
-use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState, StencilOp, StencilOpState, StencilOps, StencilState};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
 use vulkano::pipeline::graphics::multisample::MultisampleState;
-use vulkano::pipeline::graphics::rasterization::{FrontFace, RasterizationState};
+use vulkano::pipeline::graphics::rasterization::{FrontFace, PolygonMode, RasterizationState};
 use vulkano::pipeline::graphics::subpass::PipelineRenderingCreateInfo;
-use vulkano::pipeline::graphics::vertex_input::{VertexBufferDescription, VertexDefinition};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexBufferDescription, VertexDefinition};
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
 use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
 use vulkano::pipeline::{DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::image::SampleCount;
 use vulkano::render_pass::Subpass;
-use crate::renderer::vulkan::{SwapchainImages, OFFLINE_PIPELINE_COLOR_FORMAT};
+use vulkano::shader::ShaderModule;
+use crate::renderer::vulkan::SwapchainImages;
+use crate::renderer::vulkan::vertex::{VulkanModelInstance, VulkanModelVertex, VulkanModelVertexLightmapTextureCoords, VulkanModelVertexTextureCoords};
 
 #[derive(Copy, Clone, Default, PartialEq)]
 pub enum DepthAccess {
@@ -49,49 +50,102 @@ pub enum DepthAccess {
     NoDepth
 }
 
+#[derive(Copy, Clone, Default, PartialEq)]
+pub enum StencilAccess {
+    /// The stencil buffer is completely ignored.
+    #[default]
+    NoStencil,
+
+    /// The stencil value is replaced with the reference wherever the pipeline draws.
+    ///
+    /// The reference is dynamic, so it must be set with `set_stencil_reference` before drawing.
+    StencilWrite,
+
+    /// Only draw where the stencil value equals the reference.
+    ///
+    /// The reference is dynamic, so it must be set with `set_stencil_reference` before drawing.
+    StencilTestEqual
+}
+
 #[derive(Clone)]
 pub struct PipelineSettings {
     /// Determines how depth is accessed.
     pub depth_access: DepthAccess,
 
+    /// Determines how the stencil buffer is accessed.
+    ///
+    /// Anything other than [`StencilAccess::NoStencil`] requires the depth image to have a stencil buffer.
+    pub stencil_access: StencilAccess,
+
     /// Vertex data expected to be bound and sent to the shader.
     pub vertex_buffer_descriptions: Vec<VertexBufferDescription>,
 
     /// Determines how to blend
     pub color_blend_attachment_state: ColorBlendAttachmentState,
 
-    /// Sample count to use.
-    pub samples: SampleCount,
+    /// How polygons are rasterized.
+    ///
+    /// Anything other than [`PolygonMode::Fill`] requires the `fill_mode_non_solid` feature.
+    pub polygon_mode: PolygonMode,
+
+    /// How vertices are assembled into primitives.
+    pub topology: PrimitiveTopology,
 
-    /// Color format to use
-    pub format: Format
+    /// Render to a single-sampled color image with no depth image, such as for post-processing.
+    ///
+    /// This requires dynamic rendering, as there is no render pass for it.
+    pub fullscreen_pass: bool
 }
 
 impl Default for PipelineSettings {
     fn default() -> Self {
         Self {
             depth_access: Default::default(),
+            stencil_access: Default::default(),
             vertex_buffer_descriptions: Default::default(),
             color_blend_attachment_state: Default::default(),
-            samples: SampleCount::Sample1,
-            format: OFFLINE_PIPELINE_COLOR_FORMAT
+            polygon_mode: PolygonMode::Fill,
+            topology: PrimitiveTopology::TriangleList,
+            fullscreen_pass: false
         }
     }
 }
 
+/// Get the vertex buffer descriptions for pipelines that draw materials.
+///
+/// Bindings 0-2 are the vertex data, texture coordinates, and lightmap texture coordinates, and binding 3 is the
+/// per-instance transform. All of them must be bound, even if the pipeline's shaders do not use them.
+pub fn material_vertex_buffer_descriptions() -> Vec<VertexBufferDescription> {
+    vec![
+        VulkanModelVertex::per_vertex(),
+        VulkanModelVertexTextureCoords::per_vertex(),
+        VulkanModelVertexLightmapTextureCoords::per_vertex(),
+        VulkanModelInstance::per_instance()
+    ]
+}
+
+/// Vertex and fragment shader modules to load a pipeline with instead of its built-in ones.
+pub type ShaderModules = (Arc<ShaderModule>, Arc<ShaderModule>);
+
 pub fn load_pipeline(
     swapchain_images: &SwapchainImages,
     device: Arc<Device>,
-    load_vertex_shader: fn (Arc<Device>) -> Result<Arc<vulkano::shader::ShaderModule>, vulkano::Validated<vulkano::VulkanError>>,
-    load_fragment_shader: fn (Arc<Device>) -> Result<Arc<vulkano::shader::ShaderModule>, vulkano::Validated<vulkano::VulkanError>>,
+    load_vertex_shader: fn (Arc<Device>) -> Result<Arc<ShaderModule>, vulkano::Validated<vulkano::VulkanError>>,
+    load_fragment_shader: fn (Arc<Device>) -> Result<Arc<ShaderModule>, vulkano::Validated<vulkano::VulkanError>>,
+    shaders: Option<ShaderModules>,
     settings: &PipelineSettings
 ) -> MResult<Arc<GraphicsPipeline>> {
-    let vertex_shader = load_vertex_shader(device.clone())?
+    let (vertex_shader, fragment_shader) = match shaders {
+        Some(modules) => modules,
+        None => (load_vertex_shader(device.clone())?, load_fragment_shader(device.clone())?)
+    };
+
+    let vertex_shader = vertex_shader
         .entry_point("main")
-        .expect("Missing main() entry point for vertex pipeline!");
-    let fragment_shader = load_fragment_shader(device.clone())?
+        .ok_or_else(|| Error::from_data_error_string("Missing main() entry point for vertex pipeline!".to_owned()))?;
+    let fragment_shader = fragment_shader
         .entry_point("main")
-        .expect("Missing main() entry point for fragment pipeline!");
+        .ok_or_else(|| Error::from_data_error_string("Missing main() entry point for fragment pipeline!".to_owned()))?;
 
     let vertex_input_state = settings
         .vertex_buffer_descriptions
@@ -109,6 +163,41 @@ pub fn load_pipeline(
             .unwrap(),
     )?;
 
+    let uses_stencil = settings.stencil_access != StencilAccess::NoStencil;
+    if uses_stencil && !swapchain_images.has_stencil() {
+        return Err(Error::from_data_error_string("Pipeline uses the stencil buffer, but the depth image has none!".to_owned()))
+    }
+
+    let stencil_ops = match settings.stencil_access {
+        StencilAccess::NoStencil | StencilAccess::StencilWrite => StencilOps {
+            fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Replace,
+            depth_fail_op: StencilOp::Keep,
+            compare_op: CompareOp::Always
+        },
+        StencilAccess::StencilTestEqual => StencilOps {
+            fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            compare_op: CompareOp::Equal
+        }
+    };
+    let stencil_op_state = StencilOpState {
+        ops: stencil_ops,
+        compare_mask: 0xFF,
+        write_mask: if settings.stencil_access == StencilAccess::StencilWrite { 0xFF } else { 0 },
+        reference: 0
+    };
+
+    let mut dynamic_state: Vec<DynamicState> = vec![
+        DynamicState::Viewport,
+        DynamicState::Scissor,
+        DynamicState::CullMode,
+    ];
+    if uses_stencil {
+        dynamic_state.push(DynamicState::StencilReference);
+    }
+
     let blend = ColorBlendState::with_attachment_states(
         1,
         settings.color_blend_attachment_state.clone(),
@@ -120,8 +209,11 @@ pub fn load_pipeline(
         GraphicsPipelineCreateInfo {
             stages: stages.into_iter().collect(),
             vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(if let Some(fb) = swapchain_images.framebuffer.as_ref() {
+            input_assembly_state: Some(InputAssemblyState {
+                topology: settings.topology,
+                ..InputAssemblyState::default()
+            }),
+            viewport_state: Some(if let Some(fb) = swapchain_images.framebuffer.as_ref().filter(|_| !settings.fullscreen_pass) {
                 ViewportState {
                     viewports: [Viewport {
                         offset: [0.0, 0.0],
@@ -136,18 +228,17 @@ pub fn load_pipeline(
             }),
             rasterization_state: Some(RasterizationState {
                 front_face: FrontFace::Clockwise,
+                polygon_mode: settings.polygon_mode,
                 ..RasterizationState::default()
             }),
             multisample_state: Some(MultisampleState {
-                rasterization_samples: settings.samples,
+                // Always match the images being rendered to, as a mismatch is invalid.
+                rasterization_samples: if settings.fullscreen_pass { SampleCount::Sample1 } else { swapchain_images.color.image().samples() },
                 ..MultisampleState::default()
             }),
             color_blend_state: Some(blend),
-            dynamic_state: [
-                DynamicState::Viewport,
-                DynamicState::CullMode,
-            ].into_iter().collect(),
-            depth_stencil_state: Some(DepthStencilState {
+            dynamic_state: dynamic_state.into_iter().collect(),
+            depth_stencil_state: (!settings.fullscreen_pass).then(|| DepthStencilState {
                 depth: Some(DepthState {
                     write_enable: settings.depth_access == DepthAccess::DepthWrite,
                     compare_op: match settings.depth_access {
@@ -157,15 +248,20 @@ pub fn load_pipeline(
                         DepthAccess::NoDepth => CompareOp::Always
                     }
                 }),
+                stencil: uses_stencil.then_some(StencilState {
+                    front: stencil_op_state,
+                    back: stencil_op_state
+                }),
                 ..DepthStencilState::default()
             }),
-            subpass: Some(if let Some(fb) = swapchain_images.framebuffer.as_ref() {
+            subpass: Some(if let Some(fb) = swapchain_images.framebuffer.as_ref().filter(|_| !settings.fullscreen_pass) {
                 Subpass::from(fb.render_pass().clone(), 0).unwrap().into()
             }
             else {
                 let create_info = PipelineRenderingCreateInfo {
-                    color_attachment_formats: vec![Some(settings.format)],
-                    depth_attachment_format: Some(swapchain_images.depth.format()),
+                    color_attachment_formats: vec![Some(swapchain_images.color.format())],
+                    depth_attachment_format: (!settings.fullscreen_pass).then(|| swapchain_images.depth.format()),
+                    stencil_attachment_format: (!settings.fullscreen_pass && swapchain_images.has_stencil()).then(|| swapchain_images.depth.format()),
                     ..Default::default()
                 };
                 create_info.into()
@@ -1,12 +1,9 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
-use crate::renderer::vulkan::vertex::{VulkanModelVertex, VulkanModelVertexLightmapTextureCoords, VulkanModelVertexTextureCoords};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, material_vertex_buffer_descriptions, DepthAccess, PipelineSettings};
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 use std::sync::Arc;
-use std::vec;
 use vulkano::device::Device;
 use vulkano::pipeline::graphics::color_blend::ColorBlendAttachmentState;
-use vulkano::pipeline::graphics::vertex_input::Vertex;
 use vulkano::pipeline::GraphicsPipeline;
 
 mod vertex {
@@ -31,11 +28,10 @@ pub struct ShaderEnvironment {
 }
 
 impl ShaderEnvironment {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
             depth_access: DepthAccess::DepthWrite,
-            vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex(), VulkanModelVertexTextureCoords::per_vertex(), VulkanModelVertexLightmapTextureCoords::per_vertex()],
-            samples: swapchain_images.color.image().samples(),
+            vertex_buffer_descriptions: material_vertex_buffer_descriptions(),
             color_blend_attachment_state: ColorBlendAttachmentState::default(),
             ..Default::default()
         })?;
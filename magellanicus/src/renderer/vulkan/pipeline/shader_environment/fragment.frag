@@ -12,6 +12,9 @@ layout(location = 3) in vec3 vertex_position;
 layout(location = 4) in vec3 normal;
 layout(location = 5) in vec3 binormal;
 layout(location = 6) in vec3 tangent;
+layout(location = 7) flat in uint debug_view;
+
+#include "../include/debug_view.glsl"
 
 #define USE_FOG
 #define USE_LIGHTMAPS
@@ -52,21 +55,32 @@ void main() {
     );
 
     // Alpha testing
-    if((shader_environment_data.flags & SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST) == 1) {
-        // TODO: Is it just normal that discards 0-alpha pixels? The alpha is used for blending and specular on other
-        // types, so it makes no sense to test alpha on those types.
-        if(shader_environment_data.shader_environment_type == SHADER_ENVIRONMENT_TYPE_NORMAL && base_map_color.a == 0.0) {
+    //
+    // Discarding here also skips the depth write, so only kept fragments occlude anything. Alpha-tested geometry is
+    // left out of the depth prepass for the same reason.
+    if((shader_environment_data.flags & SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST) != 0) {
+        // Only normal shaders test base map alpha. The alpha is used for blending and specular on other types, so it
+        // makes no sense to test alpha on those types.
+        if(shader_environment_data.shader_environment_type == SHADER_ENVIRONMENT_TYPE_NORMAL && base_map_color.a < SHADER_ENVIRONMENT_ALPHA_TEST_THRESHOLD) {
             discard;
         }
 
-        if(bump_color.a <= 0.5) {
+        if(bump_color.a < SHADER_ENVIRONMENT_ALPHA_TEST_THRESHOLD) {
             discard;
         }
     }
+    // Kept for the specular mask, since the normal itself doesn't use alpha.
+    float bump_alpha = bump_color.a;
     bump_color.a = 1.0;
 
     vec3 bump_vector = bump_color.rgb * 2.0 - 1.0;
 
+    // Two-channel (BC5) bump maps only store X and Y, so Z has to be reconstructed.
+    bool two_channel_bump_map = (shader_environment_data.flags & SHADER_ENVIRONMENT_FLAGS_BUMPMAP_TWO_CHANNEL) != 0;
+    if(two_channel_bump_map) {
+        bump_vector.z = sqrt(max(0.0, 1.0 - dot(bump_vector.xy, bump_vector.xy)));
+    }
+
     vec4 primary_detail_map_color = texture(
         sampler2D(primary_detail_map, map_sampler),
         base_map_texture_coordinates * shader_environment_data.primary_detail_map_scale
@@ -87,6 +101,14 @@ void main() {
         lightmap_texture_coordinates
     );
 
+    if(debug_view == DEBUG_VIEW_LIGHTMAP_ONLY) {
+        f_color = vec4(lightmap_color.rgb, 1.0);
+        return;
+    }
+    else if(debug_view == DEBUG_VIEW_DIFFUSE_ONLY) {
+        lightmap_color = vec4(1.0);
+    }
+
     vec4 blended_detail;
     switch(shader_environment_data.shader_environment_type) {
         case SHADER_ENVIRONMENT_TYPE_NORMAL:
@@ -104,6 +126,11 @@ void main() {
     // Specular (based on noclip.website - https://github.com/magcius/noclip.website)
     vec3 camera_normal = normalize(camera_difference);
     vec3 world_normal = calculate_world_normal(bump_vector);
+    if(debug_view == DEBUG_VIEW_NORMALS) {
+        f_color = vec4(normalize(world_normal) * 0.5 + 0.5, 1.0);
+        return;
+    }
+
     float tangent_on_camera = dot(world_normal, camera_normal);
     vec3 reflection_normal = normalize(2.0 * tangent_on_camera * world_normal - camera_normal);
     vec3 reflection_color = texture(samplerCube(cubemap, map_sampler), reflection_normal).xyz;
@@ -115,8 +142,9 @@ void main() {
     specular *= reflect_attenuation;
 
     float specular_mask;
-    if((shader_environment_data.flags & SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK) != 0) {
-        specular_mask = bump_color.a;
+    // Two-channel bump maps have no alpha to use as a mask.
+    if((shader_environment_data.flags & SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK) != 0 && !two_channel_bump_map) {
+        specular_mask = bump_alpha;
     }
     else if(shader_environment_data.shader_environment_type == SHADER_ENVIRONMENT_TYPE_BLENDED_BASE_SPECULAR) {
         specular_mask = blended_detail.a;
@@ -125,6 +153,9 @@ void main() {
         specular_mask = base_map_color.a;
     }
     specular *= specular_mask;
+    if(debug_view == DEBUG_VIEW_DIFFUSE_ONLY) {
+        specular = vec3(0.0);
+    }
 
     // Specular
     base_map_color.rgb = clamp(base_map_color.rgb + specular.rgb, vec3(0.0), vec3(1.0));
@@ -137,12 +168,20 @@ void main() {
     scratch_color = blend_with_mix_type(base_map_color.rgb, scratch_color, shader_environment_data.detail_map_function);
     scratch_color = blend_with_mix_type(micro_detail_map_color.rgb, scratch_color, shader_environment_data.micro_detail_map_function);
 
+    // Fade detail out with distance so tiling isn't visible far away.
+    if(shader_environment_data.detail_fade.y > shader_environment_data.detail_fade.x) {
+        float detail_strength = 1.0 - smoothstep(shader_environment_data.detail_fade.x, shader_environment_data.detail_fade.y, distance_from_camera);
+        scratch_color = mix(base_map_color.rgb, scratch_color, detail_strength);
+    }
+
     // Bumpmap
     float base_shading = dot(bump_vector, vec3(0.0, 0.0, 1.0));
     scratch_color.rgb *= vec3(base_shading);
 
     // Fog stage
-    scratch_color.rgb = apply_fog(distance_from_camera, scratch_color.rgb);
+    if(debug_view != DEBUG_VIEW_DIFFUSE_ONLY) {
+        scratch_color.rgb = apply_fog(distance_from_camera, scratch_color.rgb);
+    }
 
     f_color = vec4(scratch_color, 1.0);
 }
@@ -9,6 +9,8 @@ layout(set = 3, binding = 0) uniform ShaderEnvironmentData {
     uint detail_map_function;
     uint micro_detail_map_function;
 
+    vec4 detail_fade; // x = start, y = end, zw = unused (keeps parallel_color 16-byte aligned)
+
     vec4 parallel_color; // a = brightness
     vec4 perpendicular_color; // a = brightness
 } shader_environment_data;
@@ -17,5 +19,9 @@ layout(set = 3, binding = 0) uniform ShaderEnvironmentData {
 #define SHADER_ENVIRONMENT_TYPE_BLENDED 1
 #define SHADER_ENVIRONMENT_TYPE_BLENDED_BASE_SPECULAR 2
 
+// These must match material/shader_environment.rs.
 #define SHADER_ENVIRONMENT_FLAGS_ALPHA_TEST 1
 #define SHADER_ENVIRONMENT_FLAGS_BUMPMAP_ALPHA_SPECULAR_MASK 2
+#define SHADER_ENVIRONMENT_FLAGS_BUMPMAP_TWO_CHANNEL 4
+
+#define SHADER_ENVIRONMENT_ALPHA_TEST_THRESHOLD 0.5
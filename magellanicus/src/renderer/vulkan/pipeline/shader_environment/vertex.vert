@@ -4,6 +4,7 @@
 
 #define USE_TEXTURE_COORDS
 #define USE_LIGHTMAPS
+#define USE_INSTANCES
 
 #include "../include/material.vert"
 
@@ -18,15 +19,19 @@ layout(location = 3) out vec3 vertex_position;
 layout(location = 4) out vec3 f_normal;
 layout(location = 5) out vec3 f_binormal;
 layout(location = 6) out vec3 f_tangent;
+layout(location = 7) flat out uint debug_view;
+
+// Must match the depth pre-pass exactly.
+invariant gl_Position;
 
 void main() {
-    mat4 worldview = uniforms.view * uniforms.world;
-    vertex_position = position.xyz + uniforms.offset.xyz;
+    vertex_position = (uniforms.world * instance_world * vec4(position.xyz + uniforms.offset.xyz, 1.0)).xyz;
     camera_position = uniforms.camera;
-    gl_Position = uniforms.proj * worldview * vec4(vertex_position, 1.0);
+    gl_Position = uniforms.proj * uniforms.view * vec4(vertex_position, 1.0);
     base_map_texture_coordinates = texture_coords.xy;
     lightmap_texture_coordinates = lightmap_texture_coords.xy;
-    f_normal = normal;
-    f_binormal = binormal;
-    f_tangent = tangent;
+    f_normal = uniforms.rotation * instance_rotation * normal;
+    f_binormal = uniforms.rotation * instance_rotation * binormal;
+    f_tangent = uniforms.rotation * instance_rotation * tangent;
+    debug_view = uniforms.debug_view;
 }
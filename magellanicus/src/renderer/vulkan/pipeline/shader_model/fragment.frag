@@ -0,0 +1,108 @@
+#version 450
+
+#include "shader_model_data.glsl"
+
+layout(location = 0) out vec4 f_color;
+
+layout(location = 0) in vec2 texture_coordinates;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 binormal;
+layout(location = 3) in vec3 tangent;
+layout(location = 4) in vec3 camera_position;
+layout(location = 5) in vec3 vertex_position;
+
+#define USE_FOG
+#define USE_TANGENT
+#include "../include/material.frag"
+#include "../include/blend.frag"
+
+layout(set = 3, binding = 1) uniform sampler map_sampler;
+layout(set = 3, binding = 2) uniform texture2D base_map;
+layout(set = 3, binding = 3) uniform texture2D multipurpose_map;
+layout(set = 3, binding = 4) uniform texture2D detail_map;
+layout(set = 3, binding = 5) uniform textureCube cubemap;
+
+vec3 blend_with_mix_type(vec3 color, vec3 with, uint blend_type) {
+    switch(blend_type) {
+        case 0:
+            return double_biased_multiply(color, with);
+        case 1:
+            return multiply(color, with);
+        case 2:
+            return double_biased_add(color, with);
+        default:
+            return vec3(0.0);
+    }
+}
+
+void main() {
+    vec3 camera_difference = camera_position - vertex_position;
+    float distance_from_camera = distance(camera_position, vertex_position);
+
+    vec2 map_texture_coordinates = texture_coordinates * shader_model_data.map_scale;
+    vec4 base_map_color = texture(sampler2D(base_map, map_sampler), map_texture_coordinates);
+
+    // Alpha testing
+    if((shader_model_data.flags & SHADER_MODEL_FLAGS_ALPHA_TEST) != 0 && base_map_color.a < 0.5) {
+        discard;
+    }
+
+    // Multipurpose map channels (PC layout)
+    vec4 multipurpose_color = texture(sampler2D(multipurpose_map, map_sampler), map_texture_coordinates);
+    float auxiliary_mask = multipurpose_color.r;
+    float self_illumination_mask = multipurpose_color.g;
+    float reflection_mask = multipurpose_color.b;
+    float change_color_mask = multipurpose_color.a;
+
+    vec4 detail_map_color = texture(
+        sampler2D(detail_map, map_sampler),
+        map_texture_coordinates * vec2(shader_model_data.detail_map_scale, shader_model_data.detail_map_scale * shader_model_data.detail_map_v_scale)
+    );
+
+    float detail_mask;
+    switch(shader_model_data.detail_mask) {
+        case SHADER_MODEL_DETAIL_MASK_REFLECTION_MASK_INVERSE: detail_mask = 1.0 - reflection_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_REFLECTION_MASK: detail_mask = reflection_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_SELF_ILLUMINATION_MASK_INVERSE: detail_mask = 1.0 - self_illumination_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_SELF_ILLUMINATION_MASK: detail_mask = self_illumination_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_CHANGE_COLOR_MASK_INVERSE: detail_mask = 1.0 - change_color_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_CHANGE_COLOR_MASK: detail_mask = change_color_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_AUXILIARY_MASK_INVERSE: detail_mask = 1.0 - auxiliary_mask; break;
+        case SHADER_MODEL_DETAIL_MASK_AUXILIARY_MASK: detail_mask = auxiliary_mask; break;
+        default: detail_mask = 1.0; break;
+    }
+
+    // Reflection (based on noclip.website - https://github.com/magcius/noclip.website)
+    vec3 camera_normal = normalize(camera_difference);
+    vec3 world_normal = normalize(normal);
+    float tangent_on_camera = dot(world_normal, camera_normal);
+    vec3 reflection_normal = normalize(2.0 * tangent_on_camera * world_normal - camera_normal);
+    vec3 reflection_color = texture(samplerCube(cubemap, map_sampler), reflection_normal).xyz;
+    vec3 specular_color = pow(reflection_color, vec3(8.0));
+    float diffuse_reflection = tangent_on_camera * tangent_on_camera;
+    float reflect_attenuation = mix(shader_model_data.parallel_color.a, shader_model_data.perpendicular_color.a, diffuse_reflection);
+    vec3 specular = mix(shader_model_data.parallel_color.rgb, shader_model_data.perpendicular_color.rgb, diffuse_reflection);
+    specular = mix(specular_color, reflection_color, specular);
+    specular *= reflect_attenuation * reflection_mask;
+
+    vec3 scratch_color = base_map_color.rgb;
+    vec3 detailed_color;
+    if((shader_model_data.flags & SHADER_MODEL_FLAGS_DETAIL_AFTER_REFLECTION) != 0) {
+        scratch_color = clamp(scratch_color + specular, vec3(0.0), vec3(1.0));
+        detailed_color = blend_with_mix_type(scratch_color, detail_map_color.rgb, shader_model_data.detail_map_function);
+        scratch_color = mix(scratch_color, detailed_color, detail_mask);
+    }
+    else {
+        detailed_color = blend_with_mix_type(scratch_color, detail_map_color.rgb, shader_model_data.detail_map_function);
+        scratch_color = mix(scratch_color, detailed_color, detail_mask);
+        scratch_color = clamp(scratch_color + specular, vec3(0.0), vec3(1.0));
+    }
+
+    // Self-illumination
+    scratch_color = clamp(scratch_color + shader_model_data.self_illumination_color.rgb * self_illumination_mask, vec3(0.0), vec3(1.0));
+
+    // Fog stage
+    scratch_color = apply_fog(distance_from_camera, scratch_color);
+
+    f_color = vec4(scratch_color, 1.0);
+}
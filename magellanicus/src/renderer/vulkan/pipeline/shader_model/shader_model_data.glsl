@@ -0,0 +1,26 @@
+layout(set = 3, binding = 0) uniform ShaderModelData {
+    vec4 parallel_color; // a = brightness
+    vec4 perpendicular_color; // a = brightness
+    vec4 self_illumination_color;
+
+    vec2 map_scale;
+    float detail_map_scale;
+    float detail_map_v_scale;
+
+    uint flags;
+    uint detail_map_function;
+    uint detail_mask;
+} shader_model_data;
+
+#define SHADER_MODEL_FLAGS_ALPHA_TEST 1
+#define SHADER_MODEL_FLAGS_DETAIL_AFTER_REFLECTION 2
+
+#define SHADER_MODEL_DETAIL_MASK_NONE 0
+#define SHADER_MODEL_DETAIL_MASK_REFLECTION_MASK_INVERSE 1
+#define SHADER_MODEL_DETAIL_MASK_REFLECTION_MASK 2
+#define SHADER_MODEL_DETAIL_MASK_SELF_ILLUMINATION_MASK_INVERSE 3
+#define SHADER_MODEL_DETAIL_MASK_SELF_ILLUMINATION_MASK 4
+#define SHADER_MODEL_DETAIL_MASK_CHANGE_COLOR_MASK_INVERSE 5
+#define SHADER_MODEL_DETAIL_MASK_CHANGE_COLOR_MASK 6
+#define SHADER_MODEL_DETAIL_MASK_AUXILIARY_MASK_INVERSE 7
+#define SHADER_MODEL_DETAIL_MASK_AUXILIARY_MASK 8
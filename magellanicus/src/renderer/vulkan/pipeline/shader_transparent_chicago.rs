@@ -1,12 +1,9 @@
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
-use crate::renderer::vulkan::vertex::{VulkanModelVertex, VulkanModelVertexTextureCoords};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, material_vertex_buffer_descriptions, DepthAccess, PipelineSettings};
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 use std::sync::Arc;
-use std::vec;
 use vulkano::device::Device;
 use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState};
-use vulkano::pipeline::graphics::vertex_input::Vertex;
 use vulkano::pipeline::GraphicsPipeline;
 
 mod vertex {
@@ -31,11 +28,10 @@ pub struct ShaderTransparentChicago {
 }
 
 impl ShaderTransparentChicago {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, blend_type: Option<AttachmentBlend>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, blend_type: Option<AttachmentBlend>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
             depth_access: DepthAccess::DepthReadOnlyTransparent,
-            vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex(), VulkanModelVertexTextureCoords::per_vertex()],
-            samples: swapchain_images.color.image().samples(),
+            vertex_buffer_descriptions: material_vertex_buffer_descriptions(),
             color_blend_attachment_state: ColorBlendAttachmentState {
                 blend: blend_type,
                 ..ColorBlendAttachmentState::default()
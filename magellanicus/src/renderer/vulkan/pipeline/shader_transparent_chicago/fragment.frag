@@ -7,6 +7,7 @@ layout(location = 2) in vec3 binormal;
 layout(location = 3) in vec3 tangent;
 layout(location = 4) in vec3 camera_position;
 layout(location = 5) in vec3 vertex_position;
+layout(location = 6) in float time;
 
 #define USE_FOG
 #define USE_TANGENT
@@ -16,7 +17,7 @@ layout(location = 5) in vec3 vertex_position;
 layout(location = 0) out vec4 f_color;
 layout(location = 0) in vec2 texture_coordinates;
 
-layout(set = 3, binding = 1) uniform sampler map_sampler;
+layout(set = 3, binding = 1) uniform sampler map_samplers[4];
 layout(set = 3, binding = 2) uniform textureCube map0_cube;
 layout(set = 3, binding = 3) uniform texture2D map0_2d;
 layout(set = 3, binding = 4) uniform texture2D map1;
@@ -88,29 +89,29 @@ void main() {
 
     if(shader_transparent_chicago_data.first_map_type == 0) {
         map0_color = texture(
-           sampler2D(map0_2d, map_sampler),
-           (texture_coordinates + shader_transparent_chicago_data.map0_uv) * shader_transparent_chicago_data.map0_scale
+           sampler2D(map0_2d, map_samplers[0]),
+           (texture_coordinates + shader_transparent_chicago_data.map0_uv + time * shader_transparent_chicago_data.map0_scroll) * shader_transparent_chicago_data.map0_scale
         );
     }
     else {
         vec3 asdf = calculate_world_normal(vec3(0.0, 0.0, 1.0));
         map0_color = texture(
-            samplerCube(map0_cube, map_sampler),
-            (asdf + vec3(shader_transparent_chicago_data.map0_uv, 1.0)) * vec3(shader_transparent_chicago_data.map0_scale, 1.0)
+            samplerCube(map0_cube, map_samplers[0]),
+            (asdf + vec3(shader_transparent_chicago_data.map0_uv + time * shader_transparent_chicago_data.map0_scroll, 1.0)) * vec3(shader_transparent_chicago_data.map0_scale, 1.0)
         );
     }
 
     vec4 map1_color = texture(
-        sampler2D(map1, map_sampler),
-        (texture_coordinates + shader_transparent_chicago_data.map1_uv) * shader_transparent_chicago_data.map1_scale
+        sampler2D(map1, map_samplers[1]),
+        (texture_coordinates + shader_transparent_chicago_data.map1_uv + time * shader_transparent_chicago_data.map1_scroll) * shader_transparent_chicago_data.map1_scale
     );
     vec4 map2_color = texture(
-        sampler2D(map2, map_sampler),
-        (texture_coordinates + shader_transparent_chicago_data.map2_uv) * shader_transparent_chicago_data.map2_scale
+        sampler2D(map2, map_samplers[2]),
+        (texture_coordinates + shader_transparent_chicago_data.map2_uv + time * shader_transparent_chicago_data.map2_scroll) * shader_transparent_chicago_data.map2_scale
     );
     vec4 map3_color = texture(
-        sampler2D(map3, map_sampler),
-        (texture_coordinates + shader_transparent_chicago_data.map3_uv) * shader_transparent_chicago_data.map3_scale
+        sampler2D(map3, map_samplers[3]),
+        (texture_coordinates + shader_transparent_chicago_data.map3_uv + time * shader_transparent_chicago_data.map3_scroll) * shader_transparent_chicago_data.map3_scale
     );
 
     vec4 current_color = map0_color;
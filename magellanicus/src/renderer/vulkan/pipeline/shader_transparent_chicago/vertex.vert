@@ -3,6 +3,7 @@
 #include "shader_transparent_chicago_data.glsl"
 
 #define USE_TEXTURE_COORDS
+#define USE_INSTANCES
 #include "../include/material.vert"
 
 layout(location = 4) in vec3 normal;
@@ -15,14 +16,15 @@ layout(location = 2) out vec3 f_binormal;
 layout(location = 3) out vec3 f_tangent;
 layout(location = 4) out vec3 camera_position;
 layout(location = 5) out vec3 vertex_position;
+layout(location = 6) out float time;
 
 void main() {
-    mat4 worldview = uniforms.view * uniforms.world;
-    vertex_position = position.xyz + uniforms.offset.xyz;
+    vertex_position = (uniforms.world * instance_world * vec4(position.xyz + uniforms.offset.xyz, 1.0)).xyz;
     camera_position = uniforms.camera;
-    gl_Position = uniforms.proj * worldview * vec4(vertex_position, 1.0);
+    gl_Position = uniforms.proj * uniforms.view * vec4(vertex_position, 1.0);
     texture_coordinates = texture_coords.xy;
-    f_normal = normal;
-    f_binormal = binormal;
-    f_tangent = tangent;
+    f_normal = uniforms.rotation * instance_rotation * normal;
+    f_binormal = uniforms.rotation * instance_rotation * binormal;
+    f_tangent = uniforms.rotation * instance_rotation * tangent;
+    time = uniforms.time;
 }
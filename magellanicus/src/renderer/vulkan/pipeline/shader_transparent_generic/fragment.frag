@@ -0,0 +1,308 @@
+#version 450
+
+#include "shader_transparent_generic_data.glsl"
+
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 binormal;
+layout(location = 3) in vec3 tangent;
+layout(location = 4) in vec3 camera_position;
+layout(location = 5) in vec3 vertex_position;
+
+#define USE_FOG
+#define USE_TANGENT
+#include "../include/material.frag"
+
+layout(location = 0) out vec4 f_color;
+layout(location = 0) in vec2 texture_coordinates;
+
+layout(set = 3, binding = 1) uniform sampler map_sampler;
+layout(set = 3, binding = 2) uniform textureCube map0_cube;
+layout(set = 3, binding = 3) uniform texture2D map0_2d;
+layout(set = 3, binding = 4) uniform texture2D map1;
+layout(set = 3, binding = 5) uniform texture2D map2;
+layout(set = 3, binding = 6) uniform texture2D map3;
+
+#define INPUT_MAPPING_CLAMP_X 0
+#define INPUT_MAPPING_ONE_MINUS_CLAMP_X 1
+#define INPUT_MAPPING_TWO 2
+#define INPUT_MAPPING_MINUS_TWO 3
+#define INPUT_MAPPING_CLAMP_X_MINUS_HALF 4
+#define INPUT_MAPPING_HALF_MINUS_CLAMP_X 5
+#define INPUT_MAPPING_X 6
+#define INPUT_MAPPING_MINUS_X 7
+
+#define OUTPUT_MAPPING_IDENTITY 0
+#define OUTPUT_MAPPING_SCALE_BY_HALF 1
+#define OUTPUT_MAPPING_SCALE_BY_TWO 2
+#define OUTPUT_MAPPING_SCALE_BY_FOUR 3
+#define OUTPUT_MAPPING_BIAS_BY_MINUS_HALF 4
+#define OUTPUT_MAPPING_EXPAND_NORMAL 5
+
+#define OUTPUT_FUNCTION_MULTIPLY 0
+#define OUTPUT_FUNCTION_DOT_PRODUCT 1
+
+#define OUTPUT_DISCARD 0
+
+// Registers that stages read from and write to
+vec4 map_colors[4];
+vec4 vertex_colors[2];
+vec4 scratch_colors[2];
+
+vec3 map_input(vec3 value, uint mapping) {
+    switch(mapping) {
+        case INPUT_MAPPING_CLAMP_X: return clamp(value, 0.0, 1.0);
+        case INPUT_MAPPING_ONE_MINUS_CLAMP_X: return 1.0 - clamp(value, 0.0, 1.0);
+        case INPUT_MAPPING_TWO: return 2.0 * clamp(value, 0.0, 1.0) - 1.0;
+        case INPUT_MAPPING_MINUS_TWO: return 1.0 - 2.0 * clamp(value, 0.0, 1.0);
+        case INPUT_MAPPING_CLAMP_X_MINUS_HALF: return clamp(value, 0.0, 1.0) - 0.5;
+        case INPUT_MAPPING_HALF_MINUS_CLAMP_X: return 0.5 - clamp(value, 0.0, 1.0);
+        case INPUT_MAPPING_X: return value;
+        case INPUT_MAPPING_MINUS_X: return -value;
+        default: return value;
+    }
+}
+
+vec3 map_output(vec3 value, uint mapping) {
+    switch(mapping) {
+        case OUTPUT_MAPPING_IDENTITY: return value;
+        case OUTPUT_MAPPING_SCALE_BY_HALF: return value * 0.5;
+        case OUTPUT_MAPPING_SCALE_BY_TWO: return value * 2.0;
+        case OUTPUT_MAPPING_SCALE_BY_FOUR: return value * 4.0;
+        case OUTPUT_MAPPING_BIAS_BY_MINUS_HALF: return value - 0.5;
+        case OUTPUT_MAPPING_EXPAND_NORMAL: return value * 2.0 - 1.0;
+        default: return value;
+    }
+}
+
+float get_constant_input(uint input_value) {
+    switch(input_value) {
+        case 0: return 0.0;
+        case 1: return 1.0;
+        case 2: return 0.5;
+        case 3: return -1.0;
+        case 4: return -0.5;
+        default: return 0.0;
+    }
+}
+
+vec3 get_color_input(uint input_value, uint mapping, ShaderTransparentGenericStage stage) {
+    vec3 value;
+    if(input_value <= 4) {
+        value = vec3(get_constant_input(input_value));
+    }
+    else if(input_value <= 8) {
+        value = map_colors[input_value - 5].rgb;
+    }
+    else if(input_value <= 10) {
+        value = vertex_colors[input_value - 9].rgb;
+    }
+    else if(input_value <= 12) {
+        value = scratch_colors[input_value - 11].rgb;
+    }
+    else if(input_value == 13) {
+        value = stage.constant_color0.rgb;
+    }
+    else if(input_value == 14) {
+        value = stage.constant_color1.rgb;
+    }
+    else if(input_value <= 18) {
+        value = vec3(map_colors[input_value - 15].a);
+    }
+    else if(input_value <= 20) {
+        value = vec3(vertex_colors[input_value - 19].a);
+    }
+    else if(input_value <= 22) {
+        value = vec3(scratch_colors[input_value - 21].a);
+    }
+    else if(input_value == 23) {
+        value = vec3(stage.constant_color0.a);
+    }
+    else if(input_value == 24) {
+        value = vec3(stage.constant_color1.a);
+    }
+    else {
+        value = vec3(0.0);
+    }
+    return map_input(value, mapping);
+}
+
+float get_alpha_input(uint input_value, uint mapping, ShaderTransparentGenericStage stage) {
+    float value;
+    if(input_value <= 4) {
+        value = get_constant_input(input_value);
+    }
+    else if(input_value <= 8) {
+        value = map_colors[input_value - 5].a;
+    }
+    else if(input_value <= 10) {
+        value = vertex_colors[input_value - 9].a;
+    }
+    else if(input_value <= 12) {
+        value = scratch_colors[input_value - 11].a;
+    }
+    else if(input_value == 13) {
+        value = stage.constant_color0.a;
+    }
+    else if(input_value == 14) {
+        value = stage.constant_color1.a;
+    }
+    else if(input_value <= 18) {
+        value = map_colors[input_value - 15].b;
+    }
+    else if(input_value <= 20) {
+        value = vertex_colors[input_value - 19].b;
+    }
+    else if(input_value <= 22) {
+        value = scratch_colors[input_value - 21].b;
+    }
+    else if(input_value == 23) {
+        value = stage.constant_color0.b;
+    }
+    else if(input_value == 24) {
+        value = stage.constant_color1.b;
+    }
+    else {
+        value = 0.0;
+    }
+    return map_input(vec3(value), mapping).x;
+}
+
+void write_color_output(uint output_value, vec3 value) {
+    if(output_value == OUTPUT_DISCARD) {
+        return;
+    }
+    else if(output_value <= 2) {
+        scratch_colors[output_value - 1].rgb = value;
+    }
+    else if(output_value <= 4) {
+        vertex_colors[output_value - 3].rgb = value;
+    }
+    else if(output_value <= 8) {
+        map_colors[output_value - 5].rgb = value;
+    }
+}
+
+void write_alpha_output(uint output_value, float value) {
+    if(output_value == OUTPUT_DISCARD) {
+        return;
+    }
+    else if(output_value <= 2) {
+        scratch_colors[output_value - 1].a = value;
+    }
+    else if(output_value <= 4) {
+        vertex_colors[output_value - 3].a = value;
+    }
+    else if(output_value <= 8) {
+        map_colors[output_value - 5].a = value;
+    }
+}
+
+vec3 calculate_color_function(vec3 a, vec3 b, uint function) {
+    if(function == OUTPUT_FUNCTION_DOT_PRODUCT) {
+        return vec3(dot(a, b));
+    }
+    else {
+        return a * b;
+    }
+}
+
+void run_stage(ShaderTransparentGenericStage stage) {
+    // Color
+    vec3 color_a = get_color_input(stage.color_inputs.x, stage.color_input_mappings.x, stage);
+    vec3 color_b = get_color_input(stage.color_inputs.y, stage.color_input_mappings.y, stage);
+    vec3 color_c = get_color_input(stage.color_inputs.z, stage.color_input_mappings.z, stage);
+    vec3 color_d = get_color_input(stage.color_inputs.w, stage.color_input_mappings.w, stage);
+
+    vec3 color_ab = calculate_color_function(color_a, color_b, stage.functions.x);
+    vec3 color_cd = calculate_color_function(color_c, color_d, stage.functions.y);
+    vec3 color_ab_cd;
+    if(stage.functions.z != 0) {
+        color_ab_cd = scratch_colors[0].a >= 0.5 ? color_cd : color_ab;
+    }
+    else {
+        color_ab_cd = color_ab + color_cd;
+    }
+
+    // Alpha
+    float alpha_a = get_alpha_input(stage.alpha_inputs.x, stage.alpha_input_mappings.x, stage);
+    float alpha_b = get_alpha_input(stage.alpha_inputs.y, stage.alpha_input_mappings.y, stage);
+    float alpha_c = get_alpha_input(stage.alpha_inputs.z, stage.alpha_input_mappings.z, stage);
+    float alpha_d = get_alpha_input(stage.alpha_inputs.w, stage.alpha_input_mappings.w, stage);
+
+    float alpha_ab = alpha_a * alpha_b;
+    float alpha_cd = alpha_c * alpha_d;
+    float alpha_ab_cd;
+    if(stage.functions.w != 0) {
+        alpha_ab_cd = scratch_colors[0].a >= 0.5 ? alpha_cd : alpha_ab;
+    }
+    else {
+        alpha_ab_cd = alpha_ab + alpha_cd;
+    }
+
+    // Outputs are written only after every input of the stage has been read. The output mapping applies to each
+    // output, so the AB/CD sum or mux uses the unmapped products.
+    write_color_output(stage.color_outputs.x, map_output(color_ab, stage.color_outputs.w));
+    write_color_output(stage.color_outputs.y, map_output(color_cd, stage.color_outputs.w));
+    write_color_output(stage.color_outputs.z, map_output(color_ab_cd, stage.color_outputs.w));
+
+    write_alpha_output(stage.alpha_outputs.x, map_output(vec3(alpha_ab), stage.alpha_outputs.w).x);
+    write_alpha_output(stage.alpha_outputs.y, map_output(vec3(alpha_cd), stage.alpha_outputs.w).x);
+    write_alpha_output(stage.alpha_outputs.z, map_output(vec3(alpha_ab_cd), stage.alpha_outputs.w).x);
+}
+
+vec4 sample_map(texture2D map, uint index) {
+    vec4 map_uv = shader_transparent_generic_data.map_uv[index];
+    return texture(
+        sampler2D(map, map_sampler),
+        (texture_coordinates + map_uv.xy) * map_uv.zw
+    );
+}
+
+void main() {
+    if(shader_transparent_generic_data.first_map_type == 0) {
+        map_colors[0] = sample_map(map0_2d, 0);
+    }
+    else {
+        vec4 map_uv = shader_transparent_generic_data.map_uv[0];
+        vec3 world_normal = calculate_world_normal(vec3(0.0, 0.0, 1.0));
+        map_colors[0] = texture(
+            samplerCube(map0_cube, map_sampler),
+            (world_normal + vec3(map_uv.xy, 1.0)) * vec3(map_uv.zw, 1.0)
+        );
+    }
+    map_colors[1] = sample_map(map1, 1);
+    map_colors[2] = sample_map(map2, 2);
+    map_colors[3] = sample_map(map3, 3);
+
+    vec3 camera_difference = camera_position - vertex_position;
+    float perpendicular = abs(dot(normalize(normal), normalize(camera_difference)));
+
+    // No vertex lighting yet, so the diffuse light color is always fully lit.
+    vertex_colors[0] = vec4(1.0);
+    vertex_colors[1] = vec4(perpendicular);
+
+    scratch_colors[0] = vec4(0.0);
+    scratch_colors[1] = vec4(0.0);
+
+    vec4 current_color;
+    if(shader_transparent_generic_data.stage_count == 0) {
+        current_color = map_colors[0];
+    }
+    else {
+        for(uint i = 0; i < shader_transparent_generic_data.stage_count && i < MAX_STAGES; i++) {
+            run_stage(shader_transparent_generic_data.stages[i]);
+        }
+        current_color = scratch_colors[0];
+    }
+
+    float distance_from_camera = distance(camera_position, vertex_position);
+    float inverse_density = 1.0 - calculate_fog_density(distance_from_camera);
+
+    current_color.a *= inverse_density;
+
+    if(shader_transparent_generic_data.premultiply != 0) {
+        current_color.rgb *= inverse_density;
+    }
+
+    f_color = clamp(current_color, vec4(0.0), vec4(1.0));
+}
@@ -0,0 +1,34 @@
+#define MAX_STAGES 7
+
+struct ShaderTransparentGenericStage {
+    vec4 constant_color0;
+    vec4 constant_color1;
+
+    // A, B, C, D
+    uvec4 color_inputs;
+    uvec4 color_input_mappings;
+
+    // AB, CD, AB/CD mux/sum, output mapping
+    uvec4 color_outputs;
+
+    // A, B, C, D
+    uvec4 alpha_inputs;
+    uvec4 alpha_input_mappings;
+
+    // AB, CD, AB/CD mux/sum, output mapping
+    uvec4 alpha_outputs;
+
+    // AB function, CD function, color mux, alpha mux
+    uvec4 functions;
+};
+
+layout(set = 3, binding = 0) uniform ShaderTransparentGenericData {
+    // xy = offset, zw = scale
+    vec4 map_uv[4];
+
+    ShaderTransparentGenericStage stages[MAX_STAGES];
+
+    uint first_map_type;
+    uint stage_count;
+    uint premultiply;
+} shader_transparent_generic_data;
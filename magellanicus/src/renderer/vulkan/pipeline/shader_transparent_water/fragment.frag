@@ -0,0 +1,59 @@
+#version 450
+
+#include "shader_transparent_water_data.glsl"
+
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 binormal;
+layout(location = 3) in vec3 tangent;
+layout(location = 4) in vec3 camera_position;
+layout(location = 5) in vec3 vertex_position;
+
+#define USE_FOG
+#define USE_TANGENT
+#include "../include/material.frag"
+
+layout(location = 0) out vec4 f_color;
+layout(location = 0) in vec2 texture_coordinates;
+
+layout(set = 3, binding = 1) uniform sampler map_sampler;
+layout(set = 3, binding = 2) uniform texture2D base_map;
+layout(set = 3, binding = 3) uniform texture2D ripple_map;
+layout(set = 3, binding = 4) uniform textureCube reflection_map;
+
+void main() {
+    vec4 base_map_color = texture(sampler2D(base_map, map_sampler), texture_coordinates);
+
+    vec4 ripple_color = texture(
+        sampler2D(ripple_map, map_sampler),
+        texture_coordinates * shader_transparent_water_data.ripple_scale
+    );
+    vec3 ripple_vector = ripple_color.rgb * 2.0 - 1.0;
+
+    // Reflection (same as shader_environment, but using the ripple map as the bump map)
+    vec3 camera_normal = normalize(camera_position - vertex_position);
+    vec3 world_normal = normalize(calculate_world_normal(ripple_vector));
+    float tangent_on_camera = dot(world_normal, camera_normal);
+    vec3 reflection_normal = normalize(2.0 * tangent_on_camera * world_normal - camera_normal);
+    vec3 reflection_color = texture(samplerCube(reflection_map, map_sampler), reflection_normal).rgb;
+
+    float perpendicularity = clamp(tangent_on_camera * tangent_on_camera, 0.0, 1.0);
+    vec4 tint = mix(shader_transparent_water_data.parallel_color, shader_transparent_water_data.perpendicular_color, perpendicularity);
+
+    vec3 color = reflection_color * tint.rgb;
+    float alpha = tint.a;
+
+    if((shader_transparent_water_data.flags & SHADER_TRANSPARENT_WATER_FLAGS_BASE_MAP_ALPHA_MODULATES_REFLECTION) != 0) {
+        alpha *= base_map_color.a;
+    }
+
+    if((shader_transparent_water_data.flags & SHADER_TRANSPARENT_WATER_FLAGS_BASE_MAP_COLOR_MODULATES_BACKGROUND) != 0) {
+        color = mix(base_map_color.rgb, color, alpha);
+        alpha = max(alpha, base_map_color.a);
+    }
+
+    // Fog stage
+    float distance_from_camera = distance(camera_position, vertex_position);
+    color = apply_fog(distance_from_camera, color);
+
+    f_color = clamp(vec4(color, alpha), vec4(0.0), vec4(1.0));
+}
@@ -0,0 +1,10 @@
+layout(set = 3, binding = 0) uniform ShaderTransparentWaterData {
+    vec4 perpendicular_color; // a = brightness
+    vec4 parallel_color; // a = brightness
+
+    float ripple_scale;
+    uint flags;
+} shader_transparent_water_data;
+
+#define SHADER_TRANSPARENT_WATER_FLAGS_BASE_MAP_ALPHA_MODULATES_REFLECTION 1
+#define SHADER_TRANSPARENT_WATER_FLAGS_BASE_MAP_COLOR_MODULATES_BACKGROUND 2
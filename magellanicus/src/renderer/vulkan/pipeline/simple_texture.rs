@@ -1,12 +1,9 @@
 use std::sync::Arc;
 use vulkano::device::Device;
-use std::vec;
 use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState};
 use vulkano::pipeline::GraphicsPipeline;
-use vulkano::pipeline::graphics::vertex_input::Vertex;
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
-use crate::renderer::vulkan::vertex::{VulkanModelVertex, VulkanModelVertexLightmapTextureCoords, VulkanModelVertexTextureCoords};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, material_vertex_buffer_descriptions, DepthAccess, PipelineSettings};
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 
 mod vertex {
@@ -23,24 +20,21 @@ mod fragment {
     }
 }
 
+pub use fragment::SimpleTextureData;
+
 pub struct SimpleTextureShader {
     pub pipeline: Arc<GraphicsPipeline>
 }
 
 impl SimpleTextureShader {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
             depth_access: DepthAccess::DepthReadOnlyTransparent,
-            vertex_buffer_descriptions: vec![
-                VulkanModelVertex::per_vertex(),
-                VulkanModelVertexTextureCoords::per_vertex(),
-                VulkanModelVertexLightmapTextureCoords::per_vertex()
-            ],
+            vertex_buffer_descriptions: material_vertex_buffer_descriptions(),
             color_blend_attachment_state: ColorBlendAttachmentState {
                 blend: Some(AttachmentBlend::additive()),
                 ..ColorBlendAttachmentState::default()
             },
-            samples: swapchain_images.color.image().samples(),
             ..Default::default()
         })?;
 
@@ -12,10 +12,17 @@ layout(location = 2) in float distance_from_camera;
 
 layout(set = 3, binding = 0) uniform sampler s;
 layout(set = 3, binding = 1) uniform texture2D tex;
+layout(set = 3, binding = 2) uniform texture3D volume_tex;
+layout(set = 3, binding = 3) uniform SimpleTextureData {
+    float volume_slice;
+    uint use_volume;
+} simple_texture_data;
 
 void main() {
     vec4 lightmap_color = texture(sampler2D(lightmap_texture, lightmap_sampler), lightmap_texcoords);
-    vec4 color = texture(sampler2D(tex, s), tex_coords);
+    vec4 color = simple_texture_data.use_volume != 0
+        ? texture(sampler3D(volume_tex, s), vec3(tex_coords, simple_texture_data.volume_slice))
+        : texture(sampler2D(tex, s), tex_coords);
     vec4 lightmapped_color = vec4(color.rgb * lightmap_color.rgb, 1.0);
 
     // FIXME: Messes with additive transparent stuff
@@ -2,6 +2,7 @@
 
 #define USE_TEXTURE_COORDS
 #define USE_LIGHTMAPS
+#define USE_INSTANCES
 
 #include "../include/material.vert"
 
@@ -10,10 +11,9 @@ layout(location = 1) out vec2 lightmap_texcoords;
 layout(location = 2) out float distance_from_camera;
 
 void main() {
-    mat4 worldview = uniforms.view * uniforms.world;
-    vec3 offset = position.xyz + uniforms.offset.xyz;
+    vec3 offset = (uniforms.world * instance_world * vec4(position.xyz + uniforms.offset.xyz, 1.0)).xyz;
 
-    gl_Position = uniforms.proj * worldview * vec4(offset, 1.0);
+    gl_Position = uniforms.proj * uniforms.view * vec4(offset, 1.0);
     texcoords = texture_coords.xy;
     lightmap_texcoords = lightmap_texture_coords.xy;
 
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use crate::error::MResult;
-use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, DepthAccess, PipelineSettings};
+use crate::renderer::vulkan::pipeline::pipeline_loader::{load_pipeline, ShaderModules, DepthAccess, PipelineSettings};
 use crate::renderer::vulkan::vertex::VulkanModelVertex;
 use crate::renderer::vulkan::{SwapchainImages, VulkanPipelineData};
 use std::vec;
@@ -28,12 +28,11 @@ pub struct SolidColorShader {
 }
 
 impl SolidColorShader {
-    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>) -> MResult<Self> {
-        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, &PipelineSettings {
+    pub fn new(swapchain_images: &SwapchainImages, device: Arc<Device>, shaders: Option<ShaderModules>) -> MResult<Self> {
+        let pipeline = load_pipeline(swapchain_images, device, vertex::load, fragment::load, shaders, &PipelineSettings {
             depth_access: DepthAccess::DepthWrite,
             vertex_buffer_descriptions: vec![VulkanModelVertex::per_vertex()],
             color_blend_attachment_state: ColorBlendAttachmentState::default(),
-            samples: swapchain_images.color.image().samples(),
             ..Default::default()
         })?;
 
@@ -0,0 +1,37 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+layout(location = 0) in vec2 texture_coords;
+
+layout(set = 0, binding = 0) uniform sampler s;
+layout(set = 0, binding = 1) uniform texture2D tex;
+
+layout(set = 1, binding = 0) uniform TonemapData {
+    float exposure;
+
+    // 0 = passthrough, 1 = Reinhard, 2 = ACES
+    uint tonemap_operator;
+} tonemap_data;
+
+vec3 aces(vec3 color) {
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = texture(sampler2D(tex, s), texture_coords);
+    vec3 exposed = color.rgb * tonemap_data.exposure;
+
+    switch(tonemap_data.tonemap_operator) {
+        case 1u:
+            exposed = exposed / (1.0 + exposed);
+            break;
+        case 2u:
+            exposed = aces(exposed);
+            break;
+        default:
+            break;
+    }
+
+    f_color = vec4(exposed, color.a);
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use vulkano::command_buffer::ClearColorImageInfo;
+use vulkano::format::ClearColorValue;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use crate::error::MResult;
+use crate::renderer::Resolution;
+use crate::renderer::vulkan::{SwapchainImages, VulkanRenderer, OFFLINE_PIPELINE_COLOR_FORMAT};
+
+/// Offscreen images for drawing a viewport into a texture.
+#[derive(Clone)]
+pub struct VulkanRenderTarget {
+    /// Final image that shaders sample from.
+    pub image: Arc<Image>,
+
+    pub(super) images: Arc<SwapchainImages>
+}
+
+impl VulkanRenderTarget {
+    pub fn new(vulkan_renderer: &mut VulkanRenderer, resolution: Resolution) -> MResult<Self> {
+        let image = Image::new(
+            vulkan_renderer.memory_allocator.clone(),
+            ImageCreateInfo {
+                extent: [resolution.width, resolution.height, 1],
+                format: OFFLINE_PIPELINE_COLOR_FORMAT,
+                image_type: ImageType::Dim2d,
+                usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default()
+        )?;
+
+        // Clear it so it can be sampled before anything is drawn to it.
+        vulkan_renderer.record_upload(|commands| {
+            commands.clear_color_image(ClearColorImageInfo {
+                clear_value: ClearColorValue::Float([0.0, 0.0, 0.0, 1.0]),
+                ..ClearColorImageInfo::image(image.clone())
+            })?;
+            Ok(())
+        })?;
+
+        let images = VulkanRenderer::make_offline_images(
+            vulkan_renderer.memory_allocator.clone(),
+            vulkan_renderer.samples_per_pixel,
+            vulkan_renderer.color_format,
+            vulkan_renderer.depth_format,
+            false,
+            resolution.width,
+            resolution.height,
+            Some(ImageView::new_default(image.clone())?)
+        );
+
+        Ok(Self { image, images })
+    }
+}
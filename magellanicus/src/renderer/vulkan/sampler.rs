@@ -0,0 +1,116 @@
+use crate::error::MResult;
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::image::sampler::{BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, SamplerReductionMode};
+use vulkano::pipeline::graphics::depth_stencil::CompareOp;
+
+/// Hands out shared samplers so identical samplers are only created once.
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerKey, Arc<Sampler>>
+}
+
+impl SamplerCache {
+    /// Get a sampler matching `create_info`, creating it if it does not exist yet.
+    ///
+    /// Samplers with a YCbCr conversion are never cached.
+    pub fn get(&mut self, device: Arc<Device>, create_info: SamplerCreateInfo) -> MResult<Arc<Sampler>> {
+        let Some(key) = SamplerKey::new(&create_info) else {
+            return Ok(Sampler::new(device, create_info)?)
+        };
+
+        if let Some(sampler) = self.samplers.get(&key) {
+            return Ok(sampler.clone())
+        }
+
+        let sampler = Sampler::new(device, create_info)?;
+        self.samplers.insert(key, sampler.clone());
+        Ok(sampler)
+    }
+}
+
+/// Hashable representation of a [`SamplerCreateInfo`].
+///
+/// Floats are stored as bits since they aren't hashable.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    mag_filter: Filter,
+    min_filter: Filter,
+    mipmap_mode: SamplerMipmapMode,
+    address_mode: [SamplerAddressMode; 3],
+    mip_lod_bias: u32,
+    anisotropy: Option<u32>,
+    compare: Option<CompareOp>,
+    lod: [u32; 2],
+    border_color: BorderColor,
+    unnormalized_coordinates: bool,
+    reduction_mode: SamplerReductionMode
+}
+
+impl SamplerKey {
+    fn new(create_info: &SamplerCreateInfo) -> Option<Self> {
+        if create_info.sampler_ycbcr_conversion.is_some() {
+            return None
+        }
+
+        Some(Self {
+            mag_filter: create_info.mag_filter,
+            min_filter: create_info.min_filter,
+            mipmap_mode: create_info.mipmap_mode,
+            address_mode: create_info.address_mode,
+            mip_lod_bias: create_info.mip_lod_bias.to_bits(),
+            anisotropy: create_info.anisotropy.map(f32::to_bits),
+            compare: create_info.compare,
+            lod: [create_info.lod.start().to_bits(), create_info.lod.end().to_bits()],
+            border_color: create_info.border_color,
+            unnormalized_coordinates: create_info.unnormalized_coordinates,
+            reduction_mode: create_info.reduction_mode
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(create_info: SamplerCreateInfo) -> SamplerKey {
+        SamplerKey::new(&create_info).unwrap()
+    }
+
+    #[test]
+    fn same_create_info_makes_the_same_key() {
+        assert!(key(SamplerCreateInfo::simple_repeat_linear()) == key(SamplerCreateInfo::simple_repeat_linear()));
+
+        let keys: std::collections::HashSet<SamplerKey> = [
+            SamplerCreateInfo::simple_repeat_linear(),
+            SamplerCreateInfo::simple_repeat_linear()
+        ].into_iter().map(key).collect();
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn different_create_info_makes_different_keys() {
+        let keys: std::collections::HashSet<SamplerKey> = [
+            SamplerCreateInfo::simple_repeat_linear(),
+            SamplerCreateInfo::simple_repeat_linear_no_mipmap(),
+            SamplerCreateInfo {
+                anisotropy: Some(16.0),
+                ..SamplerCreateInfo::simple_repeat_linear()
+            },
+            SamplerCreateInfo {
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..SamplerCreateInfo::simple_repeat_linear()
+            },
+            SamplerCreateInfo {
+                lod: 0.0..=1.0,
+                ..SamplerCreateInfo::simple_repeat_linear()
+            },
+            SamplerCreateInfo {
+                mip_lod_bias: 1.0,
+                ..SamplerCreateInfo::simple_repeat_linear()
+            }
+        ].into_iter().map(key).collect();
+        assert_eq!(keys.len(), 6);
+    }
+}
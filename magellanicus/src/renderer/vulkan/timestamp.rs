@@ -0,0 +1,152 @@
+use crate::error::MResult;
+use crate::renderer::FrameTimings;
+use std::sync::Arc;
+use std::vec::Vec;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::device::{Device, Queue};
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+
+/// Timestamp queries for measuring GPU frame time.
+///
+/// Queries are laid out as the start of the frame, the end of each viewport, the end of the overlays, and the end of
+/// the blit. Each swapchain image gets its own query pool so a frame in flight is never reset.
+pub struct VulkanFrameTimestamps {
+    device: Arc<Device>,
+    query_pools: Vec<Option<Arc<QueryPool>>>,
+    viewport_count: usize,
+    timestamp_period: f32,
+    valid_mask: u64
+}
+
+impl VulkanFrameTimestamps {
+    /// Returns `None` if timestamps are not supported on the queue.
+    pub fn new(device: Arc<Device>, queue: &Queue, viewport_count: usize) -> Option<Self> {
+        let physical_device = device.physical_device();
+        let timestamp_period = physical_device.properties().timestamp_period;
+        let valid_bits = physical_device
+            .queue_family_properties()
+            .get(queue.queue_family_index() as usize)?
+            .timestamp_valid_bits?;
+
+        if valid_bits == 0 || !(timestamp_period > 0.0) {
+            return None
+        }
+
+        let valid_mask = if valid_bits >= 64 { u64::MAX } else { (1u64 << valid_bits) - 1 };
+
+        Some(Self {
+            device,
+            query_pools: Vec::new(),
+            viewport_count,
+            timestamp_period,
+            valid_mask
+        })
+    }
+
+    /// Discard all query pools (e.g. when the swapchain is rebuilt).
+    pub fn clear(&mut self) {
+        self.query_pools.clear();
+    }
+
+    /// Change the number of viewports timed, discarding all query pools.
+    pub fn set_viewport_count(&mut self, viewport_count: usize) {
+        self.viewport_count = viewport_count;
+        self.clear();
+    }
+
+    /// Get the timings of the last frame drawn to `image_index` if they are available.
+    pub fn get_results(&self, image_index: u32) -> Option<FrameTimings> {
+        let pool = self.query_pools.get(image_index as usize)?.as_ref()?;
+
+        let mut ticks = vec![0u64; self.query_count() as usize];
+        if !pool.get_results(0..self.query_count(), ticks.as_mut_slice(), QueryResultFlags::empty()).ok()? {
+            return None
+        }
+
+        let to_ms = |from: usize, to: usize| {
+            let delta = ticks[to].wrapping_sub(ticks[from]) & self.valid_mask;
+            (delta as f64 * self.timestamp_period as f64 / 1000000.0) as f32
+        };
+
+        let overlays_index = self.overlays_query() as usize;
+        let blit_index = self.blit_query() as usize;
+
+        Some(FrameTimings {
+            viewports: (0..self.viewport_count).map(|v| to_ms(v, v + 1)).collect(),
+            overlays: to_ms(overlays_index - 1, overlays_index),
+            blit: to_ms(overlays_index, blit_index),
+            total: to_ms(0, blit_index)
+        })
+    }
+
+    /// Reset the queries for `image_index` and write the start of the frame.
+    ///
+    /// This must be called outside of rendering.
+    pub fn begin(&mut self, image_index: u32, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        let index = image_index as usize;
+        if self.query_pools.len() <= index {
+            self.query_pools.resize(index + 1, None);
+        }
+
+        if self.query_pools[index].is_none() {
+            self.query_pools[index] = Some(QueryPool::new(
+                self.device.clone(),
+                QueryPoolCreateInfo {
+                    query_count: self.query_count(),
+                    ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                }
+            )?);
+        }
+
+        let pool = self.query_pools[index].clone().expect("just created");
+
+        // SAFETY: the queries are not in use, as each swapchain image has its own pool and the image was acquired.
+        unsafe {
+            builder.reset_query_pool(pool.clone(), 0..self.query_count())?;
+            builder.write_timestamp(pool, 0, PipelineStage::TopOfPipe)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the end of the given viewport.
+    pub fn write_viewport_end(&self, image_index: u32, viewport: usize, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        self.write(image_index, 1 + viewport as u32, builder)
+    }
+
+    /// Write the end of the overlays (split screen bars, sprites, and debug text).
+    pub fn write_overlays_end(&self, image_index: u32, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        self.write(image_index, self.overlays_query(), builder)
+    }
+
+    /// Write the end of the blit.
+    pub fn write_blit_end(&self, image_index: u32, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        self.write(image_index, self.blit_query(), builder)
+    }
+
+    fn write(&self, image_index: u32, query: u32, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> MResult<()> {
+        let Some(pool) = self.query_pools.get(image_index as usize).and_then(|p| p.clone()) else {
+            return Ok(())
+        };
+
+        // SAFETY: the query was reset in begin() and is only written once per frame.
+        unsafe {
+            builder.write_timestamp(pool, query, PipelineStage::BottomOfPipe)?;
+        }
+
+        Ok(())
+    }
+
+    fn overlays_query(&self) -> u32 {
+        1 + self.viewport_count as u32
+    }
+
+    fn blit_query(&self) -> u32 {
+        2 + self.viewport_count as u32
+    }
+
+    fn query_count(&self) -> u32 {
+        3 + self.viewport_count as u32
+    }
+}
@@ -1,6 +1,7 @@
 use vulkano::buffer::BufferContents;
 use vulkano::pipeline::graphics::vertex_input::Vertex;
 use vulkano::padded::Padded;
+use glam::{Mat3, Mat4};
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
@@ -35,16 +36,65 @@ pub struct VulkanModelVertexLightmapTextureCoords {
     pub lightmap_texture_coords: [f32; 2],
 }
 
+/// Per-instance transform, applied on top of the world transform in [`VulkanModelData`].
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+#[derive(BufferContents, Vertex)]
+pub struct VulkanModelInstance {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_world: [[f32; 4]; 4],
+
+    /// Inverse transpose of the instance's world transform, for transforming normals.
+    #[format(R32G32B32_SFLOAT)]
+    pub instance_rotation: [[f32; 3]; 3]
+}
+
+impl VulkanModelInstance {
+    pub fn from_transform(transform: Mat4) -> Self {
+        Self {
+            instance_world: transform.to_cols_array_2d(),
+            instance_rotation: Mat3::from_mat4(transform).inverse().transpose().to_cols_array_2d()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+#[derive(BufferContents, Vertex)]
+pub struct VulkanDebugLineVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4]
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+#[derive(BufferContents, Vertex)]
+pub struct VulkanTextVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+
+    #[format(R32G32_SFLOAT)]
+    pub texture_coords: [f32; 2],
+
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4]
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 #[derive(BufferContents)]
 pub struct VulkanModelData {
-    pub camera: Padded<[f32; 3], 4>,
+    pub camera: [f32; 3],
+    pub time: f32,
     pub world: [[f32; 4]; 4],
     pub view: [[f32; 4]; 4],
     pub proj: [[f32; 4]; 4],
     pub offset: Padded<[f32; 3], 4>,
     pub rotation: [Padded<[f32; 3], 4>; 3],
+    pub debug_view: u32,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -56,4 +106,6 @@ pub struct VulkanFogData {
     pub sky_fog_to: f32,
     pub sky_fog_min_opacity: f32,
     pub sky_fog_max_opacity: f32,
+    pub sky_fog_mode: u32,
+    pub sky_fog_density: Padded<f32, 8>,
 }
@@ -17,7 +17,7 @@ pub struct LightmapVertex {
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct ModelTriangle {
-    pub indices: [u16; 3]
+    pub indices: [u32; 3]
 }
 
 #[derive(Copy, Clone, Debug)]